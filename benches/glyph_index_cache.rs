@@ -0,0 +1,54 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Compares looking glyphs up through a `GlyphIndexCache` with asking
+// DirectWrite every time, one character at a time. Run with
+// `cargo bench --bench glyph_index_cache`.
+
+use dwrote::{FontCollection, FontStretch, FontStyle, FontWeight, GlyphIndexCache};
+use std::time::Instant;
+
+const TEXT: &str = "The quick brown fox jumps over the lazy dog. 0123456789";
+const ITERATIONS: u32 = 10_000;
+
+fn main() {
+    let arial_family = FontCollection::system()
+        .get_font_family_by_name("Arial")
+        .unwrap();
+    let face = arial_family
+        .get_first_matching_font(FontWeight::Regular, FontStretch::Normal, FontStyle::Normal)
+        .try_create_font_face()
+        .unwrap();
+
+    // Summing the glyphs keeps the lookups from being optimized away.
+    let mut uncached_sum = 0u64;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        for c in TEXT.chars() {
+            uncached_sum += face.get_glyph_indices(&[c as u32])[0] as u64;
+        }
+    }
+    let uncached = start.elapsed();
+
+    let mut cache = GlyphIndexCache::new();
+    let mut cached_sum = 0u64;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        for c in TEXT.chars() {
+            cached_sum += cache.get_glyph(&face, c) as u64;
+        }
+    }
+    let cached = start.elapsed();
+    assert_eq!(uncached_sum, cached_sum);
+
+    let lookups = ITERATIONS * TEXT.chars().count() as u32;
+    println!(
+        "{} lookups: uncached {:?} ({:?} each), cached {:?} ({:?} each)",
+        lookups,
+        uncached,
+        uncached / lookups,
+        cached,
+        cached / lookups,
+    );
+}
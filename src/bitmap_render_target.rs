@@ -4,16 +4,21 @@
 
 use std::cell::UnsafeCell;
 use std::mem::{size_of, zeroed};
+use std::ops::Range;
+use std::ptr;
 use std::slice;
 use winapi::ctypes::c_void;
-use winapi::shared::windef::{HDC, RECT};
+use winapi::shared::windef::{HDC, RECT, SIZE};
+use winapi::shared::winerror::E_NOINTERFACE;
 use winapi::um::dcommon::DWRITE_MEASURING_MODE;
 use winapi::um::dwrite::IDWriteBitmapRenderTarget;
-use winapi::um::dwrite::{DWRITE_GLYPH_OFFSET, DWRITE_GLYPH_RUN};
-use winapi::um::wingdi::{GetCurrentObject, GetObjectW, BITMAP, OBJ_BITMAP, RGB};
+use winapi::um::dwrite::{DWRITE_GLYPH_OFFSET, DWRITE_GLYPH_RUN, DWRITE_MATRIX};
+use winapi::um::dwrite_1::{IDWriteBitmapRenderTarget1, DWRITE_TEXT_ANTIALIAS_MODE};
+use winapi::um::wingdi::{GdiFlush, GetCurrentObject, GetObjectW, DIBSECTION, OBJ_BITMAP, RGB};
 use wio::com::ComPtr;
 
-use super::{FontFace, RenderingParams};
+use super::{FontFace, GlyphRun, RenderingParams, TextAntialiasMode};
+use crate::error::Error;
 
 pub struct BitmapRenderTarget {
     native: UnsafeCell<ComPtr<IDWriteBitmapRenderTarget>>,
@@ -37,10 +42,105 @@ impl BitmapRenderTarget {
         }
     }
 
+    pub fn pixels_per_dip(&self) -> f32 {
+        unsafe { (*self.native.get()).GetPixelsPerDip() }
+    }
+
+    /// Sets the transform applied to later draws, on top of the pixels per
+    /// dip scale. `None` resets it to the identity.
+    pub fn set_current_transform(&self, transform: Option<&DWRITE_MATRIX>) -> Result<(), Error> {
+        unsafe {
+            let hr = (*self.native.get())
+                .SetCurrentTransform(transform.map_or(ptr::null(), |t| t as *const _));
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(())
+        }
+    }
+
+    /// Returns the current transform, or `None` if it's the identity.
+    pub fn current_transform(&self) -> Result<Option<DWRITE_MATRIX>, Error> {
+        unsafe {
+            let mut transform: DWRITE_MATRIX = zeroed();
+            let hr = (*self.native.get()).GetCurrentTransform(&mut transform);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            let is_identity = transform.m11 == 1.0
+                && transform.m12 == 0.0
+                && transform.m21 == 0.0
+                && transform.m22 == 1.0
+                && transform.dx == 0.0
+                && transform.dy == 0.0;
+            if is_identity {
+                Ok(None)
+            } else {
+                Ok(Some(transform))
+            }
+        }
+    }
+
+    /// Returns the `(width, height)` of the target's bitmap in pixels.
+    pub fn size(&self) -> Result<(u32, u32), Error> {
+        unsafe {
+            let mut size: SIZE = zeroed();
+            let hr = (*self.native.get()).GetSize(&mut size);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok((size.cx as u32, size.cy as u32))
+        }
+    }
+
+    /// Resizes the target's bitmap in place, keeping the memory DC and the
+    /// GDI objects selected into it. The contents of the bitmap are
+    /// undefined afterwards.
+    pub fn resize(&self, width: u32, height: u32) -> Result<(), Error> {
+        unsafe {
+            let hr = (*self.native.get()).Resize(width, height);
+            if hr != 0 {
+                Err(hr.into())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    /// Sets whether later draws use ClearType or grayscale antialiasing.
+    /// Grayscale is needed when the target will be composited onto a
+    /// transparent layer, where per-channel coverage is wrong.
+    ///
+    /// Fails with `E_NOINTERFACE` if `IDWriteBitmapRenderTarget1` isn't
+    /// available.
+    pub fn set_text_antialias_mode(&self, mode: TextAntialiasMode) -> Result<(), Error> {
+        unsafe {
+            let target1: ComPtr<IDWriteBitmapRenderTarget1> =
+                (*self.native.get()).cast().map_err(|_| E_NOINTERFACE)?;
+            let hr = target1.SetTextAntialiasMode(mode as DWRITE_TEXT_ANTIALIAS_MODE);
+            if hr != 0 {
+                Err(hr.into())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns `None` if `IDWriteBitmapRenderTarget1` isn't available.
+    pub fn get_text_antialias_mode(&self) -> Option<TextAntialiasMode> {
+        unsafe {
+            let target1: ComPtr<IDWriteBitmapRenderTarget1> = (*self.native.get()).cast().ok()?;
+            Some(TextAntialiasMode::from_raw(target1.GetTextAntialiasMode()))
+        }
+    }
+
     pub fn get_memory_dc(&self) -> HDC {
         unsafe { (*self.native.get()).GetMemoryDC() }
     }
 
+    /// Draws a glyph run with its baseline origin at the given (possibly
+    /// fractional) position, and returns the rectangle of pixels that were
+    /// modified, or `None` if nothing was drawn.
     pub fn draw_glyph_run(
         &self,
         baseline_origin_x: f32,
@@ -53,15 +153,11 @@ impl BitmapRenderTarget {
         glyph_offsets: &[DWRITE_GLYPH_OFFSET],
         rendering_params: &RenderingParams,
         color: &(f32, f32, f32),
-    ) -> RECT {
+    ) -> Result<Option<RECT>, Error> {
         unsafe {
             assert!(glyph_indices.len() == glyph_advances.len());
             assert!(glyph_indices.len() == glyph_offsets.len());
 
-            let r = (color.0 * 255.0) as u8;
-            let g = (color.1 * 255.0) as u8;
-            let b = (color.2 * 255.0) as u8;
-
             let mut glyph_run: DWRITE_GLYPH_RUN = zeroed();
             glyph_run.fontFace = font_face.as_ptr();
             glyph_run.fontEmSize = em_size;
@@ -72,18 +168,72 @@ impl BitmapRenderTarget {
             glyph_run.isSideways = 0;
             glyph_run.bidiLevel = 0;
 
+            self.draw_raw_glyph_run(
+                baseline_origin_x,
+                baseline_origin_y,
+                measuring_mode,
+                &glyph_run,
+                rendering_params,
+                color,
+            )
+        }
+    }
+
+    /// Like `draw_glyph_run`, but takes an owned `GlyphRun`, which may also
+    /// be sideways or right-to-left.
+    pub fn draw_run(
+        &self,
+        baseline_origin_x: f32,
+        baseline_origin_y: f32,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        glyph_run: &GlyphRun,
+        rendering_params: &RenderingParams,
+        color: &(f32, f32, f32),
+    ) -> Result<Option<RECT>, Error> {
+        self.draw_raw_glyph_run(
+            baseline_origin_x,
+            baseline_origin_y,
+            measuring_mode,
+            &glyph_run.as_raw(),
+            rendering_params,
+            color,
+        )
+    }
+
+    fn draw_raw_glyph_run(
+        &self,
+        baseline_origin_x: f32,
+        baseline_origin_y: f32,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        glyph_run: &DWRITE_GLYPH_RUN,
+        rendering_params: &RenderingParams,
+        color: &(f32, f32, f32),
+    ) -> Result<Option<RECT>, Error> {
+        if glyph_run.glyphCount == 0 {
+            return Ok(None);
+        }
+        unsafe {
+            let r = (color.0 * 255.0) as u8;
+            let g = (color.1 * 255.0) as u8;
+            let b = (color.2 * 255.0) as u8;
+
             let mut rect: RECT = zeroed();
             let hr = (*self.native.get()).DrawGlyphRun(
                 baseline_origin_x,
                 baseline_origin_y,
                 measuring_mode,
-                &glyph_run,
+                glyph_run,
                 rendering_params.as_ptr(),
                 RGB(r, g, b),
                 &mut rect,
             );
-            assert!(hr == 0);
-            rect
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            if rect.left >= rect.right || rect.top >= rect.bottom {
+                return Ok(None);
+            }
+            Ok(Some(rect))
         }
     }
 
@@ -93,37 +243,162 @@ impl BitmapRenderTarget {
     // channel, with the color white.  That is, it performs:
     // RGBX -> xxxR, where xxx = 0xff
     pub fn get_opaque_values_as_mask(&self) -> Vec<u8> {
-        // Now grossness to pull out the pixels
+        // The view doesn't outlive this call, and nothing draws meanwhile.
+        let pixels = unsafe { self.pixel_view() };
+        let (width, height) = (pixels.width(), pixels.height());
+        let mut out_bytes: Vec<u8> = vec![0; width * height * 4];
+        for row in 0..height {
+            let in_row = pixels.row(row);
+            let out_row = &mut out_bytes[row * width * 4..(row + 1) * width * 4];
+            for col in 0..width {
+                // BGRX in memory, so R is the third byte.
+                let r = in_row[col * 4 + 2];
+                out_row[col * 4..col * 4 + 4].copy_from_slice(&[0xff, 0xff, 0xff, r]);
+            }
+        }
+        out_bytes
+    }
+
+    /// Returns a view of the target's pixels, without copying them.
+    ///
+    /// This borrows the target mutably, even though the view is read-only,
+    /// because drawing and `resize` only need `&self` but write to (or
+    /// reallocate) the memory under the view.
+    pub fn pixels(&mut self) -> PixelView<'_> {
+        unsafe { self.pixel_view() }
+    }
+
+    // The caller must make sure nothing draws to or resizes the target while
+    // the view is alive.
+    unsafe fn pixel_view(&self) -> PixelView<'_> {
+        let (bits, layout) = self.get_pixel_layout();
+        PixelView {
+            data: slice::from_raw_parts(bits, layout.len()),
+            layout,
+        }
+    }
+
+    /// Like `pixels`, but allows writing to them, for example to clear the
+    /// background before drawing.
+    pub fn pixels_mut(&mut self) -> PixelViewMut<'_> {
         unsafe {
-            let memory_dc = self.get_memory_dc();
-            let mut bitmap: BITMAP = zeroed();
-            let ret = GetObjectW(
-                GetCurrentObject(memory_dc, OBJ_BITMAP),
-                size_of::<BITMAP>() as i32,
-                &mut bitmap as *mut _ as *mut c_void,
-            );
-            assert!(ret == size_of::<BITMAP>() as i32);
-            assert!(bitmap.bmBitsPixel == 32);
-
-            let width = bitmap.bmWidth as usize;
-            let stride = bitmap.bmWidthBytes as usize;
-            let height = bitmap.bmHeight as usize;
-
-            let mut out_bytes: Vec<u8> = vec![0; width * height * 4];
-            let out_u32 =
-                slice::from_raw_parts_mut(out_bytes.as_mut_ptr() as *mut u32, width * height);
-
-            for row in 0..height {
-                let in_offset = (row * stride) as isize;
-                let in_u32 =
-                    slice::from_raw_parts(bitmap.bmBits.offset(in_offset) as *const u32, width);
-                for col in 0..width {
-                    let r = in_u32[col] & 0xff;
-                    out_u32[width * row + col] = (r << 24) | (0x00ffffffu32);
-                }
+            let (bits, layout) = self.get_pixel_layout();
+            PixelViewMut {
+                data: slice::from_raw_parts_mut(bits, layout.len()),
+                layout,
             }
+        }
+    }
+
+    unsafe fn get_pixel_layout(&self) -> (*mut u8, PixelLayout) {
+        // Make sure GDI has finished any drawing it has batched up.
+        GdiFlush();
+        let mut dib: DIBSECTION = zeroed();
+        let ret = GetObjectW(
+            GetCurrentObject(self.get_memory_dc(), OBJ_BITMAP),
+            size_of::<DIBSECTION>() as i32,
+            &mut dib as *mut _ as *mut c_void,
+        );
+        assert!(ret == size_of::<DIBSECTION>() as i32);
+        assert!(dib.dsBm.bmBitsPixel == 32);
+        let layout = PixelLayout {
+            width: dib.dsBm.bmWidth as usize,
+            height: dib.dsBm.bmHeight as usize,
+            stride: dib.dsBm.bmWidthBytes as usize,
+            bottom_up: dib.dsBmih.biHeight > 0,
+        };
+        (dib.dsBm.bmBits as *mut u8, layout)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct PixelLayout {
+    width: usize,
+    height: usize,
+    stride: usize,
+    bottom_up: bool,
+}
+
+impl PixelLayout {
+    fn len(&self) -> usize {
+        self.stride * self.height
+    }
+
+    fn row_range(&self, row: usize) -> Range<usize> {
+        assert!(row < self.height);
+        let row = if self.bottom_up {
+            self.height - 1 - row
+        } else {
+            row
+        };
+        row * self.stride..row * self.stride + self.width * 4
+    }
+}
+
+/// The pixels of a `BitmapRenderTarget`: 32 bits per pixel, in BGRX order
+/// (the X byte is unused, and is not alpha).
+///
+/// Rows are indexed from the top, whichever way up the underlying bitmap is
+/// stored.
+pub struct PixelView<'a> {
+    data: &'a [u8],
+    layout: PixelLayout,
+}
+
+impl<'a> PixelView<'a> {
+    pub fn width(&self) -> usize {
+        self.layout.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.layout.height
+    }
+
+    /// The distance in bytes between the starts of consecutive rows in
+    /// memory, which may be more than `width() * 4`.
+    pub fn stride(&self) -> usize {
+        self.layout.stride
+    }
+
+    /// Returns the `width() * 4` bytes of row `row`, counting from the top.
+    pub fn row(&self, row: usize) -> &[u8] {
+        &self.data[self.layout.row_range(row)]
+    }
+}
+
+/// A mutable `PixelView`.
+pub struct PixelViewMut<'a> {
+    data: &'a mut [u8],
+    layout: PixelLayout,
+}
+
+impl<'a> PixelViewMut<'a> {
+    pub fn width(&self) -> usize {
+        self.layout.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.layout.height
+    }
 
-            out_bytes
+    pub fn stride(&self) -> usize {
+        self.layout.stride
+    }
+
+    pub fn row(&self, row: usize) -> &[u8] {
+        &self.data[self.layout.row_range(row)]
+    }
+
+    pub fn row_mut(&mut self, row: usize) -> &mut [u8] {
+        &mut self.data[self.layout.row_range(row)]
+    }
+
+    /// Sets every pixel to the given `(b, g, r)` color.
+    pub fn fill(&mut self, color: (u8, u8, u8)) {
+        for row in 0..self.height() {
+            for pixel in self.row_mut(row).chunks_mut(4) {
+                pixel.copy_from_slice(&[color.0, color.1, color.2, 0]);
+            }
         }
     }
 }
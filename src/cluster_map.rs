@@ -0,0 +1,66 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::ops::Range;
+
+/// Maps between the UTF-16 code units of a shaped run and its glyphs, using
+/// the cluster map produced by `IDWriteTextAnalyzer::GetGlyphs`.
+///
+/// The cluster map has one entry per code unit, holding the index of the
+/// first glyph of the cluster that code unit belongs to. Several code units
+/// share a cluster when they form a ligature, and a cluster spans several
+/// glyphs when a character decomposes. Both are in logical order, even for
+/// right-to-left runs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClusterMap {
+    map: Vec<u16>,
+    glyph_count: usize,
+}
+
+impl ClusterMap {
+    pub fn new(map: Vec<u16>, glyph_count: usize) -> ClusterMap {
+        debug_assert!(map.windows(2).all(|w| w[0] <= w[1]));
+        debug_assert!(map.iter().all(|&g| (g as usize) < glyph_count.max(1)));
+        ClusterMap { map, glyph_count }
+    }
+
+    pub fn text_len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn glyph_count(&self) -> usize {
+        self.glyph_count
+    }
+
+    /// Returns the first code unit of the cluster `glyph` belongs to.
+    ///
+    /// Panics if `glyph` is out of range.
+    pub fn char_index_for_glyph(&self, glyph: usize) -> usize {
+        assert!(glyph < self.glyph_count);
+        let cluster_start = self
+            .map
+            .iter()
+            .rev()
+            .map(|&g| g as usize)
+            .find(|&g| g <= glyph)
+            .unwrap_or(0);
+        self.map
+            .iter()
+            .position(|&g| g as usize == cluster_start)
+            .unwrap_or(0)
+    }
+
+    /// Returns the glyphs of the cluster that `char_index` belongs to. Every
+    /// code unit of a ligature gets the same range.
+    ///
+    /// Panics if `char_index` is out of range.
+    pub fn glyph_range_for_char(&self, char_index: usize) -> Range<usize> {
+        let start = self.map[char_index];
+        let end = self.map[char_index..]
+            .iter()
+            .find(|&&g| g != start)
+            .map_or(self.glyph_count, |&g| g as usize);
+        start as usize..end
+    }
+}
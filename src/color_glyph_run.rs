@@ -0,0 +1,158 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::mem;
+use std::ptr;
+use std::slice;
+use winapi::shared::minwindef::{BOOL, FALSE};
+use winapi::shared::winerror::{DWRITE_E_NOCOLOR, E_NOINTERFACE, S_OK};
+use winapi::um::dcommon::DWRITE_MEASURING_MODE;
+use winapi::um::dwrite::{DWRITE_GLYPH_RUN, DWRITE_MATRIX};
+use winapi::um::dwrite_2::{IDWriteColorGlyphRunEnumerator, IDWriteFactory2};
+use winapi::um::dwrite_2::{DWRITE_COLOR_F, DWRITE_COLOR_GLYPH_RUN};
+use wio::com::ComPtr;
+
+use super::{FontFace, GlyphRun};
+use crate::error::Error;
+use crate::factory::{factory_or_shared, Factory};
+
+/// One layer of a color glyph run, as produced by
+/// `ColorGlyphRun::translate`. Layers are meant to be drawn in order, each
+/// as a plain glyph run in a single color.
+#[derive(Clone)]
+pub struct ColorGlyphRun {
+    pub glyph_run: GlyphRun,
+    pub baseline_origin_x: f32,
+    pub baseline_origin_y: f32,
+    /// The color of this layer, or `None` if it should be drawn in the
+    /// text's foreground color.
+    pub run_color: Option<DWRITE_COLOR_F>,
+    /// The entry of the palette this layer's color came from, or `None`
+    /// for the foreground color.
+    pub palette_index: Option<u16>,
+}
+
+impl ColorGlyphRun {
+    /// Splits `glyph_run` into its color layers, using the font's first
+    /// color palette.
+    ///
+    /// Returns `Ok(None)` if none of the glyphs in the run have color
+    /// layers, in which case the run should be drawn as usual.
+    pub fn translate(
+        baseline_origin_x: f32,
+        baseline_origin_y: f32,
+        glyph_run: &GlyphRun,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        transform: Option<&DWRITE_MATRIX>,
+    ) -> Result<Option<Vec<ColorGlyphRun>>, Error> {
+        ColorGlyphRun::translate_with_palette(
+            baseline_origin_x,
+            baseline_origin_y,
+            glyph_run,
+            measuring_mode,
+            transform,
+            0,
+            None,
+        )
+    }
+
+    /// Like `translate`, but uses the palette `palette_index` (see
+    /// `FontFace::get_color_palette_count`), such as a dark-mode palette.
+    ///
+    /// Fails with `E_NOINTERFACE` if `IDWriteFactory2` isn't available.
+    pub fn translate_with_palette(
+        baseline_origin_x: f32,
+        baseline_origin_y: f32,
+        glyph_run: &GlyphRun,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        transform: Option<&DWRITE_MATRIX>,
+        palette_index: u32,
+        factory: Option<&Factory>,
+    ) -> Result<Option<Vec<ColorGlyphRun>>, Error> {
+        unsafe {
+            let factory = ComPtr::from_raw(factory_or_shared(factory));
+            let factory2: Option<ComPtr<IDWriteFactory2>> = factory.cast().ok();
+            mem::forget(factory);
+            let factory2 = factory2.ok_or(E_NOINTERFACE)?;
+
+            let raw_run = glyph_run.as_raw();
+            let mut native: *mut IDWriteColorGlyphRunEnumerator = ptr::null_mut();
+            let hr = factory2.TranslateColorGlyphRun(
+                baseline_origin_x,
+                baseline_origin_y,
+                &raw_run,
+                ptr::null(),
+                measuring_mode,
+                transform.map_or(ptr::null(), |t| t as *const _),
+                palette_index,
+                &mut native,
+            );
+            if hr == DWRITE_E_NOCOLOR {
+                return Ok(None);
+            }
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            let enumerator = ComPtr::from_raw(native);
+
+            let mut layers = vec![];
+            loop {
+                let mut has_run: BOOL = FALSE;
+                let hr = enumerator.MoveNext(&mut has_run);
+                if hr != S_OK {
+                    return Err(hr.into());
+                }
+                if has_run == FALSE {
+                    break;
+                }
+                let mut color_run: *const DWRITE_COLOR_GLYPH_RUN = ptr::null();
+                let hr = enumerator.GetCurrentRun(&mut color_run);
+                if hr != S_OK {
+                    return Err(hr.into());
+                }
+                layers.push(ColorGlyphRun::from_raw(&*color_run));
+            }
+            Ok(Some(layers))
+        }
+    }
+
+    // Copies a layer out of the enumerator, which owns the arrays it points
+    // to.
+    unsafe fn from_raw(color_run: &DWRITE_COLOR_GLYPH_RUN) -> ColorGlyphRun {
+        let run: &DWRITE_GLYPH_RUN = &color_run.glyphRun;
+        let count = run.glyphCount as usize;
+        (*run.fontFace).AddRef();
+        let face = FontFace::take(ComPtr::from_raw(run.fontFace));
+        let mut glyph_run = GlyphRun::new(&face, run.fontEmSize)
+            .sideways(run.isSideways != FALSE)
+            .bidi_level(run.bidiLevel);
+        if count != 0 {
+            glyph_run = glyph_run.with_glyphs(slice::from_raw_parts(run.glyphIndices, count));
+            if !run.glyphAdvances.is_null() {
+                glyph_run =
+                    glyph_run.with_advances(slice::from_raw_parts(run.glyphAdvances, count));
+            }
+            if !run.glyphOffsets.is_null() {
+                glyph_run = glyph_run.with_offsets(slice::from_raw_parts(run.glyphOffsets, count));
+            }
+        }
+        // A palette index of 0xFFFF means the layer uses the foreground color.
+        let uses_foreground = color_run.paletteIndex == 0xFFFF;
+        ColorGlyphRun {
+            glyph_run,
+            baseline_origin_x: color_run.baselineOriginX,
+            baseline_origin_y: color_run.baselineOriginY,
+            run_color: if uses_foreground {
+                None
+            } else {
+                Some(color_run.runColor)
+            },
+            palette_index: if uses_foreground {
+                None
+            } else {
+                Some(color_run.paletteIndex)
+            },
+        }
+    }
+}
@@ -1,39 +1,26 @@
-// This is only handy for implementing a single-interface-implementing IUnknown.
+use std::mem;
+
+// Implements IUnknown for an object that implements a single interface, or a
+// chain of interfaces each extending the next, e.g.
+// `[IDWriteTextAnalysisSource1, IDWriteTextAnalysisSource]`. They all share
+// the object's one vtable pointer, so that vtable must be the one for the
+// first, most derived, interface.
 
 macro_rules! implement_iunknown {
     ($interface:ident, $typ:ident) => {
+        implement_iunknown!([$interface], $typ)
+    };
+    (static $interface:ident, $typ:ident) => {
+        implement_iunknown!(static [$interface], $typ)
+    };
+    ([$first:ident $(, $rest:ident)*], $typ:ident) => {
         IUnknownVtbl {
-            QueryInterface: {
-                #[allow(non_snake_case)]
-                unsafe extern "system" fn QueryInterface(
-                    unknown_this: *mut IUnknown,
-                    riid: REFIID,
-                    ppv_object: *mut *mut c_void,
-                ) -> HRESULT {
-                    use $crate::winapi::Interface;
-                    let this = if $crate::winapi::shared::guiddef::IsEqualGUID(
-                        &*riid,
-                        &$interface::uuidof(),
-                    ) {
-                        mem::transmute(unknown_this)
-                    } else if $crate::winapi::shared::guiddef::IsEqualGUID(
-                        &*riid,
-                        &IUnknown::uuidof(),
-                    ) {
-                        mem::transmute(unknown_this)
-                    } else {
-                        return $crate::winapi::shared::winerror::E_NOINTERFACE;
-                    };
-
-                    (*unknown_this).AddRef();
-                    *ppv_object = this;
-                    return S_OK;
-                }
-                QueryInterface
-            },
+            QueryInterface: implement_iunknown!(@query_interface [$first $(, $rest)*]),
             AddRef: {
                 unsafe extern "system" fn AddRef(unknown_this: *mut IUnknown) -> ULONG {
                     let this = $typ::from_interface(unknown_this);
+                    // Relaxed is enough: the caller already holds a
+                    // reference, so the object can't be dropped meanwhile.
                     let count = this.refcount.fetch_add(1, atomic::Ordering::Relaxed) + 1;
                     count as ULONG
                 }
@@ -44,7 +31,11 @@ macro_rules! implement_iunknown {
                     let this = $typ::from_interface(unknown_this);
                     let count = this.refcount.fetch_sub(1, atomic::Ordering::Release) - 1;
                     if count == 0 {
-                        <$typ as Com<$interface>>::destroy(unknown_this as *mut $interface);
+                        // Pairs with the `Release` decrements on other
+                        // threads, so that everything they did with the
+                        // object happens before it's dropped. See `Arc`.
+                        atomic::fence(atomic::Ordering::Acquire);
+                        <$typ as Com<$first>>::destroy(unknown_this as *mut $first);
                     }
                     count as ULONG
                 }
@@ -52,36 +43,9 @@ macro_rules! implement_iunknown {
             },
         }
     };
-    (static $interface:ident, $typ:ident) => {
+    (static [$($interface:ident),+], $typ:ident) => {
         IUnknownVtbl {
-            QueryInterface: {
-                #[allow(non_snake_case)]
-                unsafe extern "system" fn QueryInterface(
-                    unknown_this: *mut IUnknown,
-                    riid: REFIID,
-                    ppvObject: *mut *mut $crate::winapi::ctypes::c_void,
-                ) -> HRESULT {
-                    use $crate::winapi::Interface;
-                    let this = if $crate::winapi::shared::guiddef::IsEqualGUID(
-                        &*riid,
-                        &$interface::uuidof(),
-                    ) {
-                        mem::transmute(unknown_this)
-                    } else if $crate::winapi::shared::guiddef::IsEqualGUID(
-                        &*riid,
-                        &IUnknown::uuidof(),
-                    ) {
-                        mem::transmute(unknown_this)
-                    } else {
-                        return $crate::winapi::shared::winerror::E_NOINTERFACE;
-                    };
-
-                    (*unknown_this).AddRef();
-                    *ppvObject = this;
-                    return S_OK;
-                }
-                QueryInterface
-            },
+            QueryInterface: implement_iunknown!(@query_interface [$($interface),+]),
             AddRef: {
                 // FIXME(pcwalton): Uh? Maybe we should actually reference count?
                 #[allow(non_snake_case)]
@@ -99,8 +63,33 @@ macro_rules! implement_iunknown {
             },
         }
     };
+    (@query_interface [$($interface:ident),+]) => {{
+        #[allow(non_snake_case)]
+        unsafe extern "system" fn QueryInterface(
+            unknown_this: *mut IUnknown,
+            riid: REFIID,
+            ppv_object: *mut *mut $crate::winapi::ctypes::c_void,
+        ) -> HRESULT {
+            use $crate::winapi::shared::guiddef::IsEqualGUID;
+            use $crate::winapi::Interface;
+            let supported = IsEqualGUID(&*riid, &IUnknown::uuidof())
+                $(|| IsEqualGUID(&*riid, &$interface::uuidof()))+;
+            if !supported {
+                *ppv_object = ::std::ptr::null_mut();
+                return $crate::winapi::shared::winerror::E_NOINTERFACE;
+            }
+
+            (*unknown_this).AddRef();
+            *ppv_object = unknown_this as *mut _;
+            S_OK
+        }
+        QueryInterface
+    }};
 }
 
+// The interface pointer handed to COM points at one of these, so the vtable
+// pointer must come first; `Com::LAYOUT` checks that. Fields of `Type`, such
+// as the refcount, are only ever accessed by name through `from_interface`.
 #[repr(C)]
 pub struct ComRepr<Type, Vtbl>(*const Vtbl, Type);
 
@@ -112,16 +101,21 @@ where
 
     fn vtbl() -> &'static Self::Vtbl;
 
+    // Evaluated at compile time, for each implementation, wherever it's used.
+    const LAYOUT: () = assert!(mem::offset_of!(ComRepr<Self, Self::Vtbl>, 0) == 0);
+
     fn into_interface(self) -> *mut Interface {
+        let () = Self::LAYOUT;
         let com = Box::new(ComRepr(Self::vtbl(), self));
         Box::into_raw(com) as *mut Interface
     }
 
     unsafe fn from_interface<'a>(thing: *mut Interface) -> &'a mut Self {
+        let () = Self::LAYOUT;
         &mut (*(thing as *mut ComRepr<Self, Self::Vtbl>)).1
     }
 
     unsafe fn destroy(thing: *mut Interface) {
-        Box::from_raw(thing as *mut ComRepr<Self, Self::Vtbl>);
+        drop(Box::from_raw(thing as *mut ComRepr<Self, Self::Vtbl>));
     }
 }
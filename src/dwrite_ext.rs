@@ -0,0 +1,92 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! DirectWrite interfaces that the `winapi` crate doesn't declare (yet).
+//!
+//! These follow the layout of the Windows SDK headers; methods that we don't
+//! call still have to be listed so that the vtables line up.
+
+#![allow(non_camel_case_types, non_snake_case)]
+
+use winapi::ctypes::c_void;
+use winapi::shared::basetsd::UINT32;
+use winapi::um::d2d1::D2D1_POINT_2F;
+use winapi::um::dcommon::{DWRITE_GLYPH_IMAGE_FORMATS, DWRITE_MEASURING_MODE};
+use winapi::um::dwrite::{IDWriteFactory, IDWriteFontFile, IDWriteFontFileStream};
+use winapi::um::dwrite::{IDWriteFontFileLoader, IDWriteFontFileLoaderVtbl};
+use winapi::um::dwrite::{DWRITE_GLYPH_RUN, DWRITE_GLYPH_RUN_DESCRIPTION, DWRITE_MATRIX};
+use winapi::um::dwrite_3::{IDWriteFactory3, IDWriteFactory3Vtbl};
+use winapi::um::unknwnbase::IUnknown;
+use winapi::um::winnt::{HRESULT, WCHAR};
+use winapi::{ENUM, RIDL};
+
+ENUM! {enum DWRITE_CONTAINER_TYPE {
+    DWRITE_CONTAINER_TYPE_UNKNOWN,
+    DWRITE_CONTAINER_TYPE_WOFF,
+    DWRITE_CONTAINER_TYPE_WOFF2,
+}}
+
+RIDL! {#[uuid(0x4b0b5bd3, 0x0797, 0x4549, 0x8a, 0xc5, 0xfe, 0x91, 0x5c, 0xc5, 0x38, 0x56)]
+interface IDWriteFactory4(IDWriteFactory4Vtbl): IDWriteFactory3(IDWriteFactory3Vtbl) {
+    fn TranslateColorGlyphRun(
+        baselineOrigin: D2D1_POINT_2F,
+        glyphRun: *const DWRITE_GLYPH_RUN,
+        glyphRunDescription: *const DWRITE_GLYPH_RUN_DESCRIPTION,
+        desiredGlyphImageFormats: DWRITE_GLYPH_IMAGE_FORMATS,
+        measuringMode: DWRITE_MEASURING_MODE,
+        worldAndDpiTransform: *const DWRITE_MATRIX,
+        colorPaletteIndex: UINT32,
+        colorLayers: *mut *mut IUnknown,
+    ) -> HRESULT,
+    fn ComputeGlyphOrigins_2(
+        glyphRun: *const DWRITE_GLYPH_RUN,
+        measuringMode: DWRITE_MEASURING_MODE,
+        baselineOrigin: D2D1_POINT_2F,
+        worldAndDpiTransform: *const DWRITE_MATRIX,
+        glyphOrigins: *mut D2D1_POINT_2F,
+    ) -> HRESULT,
+    fn ComputeGlyphOrigins_1(
+        glyphRun: *const DWRITE_GLYPH_RUN,
+        baselineOrigin: D2D1_POINT_2F,
+        glyphOrigins: *mut D2D1_POINT_2F,
+    ) -> HRESULT,
+}}
+
+RIDL! {#[uuid(0x958db99a, 0xbe2a, 0x4f09, 0xaf, 0x7d, 0x65, 0x18, 0x98, 0x03, 0xd1, 0xd3)]
+interface IDWriteFactory5(IDWriteFactory5Vtbl): IDWriteFactory4(IDWriteFactory4Vtbl) {
+    fn CreateFontSetBuilder(
+        fontSetBuilder: *mut *mut IUnknown,
+    ) -> HRESULT,
+    fn CreateInMemoryFontFileLoader(
+        newLoader: *mut *mut IDWriteInMemoryFontFileLoader,
+    ) -> HRESULT,
+    fn CreateHttpFontFileLoader(
+        referrerUrl: *const WCHAR,
+        extraHeaders: *const WCHAR,
+        newLoader: *mut *mut IUnknown,
+    ) -> HRESULT,
+    fn AnalyzeContainerType(
+        fileData: *const c_void,
+        fileDataSize: UINT32,
+    ) -> DWRITE_CONTAINER_TYPE,
+    fn UnpackFontFile(
+        containerType: DWRITE_CONTAINER_TYPE,
+        fileData: *const c_void,
+        fileDataSize: UINT32,
+        unpackedFontStream: *mut *mut IDWriteFontFileStream,
+    ) -> HRESULT,
+}}
+
+RIDL! {#[uuid(0xdc102f47, 0xa12d, 0x4b1c, 0x82, 0x2d, 0x9e, 0x11, 0x7e, 0x33, 0x04, 0x3f)]
+interface IDWriteInMemoryFontFileLoader(IDWriteInMemoryFontFileLoaderVtbl):
+    IDWriteFontFileLoader(IDWriteFontFileLoaderVtbl) {
+    fn CreateInMemoryFontFileReference(
+        factory: *mut IDWriteFactory,
+        fontData: *const c_void,
+        fontDataSize: UINT32,
+        ownerObject: *mut IUnknown,
+        fontFile: *mut *mut IDWriteFontFile,
+    ) -> HRESULT,
+    fn GetFileCount() -> UINT32,
+}}
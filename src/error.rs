@@ -0,0 +1,100 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::error;
+use std::fmt;
+use winapi::shared::winerror::{DWRITE_E_FILEFORMAT, DWRITE_E_FILENOTFOUND, DWRITE_E_NOCOLOR};
+use winapi::shared::winerror::{DWRITE_E_NOFONT, E_ACCESSDENIED, E_INVALIDARG};
+use winapi::um::winnt::HRESULT;
+
+// Not in winapi's winerror.
+pub const DWRITE_E_REMOTEFONT: HRESULT = 0x8898500D_u32 as HRESULT;
+
+/// A failed DirectWrite call.
+///
+/// The failures callers commonly want to handle get their own variant; any
+/// other `HRESULT` is kept as `Other`. `hresult` gives back the exact code
+/// either way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The file isn't a font, or is of a format DirectWrite doesn't support
+    /// (`DWRITE_E_FILEFORMAT`).
+    FileFormat,
+    /// No font matched, e.g. a GDI face name that isn't installed
+    /// (`DWRITE_E_NOFONT`).
+    NoFont,
+    /// A font file couldn't be found (`DWRITE_E_FILENOTFOUND`).
+    FileNotFound,
+    /// The font is a downloadable font whose data isn't local yet
+    /// (`DWRITE_E_REMOTEFONT`).
+    RemoteFont,
+    /// The glyph run has no color glyphs (`DWRITE_E_NOCOLOR`).
+    NoColor,
+    /// Access was denied, e.g. to a font file (`E_ACCESSDENIED`).
+    AccessDenied,
+    /// An argument was invalid (`E_INVALIDARG`).
+    InvalidArg,
+    Other(HRESULT),
+}
+
+impl Error {
+    pub fn from_hresult(hr: HRESULT) -> Error {
+        match hr {
+            DWRITE_E_FILEFORMAT => Error::FileFormat,
+            DWRITE_E_NOFONT => Error::NoFont,
+            DWRITE_E_FILENOTFOUND => Error::FileNotFound,
+            DWRITE_E_REMOTEFONT => Error::RemoteFont,
+            DWRITE_E_NOCOLOR => Error::NoColor,
+            E_ACCESSDENIED => Error::AccessDenied,
+            E_INVALIDARG => Error::InvalidArg,
+            _ => Error::Other(hr),
+        }
+    }
+
+    pub fn hresult(&self) -> HRESULT {
+        match *self {
+            Error::FileFormat => DWRITE_E_FILEFORMAT,
+            Error::NoFont => DWRITE_E_NOFONT,
+            Error::FileNotFound => DWRITE_E_FILENOTFOUND,
+            Error::RemoteFont => DWRITE_E_REMOTEFONT,
+            Error::NoColor => DWRITE_E_NOCOLOR,
+            Error::AccessDenied => E_ACCESSDENIED,
+            Error::InvalidArg => E_INVALIDARG,
+            Error::Other(hr) => hr,
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match *self {
+            Error::FileFormat => "unsupported font file format",
+            Error::NoFont => "no matching font",
+            Error::FileNotFound => "font file not found",
+            Error::RemoteFont => "font data is not available locally",
+            Error::NoColor => "no color glyphs",
+            Error::AccessDenied => "access denied",
+            Error::InvalidArg => "invalid argument",
+            Error::Other(_) => "DirectWrite call failed",
+        }
+    }
+}
+
+impl From<HRESULT> for Error {
+    fn from(hr: HRESULT) -> Error {
+        Error::from_hresult(hr)
+    }
+}
+
+impl From<Error> for HRESULT {
+    fn from(error: Error) -> HRESULT {
+        error.hresult()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({:#010x})", self.message(), self.hresult())
+    }
+}
+
+impl error::Error for Error {}
@@ -0,0 +1,205 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::UnsafeCell;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use winapi::shared::guiddef::GUID;
+use winapi::shared::winerror::S_OK;
+use winapi::um::dwrite::DWRITE_FACTORY_TYPE_ISOLATED;
+use winapi::um::dwrite::{IDWriteFactory, IDWriteGdiInterop};
+use winapi::um::dwrite_1::IDWriteFactory1;
+use winapi::um::dwrite_2::IDWriteFactory2;
+use winapi::um::dwrite_3::IDWriteFactory3;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use super::{create_dwrite_factory, DWriteFactory, GdiInterop};
+use crate::dwrite_ext::{IDWriteFactory4, IDWriteFactory5};
+use crate::error::Error;
+use crate::font_file_loader_impl::DataFontHelper;
+
+// The crate doesn't declare these, we only need their IIDs for probing.
+const IID_IDWRITE_FACTORY6: GUID = GUID {
+    Data1: 0xf3744d80,
+    Data2: 0x21f7,
+    Data3: 0x42eb,
+    Data4: [0xb3, 0x5d, 0x99, 0x5b, 0xc7, 0x2f, 0xc2, 0x23],
+};
+const IID_IDWRITE_FACTORY7: GUID = GUID {
+    Data1: 0x35d0e0b3,
+    Data2: 0x9076,
+    Data3: 0x4d2e,
+    Data4: [0xa0, 0x16, 0xa9, 0x1b, 0x56, 0x8a, 0x06, 0xb4],
+};
+
+const INTERFACE_LEVEL_UNKNOWN: usize = usize::MAX;
+
+lazy_static! {
+    static ref SHARED_INTERFACE_LEVEL: u32 = unsafe { probe_interface_level(DWriteFactory()) };
+}
+
+/// A DirectWrite factory.
+///
+/// Most of the crate goes through the process-wide shared factory. An
+/// isolated factory keeps its own font caches and loaders, so that custom
+/// collections created through it don't leak into (or get affected by) other
+/// components in the same process.
+pub struct Factory {
+    native: UnsafeCell<ComPtr<IDWriteFactory>>,
+    interface_level: AtomicUsize,
+}
+
+/// Feature groups that depend on the `IDWriteFactoryN` interfaces a factory
+/// implements, see `Factory::capabilities`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// `FontFallback` (`IDWriteFactory2`).
+    pub has_font_fallback: bool,
+    /// Font sets and font face references (`IDWriteFactory3`).
+    pub has_font_set: bool,
+    /// Color glyph images beyond COLR layers (`IDWriteFactory4`).
+    pub has_color_glyph_images: bool,
+    /// The system in-memory font file loader (`IDWriteFactory5`).
+    pub has_in_memory_loader: bool,
+    /// Font resources and variable font axes (`IDWriteFactory6`).
+    pub has_variable_fonts: bool,
+}
+
+impl Capabilities {
+    pub fn from_interface_level(level: u32) -> Capabilities {
+        Capabilities {
+            has_font_fallback: level >= 2,
+            has_font_set: level >= 3,
+            has_color_glyph_images: level >= 4,
+            has_in_memory_loader: level >= 5,
+            has_variable_fonts: level >= 6,
+        }
+    }
+}
+
+impl Factory {
+    /// Returns the shared factory that is used when no factory is given.
+    pub fn shared() -> Factory {
+        unsafe {
+            let factory = DWriteFactory();
+            (*factory).AddRef();
+            Factory::take(ComPtr::from_raw(factory))
+        }
+    }
+
+    /// Creates a new factory of type `DWRITE_FACTORY_TYPE_ISOLATED`, which
+    /// shares no state or cache with the shared factory.
+    pub fn isolated() -> Result<Factory, Error> {
+        unsafe {
+            let native = ComPtr::from_raw(create_dwrite_factory(DWRITE_FACTORY_TYPE_ISOLATED)?);
+            // Loaders are registered per factory, so `FontFile::new_from_data`
+            // needs ours to be known here as well.
+            let hr = DataFontHelper::register_loader(native.as_raw());
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(Factory::take(native))
+        }
+    }
+
+    /// Same as `isolated`.
+    pub fn new_isolated() -> Result<Factory, Error> {
+        Factory::isolated()
+    }
+
+    /// Returns the factory's GDI interop object. It can be kept around and
+    /// reused, e.g. for creating many bitmap render targets.
+    pub fn gdi_interop(&self) -> Result<GdiInterop, Error> {
+        unsafe {
+            let mut native: *mut IDWriteGdiInterop = ptr::null_mut();
+            let hr = (*self.as_ptr()).GetGdiInterop(&mut native);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(GdiInterop::take(ComPtr::from_raw(native)))
+        }
+    }
+
+    /// Returns the highest `N` for which the factory implements
+    /// `IDWriteFactoryN`, or 0 if it's only an `IDWriteFactory`.
+    ///
+    /// This is probed on first use and cached.
+    pub fn max_interface_level(&self) -> u32 {
+        let level = self.interface_level.load(Ordering::Acquire);
+        if level != INTERFACE_LEVEL_UNKNOWN {
+            return level as u32;
+        }
+        let level = unsafe { probe_interface_level(self.as_ptr()) };
+        self.interface_level
+            .store(level as usize, Ordering::Release);
+        level
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::from_interface_level(self.max_interface_level())
+    }
+
+    pub fn take(native: ComPtr<IDWriteFactory>) -> Factory {
+        Factory {
+            native: UnsafeCell::new(native),
+            interface_level: AtomicUsize::new(INTERFACE_LEVEL_UNKNOWN),
+        }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut IDWriteFactory {
+        (*self.native.get()).as_raw()
+    }
+}
+
+impl Clone for Factory {
+    fn clone(&self) -> Factory {
+        unsafe {
+            Factory {
+                native: UnsafeCell::new((*self.native.get()).clone()),
+                interface_level: AtomicUsize::new(self.interface_level.load(Ordering::Acquire)),
+            }
+        }
+    }
+}
+
+// Resolve an optional factory argument to the factory that should be used.
+pub(crate) unsafe fn factory_or_shared(factory: Option<&Factory>) -> *mut IDWriteFactory {
+    match factory {
+        Some(factory) => factory.as_ptr(),
+        None => DWriteFactory(),
+    }
+}
+
+/// `Factory::max_interface_level` for the shared factory.
+pub fn max_interface_level() -> u32 {
+    *SHARED_INTERFACE_LEVEL
+}
+
+/// `Factory::capabilities` for the shared factory.
+pub fn capabilities() -> Capabilities {
+    Capabilities::from_interface_level(max_interface_level())
+}
+
+unsafe fn probe_interface_level(factory: *mut IDWriteFactory) -> u32 {
+    let iids = [
+        IID_IDWRITE_FACTORY7,
+        IID_IDWRITE_FACTORY6,
+        IDWriteFactory5::uuidof(),
+        IDWriteFactory4::uuidof(),
+        IDWriteFactory3::uuidof(),
+        IDWriteFactory2::uuidof(),
+        IDWriteFactory1::uuidof(),
+    ];
+    for (i, iid) in iids.iter().enumerate() {
+        let mut native: *mut IUnknown = ptr::null_mut();
+        let hr = (*factory).QueryInterface(iid, &mut native as *mut _ as *mut _);
+        if hr == S_OK && !native.is_null() {
+            (*native).Release();
+            return (iids.len() - i) as u32;
+        }
+    }
+    0
+}
@@ -5,6 +5,7 @@
 use std::cell::UnsafeCell;
 use std::mem;
 use std::ptr;
+use std::sync::Mutex;
 use winapi::shared::minwindef::{FALSE, TRUE};
 use winapi::shared::winerror::S_OK;
 use winapi::um::dwrite::IDWriteFont;
@@ -12,24 +13,41 @@ use winapi::um::dwrite::IDWriteFontFace;
 use winapi::um::dwrite::IDWriteFontFamily;
 use winapi::um::dwrite::IDWriteLocalizedStrings;
 use winapi::um::dwrite::DWRITE_FONT_METRICS;
-use winapi::um::dwrite::DWRITE_INFORMATIONAL_STRING_FULL_NAME;
-use winapi::um::dwrite::DWRITE_INFORMATIONAL_STRING_ID;
-use winapi::um::dwrite::DWRITE_INFORMATIONAL_STRING_POSTSCRIPT_CID_NAME;
-use winapi::um::dwrite::DWRITE_INFORMATIONAL_STRING_POSTSCRIPT_NAME;
-use winapi::um::dwrite_1::{IDWriteFont1, DWRITE_FONT_METRICS1};
+use winapi::um::dwrite::{
+    DWRITE_INFORMATIONAL_STRING_COPYRIGHT_NOTICE, DWRITE_INFORMATIONAL_STRING_DESCRIPTION,
+    DWRITE_INFORMATIONAL_STRING_DESIGNER, DWRITE_INFORMATIONAL_STRING_DESIGNER_URL,
+    DWRITE_INFORMATIONAL_STRING_DESIGN_SCRIPT_LANGUAGE_TAG,
+    DWRITE_INFORMATIONAL_STRING_FONT_VENDOR_URL, DWRITE_INFORMATIONAL_STRING_FULL_NAME,
+    DWRITE_INFORMATIONAL_STRING_ID, DWRITE_INFORMATIONAL_STRING_LICENSE_DESCRIPTION,
+    DWRITE_INFORMATIONAL_STRING_LICENSE_INFO_URL, DWRITE_INFORMATIONAL_STRING_MANUFACTURER,
+    DWRITE_INFORMATIONAL_STRING_POSTSCRIPT_CID_NAME, DWRITE_INFORMATIONAL_STRING_POSTSCRIPT_NAME,
+    DWRITE_INFORMATIONAL_STRING_PREFERRED_FAMILY_NAMES,
+    DWRITE_INFORMATIONAL_STRING_PREFERRED_SUBFAMILY_NAMES, DWRITE_INFORMATIONAL_STRING_SAMPLE_TEXT,
+    DWRITE_INFORMATIONAL_STRING_SUPPORTED_SCRIPT_LANGUAGE_TAG,
+    DWRITE_INFORMATIONAL_STRING_TRADEMARK, DWRITE_INFORMATIONAL_STRING_VERSION_STRINGS,
+    DWRITE_INFORMATIONAL_STRING_WIN32_FAMILY_NAMES,
+    DWRITE_INFORMATIONAL_STRING_WIN32_SUBFAMILY_NAMES, DWRITE_INFORMATIONAL_STRING_WWS_FAMILY_NAME,
+};
+use winapi::um::dwrite_1::{IDWriteFont1, DWRITE_FONT_METRICS1, DWRITE_PANOSE};
 use wio::com::ComPtr;
 
 use super::*;
-use helpers::*;
 
 pub struct Font {
     native: UnsafeCell<ComPtr<IDWriteFont>>,
+    face: Mutex<Option<FontFace>>,
 }
 
+// DirectWrite objects are free-threaded; the only mutable state here is the
+// face cache, which is behind a mutex.
+unsafe impl Send for Font {}
+unsafe impl Sync for Font {}
+
 impl Font {
     pub fn take(native: ComPtr<IDWriteFont>) -> Font {
         Font {
             native: UnsafeCell::new(native),
+            face: Mutex::new(None),
         }
     }
 
@@ -65,6 +83,40 @@ impl Font {
         }
     }
 
+    /// Returns the PANOSE classification of the font, or `None` if
+    /// `IDWriteFont1` isn't available.
+    pub fn panose(&self) -> Option<Panose> {
+        unsafe {
+            let font1: ComPtr<IDWriteFont1> = (*self.native.get()).cast().ok()?;
+            let mut panose: DWRITE_PANOSE = mem::zeroed();
+            font1.GetPanose(&mut panose);
+            Some(Panose::from_native(&panose))
+        }
+    }
+
+    /// Returns whether the font has a glyph for `c`.
+    pub fn has_character(&self, c: char) -> Result<bool, Error> {
+        unsafe {
+            let mut exists = FALSE;
+            let hr = (*self.native.get()).HasCharacter(c as u32, &mut exists);
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(exists == TRUE)
+        }
+    }
+
+    /// Returns the first character of `s` that the font has no glyph for,
+    /// together with its byte index in `s`.
+    pub fn first_missing_character(&self, s: &str) -> Option<(usize, char)> {
+        s.char_indices()
+            .find(|&(_, c)| self.has_character(c) != Ok(true))
+    }
+
+    pub fn is_symbol_font(&self) -> bool {
+        unsafe { (*self.native.get()).IsSymbolFont() == TRUE }
+    }
+
     pub fn simulations(&self) -> FontSimulations {
         unsafe { mem::transmute::<u32, FontSimulations>((*self.native.get()).GetSimulations()) }
     }
@@ -80,41 +132,101 @@ impl Font {
     }
 
     pub fn face_name(&self) -> String {
+        self.face_names().default_string().unwrap()
+    }
+
+    /// Returns the face name (e.g. "Bold Italic") in every locale the font
+    /// provides it in.
+    pub fn face_names(&self) -> LocalizedStrings {
         unsafe {
             let mut names: *mut IDWriteLocalizedStrings = ptr::null_mut();
             let hr = (*self.native.get()).GetFaceNames(&mut names);
             assert!(hr == 0);
 
-            get_locale_string(&mut ComPtr::from_raw(names))
+            LocalizedStrings::take(ComPtr::from_raw(names))
         }
     }
 
+    /// Returns the face name for `locale`, falling back to the user's default
+    /// locale, then `en-us`, then whichever name comes first.
+    pub fn face_name_for_locale(&self, locale: &str) -> Option<String> {
+        self.face_names().string_for_locale(locale)
+    }
+
+    /// Returns the PostScript name of the font (e.g. "SegoeUI-Semibold"), as
+    /// used by `local()` sources in `@font-face` rules.
+    pub fn postscript_name(&self) -> Option<String> {
+        self.informational_string(InformationalStringId::PostscriptName)
+    }
+
     pub fn informational_string(&self, id: InformationalStringId) -> Option<String> {
+        self.informational_strings(id).ok()??.default_string()
+    }
+
+    /// Returns all localized versions of an informational string, or `None`
+    /// if the font doesn't have it.
+    pub fn informational_strings(
+        &self,
+        id: InformationalStringId,
+    ) -> Result<Option<LocalizedStrings>, Error> {
         unsafe {
             let mut names: *mut IDWriteLocalizedStrings = ptr::null_mut();
             let mut exists = FALSE;
             let id = id as DWRITE_INFORMATIONAL_STRING_ID;
             let hr = (*self.native.get()).GetInformationalStrings(id, &mut names, &mut exists);
-            assert!(hr == S_OK);
+            if hr != S_OK {
+                return Err(hr.into());
+            }
             if exists == TRUE {
-                Some(get_locale_string(&mut ComPtr::from_raw(names)))
+                Ok(Some(LocalizedStrings::take(ComPtr::from_raw(names))))
             } else {
-                None
+                Ok(None)
             }
         }
     }
 
+    #[deprecated(note = "use `try_create_font_face`, which returns an error instead of panicking")]
     pub fn create_font_face(&self) -> FontFace {
-        // FIXME create_font_face should cache the FontFace and return it,
-        // there's a 1:1 relationship
+        self.try_create_font_face()
+            .expect("error creating font face")
+    }
+
+    /// Creates a new face for this font. See `try_create_font_face_cached` to
+    /// reuse a single face instead.
+    ///
+    /// Fails if the face can't be created, e.g. with `Error::RemoteFont` if
+    /// the font is a downloadable font that isn't local yet.
+    pub fn try_create_font_face(&self) -> Result<FontFace, Error> {
         unsafe {
             let mut face: *mut IDWriteFontFace = ptr::null_mut();
             let hr = (*self.native.get()).CreateFontFace(&mut face);
-            assert!(hr == 0);
-            FontFace::take(ComPtr::from_raw(face))
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(FontFace::take(ComPtr::from_raw(face)))
         }
     }
 
+    /// Returns the face for this font, creating it on first use and returning
+    /// the same face afterwards. A failure isn't cached, so a later call
+    /// tries again.
+    pub fn try_create_font_face_cached(&self) -> Result<FontFace, Error> {
+        let mut face = self.face.lock().unwrap();
+        if let Some(ref face) = *face {
+            return Ok(face.clone());
+        }
+        let new_face = self.try_create_font_face()?;
+        *face = Some(new_face.clone());
+        Ok(new_face)
+    }
+
+    /// Returns the extended metrics (glyph box, sub/superscript positions, and
+    /// so on). Without `IDWriteFont1` they are synthesized from `metrics()`;
+    /// see `FontMetrics1::synthesized`.
+    pub fn metrics1(&self) -> FontMetrics1 {
+        self.metrics().metrics1()
+    }
+
     pub fn metrics(&self) -> FontMetrics {
         unsafe {
             let font_1: Option<ComPtr<IDWriteFont1>> = (*self.native.get()).cast().ok();
@@ -139,17 +251,37 @@ impl Clone for Font {
         unsafe {
             Font {
                 native: UnsafeCell::new((*self.native.get()).clone()),
+                face: Mutex::new(None),
             }
         }
     }
 }
 
+// mirrors DWRITE_INFORMATIONAL_STRING_ID
 #[repr(u32)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum InformationalStringId {
+    CopyrightNotice = DWRITE_INFORMATIONAL_STRING_COPYRIGHT_NOTICE,
+    VersionStrings = DWRITE_INFORMATIONAL_STRING_VERSION_STRINGS,
+    Trademark = DWRITE_INFORMATIONAL_STRING_TRADEMARK,
+    Manufacturer = DWRITE_INFORMATIONAL_STRING_MANUFACTURER,
+    Designer = DWRITE_INFORMATIONAL_STRING_DESIGNER,
+    DesignerUrl = DWRITE_INFORMATIONAL_STRING_DESIGNER_URL,
+    Description = DWRITE_INFORMATIONAL_STRING_DESCRIPTION,
+    FontVendorUrl = DWRITE_INFORMATIONAL_STRING_FONT_VENDOR_URL,
+    LicenseDescription = DWRITE_INFORMATIONAL_STRING_LICENSE_DESCRIPTION,
+    LicenseInfoUrl = DWRITE_INFORMATIONAL_STRING_LICENSE_INFO_URL,
+    Win32FamilyNames = DWRITE_INFORMATIONAL_STRING_WIN32_FAMILY_NAMES,
+    Win32SubfamilyNames = DWRITE_INFORMATIONAL_STRING_WIN32_SUBFAMILY_NAMES,
+    PreferredFamilyNames = DWRITE_INFORMATIONAL_STRING_PREFERRED_FAMILY_NAMES,
+    PreferredSubfamilyNames = DWRITE_INFORMATIONAL_STRING_PREFERRED_SUBFAMILY_NAMES,
+    SampleText = DWRITE_INFORMATIONAL_STRING_SAMPLE_TEXT,
     FullName = DWRITE_INFORMATIONAL_STRING_FULL_NAME,
     PostscriptName = DWRITE_INFORMATIONAL_STRING_POSTSCRIPT_NAME,
     PostscriptCidName = DWRITE_INFORMATIONAL_STRING_POSTSCRIPT_CID_NAME,
+    WwsFamilyName = DWRITE_INFORMATIONAL_STRING_WWS_FAMILY_NAME,
+    DesignScriptLanguageTag = DWRITE_INFORMATIONAL_STRING_DESIGN_SCRIPT_LANGUAGE_TAG,
+    SupportedScriptLanguageTag = DWRITE_INFORMATIONAL_STRING_SUPPORTED_SCRIPT_LANGUAGE_TAG,
 }
 
 /// A wrapper around the `DWRITE_FONT_METRICS` and `DWRITE_FONT_METRICS1` types.
@@ -176,7 +308,54 @@ impl FontMetrics {
                 underlineThickness: metrics.underlineThickness,
                 strikethroughPosition: metrics.strikethroughPosition,
                 strikethroughThickness: metrics.strikethroughThickness,
-            }
+            },
+        }
+    }
+
+    /// Returns whether these are the extended metrics reported by DirectWrite,
+    /// as opposed to ones that `metrics1` has to synthesize.
+    pub fn is_metrics1(&self) -> bool {
+        match *self {
+            FontMetrics::Metrics0(_) => false,
+            FontMetrics::Metrics1(_) => true,
+        }
+    }
+
+    /// Convert self to the Metrics1 arm. For the Metrics0 arm, the result is
+    /// marked as synthesized; see `FontMetrics1::synthesized`.
+    pub fn metrics1(self) -> FontMetrics1 {
+        let synthesized = !self.is_metrics1();
+        let metrics = match self {
+            FontMetrics::Metrics0(metrics) => DWRITE_FONT_METRICS1 {
+                designUnitsPerEm: metrics.designUnitsPerEm,
+                ascent: metrics.ascent,
+                descent: metrics.descent,
+                lineGap: metrics.lineGap,
+                capHeight: metrics.capHeight,
+                xHeight: metrics.xHeight,
+                underlinePosition: metrics.underlinePosition,
+                underlineThickness: metrics.underlineThickness,
+                strikethroughPosition: metrics.strikethroughPosition,
+                strikethroughThickness: metrics.strikethroughThickness,
+                ..unsafe { mem::zeroed() }
+            },
+            FontMetrics::Metrics1(metrics) => metrics,
+        };
+        FontMetrics1 {
+            metrics,
+            synthesized,
         }
     }
 }
+
+/// The extended font metrics returned by `Font::metrics1` and
+/// `FontFace::metrics1`.
+#[derive(Clone, Copy)]
+pub struct FontMetrics1 {
+    pub metrics: DWRITE_FONT_METRICS1,
+    /// Whether DirectWrite couldn't provide the extended metrics (Windows 7),
+    /// so `metrics` was filled in from `DWRITE_FONT_METRICS`. The glyph box
+    /// and sub/superscript fields are then zero and `hasTypographicMetrics`
+    /// is false.
+    pub synthesized: bool,
+}
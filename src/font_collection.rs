@@ -3,6 +3,9 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use std::cell::UnsafeCell;
+use std::error;
+use std::fmt;
+use std::iter::FusedIterator;
 use std::mem;
 use std::ptr;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -10,48 +13,119 @@ use winapi::shared::minwindef::{BOOL, FALSE, TRUE};
 use winapi::shared::winerror::S_OK;
 use winapi::um::dwrite::IDWriteFontCollectionLoader;
 use winapi::um::dwrite::{IDWriteFont, IDWriteFontCollection, IDWriteFontFamily};
+use winapi::um::dwrite_1::IDWriteFactory1;
+use winapi::um::winnt::HRESULT;
 use wio::com::ComPtr;
 
+use super::{Font, FontDescriptor, FontFace, FontFamily};
+use crate::error::Error;
+use crate::factory::{factory_or_shared, Factory};
 use crate::helpers::*;
-use super::{DWriteFactory, Font, FontDescriptor, FontFace, FontFamily};
 
 static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
 
-pub struct FontCollectionFamilyIterator {
+/// Iterates over the families of a `FontCollection`, in index order. A family
+/// DirectWrite fails to return is yielded as an error.
+///
+/// The iterator holds its own reference to the collection, so it stays valid
+/// if the `FontCollection` it came from is dropped.
+pub struct FamilyIter {
     collection: ComPtr<IDWriteFontCollection>,
     curr: u32,
     count: u32,
 }
 
-impl Iterator for FontCollectionFamilyIterator {
-    type Item = FontFamily;
-    fn next(&mut self) -> Option<FontFamily> {
+impl FamilyIter {
+    fn get(&self, index: u32) -> Result<FontFamily, Error> {
+        unsafe {
+            let mut family: *mut IDWriteFontFamily = ptr::null_mut();
+            let hr = self.collection.GetFontFamily(index, &mut family);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(FontFamily::take(ComPtr::from_raw(family)))
+        }
+    }
+}
+
+impl Iterator for FamilyIter {
+    type Item = Result<FontFamily, Error>;
+    fn next(&mut self) -> Option<Result<FontFamily, Error>> {
         if self.curr == self.count {
             return None;
         }
+        self.curr += 1;
+        Some(self.get(self.curr - 1))
+    }
 
-        unsafe {
-            let mut family: *mut IDWriteFontFamily = ptr::null_mut();
-            let hr = self.collection.GetFontFamily(self.curr, &mut family);
-            assert!(hr == 0);
-            self.curr += 1;
-            Some(FontFamily::take(ComPtr::from_raw(family)))
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.count - self.curr) as usize;
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for FamilyIter {
+    fn next_back(&mut self) -> Option<Result<FontFamily, Error>> {
+        if self.curr == self.count {
+            return None;
         }
+        self.count -= 1;
+        Some(self.get(self.count))
+    }
+}
+
+impl ExactSizeIterator for FamilyIter {}
+
+impl FusedIterator for FamilyIter {}
+
+/// Iterates over the families of a `FontCollection`, skipping any that
+/// DirectWrite fails to return. See `FamilyIter` to see the failures.
+pub struct FontCollectionFamilyIterator {
+    families: FamilyIter,
+}
+
+impl Iterator for FontCollectionFamilyIterator {
+    type Item = FontFamily;
+    fn next(&mut self) -> Option<FontFamily> {
+        self.families.find_map(Result::ok)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // Any of the remaining families may fail to resolve and be skipped.
+        (0, Some(self.families.len()))
     }
 }
 
+impl DoubleEndedIterator for FontCollectionFamilyIterator {
+    fn next_back(&mut self) -> Option<FontFamily> {
+        self.families.by_ref().rev().find_map(Result::ok)
+    }
+}
+
+impl FusedIterator for FontCollectionFamilyIterator {}
+
 pub struct FontCollection {
     native: UnsafeCell<ComPtr<IDWriteFontCollection>>,
 }
 
+// Collections are immutable once created, and DirectWrite allows using them
+// from any thread.
+unsafe impl Send for FontCollection {}
+unsafe impl Sync for FontCollection {}
+
 impl FontCollection {
+    /// Returns the system font collection. With `update`, DirectWrite first
+    /// checks for fonts installed or removed since the collection was last
+    /// fetched; otherwise it may hand out a stale cached collection.
     pub fn get_system(update: bool) -> FontCollection {
+        FontCollection::get_system_with_factory(update, None)
+    }
+
+    pub fn get_system_with_factory(update: bool, factory: Option<&Factory>) -> FontCollection {
         unsafe {
             let mut native: *mut IDWriteFontCollection = ptr::null_mut();
-            let hr = (*DWriteFactory()).GetSystemFontCollection(
-                &mut native,
-                if update { TRUE } else { FALSE },
-            );
+            let hr = (*factory_or_shared(factory))
+                .GetSystemFontCollection(&mut native, if update { TRUE } else { FALSE });
             assert!(hr == 0);
 
             FontCollection {
@@ -64,6 +138,48 @@ impl FontCollection {
         FontCollection::get_system(false)
     }
 
+    /// Re-acquires the system collection, so that it reflects fonts
+    /// installed or removed since `system` was last called. Collections
+    /// fetched earlier keep their old contents. See `SystemFontWatcher` to
+    /// find out whether anything changed.
+    pub fn refresh() -> FontCollection {
+        FontCollection::get_system(true)
+    }
+
+    /// Returns the collection of end-user defined character (EUDC) fonts, or
+    /// `None` if `IDWriteFactory1` isn't available.
+    pub fn eudc() -> Option<FontCollection> {
+        FontCollection::eudc_with_factory(None)
+    }
+
+    pub fn eudc_with_factory(factory: Option<&Factory>) -> Option<FontCollection> {
+        unsafe {
+            let factory = ComPtr::from_raw(factory_or_shared(factory));
+            let factory1: Option<ComPtr<IDWriteFactory1>> = factory.cast().ok();
+            mem::forget(factory);
+            let factory1 = factory1?;
+            let mut native: *mut IDWriteFontCollection = ptr::null_mut();
+            let hr = factory1.GetEudcFontCollection(&mut native, FALSE);
+            if hr != S_OK || native.is_null() {
+                return None;
+            }
+            Some(FontCollection::take(ComPtr::from_raw(native)))
+        }
+    }
+
+    /// Looks for an EUDC font that has a glyph for `c`, which is typically a
+    /// private use character that normal font fallback couldn't map.
+    pub fn map_pua_character(c: char) -> Option<Font> {
+        FontCollection::eudc()?.find_font_for_character(c)
+    }
+
+    /// Returns the first font in the collection that has a glyph for `c`.
+    pub fn find_font_for_character(&self, c: char) -> Option<Font> {
+        self.families_iter()
+            .flat_map(|family| family.fonts_iter())
+            .find(|font| font.has_character(c) == Ok(true))
+    }
+
     pub fn take(native: ComPtr<IDWriteFontCollection>) -> FontCollection {
         FontCollection {
             native: UnsafeCell::new(native),
@@ -71,8 +187,15 @@ impl FontCollection {
     }
 
     pub fn from_loader(collection_loader: ComPtr<IDWriteFontCollectionLoader>) -> FontCollection {
+        FontCollection::from_loader_with_factory(collection_loader, None)
+    }
+
+    pub fn from_loader_with_factory(
+        collection_loader: ComPtr<IDWriteFontCollectionLoader>,
+        factory: Option<&Factory>,
+    ) -> FontCollection {
         unsafe {
-            let factory = DWriteFactory();
+            let factory = factory_or_shared(factory);
             assert_eq!(
                 (*factory).RegisterFontCollectionLoader(collection_loader.clone().into_raw()),
                 S_OK
@@ -96,9 +219,18 @@ impl FontCollection {
         (*self.native.get()).as_raw()
     }
 
-    pub fn families_iter(&self) -> FontCollectionFamilyIterator {
+    /// Returns an iterator over the families in this collection, yielding an
+    /// error for any family DirectWrite fails to return.
+    ///
+    /// ```no_run
+    /// let collection = dwrote::FontCollection::system();
+    /// for family in collection.families() {
+    ///     println!("{}", family.unwrap().name());
+    /// }
+    /// ```
+    pub fn families(&self) -> FamilyIter {
         unsafe {
-            FontCollectionFamilyIterator {
+            FamilyIter {
                 collection: (*self.native.get()).clone(),
                 curr: 0,
                 count: (*self.native.get()).GetFontFamilyCount(),
@@ -106,6 +238,14 @@ impl FontCollection {
         }
     }
 
+    /// Returns an iterator over the families in this collection, skipping
+    /// any that DirectWrite fails to return.
+    pub fn families_iter(&self) -> FontCollectionFamilyIterator {
+        FontCollectionFamilyIterator {
+            families: self.families(),
+        }
+    }
+
     pub fn get_font_family_count(&self) -> u32 {
         unsafe { (*self.native.get()).GetFontFamilyCount() }
     }
@@ -122,18 +262,67 @@ impl FontCollection {
     // Find a font matching the given font descriptor in this
     // font collection.
     pub fn get_font_from_descriptor(&self, desc: &FontDescriptor) -> Option<Font> {
-        if let Some(family) = self.get_font_family_by_name(&desc.family_name) {
-            let font = family.get_first_matching_font(desc.weight, desc.stretch, desc.style);
-            // Exact matches only here
+        // Exact matches only here
+        self.resolve_descriptor(desc).ok()
+    }
+
+    /// Finds the font in the descriptor's family that best matches its weight,
+    /// stretch and style, like `FontFamily::get_first_matching_font`. Returns
+    /// `None` if the family isn't in this collection.
+    pub fn resolve(&self, desc: &FontDescriptor) -> Option<Font> {
+        match self.resolve_descriptor(desc) {
+            Ok(font) => Some(font),
+            Err(DescriptorError::NoMatchingStyle { nearest }) => Some(nearest),
+            Err(_) => None,
+        }
+    }
+
+    /// Finds the font matching the given font descriptor exactly, like
+    /// `get_font_from_descriptor`, but says why that failed.
+    pub fn resolve_descriptor(&self, desc: &FontDescriptor) -> Result<Font, DescriptorError> {
+        unsafe {
+            let mut index: u32 = 0;
+            let mut exists: BOOL = FALSE;
+            let hr = (*self.native.get()).FindFamilyName(
+                desc.family_name.to_wide_null().as_ptr(),
+                &mut index,
+                &mut exists,
+            );
+            if hr != S_OK {
+                return Err(DescriptorError::Com(hr));
+            }
+            if exists == FALSE {
+                return Err(DescriptorError::FamilyNotFound);
+            }
+
+            let mut family: *mut IDWriteFontFamily = ptr::null_mut();
+            let hr = (*self.native.get()).GetFontFamily(index, &mut family);
+            if hr != S_OK {
+                return Err(DescriptorError::Com(hr));
+            }
+            let family = ComPtr::from_raw(family);
+
+            let mut font: *mut IDWriteFont = ptr::null_mut();
+            let hr = family.GetFirstMatchingFont(
+                desc.weight.t(),
+                desc.stretch.t(),
+                desc.style.t(),
+                &mut font,
+            );
+            if hr != S_OK {
+                return Err(DescriptorError::Com(hr));
+            }
+            let font = Font::take(ComPtr::from_raw(font));
+
             if font.weight() == desc.weight
                 && font.stretch() == desc.stretch
                 && font.style() == desc.style
             {
-                return Some(font);
+                Ok(font)
+            } else {
+                Err(DescriptorError::NoMatchingStyle { nearest: font })
             }
         }
-
-        None
     }
 
     pub fn get_font_from_face(&self, face: &FontFace) -> Option<Font> {
@@ -147,7 +336,27 @@ impl FontCollection {
         }
     }
 
+    /// Looks up a family by name. The name may be given in any of the
+    /// locales that the family provides names for (see
+    /// `FontFamily::family_names`).
     pub fn get_font_family_by_name(&self, family_name: &str) -> Option<FontFamily> {
+        let index = self.find_family_index(family_name)?;
+        unsafe {
+            let mut family: *mut IDWriteFontFamily = ptr::null_mut();
+            let hr = (*self.native.get()).GetFontFamily(index, &mut family);
+            assert!(hr == 0);
+
+            Some(FontFamily::take(ComPtr::from_raw(family)))
+        }
+    }
+
+    /// Returns whether the collection has a family named `family_name`,
+    /// without creating the family.
+    pub fn contains_family(&self, family_name: &str) -> bool {
+        self.find_family_index(family_name).is_some()
+    }
+
+    fn find_family_index(&self, family_name: &str) -> Option<u32> {
         unsafe {
             let mut index: u32 = 0;
             let mut exists: BOOL = FALSE;
@@ -160,12 +369,102 @@ impl FontCollection {
             if exists == FALSE {
                 return None;
             }
+            Some(index)
+        }
+    }
+}
 
-            let mut family: *mut IDWriteFontFamily = ptr::null_mut();
-            let hr = (*self.native.get()).GetFontFamily(index, &mut family);
-            assert!(hr == 0);
+/// Keeps track of the system font collection, so that long-running programs
+/// can notice fonts being installed or removed.
+pub struct SystemFontWatcher {
+    collection: FontCollection,
+    factory: Option<Factory>,
+}
 
-            Some(FontFamily::take(ComPtr::from_raw(family)))
+impl SystemFontWatcher {
+    pub fn new() -> SystemFontWatcher {
+        SystemFontWatcher::new_with_factory(None)
+    }
+
+    pub fn new_with_factory(factory: Option<&Factory>) -> SystemFontWatcher {
+        SystemFontWatcher {
+            collection: FontCollection::get_system_with_factory(true, factory),
+            factory: factory.cloned(),
         }
     }
+
+    /// Returns the most recently fetched system collection.
+    pub fn collection(&self) -> &FontCollection {
+        &self.collection
+    }
+
+    /// Asks DirectWrite whether the set of installed fonts changed since the
+    /// last check, and if so, switches to the new collection and returns
+    /// true. Families and fonts obtained from the old collection keep
+    /// working, but won't reflect the change.
+    pub fn check_now(&mut self) -> bool {
+        let collection = FontCollection::get_system_with_factory(true, self.factory.as_ref());
+        // DirectWrite hands out the same collection object for as long as the
+        // set of fonts is unchanged.
+        let changed = unsafe { collection.as_ptr() != self.collection.as_ptr() };
+        if changed {
+            self.collection = collection;
+        }
+        changed
+    }
+}
+
+impl Default for SystemFontWatcher {
+    fn default() -> SystemFontWatcher {
+        SystemFontWatcher::new()
+    }
 }
+
+/// Why `FontCollection::resolve_descriptor` couldn't find a font.
+pub enum DescriptorError {
+    /// The collection has no family with the requested name.
+    FamilyNotFound,
+    /// The family exists, but has no font with the requested weight, stretch
+    /// and style. `nearest` is the family's closest match.
+    NoMatchingStyle { nearest: Font },
+    /// A DirectWrite call failed.
+    Com(HRESULT),
+}
+
+impl DescriptorError {
+    /// Returns the `HRESULT` of a failed DirectWrite call, or `None` if the
+    /// lookup itself succeeded without finding a font.
+    pub fn hresult(&self) -> Option<HRESULT> {
+        match *self {
+            DescriptorError::Com(hr) => Some(hr),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DescriptorError::FamilyNotFound => f.write_str("FamilyNotFound"),
+            DescriptorError::NoMatchingStyle { ref nearest } => f
+                .debug_struct("NoMatchingStyle")
+                .field("nearest", &nearest.to_descriptor())
+                .finish(),
+            DescriptorError::Com(hr) => write!(f, "Com({:#010x})", hr),
+        }
+    }
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DescriptorError::FamilyNotFound => f.write_str("font family not found"),
+            DescriptorError::NoMatchingStyle { .. } => {
+                f.write_str("no font with the requested weight, stretch and style")
+            }
+            DescriptorError::Com(hr) => write!(f, "DirectWrite call failed: {:#010x}", hr),
+        }
+    }
+}
+
+impl error::Error for DescriptorError {}
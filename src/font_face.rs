@@ -8,38 +8,53 @@ use std::ptr;
 use std::slice;
 use winapi::ctypes::c_void;
 use winapi::shared::minwindef::{BOOL, FALSE, TRUE};
-use winapi::shared::winerror::S_OK;
-use winapi::um::dcommon::DWRITE_MEASURING_MODE;
+use winapi::shared::winerror::{E_INVALIDARG, E_NOINTERFACE, S_OK};
+use winapi::um::d2d1::ID2D1SimplifiedGeometrySink;
+use winapi::um::dcommon::{DWRITE_GLYPH_IMAGE_DATA, DWRITE_GLYPH_IMAGE_FORMATS};
+use winapi::um::dcommon::{DWRITE_GLYPH_IMAGE_FORMATS_NONE, DWRITE_MEASURING_MODE_NATURAL};
+use winapi::um::dcommon::{DWRITE_GLYPH_IMAGE_FORMATS_SVG, DWRITE_MEASURING_MODE};
 use winapi::um::dwrite::IDWriteRenderingParams;
-use winapi::um::dwrite::DWRITE_FONT_FACE_TYPE_TRUETYPE;
+use winapi::um::dwrite::DWRITE_FONT_FACE_TYPE_UNKNOWN;
+use winapi::um::dwrite::DWRITE_RENDERING_MODE_OUTLINE;
 use winapi::um::dwrite::{IDWriteFontFace, IDWriteFontFile};
+use winapi::um::dwrite::{DWRITE_FONT_FACE_TYPE, DWRITE_FONT_FACE_TYPE_TRUETYPE};
 use winapi::um::dwrite::{DWRITE_FONT_FACE_TYPE_BITMAP, DWRITE_FONT_FACE_TYPE_CFF};
 use winapi::um::dwrite::{DWRITE_FONT_FACE_TYPE_RAW_CFF, DWRITE_FONT_FACE_TYPE_TYPE1};
 use winapi::um::dwrite::{DWRITE_FONT_FACE_TYPE_TRUETYPE_COLLECTION, DWRITE_FONT_FACE_TYPE_VECTOR};
-use winapi::um::dwrite::{DWRITE_FONT_SIMULATIONS, DWRITE_GLYPH_METRICS};
+use winapi::um::dwrite::{DWRITE_FONT_METRICS, DWRITE_FONT_SIMULATIONS, DWRITE_GLYPH_METRICS};
 use winapi::um::dwrite::{DWRITE_GLYPH_OFFSET, DWRITE_MATRIX, DWRITE_RENDERING_MODE};
 use winapi::um::dwrite::{DWRITE_RENDERING_MODE_DEFAULT, DWRITE_RENDERING_MODE_NATURAL_SYMMETRIC};
-use winapi::um::dwrite_1::IDWriteFontFace1;
+use winapi::um::dwrite_1::DWRITE_OUTLINE_THRESHOLD_ANTIALIASED;
+use winapi::um::dwrite_1::{IDWriteFontFace1, DWRITE_CARET_METRICS};
+use winapi::um::dwrite_2::{IDWriteFontFace2, DWRITE_COLOR_F, DWRITE_GRID_FIT_MODE_DEFAULT};
+use winapi::um::dwrite_3::{IDWriteFontFace3, IDWriteFontFace4, IDWriteFontFaceReference};
 use winapi::um::dwrite_3::{IDWriteFontFace5, IDWriteFontResource, DWRITE_FONT_AXIS_VALUE};
+use winapi::um::dwrite_3::{DWRITE_RENDERING_MODE1, DWRITE_RENDERING_MODE1_DEFAULT};
+use winapi::um::winnt::HRESULT;
 use wio::com::ComPtr;
 
-use super::{DWriteFactory, DefaultDWriteRenderParams, FontFile, FontMetrics};
+use super::{DWriteFactory, DefaultDWriteRenderParams, FontFile, FontMetrics, FontMetrics1};
+use super::{FontSimulations, GridFitMode, RenderingParams};
 use crate::com_helpers::Com;
+use crate::error::Error;
 use crate::geometry_sink_impl::GeometrySinkImpl;
-use crate::outline_builder::OutlineBuilder;
+use crate::inflate;
+use crate::outline_builder::{self, TryOutlineBuilder};
+use crate::outline_builder::{OutlineBuilder, OutlineError, PathCollector, PathCommand};
 
 pub struct FontFace {
     native: UnsafeCell<ComPtr<IDWriteFontFace>>,
-    face5: UnsafeCell<Option<ComPtr<IDWriteFontFace5>>>,
 }
 
+// DirectWrite objects are free-threaded, and `native` is only ever read after
+// construction.
+unsafe impl Send for FontFace {}
+unsafe impl Sync for FontFace {}
+
 impl FontFace {
     pub fn take(native: ComPtr<IDWriteFontFace>) -> FontFace {
         let cell = UnsafeCell::new(native);
-        FontFace {
-            native: cell,
-            face5: UnsafeCell::new(None),
-        }
+        FontFace { native: cell }
     }
 
     pub unsafe fn as_ptr(&self) -> *mut IDWriteFontFace {
@@ -47,15 +62,23 @@ impl FontFace {
     }
 
     unsafe fn get_raw_files(&self) -> Vec<*mut IDWriteFontFile> {
+        self.try_get_raw_files().unwrap()
+    }
+
+    unsafe fn try_get_raw_files(&self) -> Result<Vec<*mut IDWriteFontFile>, Error> {
         let mut number_of_files: u32 = 0;
         let hr = (*self.native.get()).GetFiles(&mut number_of_files, ptr::null_mut());
-        assert!(hr == 0);
+        if hr != 0 {
+            return Err(hr.into());
+        }
 
         let mut file_ptrs: Vec<*mut IDWriteFontFile> =
             vec![ptr::null_mut(); number_of_files as usize];
         let hr = (*self.native.get()).GetFiles(&mut number_of_files, file_ptrs.as_mut_ptr());
-        assert!(hr == 0);
-        file_ptrs
+        if hr != 0 {
+            return Err(hr.into());
+        }
+        Ok(file_ptrs)
     }
 
     pub fn get_files(&self) -> Vec<FontFile> {
@@ -68,6 +91,21 @@ impl FontFace {
         }
     }
 
+    /// Returns the files backing this face, like `get_files`, but reports
+    /// failures instead of panicking.
+    pub fn files(&self) -> Result<Vec<FontFile>, Error> {
+        unsafe {
+            let file_ptrs = self.try_get_raw_files()?;
+            Ok(file_ptrs
+                .iter()
+                .map(|p| FontFile::take(ComPtr::from_raw(*p)))
+                .collect())
+        }
+    }
+
+    /// Creates a face for the same file(s) and index as this one, with the
+    /// given simulations. These replace (rather than add to) the ones already
+    /// applied to this face, see `get_simulations`.
     pub fn create_font_face_with_simulations(
         &self,
         simulations: DWRITE_FONT_SIMULATIONS,
@@ -115,16 +153,104 @@ impl FontFace {
         }
     }
 
+    /// Returns the face's metrics scaled to a font size of `em_size`, in
+    /// whatever unit `em_size` is given (pixels or DIPs). `metrics()` returns
+    /// the same values in design units.
+    pub fn scaled_metrics(&self, em_size: f32) -> ScaledFontMetrics {
+        ScaledFontMetrics::new(&self.metrics().metrics0(), em_size)
+    }
+
+    /// Returns the number of design units per em, which is what all design
+    /// metrics (glyph metrics, `metrics()`, and so on) are relative to.
+    pub fn design_units_per_em(&self) -> u16 {
+        unsafe {
+            let mut metrics = mem::zeroed();
+            (*self.native.get()).GetMetrics(&mut metrics);
+            metrics.designUnitsPerEm
+        }
+    }
+
+    /// Returns the extended metrics (glyph box, sub/superscript positions, and
+    /// so on). Without `IDWriteFontFace1` they are synthesized from
+    /// `metrics()`; see `FontMetrics1::synthesized`.
+    pub fn metrics1(&self) -> FontMetrics1 {
+        self.metrics().metrics1()
+    }
+
+    /// Returns the caret slope and offset, in font design units, or `None` if
+    /// `IDWriteFontFace1` isn't available.
+    pub fn caret_metrics(&self) -> Option<DWRITE_CARET_METRICS> {
+        unsafe {
+            let font_1: ComPtr<IDWriteFontFace1> = (*self.native.get()).cast().ok()?;
+            let mut caret_metrics = mem::zeroed();
+            font_1.GetCaretMetrics(&mut caret_metrics);
+            Some(caret_metrics)
+        }
+    }
+
+    /// Returns the face metrics rounded the way GDI would at the given size.
+    pub fn get_gdi_compatible_metrics(
+        &self,
+        em_size: f32,
+        pixels_per_dip: f32,
+        transform: Option<&DWRITE_MATRIX>,
+    ) -> Result<FontMetrics, Error> {
+        unsafe {
+            let transform = transform.map(|t| t as *const _).unwrap_or(ptr::null());
+            let font_1: Option<ComPtr<IDWriteFontFace1>> = (*self.native.get()).cast().ok();
+            match font_1 {
+                None => {
+                    let mut metrics = mem::zeroed();
+                    let hr = (*self.native.get()).GetGdiCompatibleMetrics(
+                        em_size,
+                        pixels_per_dip,
+                        transform,
+                        &mut metrics,
+                    );
+                    if hr != S_OK {
+                        return Err(hr.into());
+                    }
+                    Ok(FontMetrics::Metrics0(metrics))
+                }
+                Some(font_1) => {
+                    let mut metrics_1 = mem::zeroed();
+                    let hr = font_1.GetGdiCompatibleMetrics(
+                        em_size,
+                        pixels_per_dip,
+                        transform,
+                        &mut metrics_1,
+                    );
+                    if hr != S_OK {
+                        return Err(hr.into());
+                    }
+                    Ok(FontMetrics::Metrics1(metrics_1))
+                }
+            }
+        }
+    }
+
+    /// Maps each code point to a glyph index. Code points that the face
+    /// doesn't cover map to glyph 0 (`.notdef`), which callers can use as a
+    /// signal to fall back to another font.
     pub fn get_glyph_indices(&self, code_points: &[u32]) -> Vec<u16> {
+        let mut glyph_indices = Vec::new();
+        self.get_glyph_indices_into(code_points, &mut glyph_indices);
+        glyph_indices
+    }
+
+    /// Like `get_glyph_indices`, but writes the result into `out` so that its
+    /// allocation can be reused across calls. `out` is cleared first and ends
+    /// up with one entry per code point.
+    pub fn get_glyph_indices_into(&self, code_points: &[u32], out: &mut Vec<u16>) {
+        out.clear();
+        out.resize(code_points.len(), 0);
         unsafe {
-            let mut glyph_indices: Vec<u16> = vec![0; code_points.len()];
             let hr = (*self.native.get()).GetGlyphIndices(
                 code_points.as_ptr(),
                 code_points.len() as u32,
-                glyph_indices.as_mut_ptr(),
+                out.as_mut_ptr(),
             );
             assert!(hr == 0);
-            glyph_indices
         }
     }
 
@@ -133,6 +259,15 @@ impl FontFace {
         glyph_indices: &[u16],
         is_sideways: bool,
     ) -> Vec<DWRITE_GLYPH_METRICS> {
+        self.try_get_design_glyph_metrics(glyph_indices, is_sideways)
+            .expect("error getting design glyph metrics")
+    }
+
+    fn try_get_design_glyph_metrics(
+        &self,
+        glyph_indices: &[u16],
+        is_sideways: bool,
+    ) -> Result<Vec<DWRITE_GLYPH_METRICS>, Error> {
         unsafe {
             let mut metrics: Vec<DWRITE_GLYPH_METRICS> = vec![zeroed(); glyph_indices.len()];
             let hr = (*self.native.get()).GetDesignGlyphMetrics(
@@ -141,8 +276,62 @@ impl FontFace {
                 metrics.as_mut_ptr(),
                 is_sideways as BOOL,
             );
-            assert!(hr == 0);
-            metrics
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(metrics)
+        }
+    }
+
+    /// Returns the vertical metrics of `glyph_indices`, for laying them out
+    /// in vertical text.
+    pub fn get_vertical_glyph_metrics(&self, glyph_indices: &[u16]) -> Vec<VerticalGlyphMetrics> {
+        self.get_design_glyph_metrics(glyph_indices, true)
+            .iter()
+            .map(VerticalGlyphMetrics::from)
+            .collect()
+    }
+
+    /// Returns the advances of `glyph_indices` in design units, or their
+    /// vertical advances if `is_sideways` is true. This is cheaper than
+    /// shaping when kerning and OpenType features aren't needed.
+    ///
+    /// Falls back to `get_design_glyph_metrics` if `IDWriteFontFace1` isn't
+    /// available.
+    pub fn get_design_glyph_advances(
+        &self,
+        glyph_indices: &[u16],
+        is_sideways: bool,
+    ) -> Result<Vec<i32>, Error> {
+        unsafe {
+            let face1: Option<ComPtr<IDWriteFontFace1>> = (*self.native.get()).cast().ok();
+            let face1 = match face1 {
+                Some(face1) => face1,
+                None => {
+                    return Ok(self
+                        .try_get_design_glyph_metrics(glyph_indices, is_sideways)?
+                        .iter()
+                        .map(|metrics| {
+                            if is_sideways {
+                                metrics.advanceHeight as i32
+                            } else {
+                                metrics.advanceWidth as i32
+                            }
+                        })
+                        .collect());
+                }
+            };
+            let mut advances = vec![0; glyph_indices.len()];
+            let hr = face1.GetDesignGlyphAdvances(
+                glyph_indices.len() as u32,
+                glyph_indices.as_ptr(),
+                advances.as_mut_ptr(),
+                is_sideways as BOOL,
+            );
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(advances)
         }
     }
 
@@ -155,6 +344,26 @@ impl FontFace {
         glyph_indices: &[u16],
         is_sideways: bool,
     ) -> Vec<DWRITE_GLYPH_METRICS> {
+        self.try_get_gdi_compatible_glyph_metrics(
+            em_size,
+            pixels_per_dip,
+            transform,
+            use_gdi_natural,
+            glyph_indices,
+            is_sideways,
+        )
+        .expect("error getting GDI compatible glyph metrics")
+    }
+
+    fn try_get_gdi_compatible_glyph_metrics(
+        &self,
+        em_size: f32,
+        pixels_per_dip: f32,
+        transform: *const DWRITE_MATRIX,
+        use_gdi_natural: bool,
+        glyph_indices: &[u16],
+        is_sideways: bool,
+    ) -> Result<Vec<DWRITE_GLYPH_METRICS>, Error> {
         unsafe {
             let mut metrics: Vec<DWRITE_GLYPH_METRICS> = vec![zeroed(); glyph_indices.len()];
             let hr = (*self.native.get()).GetGdiCompatibleGlyphMetrics(
@@ -167,8 +376,69 @@ impl FontFace {
                 metrics.as_mut_ptr(),
                 is_sideways as BOOL,
             );
-            assert!(hr == 0);
-            metrics
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(metrics)
+        }
+    }
+
+    /// Returns the hinted advances of `glyph_indices` as GDI would measure
+    /// them at the given size, in design units. Scale by
+    /// `em_size / design_units_per_em` to get DIPs.
+    ///
+    /// Falls back to `get_gdi_compatible_glyph_metrics` if `IDWriteFontFace1`
+    /// isn't available.
+    pub fn get_gdi_compatible_glyph_advances(
+        &self,
+        em_size: f32,
+        pixels_per_dip: f32,
+        transform: Option<&DWRITE_MATRIX>,
+        use_gdi_natural: bool,
+        is_sideways: bool,
+        glyph_indices: &[u16],
+    ) -> Result<Vec<i32>, Error> {
+        let transform = transform.map_or(ptr::null(), |t| t as *const _);
+        unsafe {
+            let face1: Option<ComPtr<IDWriteFontFace1>> = (*self.native.get()).cast().ok();
+            let face1 = match face1 {
+                Some(face1) => face1,
+                None => {
+                    return Ok(self
+                        .try_get_gdi_compatible_glyph_metrics(
+                            em_size,
+                            pixels_per_dip,
+                            transform,
+                            use_gdi_natural,
+                            glyph_indices,
+                            is_sideways,
+                        )?
+                        .iter()
+                        .map(|metrics| {
+                            if is_sideways {
+                                metrics.advanceHeight as i32
+                            } else {
+                                metrics.advanceWidth as i32
+                            }
+                        })
+                        .collect());
+                }
+            };
+            let mut advances = vec![0; glyph_indices.len()];
+            let hr = face1.GetGdiCompatibleGlyphAdvances(
+                em_size,
+                pixels_per_dip,
+                transform,
+                use_gdi_natural as BOOL,
+                is_sideways as BOOL,
+                glyph_indices.len() as u32,
+                glyph_indices.as_ptr(),
+                advances.as_mut_ptr(),
+            );
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(advances)
         }
     }
 
@@ -243,6 +513,86 @@ impl FontFace {
         )
     }
 
+    /// Returns the rendering mode DirectWrite recommends for this face at
+    /// the given size, which depends on the font's `gasp` table and on
+    /// `rendering_params`.
+    pub fn recommended_rendering_mode(
+        &self,
+        em_size: f32,
+        pixels_per_dip: f32,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        rendering_params: &RenderingParams,
+    ) -> DWRITE_RENDERING_MODE {
+        unsafe {
+            self.get_recommended_rendering_mode(
+                em_size,
+                pixels_per_dip,
+                measuring_mode,
+                rendering_params.as_ptr(),
+            )
+        }
+    }
+
+    /// Like `recommended_rendering_mode`, but also returns the recommended
+    /// grid-fit mode, for use with `GlyphRunAnalysis::create2`.
+    ///
+    /// Returns `None` if `IDWriteFontFace3` isn't available.
+    pub fn recommended_rendering_mode3(
+        &self,
+        em_size: f32,
+        pixels_per_dip: f32,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        rendering_params: &RenderingParams,
+    ) -> Option<(DWRITE_RENDERING_MODE1, GridFitMode)> {
+        unsafe {
+            let face3: ComPtr<IDWriteFontFace3> = (*self.native.get()).cast().ok()?;
+            let mut rendering_mode = DWRITE_RENDERING_MODE1_DEFAULT;
+            let mut grid_fit_mode = DWRITE_GRID_FIT_MODE_DEFAULT;
+            let hr = face3.GetRecommendedRenderingMode(
+                em_size,
+                96.0 * pixels_per_dip,
+                96.0 * pixels_per_dip,
+                ptr::null(),
+                FALSE,
+                DWRITE_OUTLINE_THRESHOLD_ANTIALIASED,
+                measuring_mode,
+                rendering_params.as_ptr(),
+                &mut rendering_mode,
+                &mut grid_fit_mode,
+            );
+            if hr != S_OK {
+                return None;
+            }
+            Some((rendering_mode, GridFitMode::from_raw(grid_fit_mode)))
+        }
+    }
+
+    /// Returns whether DirectWrite recommends rendering this face as outlines
+    /// at the given size, rather than through a glyph run analysis.
+    pub fn should_use_outline(
+        &self,
+        em_size: f32,
+        pixels_per_dip: f32,
+        rendering_params: &RenderingParams,
+    ) -> bool {
+        let rendering_mode = unsafe {
+            self.get_recommended_rendering_mode(
+                em_size,
+                pixels_per_dip,
+                DWRITE_MEASURING_MODE_NATURAL,
+                rendering_params.as_ptr(),
+            )
+        };
+        rendering_mode == DWRITE_RENDERING_MODE_OUTLINE
+    }
+
+    /// Converts a run of glyphs into a single outline, fed to
+    /// `outline_builder` in DIPs. Glyphs are placed as in a `GlyphRun`: each
+    /// one is advanced by the previous glyph's entry in `glyph_advances` (or
+    /// the font's default advance), then shifted by its `glyph_offsets` entry.
+    ///
+    /// Fails with `Error::InvalidArg`, without calling into DirectWrite, if
+    /// `glyph_advances` or `glyph_offsets` doesn't have one entry per glyph.
     pub fn get_glyph_run_outline(
         &self,
         em_size: f32,
@@ -251,23 +601,96 @@ impl FontFace {
         glyph_offsets: Option<&[DWRITE_GLYPH_OFFSET]>,
         is_sideways: bool,
         is_right_to_left: bool,
-        outline_builder: Box<dyn OutlineBuilder>,
-    ) {
+        mut outline_builder: Box<dyn OutlineBuilder>,
+    ) -> Result<(), Error> {
+        self.outline_into(
+            em_size,
+            glyph_indices,
+            glyph_advances,
+            glyph_offsets,
+            is_sideways,
+            is_right_to_left,
+            &mut outline_builder,
+        )
+    }
+
+    /// Like `get_glyph_run_outline`, but with a builder that can fail. The
+    /// first error it returns stops extraction and is returned from here.
+    pub fn try_get_glyph_run_outline(
+        &self,
+        em_size: f32,
+        glyph_indices: &[u16],
+        glyph_advances: Option<&[f32]>,
+        glyph_offsets: Option<&[DWRITE_GLYPH_OFFSET]>,
+        is_sideways: bool,
+        is_right_to_left: bool,
+        outline_builder: &mut dyn TryOutlineBuilder,
+    ) -> Result<(), OutlineError> {
+        let (hr, error) = self.run_glyph_run_outline(
+            em_size,
+            glyph_indices,
+            glyph_advances,
+            glyph_offsets,
+            is_sideways,
+            is_right_to_left,
+            outline_builder,
+        );
+        if let Some(error) = error {
+            return Err(error);
+        }
+        if hr != S_OK {
+            return Err(OutlineError::Com(hr));
+        }
+        Ok(())
+    }
+
+    // `try_get_glyph_run_outline` for builders that can't fail, so that only
+    // DirectWrite's result needs checking.
+    fn outline_into(
+        &self,
+        em_size: f32,
+        glyph_indices: &[u16],
+        glyph_advances: Option<&[f32]>,
+        glyph_offsets: Option<&[DWRITE_GLYPH_OFFSET]>,
+        is_sideways: bool,
+        is_right_to_left: bool,
+        outline_builder: &mut impl OutlineBuilder,
+    ) -> Result<(), Error> {
+        let (hr, _) = self.run_glyph_run_outline(
+            em_size,
+            glyph_indices,
+            glyph_advances,
+            glyph_offsets,
+            is_sideways,
+            is_right_to_left,
+            outline_builder,
+        );
+        if hr != S_OK {
+            return Err(hr.into());
+        }
+        Ok(())
+    }
+
+    // Feeds the outline into `outline_builder`, returning DirectWrite's result
+    // and the error, if any, that the builder stopped extraction with.
+    fn run_glyph_run_outline(
+        &self,
+        em_size: f32,
+        glyph_indices: &[u16],
+        glyph_advances: Option<&[f32]>,
+        glyph_offsets: Option<&[DWRITE_GLYPH_OFFSET]>,
+        is_sideways: bool,
+        is_right_to_left: bool,
+        outline_builder: &mut dyn TryOutlineBuilder,
+    ) -> (HRESULT, Option<OutlineError>) {
+        if matches!(glyph_advances, Some(a) if a.len() != glyph_indices.len())
+            || matches!(glyph_offsets, Some(o) if o.len() != glyph_indices.len())
+        {
+            return (E_INVALIDARG, None);
+        }
         unsafe {
-            let glyph_advances = match glyph_advances {
-                None => ptr::null(),
-                Some(glyph_advances) => {
-                    assert_eq!(glyph_advances.len(), glyph_indices.len());
-                    glyph_advances.as_ptr()
-                }
-            };
-            let glyph_offsets = match glyph_offsets {
-                None => ptr::null(),
-                Some(glyph_offsets) => {
-                    assert_eq!(glyph_offsets.len(), glyph_indices.len());
-                    glyph_offsets.as_ptr()
-                }
-            };
+            let glyph_advances = glyph_advances.map_or(ptr::null(), |a| a.as_ptr());
+            let glyph_offsets = glyph_offsets.map_or(ptr::null(), |o| o.as_ptr());
             let is_sideways = if is_sideways { TRUE } else { FALSE };
             let is_right_to_left = if is_right_to_left { TRUE } else { FALSE };
             let geometry_sink = GeometrySinkImpl::new(outline_builder);
@@ -282,37 +705,280 @@ impl FontFace {
                 is_right_to_left,
                 geometry_sink,
             );
-            assert_eq!(hr, S_OK);
+            let error = GeometrySinkImpl::from_interface(geometry_sink).take_error();
+            <GeometrySinkImpl as Com<ID2D1SimplifiedGeometrySink>>::destroy(geometry_sink);
+            (hr, error)
         }
     }
 
+    /// Feeds the outline of a single glyph at `em_size`, in DIPs, straight
+    /// into `builder`, without collecting it first.
+    pub fn extract_outline(
+        &self,
+        glyph: u16,
+        em_size: f32,
+        builder: &mut impl OutlineBuilder,
+    ) -> Result<(), Error> {
+        self.outline_into(em_size, &[glyph], None, None, false, false, builder)
+    }
+
+    /// Returns the outline of a single glyph at `em_size`, as a list of path
+    /// commands in DIPs.
+    pub fn get_glyph_outline(&self, glyph: u16, em_size: f32) -> Result<Vec<PathCommand>, Error> {
+        self.get_glyph_outline_with_orientation(glyph, em_size, false, false)
+    }
+
+    /// Like `get_glyph_outline`, but for glyphs laid out sideways (as in
+    /// vertical text) and/or right-to-left.
+    pub fn get_glyph_outline_with_orientation(
+        &self,
+        glyph: u16,
+        em_size: f32,
+        is_sideways: bool,
+        is_right_to_left: bool,
+    ) -> Result<Vec<PathCommand>, Error> {
+        let mut collector = PathCollector::new();
+        self.outline_into(
+            em_size,
+            &[glyph],
+            None,
+            None,
+            is_sideways,
+            is_right_to_left,
+            &mut collector,
+        )?;
+        Ok(collector.take_commands())
+    }
+
+    /// Returns the outline of a single glyph at `em_size` as polygons, one
+    /// per closed contour, with curves flattened to line segments that stray
+    /// at most `tolerance` DIPs from the true outline.
+    pub fn get_glyph_outline_flattened(
+        &self,
+        glyph: u16,
+        em_size: f32,
+        tolerance: f32,
+    ) -> Result<Vec<Vec<(f32, f32)>>, Error> {
+        let outline = self.get_glyph_outline(glyph, em_size)?;
+        Ok(outline_builder::flatten(&outline, tolerance))
+    }
+
     #[inline]
     pub fn get_type(&self) -> FontFaceType {
-        unsafe {
-            match (*self.native.get()).GetType() {
-                DWRITE_FONT_FACE_TYPE_CFF => FontFaceType::Cff,
-                DWRITE_FONT_FACE_TYPE_RAW_CFF => FontFaceType::RawCff,
-                DWRITE_FONT_FACE_TYPE_TRUETYPE => FontFaceType::TrueType,
-                DWRITE_FONT_FACE_TYPE_TRUETYPE_COLLECTION => FontFaceType::TrueTypeCollection,
-                DWRITE_FONT_FACE_TYPE_TYPE1 => FontFaceType::Type1,
-                DWRITE_FONT_FACE_TYPE_VECTOR => FontFaceType::Vector,
-                DWRITE_FONT_FACE_TYPE_BITMAP => FontFaceType::Bitmap,
-                _ => FontFaceType::Unknown,
-            }
-        }
+        unsafe { FontFaceType::from_native((*self.native.get()).GetType()) }
     }
 
+    /// Returns the index of this face within its font file, which is
+    /// non-zero only for font collections (.ttc/.otc). Together with
+    /// `get_files` this identifies the face.
     #[inline]
     pub fn get_index(&self) -> u32 {
         unsafe { (*self.native.get()).GetIndex() }
     }
 
+    pub fn simulations(&self) -> FontSimulations {
+        unsafe { mem::transmute::<u32, FontSimulations>(self.get_simulations()) }
+    }
+
+    /// Returns the algorithmic emboldening/slanting applied to this face, as
+    /// raw `DWRITE_FONT_SIMULATIONS` flags.
+    #[inline]
+    pub fn get_simulations(&self) -> DWRITE_FONT_SIMULATIONS {
+        unsafe { (*self.native.get()).GetSimulations() }
+    }
+
+    /// Returns whether both faces refer to the same face of the same font
+    /// file(s), with the same simulations. Despite the name, this doesn't
+    /// compare the COM pointers, so it also holds for distinct face objects
+    /// created from the same font.
+    pub fn ptr_eq(&self, other: &FontFace) -> bool {
+        unsafe {
+            if let (Some(a), Some(b)) = (self.get_face_reference(), other.get_face_reference()) {
+                return a.Equals(b.as_raw()) == TRUE;
+            }
+        }
+
+        if self.get_index() != other.get_index()
+            || self.get_simulations() != other.get_simulations()
+        {
+            return false;
+        }
+        let (files, other_files) = (self.get_files(), other.get_files());
+        files.len() == other_files.len()
+            && files.iter().zip(other_files.iter()).all(|(a, b)| {
+                a.loader_identity() == b.loader_identity() && a.reference_key() == b.reference_key()
+            })
+    }
+
+    unsafe fn get_face_reference(&self) -> Option<ComPtr<IDWriteFontFaceReference>> {
+        let face3: ComPtr<IDWriteFontFace3> = (*self.native.get()).cast().ok()?;
+        let mut reference: *mut IDWriteFontFaceReference = ptr::null_mut();
+        let hr = face3.GetFontFaceReference(&mut reference);
+        if hr != S_OK {
+            return None;
+        }
+        Some(ComPtr::from_raw(reference))
+    }
+
+    /// Returns whether all glyphs in the face have the same advance width.
+    /// Returns false if `IDWriteFontFace1` isn't available.
+    pub fn is_monospaced_font(&self) -> bool {
+        unsafe {
+            let face1: Option<ComPtr<IDWriteFontFace1>> = (*self.native.get()).cast().ok();
+            matches!(face1, Some(face1) if face1.IsMonospacedFont() == TRUE)
+        }
+    }
+
+    pub fn is_symbol_font(&self) -> bool {
+        unsafe { (*self.native.get()).IsSymbolFont() == TRUE }
+    }
+
+    /// Returns whether the face has color glyphs. Returns false if
+    /// `IDWriteFontFace2` isn't available.
+    pub fn is_color_font(&self) -> bool {
+        unsafe {
+            let face2: Option<ComPtr<IDWriteFontFace2>> = (*self.native.get()).cast().ok();
+            matches!(face2, Some(face2) if face2.IsColorFont() == TRUE)
+        }
+    }
+
+    /// Returns the number of color palettes in the font's `CPAL` table, or 0
+    /// if it has none or `IDWriteFontFace2` isn't available.
+    pub fn get_color_palette_count(&self) -> u32 {
+        unsafe {
+            let face2: Option<ComPtr<IDWriteFontFace2>> = (*self.native.get()).cast().ok();
+            face2.map_or(0, |face2| face2.GetColorPaletteCount())
+        }
+    }
+
+    /// Returns the number of entries in each of the font's color palettes.
+    pub fn get_palette_entry_count(&self) -> u32 {
+        unsafe {
+            let face2: Option<ComPtr<IDWriteFontFace2>> = (*self.native.get()).cast().ok();
+            face2.map_or(0, |face2| face2.GetPaletteEntryCount())
+        }
+    }
+
+    /// Returns `count` colors of the palette `palette_index`, starting at
+    /// `first_entry`.
+    ///
+    /// Fails with `Error::InvalidArg` if the palette or entries are out of range.
+    pub fn get_palette_entries(
+        &self,
+        palette_index: u32,
+        first_entry: u32,
+        count: u32,
+    ) -> Result<Vec<DWRITE_COLOR_F>, Error> {
+        unsafe {
+            let face2: ComPtr<IDWriteFontFace2> =
+                (*self.native.get()).cast().map_err(|_| E_NOINTERFACE)?;
+            let entry_count = face2.GetPaletteEntryCount();
+            if palette_index >= face2.GetColorPaletteCount()
+                || first_entry > entry_count
+                || count > entry_count - first_entry
+            {
+                return Err(Error::InvalidArg);
+            }
+            let mut entries = vec![zeroed(); count as usize];
+            let hr =
+                face2.GetPaletteEntries(palette_index, first_entry, count, entries.as_mut_ptr());
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(entries)
+        }
+    }
+
+    /// Returns whether `glyph` has a color definition in the font's `COLR`
+    /// table, so it can be drawn as color layers rather than as a plain
+    /// outline. This only reads the table, without setting up a color glyph
+    /// run.
+    pub fn glyph_has_color_layers(&self, glyph: u16) -> bool {
+        if !self.is_color_font() {
+            return false;
+        }
+        match self.get_font_table(u32::from_le_bytes(*b"COLR")) {
+            Some(colr) => colr_has_glyph(&colr, glyph),
+            None => false,
+        }
+    }
+
     #[inline]
     unsafe fn get_face5(&self) -> Option<ComPtr<IDWriteFontFace5>> {
-        if (*self.face5.get()).is_none() {
-            *self.face5.get() = (*self.native.get()).cast().ok()
+        (*self.native.get()).cast().ok()
+    }
+
+    /// Returns the kinds of glyph images (outlines, COLR, SVG, bitmaps, ...)
+    /// that the face contains, or `None` if `IDWriteFontFace4` isn't
+    /// available.
+    pub fn get_glyph_image_formats(&self) -> Option<DWRITE_GLYPH_IMAGE_FORMATS> {
+        unsafe {
+            let face4: ComPtr<IDWriteFontFace4> = (*self.native.get()).cast().ok()?;
+            Some(face4.GetGlyphImageFormats_1())
+        }
+    }
+
+    /// Returns the kinds of images available for `glyph` at sizes between
+    /// `ppem_first` and `ppem_last` pixels per em, or `None` if
+    /// `IDWriteFontFace4` isn't available or the query fails.
+    pub fn get_glyph_image_formats_for_glyph(
+        &self,
+        glyph: u16,
+        ppem_first: u32,
+        ppem_last: u32,
+    ) -> Option<DWRITE_GLYPH_IMAGE_FORMATS> {
+        unsafe {
+            let face4: ComPtr<IDWriteFontFace4> = (*self.native.get()).cast().ok()?;
+            let mut formats = DWRITE_GLYPH_IMAGE_FORMATS_NONE;
+            let hr = face4.GetGlyphImageFormats_2(glyph, ppem_first, ppem_last, &mut formats);
+            if hr != S_OK {
+                return None;
+            }
+            Some(formats)
+        }
+    }
+
+    /// Returns the SVG document for `glyph` at `ppem` pixels per em, or
+    /// `None` if the face has no SVG image for it (or `IDWriteFontFace4`
+    /// isn't available).
+    ///
+    /// Fonts may store the document gzip-compressed (starting with the bytes
+    /// `1f 8b`); it's decompressed here, so the result is always plain SVG.
+    /// A compressed document that fails to decompress gives `None`.
+    pub fn get_svg_glyph(&self, glyph: u16, ppem: u32) -> Option<Vec<u8>> {
+        let document = self.get_glyph_image_data(glyph, ppem, DWRITE_GLYPH_IMAGE_FORMATS_SVG)?;
+        if inflate::is_gzip(&document) {
+            inflate::gunzip(&document)
+        } else {
+            Some(document)
+        }
+    }
+
+    fn get_glyph_image_data(
+        &self,
+        glyph: u16,
+        ppem: u32,
+        format: DWRITE_GLYPH_IMAGE_FORMATS,
+    ) -> Option<Vec<u8>> {
+        unsafe {
+            let face4: ComPtr<IDWriteFontFace4> = (*self.native.get()).cast().ok()?;
+            let mut data: DWRITE_GLYPH_IMAGE_DATA = zeroed();
+            let mut context: *mut c_void = ptr::null_mut();
+            let hr = face4.GetGlyphImageData(glyph, ppem, format, &mut data, &mut context);
+            if hr != S_OK {
+                return None;
+            }
+            let bytes = if data.imageData.is_null() || data.imageDataSize == 0 {
+                None
+            } else {
+                Some(
+                    slice::from_raw_parts(data.imageData as *const u8, data.imageDataSize as usize)
+                        .to_vec(),
+                )
+            };
+            face4.ReleaseGlyphImageData(context);
+            bytes
         }
-        (*self.face5.get()).clone()
     }
 
     pub fn has_variations(&self) -> bool {
@@ -358,7 +1024,6 @@ impl Clone for FontFace {
         unsafe {
             FontFace {
                 native: UnsafeCell::new((*self.native.get()).clone()),
-                face5: UnsafeCell::new(None),
             }
         }
     }
@@ -375,3 +1040,172 @@ pub enum FontFaceType {
     Vector,
     Bitmap,
 }
+
+impl FontFaceType {
+    pub(crate) fn from_native(face_type: DWRITE_FONT_FACE_TYPE) -> FontFaceType {
+        match face_type {
+            DWRITE_FONT_FACE_TYPE_CFF => FontFaceType::Cff,
+            DWRITE_FONT_FACE_TYPE_RAW_CFF => FontFaceType::RawCff,
+            DWRITE_FONT_FACE_TYPE_TRUETYPE => FontFaceType::TrueType,
+            DWRITE_FONT_FACE_TYPE_TRUETYPE_COLLECTION => FontFaceType::TrueTypeCollection,
+            DWRITE_FONT_FACE_TYPE_TYPE1 => FontFaceType::Type1,
+            DWRITE_FONT_FACE_TYPE_VECTOR => FontFaceType::Vector,
+            DWRITE_FONT_FACE_TYPE_BITMAP => FontFaceType::Bitmap,
+            _ => FontFaceType::Unknown,
+        }
+    }
+
+    pub(crate) fn to_native(self) -> DWRITE_FONT_FACE_TYPE {
+        match self {
+            FontFaceType::Unknown => DWRITE_FONT_FACE_TYPE_UNKNOWN,
+            FontFaceType::Cff => DWRITE_FONT_FACE_TYPE_CFF,
+            FontFaceType::RawCff => DWRITE_FONT_FACE_TYPE_RAW_CFF,
+            FontFaceType::TrueType => DWRITE_FONT_FACE_TYPE_TRUETYPE,
+            FontFaceType::TrueTypeCollection => DWRITE_FONT_FACE_TYPE_TRUETYPE_COLLECTION,
+            FontFaceType::Type1 => DWRITE_FONT_FACE_TYPE_TYPE1,
+            FontFaceType::Vector => DWRITE_FONT_FACE_TYPE_VECTOR,
+            FontFaceType::Bitmap => DWRITE_FONT_FACE_TYPE_BITMAP,
+        }
+    }
+}
+
+/// Font metrics scaled from design units to a given em size.
+///
+/// Positions are measured upwards from the baseline, so the underline and
+/// strikethrough positions follow the font's y-up convention: a typical
+/// underline position is negative. `ascent` and `descent` are both distances
+/// and positive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScaledFontMetrics {
+    pub em_size: f32,
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+    pub cap_height: f32,
+    pub x_height: f32,
+    pub underline_position: f32,
+    pub underline_thickness: f32,
+    pub strikethrough_position: f32,
+    pub strikethrough_thickness: f32,
+}
+
+impl ScaledFontMetrics {
+    pub fn new(metrics: &DWRITE_FONT_METRICS, em_size: f32) -> ScaledFontMetrics {
+        let scale = em_size / metrics.designUnitsPerEm as f32;
+        ScaledFontMetrics {
+            em_size,
+            ascent: metrics.ascent as f32 * scale,
+            descent: metrics.descent as f32 * scale,
+            line_gap: metrics.lineGap as f32 * scale,
+            cap_height: metrics.capHeight as f32 * scale,
+            x_height: metrics.xHeight as f32 * scale,
+            underline_position: metrics.underlinePosition as f32 * scale,
+            underline_thickness: metrics.underlineThickness as f32 * scale,
+            strikethrough_position: metrics.strikethroughPosition as f32 * scale,
+            strikethrough_thickness: metrics.strikethroughThickness as f32 * scale,
+        }
+    }
+
+    /// The default distance between consecutive baselines.
+    pub fn line_height(&self) -> f32 {
+        self.ascent + self.descent + self.line_gap
+    }
+}
+
+/// The vertical metrics of a glyph, in design units, taken from its
+/// `DWRITE_GLYPH_METRICS`.
+///
+/// Bearings are distances, not coordinates: they are positive when the
+/// glyph's black box lies inside its advance box. `vertical_origin_y` is a
+/// coordinate in the font's y-up design space, measured from the baseline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VerticalGlyphMetrics {
+    /// The distance from the vertical origin down to the top of the next
+    /// glyph's advance box.
+    pub advance_height: u32,
+    /// The distance from the vertical origin down to the top of the black
+    /// box.
+    pub top_side_bearing: i32,
+    /// The distance from the bottom of the black box down to the bottom of
+    /// the advance box.
+    pub bottom_side_bearing: i32,
+    /// The height of the vertical origin above the baseline.
+    pub vertical_origin_y: i32,
+}
+
+impl VerticalGlyphMetrics {
+    /// The height of the top of the black box above the baseline.
+    pub fn black_box_top(&self) -> i32 {
+        self.vertical_origin_y - self.top_side_bearing
+    }
+
+    pub fn black_box_height(&self) -> i32 {
+        self.advance_height as i32 - self.top_side_bearing - self.bottom_side_bearing
+    }
+}
+
+impl<'a> From<&'a DWRITE_GLYPH_METRICS> for VerticalGlyphMetrics {
+    fn from(metrics: &'a DWRITE_GLYPH_METRICS) -> VerticalGlyphMetrics {
+        VerticalGlyphMetrics {
+            advance_height: metrics.advanceHeight,
+            top_side_bearing: metrics.topSideBearing,
+            bottom_side_bearing: metrics.bottomSideBearing,
+            vertical_origin_y: metrics.verticalOriginY,
+        }
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// Binary searches a table of `count` records of `record_size` bytes, sorted
+// by the glyph id in their first two bytes.
+fn find_glyph_record(
+    data: &[u8],
+    start: usize,
+    count: usize,
+    record_size: usize,
+    glyph: u16,
+) -> bool {
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let mid = (low + high) / 2;
+        match read_u16(data, start + mid * record_size) {
+            Some(id) if id == glyph => return true,
+            Some(id) if id < glyph => low = mid + 1,
+            Some(_) => high = mid,
+            None => return false,
+        }
+    }
+    false
+}
+
+// Looks `glyph` up in the base glyph records of a COLR table, and in the
+// base glyph paint records as well for version 1 tables.
+fn colr_has_glyph(colr: &[u8], glyph: u16) -> bool {
+    let version = match read_u16(colr, 0) {
+        Some(version) => version,
+        None => return false,
+    };
+    if let (Some(count), Some(offset)) = (read_u16(colr, 2), read_u32(colr, 4)) {
+        if offset != 0 && find_glyph_record(colr, offset as usize, count as usize, 6, glyph) {
+            return true;
+        }
+    }
+    if version >= 1 {
+        if let Some(list_offset) = read_u32(colr, 14).filter(|&offset| offset != 0) {
+            let list_offset = list_offset as usize;
+            if let Some(count) = read_u32(colr, list_offset) {
+                return find_glyph_record(colr, list_offset + 4, count as usize, 6, glyph);
+            }
+        }
+    }
+    false
+}
@@ -8,6 +8,7 @@ use winapi::um::dwrite_2::{IDWriteFactory2, IDWriteFontFallback};
 use wio::com::ComPtr;
 
 use super::*;
+use crate::factory::factory_or_shared;
 
 pub struct FontFallback {
     native: UnsafeCell<ComPtr<IDWriteFontFallback>>,
@@ -16,16 +17,22 @@ pub struct FontFallback {
 pub struct FallbackResult {
     /// Length of mapped substring, in utf-16 code units.
     pub mapped_length: usize,
-    /// The font that should be used to render the substring.
+    /// The font that should be used to render the substring, or `None` if no
+    /// font can render it.
     pub mapped_font: Option<Font>,
-    /// The scale factor to apply.
+    /// The scale factor to apply to the mapped font's em size so that it
+    /// visually matches the base font.
     pub scale: f32,
 }
 
 impl FontFallback {
     pub fn get_system_fallback() -> Option<FontFallback> {
+        FontFallback::get_system_fallback_with_factory(None)
+    }
+
+    pub fn get_system_fallback_with_factory(factory: Option<&Factory>) -> Option<FontFallback> {
         unsafe {
-            let factory = ComPtr::from_raw(DWriteFactory());
+            let factory = ComPtr::from_raw(factory_or_shared(factory));
             let factory2: Option<ComPtr<IDWriteFactory2>> = factory.cast().ok();
             std::mem::forget(factory);
             let factory2 = factory2?;
@@ -47,7 +54,12 @@ impl FontFallback {
         (*self.native.get()).as_raw()
     }
 
-    // TODO: map_characters (main function)
+    /// Finds a font that can render (a prefix of) the given range of text.
+    ///
+    /// The base collection, family, weight, style and stretch describe the
+    /// font that the text would otherwise use, and bias the choice of
+    /// fallback font towards it. The locale that DirectWrite matches against
+    /// is the one reported by the text analysis source for `text_position`.
     pub fn map_characters(
         &self,
         text_analysis_source: &TextAnalysisSource,
@@ -59,6 +71,8 @@ impl FontFallback {
         base_style: FontStyle,
         base_stretch: FontStretch,
     ) -> FallbackResult {
+        // Keep the name alive until the call returns.
+        let mut base_family = base_family.map(|s| s.to_wide_null());
         unsafe {
             let mut font = null_mut();
             let mut mapped_length = 0;
@@ -68,9 +82,7 @@ impl FontFallback {
                 text_position,
                 text_length,
                 base_font.as_ptr(),
-                base_family
-                    .map(|s| s.to_wide_null().as_mut_ptr())
-                    .unwrap_or(null_mut()),
+                base_family.as_mut().map_or(null_mut(), |s| s.as_mut_ptr()),
                 base_weight.t(),
                 base_style.t(),
                 base_stretch.t(),
@@ -3,18 +3,102 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use std::cell::UnsafeCell;
+use std::iter::FusedIterator;
 use std::ptr;
 use winapi::um::dwrite::IDWriteLocalizedStrings;
-use winapi::um::dwrite::{IDWriteFont, IDWriteFontCollection, IDWriteFontFamily};
+use winapi::um::dwrite::{IDWriteFont, IDWriteFontCollection, IDWriteFontFamily, IDWriteFontList};
 use wio::com::ComPtr;
 
 use super::*;
-use helpers::*;
+
+/// Iterates over the fonts of a `FontFamily`, in index order. A font
+/// DirectWrite fails to return is yielded as an error.
+///
+/// The iterator holds its own reference to the family, so it stays valid if
+/// the `FontFamily` it came from is dropped.
+pub struct FontIter {
+    family: ComPtr<IDWriteFontFamily>,
+    curr: u32,
+    count: u32,
+}
+
+impl FontIter {
+    fn get(&self, index: u32) -> Result<Font, Error> {
+        unsafe {
+            let mut font: *mut IDWriteFont = ptr::null_mut();
+            let hr = self.family.GetFont(index, &mut font);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(Font::take(ComPtr::from_raw(font)))
+        }
+    }
+}
+
+impl Iterator for FontIter {
+    type Item = Result<Font, Error>;
+    fn next(&mut self) -> Option<Result<Font, Error>> {
+        if self.curr == self.count {
+            return None;
+        }
+        self.curr += 1;
+        Some(self.get(self.curr - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.count - self.curr) as usize;
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for FontIter {
+    fn next_back(&mut self) -> Option<Result<Font, Error>> {
+        if self.curr == self.count {
+            return None;
+        }
+        self.count -= 1;
+        Some(self.get(self.count))
+    }
+}
+
+impl ExactSizeIterator for FontIter {}
+
+impl FusedIterator for FontIter {}
+
+/// Iterates over the fonts of a `FontFamily`, skipping any that DirectWrite
+/// fails to return. See `FontIter` to see the failures.
+pub struct FontFamilyFontIterator {
+    fonts: FontIter,
+}
+
+impl Iterator for FontFamilyFontIterator {
+    type Item = Font;
+    fn next(&mut self) -> Option<Font> {
+        self.fonts.find_map(Result::ok)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // Any of the remaining fonts may fail to resolve and be skipped.
+        (0, Some(self.fonts.len()))
+    }
+}
+
+impl DoubleEndedIterator for FontFamilyFontIterator {
+    fn next_back(&mut self) -> Option<Font> {
+        self.fonts.by_ref().rev().find_map(Result::ok)
+    }
+}
+
+impl FusedIterator for FontFamilyFontIterator {}
 
 pub struct FontFamily {
     native: UnsafeCell<ComPtr<IDWriteFontFamily>>,
 }
 
+// See the comment on `FontCollection`.
+unsafe impl Send for FontFamily {}
+unsafe impl Sync for FontFamily {}
+
 impl FontFamily {
     pub fn take(native: ComPtr<IDWriteFontFamily>) -> FontFamily {
         FontFamily {
@@ -27,15 +111,45 @@ impl FontFamily {
     }
 
     pub fn name(&self) -> String {
+        self.family_names().default_string().unwrap()
+    }
+
+    /// Returns the family name in every locale the font provides it in.
+    pub fn family_names(&self) -> LocalizedStrings {
         unsafe {
             let mut family_names: *mut IDWriteLocalizedStrings = ptr::null_mut();
             let hr = (*self.native.get()).GetFamilyNames(&mut family_names);
             assert!(hr == 0);
-
-            get_locale_string(&mut ComPtr::from_raw(family_names))
+            LocalizedStrings::take(ComPtr::from_raw(family_names))
         }
     }
 
+    /// Returns the `(locale, name)` pairs of `family_names`.
+    pub fn family_name_pairs(&self) -> Result<Vec<(String, String)>, Error> {
+        self.family_names().iter().collect()
+    }
+
+    /// Returns the family name for `locale`, falling back to the user's
+    /// default locale, then `en-us`, then whichever name comes first. This
+    /// suits names shown to the user; see `name_for_locale_or_english` for
+    /// names that shouldn't depend on the system.
+    pub fn name_for_locale(&self, locale: &str) -> Option<String> {
+        self.family_names().string_for_locale(locale)
+    }
+
+    /// Returns the family name for `locale`, falling back to `en-us`, then
+    /// whichever name comes first, regardless of the user's locale.
+    pub fn name_for_locale_or_english(&self, locale: &str) -> Option<String> {
+        self.family_names().string_for_locale_or_english(locale)
+    }
+
+    /// Returns the `en-us` family name, for matching families by name
+    /// independently of the user's locale. Falls back to the first name for
+    /// fonts without an English name.
+    pub fn english_name(&self) -> String {
+        self.name_for_locale_or_english("en-us").unwrap()
+    }
+
     pub fn get_first_matching_font(
         &self,
         weight: FontWeight,
@@ -55,6 +169,29 @@ impl FontFamily {
         }
     }
 
+    /// Returns the fonts in this family that match the given properties,
+    /// ordered from best to worst match.
+    pub fn matching_fonts(
+        &self,
+        weight: FontWeight,
+        stretch: FontStretch,
+        style: FontStyle,
+    ) -> Result<FontList, Error> {
+        unsafe {
+            let mut fonts: *mut IDWriteFontList = ptr::null_mut();
+            let hr = (*self.native.get()).GetMatchingFonts(
+                weight.t(),
+                stretch.t(),
+                style.t(),
+                &mut fonts,
+            );
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(FontList::take(ComPtr::from_raw(fonts)))
+        }
+    }
+
     pub fn get_font_collection(&self) -> FontCollection {
         unsafe {
             let mut collection: *mut IDWriteFontCollection = ptr::null_mut();
@@ -64,6 +201,26 @@ impl FontFamily {
         }
     }
 
+    /// Returns an iterator over the fonts in this family, yielding an error
+    /// for any font DirectWrite fails to return.
+    pub fn fonts(&self) -> FontIter {
+        unsafe {
+            FontIter {
+                family: (*self.native.get()).clone(),
+                curr: 0,
+                count: (*self.native.get()).GetFontCount(),
+            }
+        }
+    }
+
+    /// Returns an iterator over the fonts in this family, skipping any that
+    /// DirectWrite fails to return.
+    pub fn fonts_iter(&self) -> FontFamilyFontIterator {
+        FontFamilyFontIterator {
+            fonts: self.fonts(),
+        }
+    }
+
     pub fn get_font_count(&self) -> u32 {
         unsafe { (*self.native.get()).GetFontCount() }
     }
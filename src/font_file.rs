@@ -4,6 +4,8 @@
 
 use std::cell::UnsafeCell;
 use std::ffi::OsString;
+use std::fs::File;
+use std::io;
 use std::os::windows::ffi::{OsStrExt, OsStringExt};
 use std::path::Path;
 use std::path::PathBuf;
@@ -11,26 +13,40 @@ use std::ptr;
 use std::slice;
 use std::sync::Arc;
 use winapi::ctypes::c_void;
+use winapi::shared::winerror::{E_BOUNDS, E_FAIL, HRESULT_FROM_WIN32};
 use winapi::um::dwrite::{IDWriteFontFace, IDWriteFontFile, IDWriteFontFileStream};
 use winapi::um::dwrite::{IDWriteFontFileLoader, IDWriteLocalFontFileLoader};
-use winapi::um::dwrite::{DWRITE_FONT_FACE_TYPE, DWRITE_FONT_FILE_TYPE_UNKNOWN};
+use winapi::um::dwrite::{DWRITE_FONT_FACE_TYPE, DWRITE_FONT_FILE_TYPE};
 use winapi::um::dwrite::{DWRITE_FONT_FACE_TYPE_UNKNOWN, DWRITE_FONT_SIMULATIONS};
-use winapi::um::winnt::HRESULT;
+use winapi::um::dwrite::{DWRITE_FONT_FILE_TYPE_BITMAP, DWRITE_FONT_FILE_TYPE_CFF};
+use winapi::um::dwrite::{
+    DWRITE_FONT_FILE_TYPE_OPENTYPE_COLLECTION, DWRITE_FONT_FILE_TYPE_TRUETYPE,
+};
+use winapi::um::dwrite::{DWRITE_FONT_FILE_TYPE_TYPE1_PFB, DWRITE_FONT_FILE_TYPE_TYPE1_PFM};
+use winapi::um::dwrite::{DWRITE_FONT_FILE_TYPE_UNKNOWN, DWRITE_FONT_FILE_TYPE_VECTOR};
 use wio::com::ComPtr;
 
 use super::DWriteFactory;
-use crate::font_face::FontFace;
+use crate::error::Error;
+use crate::factory::{factory_or_shared, Factory};
+use crate::font_face::{FontFace, FontFaceType};
 use crate::font_file_loader_impl::DataFontHelper;
 
 pub struct FontFile {
     native: UnsafeCell<ComPtr<IDWriteFontFile>>,
     stream: UnsafeCell<Option<ComPtr<IDWriteFontFileStream>>>,
     data_key: usize,
-    face_type: DWRITE_FONT_FACE_TYPE,
 }
 
 impl FontFile {
     pub fn new_from_path<P>(path: P) -> Option<FontFile>
+    where
+        P: AsRef<Path>,
+    {
+        FontFile::new_from_path_with_factory(path, None)
+    }
+
+    pub fn new_from_path_with_factory<P>(path: P, factory: Option<&Factory>) -> Option<FontFile>
     where
         P: AsRef<Path>,
     {
@@ -39,7 +55,7 @@ impl FontFile {
             path.push(0);
 
             let mut font_file: *mut IDWriteFontFile = ptr::null_mut();
-            let hr = (*DWriteFactory()).CreateFontFileReference(
+            let hr = (*factory_or_shared(factory)).CreateFontFileReference(
                 path.as_ptr(),
                 ptr::null(),
                 &mut font_file,
@@ -48,14 +64,13 @@ impl FontFile {
                 return None;
             }
 
-            let mut ff = FontFile {
+            let ff = FontFile {
                 native: UnsafeCell::new(ComPtr::from_raw(font_file)),
                 stream: UnsafeCell::new(None),
                 data_key: 0,
-                face_type: DWRITE_FONT_FACE_TYPE_UNKNOWN,
             };
 
-            if ff.analyze() == 0 {
+            if ff.face_count() == 0 {
                 None
             } else {
                 Some(ff)
@@ -63,66 +78,226 @@ impl FontFile {
         }
     }
 
+    /// Create a font file that reads from `path` on demand instead of holding
+    /// a copy of the whole file in memory.
+    ///
+    /// The file stays open for as long as DirectWrite references it.
+    pub fn new_from_file_mapped(path: &Path) -> Result<FontFile, Error> {
+        FontFile::new_from_file_mapped_with_factory(path, None)
+    }
+
+    pub fn new_from_file_mapped_with_factory(
+        path: &Path,
+        factory: Option<&Factory>,
+    ) -> Result<FontFile, Error> {
+        let io_error_to_hresult = |error: io::Error| match error.raw_os_error() {
+            Some(code) => HRESULT_FROM_WIN32(code as u32),
+            None => E_FAIL,
+        };
+        let file = File::open(path).map_err(io_error_to_hresult)?;
+        let (font_file, font_file_stream, key) =
+            unsafe { DataFontHelper::register_font_file(file, factory_or_shared(factory)) }
+                .map_err(io_error_to_hresult)?;
+
+        let ff = FontFile {
+            native: UnsafeCell::new(font_file),
+            stream: UnsafeCell::new(Some(font_file_stream)),
+            data_key: key,
+        };
+
+        if ff.face_count() == 0 {
+            Err(Error::FileFormat)
+        } else {
+            Ok(ff)
+        }
+    }
+
     pub fn new_from_data(data: Arc<Vec<u8>>) -> Option<FontFile> {
-        let (font_file, font_file_stream, key) = DataFontHelper::register_font_data(data);
+        FontFile::new_from_data_with_factory(data, None)
+    }
+
+    pub fn new_from_data_with_factory(
+        data: Arc<Vec<u8>>,
+        factory: Option<&Factory>,
+    ) -> Option<FontFile> {
+        let (font_file, font_file_stream, key) =
+            unsafe { DataFontHelper::register_font_data(data, factory_or_shared(factory)) };
 
-        let mut ff = FontFile {
+        let ff = FontFile {
             native: UnsafeCell::new(font_file),
             stream: UnsafeCell::new(Some(font_file_stream)),
             data_key: key,
-            face_type: DWRITE_FONT_FACE_TYPE_UNKNOWN,
         };
 
-        if ff.analyze() == 0 {
+        if ff.face_count() == 0 {
             None
         } else {
             Some(ff)
         }
     }
 
+    /// Create a font file backed by `data` without copying it.
+    ///
+    /// This goes through DirectWrite's own in-memory loader where available
+    /// (Windows 10 Creators Update and later), which keeps `data` alive for as
+    /// long as DirectWrite references it. On older systems this falls back to
+    /// the crate's custom loader.
+    pub fn new_from_data_in_memory(
+        data: Arc<dyn AsRef<[u8]> + Send + Sync>,
+    ) -> Result<FontFile, Error> {
+        FontFile::new_from_data_in_memory_with_factory(data, None)
+    }
+
+    /// Like `new_from_data_in_memory`, but creates the font file through
+    /// `factory`. The system in-memory loader is only registered with the
+    /// shared factory, so other factories always use the custom loader.
+    pub fn new_from_data_in_memory_with_factory(
+        data: Arc<dyn AsRef<[u8]> + Send + Sync>,
+        factory: Option<&Factory>,
+    ) -> Result<FontFile, Error> {
+        let ff = FontFile::in_memory(data, factory)?;
+        if ff.face_count() == 0 {
+            Err(Error::FileFormat)
+        } else {
+            Ok(ff)
+        }
+    }
+
+    /// Creates a face straight from font data whose format the caller already
+    /// knows, passing `face_type` to DirectWrite instead of the type it would
+    /// pick itself.
+    ///
+    /// The data is still analyzed once, and this fails with
+    /// `Error::FileFormat` if DirectWrite doesn't support it or detects a
+    /// different file or face type, and with `E_BOUNDS` if `face_index` is
+    /// out of range.
+    pub fn new_from_buffer_with_type(
+        data: Arc<dyn AsRef<[u8]> + Send + Sync>,
+        file_type: FontFileType,
+        face_type: FontFaceType,
+        face_index: u32,
+        simulations: DWRITE_FONT_SIMULATIONS,
+    ) -> Result<FontFace, Error> {
+        FontFile::new_from_buffer_with_type_and_factory(
+            data,
+            file_type,
+            face_type,
+            face_index,
+            simulations,
+            None,
+        )
+    }
+
+    pub fn new_from_buffer_with_type_and_factory(
+        data: Arc<dyn AsRef<[u8]> + Send + Sync>,
+        file_type: FontFileType,
+        face_type: FontFaceType,
+        face_index: u32,
+        simulations: DWRITE_FONT_SIMULATIONS,
+        factory: Option<&Factory>,
+    ) -> Result<FontFace, Error> {
+        let ff = FontFile::in_memory(data, factory)?;
+        let (is_supported, actual_file_type, actual_face_type, number_of_faces) =
+            ff.analyze_native()?;
+        if !is_supported
+            || FontFileType::from_native(actual_file_type) != file_type
+            || FontFaceType::from_native(actual_face_type) != face_type
+        {
+            return Err(Error::FileFormat);
+        }
+        if face_index >= number_of_faces {
+            return Err(E_BOUNDS.into());
+        }
+        ff.create_face_of_type(face_type.to_native(), face_index, simulations, factory)
+    }
+
+    // Wraps `data` in a font file without checking that it's a font.
+    fn in_memory(
+        data: Arc<dyn AsRef<[u8]> + Send + Sync>,
+        factory: Option<&Factory>,
+    ) -> Result<FontFile, Error> {
+        let in_memory_font_file = match factory {
+            None => DataFontHelper::create_in_memory_font_file(data.clone()),
+            Some(_) => None,
+        };
+        let ff = match in_memory_font_file {
+            Some(font_file) => FontFile {
+                native: UnsafeCell::new(font_file?),
+                stream: UnsafeCell::new(None),
+                data_key: 0,
+            },
+            None => {
+                let (font_file, font_file_stream, key) =
+                    unsafe { DataFontHelper::register_font_data(data, factory_or_shared(factory)) };
+                FontFile {
+                    native: UnsafeCell::new(font_file),
+                    stream: UnsafeCell::new(Some(font_file_stream)),
+                    data_key: key,
+                }
+            }
+        };
+        Ok(ff)
+    }
+
     pub fn analyze_data(data: Arc<Vec<u8>>) -> u32 {
-        let (font_file, font_file_stream, key) = DataFontHelper::register_font_data(data);
+        let (font_file, font_file_stream, key) =
+            DataFontHelper::register_font_data(data, DWriteFactory());
 
-        let mut ff = FontFile {
+        let ff = FontFile {
             native: UnsafeCell::new(font_file),
             stream: UnsafeCell::new(Some(font_file_stream)),
             data_key: key,
-            face_type: DWRITE_FONT_FACE_TYPE_UNKNOWN,
         };
 
-        ff.analyze()
+        ff.face_count()
+    }
+
+    /// Inspect the file to find out whether DirectWrite supports it, and what
+    /// kind of font (and how many faces) it contains.
+    pub fn analyze(&self) -> Result<FontFileAnalysis, Error> {
+        let (is_supported, file_type, face_type, number_of_faces) = self.analyze_native()?;
+        Ok(FontFileAnalysis {
+            is_supported,
+            file_type: FontFileType::from_native(file_type),
+            face_type: FontFaceType::from_native(face_type),
+            number_of_faces,
+        })
     }
 
-    fn analyze(&mut self) -> u32 {
+    fn analyze_native(
+        &self,
+    ) -> Result<(bool, DWRITE_FONT_FILE_TYPE, DWRITE_FONT_FACE_TYPE, u32), Error> {
         let mut face_type = DWRITE_FONT_FACE_TYPE_UNKNOWN;
+        let mut file_type = DWRITE_FONT_FILE_TYPE_UNKNOWN;
         let mut num_faces = 0;
+        let mut supported = 0;
         unsafe {
-            let mut supported = 0;
-            let mut _file_type = DWRITE_FONT_FILE_TYPE_UNKNOWN;
-
             let hr = (*self.native.get()).Analyze(
                 &mut supported,
-                &mut _file_type,
+                &mut file_type,
                 &mut face_type,
                 &mut num_faces,
             );
-            if hr != 0 || supported == 0 {
-                return 0;
+            if hr != 0 {
+                return Err(hr.into());
             }
         }
-        self.face_type = face_type;
-        num_faces as u32
+        Ok((supported != 0, file_type, face_type, num_faces))
+    }
+
+    fn face_count(&self) -> u32 {
+        match self.analyze_native() {
+            Ok((true, _, _, num_faces)) => num_faces,
+            _ => 0,
+        }
     }
 
     pub fn take(native: ComPtr<IDWriteFontFile>) -> FontFile {
-        let mut ff = FontFile {
+        FontFile {
             native: UnsafeCell::new(native),
             stream: UnsafeCell::new(None),
             data_key: 0,
-            face_type: DWRITE_FONT_FACE_TYPE_UNKNOWN,
-        };
-        ff.analyze();
-        ff
+        }
     }
 
     pub fn data_key(&self) -> Option<usize> {
@@ -137,20 +312,49 @@ impl FontFile {
         (*self.native.get()).clone()
     }
 
+    unsafe fn get_raw_reference_key(&self) -> (*const c_void, u32) {
+        let mut ref_key: *const c_void = ptr::null();
+        let mut ref_key_size: u32 = 0;
+        let hr = (*self.native.get()).GetReferenceKey(&mut ref_key, &mut ref_key_size);
+        assert!(hr == 0);
+        (ref_key, ref_key_size)
+    }
+
+    unsafe fn get_loader(&self) -> ComPtr<IDWriteFontFileLoader> {
+        let mut loader: *mut IDWriteFontFileLoader = ptr::null_mut();
+        let hr = (*self.native.get()).GetLoader(&mut loader);
+        assert!(hr == 0);
+        ComPtr::from_raw(loader)
+    }
+
+    /// Returns a copy of the key that identifies this file to its loader.
+    ///
+    /// Together with `loader_identity`, this identifies the file: for the
+    /// local file loader the key encodes the file path and last write time.
+    pub fn reference_key(&self) -> Vec<u8> {
+        unsafe {
+            let (ref_key, ref_key_size) = self.get_raw_reference_key();
+            slice::from_raw_parts(ref_key as *const u8, ref_key_size as usize).to_vec()
+        }
+    }
+
+    pub fn loader_identity(&self) -> FontFileLoaderId {
+        unsafe {
+            let loader = self.get_loader();
+            match loader.cast::<IDWriteLocalFontFileLoader>() {
+                Ok(_) => FontFileLoaderId::Local,
+                Err(_) => FontFileLoaderId::Custom(loader.as_raw() as usize),
+            }
+        }
+    }
+
     // This is a helper to read the contents of this FontFile,
     // without requiring callers to deal with loaders, keys,
     // or streams.
     pub fn get_font_file_bytes(&self) -> Vec<u8> {
         unsafe {
-            let mut ref_key: *const c_void = ptr::null();
-            let mut ref_key_size: u32 = 0;
-            let hr = (*self.native.get()).GetReferenceKey(&mut ref_key, &mut ref_key_size);
-            assert!(hr == 0);
-
-            let mut loader: *mut IDWriteFontFileLoader = ptr::null_mut();
-            let hr = (*self.native.get()).GetLoader(&mut loader);
-            assert!(hr == 0);
-            let loader = ComPtr::from_raw(loader);
+            let (ref_key, ref_key_size) = self.get_raw_reference_key();
+            let loader = self.get_loader();
 
             let mut stream: *mut IDWriteFontFileStream = ptr::null_mut();
             let hr = loader.CreateStreamFromKey(ref_key, ref_key_size, &mut stream);
@@ -180,15 +384,8 @@ impl FontFile {
     // without requiring callers to deal with loaders.
     pub fn get_font_file_path(&self) -> Option<PathBuf> {
         unsafe {
-            let mut ref_key: *const c_void = ptr::null();
-            let mut ref_key_size: u32 = 0;
-            let hr = (*self.native.get()).GetReferenceKey(&mut ref_key, &mut ref_key_size);
-            assert!(hr == 0);
-
-            let mut loader: *mut IDWriteFontFileLoader = ptr::null_mut();
-            let hr = (*self.native.get()).GetLoader(&mut loader);
-            assert!(hr == 0);
-            let loader = ComPtr::from_raw(loader);
+            let (ref_key, ref_key_size) = self.get_raw_reference_key();
+            let loader = self.get_loader();
 
             let local_loader: ComPtr<IDWriteLocalFontFileLoader> = match loader.cast() {
                 Ok(local_loader) => local_loader,
@@ -217,16 +414,47 @@ impl FontFile {
         }
     }
 
+    /// Create a face for the face at `face_index` in this file, using the face
+    /// type that `analyze` reports.
+    ///
+    /// Fails with `Error::FileFormat` if the file isn't a supported font,
+    /// and with `E_BOUNDS` if `face_index` is past the number of faces in it.
     pub fn create_face(
         &self,
         face_index: u32,
         simulations: DWRITE_FONT_SIMULATIONS,
-    ) -> Result<FontFace, HRESULT> {
+    ) -> Result<FontFace, Error> {
+        self.create_face_with_factory(face_index, simulations, None)
+    }
+
+    pub fn create_face_with_factory(
+        &self,
+        face_index: u32,
+        simulations: DWRITE_FONT_SIMULATIONS,
+        factory: Option<&Factory>,
+    ) -> Result<FontFace, Error> {
+        let (is_supported, _, face_type, number_of_faces) = self.analyze_native()?;
+        if !is_supported {
+            return Err(Error::FileFormat);
+        }
+        if face_index >= number_of_faces {
+            return Err(E_BOUNDS.into());
+        }
+        self.create_face_of_type(face_type, face_index, simulations, factory)
+    }
+
+    fn create_face_of_type(
+        &self,
+        face_type: DWRITE_FONT_FACE_TYPE,
+        face_index: u32,
+        simulations: DWRITE_FONT_SIMULATIONS,
+        factory: Option<&Factory>,
+    ) -> Result<FontFace, Error> {
         unsafe {
             let mut face: *mut IDWriteFontFace = ptr::null_mut();
             let ptr = self.as_com_ptr();
-            let hr = (*DWriteFactory()).CreateFontFace(
-                self.face_type,
+            let hr = (*factory_or_shared(factory)).CreateFontFace(
+                face_type,
                 1,
                 &ptr.as_raw(),
                 face_index,
@@ -234,7 +462,7 @@ impl FontFile {
                 &mut face,
             );
             if hr != 0 {
-                Err(hr)
+                Err(hr.into())
             } else {
                 Ok(FontFace::take(ComPtr::from_raw(face)))
             }
@@ -249,8 +477,57 @@ impl Clone for FontFile {
                 native: UnsafeCell::new((*self.native.get()).clone()),
                 stream: UnsafeCell::new((*self.stream.get()).clone()),
                 data_key: self.data_key,
-                face_type: self.face_type,
             }
         }
     }
 }
+
+/// Identifies the loader that a `FontFile`'s reference key belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FontFileLoaderId {
+    /// DirectWrite's loader for files on disk. Keys from this loader are
+    /// stable across processes.
+    Local,
+    /// Any other loader, identified by its address. Keys from these loaders
+    /// are only meaningful within the current process.
+    Custom(usize),
+}
+
+/// The result of `FontFile::analyze`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontFileAnalysis {
+    pub is_supported: bool,
+    pub file_type: FontFileType,
+    /// The face type that `FontFile::create_face` will use.
+    pub face_type: FontFaceType,
+    /// A collection (such as a .ttc) has one face per index.
+    pub number_of_faces: u32,
+}
+
+// mirrors DWRITE_FONT_FILE_TYPE
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FontFileType {
+    Unknown,
+    Cff,
+    TrueType,
+    OpenTypeCollection,
+    Type1Pfm,
+    Type1Pfb,
+    Vector,
+    Bitmap,
+}
+
+impl FontFileType {
+    fn from_native(file_type: DWRITE_FONT_FILE_TYPE) -> FontFileType {
+        match file_type {
+            DWRITE_FONT_FILE_TYPE_CFF => FontFileType::Cff,
+            DWRITE_FONT_FILE_TYPE_TRUETYPE => FontFileType::TrueType,
+            DWRITE_FONT_FILE_TYPE_OPENTYPE_COLLECTION => FontFileType::OpenTypeCollection,
+            DWRITE_FONT_FILE_TYPE_TYPE1_PFM => FontFileType::Type1Pfm,
+            DWRITE_FONT_FILE_TYPE_TYPE1_PFB => FontFileType::Type1Pfb,
+            DWRITE_FONT_FILE_TYPE_VECTOR => FontFileType::Vector,
+            DWRITE_FONT_FILE_TYPE_BITMAP => FontFileType::Bitmap,
+            _ => FontFileType::Unknown,
+        }
+    }
+}
@@ -1,7 +1,10 @@
 #![allow(non_snake_case, non_upper_case_globals)]
 
 use std::collections::HashMap;
+use std::fs::File;
+use std::io;
 use std::marker::Send;
+use std::os::windows::fs::{FileExt, MetadataExt};
 use std::sync::atomic::AtomicUsize;
 use std::sync::{atomic, Arc, Mutex};
 use std::{mem, ptr};
@@ -10,7 +13,7 @@ use winapi::shared::basetsd::{UINT32, UINT64};
 use winapi::shared::guiddef::REFIID;
 use winapi::shared::minwindef::ULONG;
 use winapi::shared::winerror::{E_FAIL, E_INVALIDARG, E_NOTIMPL, S_OK};
-use winapi::um::dwrite::IDWriteFontFile;
+use winapi::um::dwrite::{IDWriteFactory, IDWriteFontFile};
 use winapi::um::dwrite::{IDWriteFontFileLoader, IDWriteFontFileLoaderVtbl};
 use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
 use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
@@ -19,6 +22,7 @@ use wio::com::ComPtr;
 
 use super::DWriteFactory;
 use crate::com_helpers::*;
+use crate::dwrite_ext::{IDWriteFactory5, IDWriteInMemoryFontFileLoader};
 
 struct FontFileLoader;
 
@@ -80,7 +84,7 @@ unsafe impl Sync for FontFileLoader {}
 struct FontFileStream {
     refcount: atomic::AtomicUsize,
     key: usize,
-    data: Arc<Vec<u8>>,
+    data: Arc<dyn AsRef<[u8]> + Sync + Send>,
 }
 
 const FontFileStreamVtbl: &'static IDWriteFontFileStreamVtbl = &IDWriteFontFileStreamVtbl {
@@ -95,11 +99,11 @@ const FontFileStreamVtbl: &'static IDWriteFontFileStreamVtbl = &IDWriteFontFileS
         ) -> HRESULT {
             let this = FontFileStream::from_interface(This);
             *fragmentContext = ptr::null_mut();
-            if (fileOffset + fragmentSize) as usize > this.data.len() {
+            if (fileOffset + fragmentSize) as usize > this.bytes().len() {
                 return E_INVALIDARG;
             }
             let index = fileOffset as usize;
-            *fragmentStart = this.data[index..].as_ptr() as *const c_void;
+            *fragmentStart = this.bytes()[index..].as_ptr() as *const c_void;
             S_OK
         }
         ReadFileFragment
@@ -118,7 +122,7 @@ const FontFileStreamVtbl: &'static IDWriteFontFileStreamVtbl = &IDWriteFontFileS
             fileSize: *mut UINT64,
         ) -> HRESULT {
             let this = FontFileStream::from_interface(This);
-            *fileSize = this.data.len() as UINT64;
+            *fileSize = this.bytes().len() as UINT64;
             S_OK
         }
         GetFileSize
@@ -135,13 +139,17 @@ const FontFileStreamVtbl: &'static IDWriteFontFileStreamVtbl = &IDWriteFontFileS
 };
 
 impl FontFileStream {
-    pub fn new(key: usize, data: Arc<Vec<u8>>) -> FontFileStream {
+    pub fn new(key: usize, data: Arc<dyn AsRef<[u8]> + Sync + Send>) -> FontFileStream {
         FontFileStream {
             refcount: AtomicUsize::new(1),
             key,
             data,
         }
     }
+
+    fn bytes(&self) -> &[u8] {
+        (*self.data).as_ref()
+    }
 }
 
 impl Drop for FontFileStream {
@@ -164,11 +172,127 @@ impl Com<IUnknown> for FontFileStream {
     }
 }
 
+// A stream that reads fragments out of an open file on demand, rather than
+// keeping the whole font in memory.
+struct FileFontFileStream {
+    refcount: atomic::AtomicUsize,
+    key: usize,
+    file: File,
+    file_size: u64,
+    last_write_time: u64,
+}
+
+const FileFontFileStreamVtbl: &'static IDWriteFontFileStreamVtbl = &IDWriteFontFileStreamVtbl {
+    parent: implement_iunknown!(IDWriteFontFileStream, FileFontFileStream),
+    ReadFileFragment: {
+        unsafe extern "system" fn ReadFileFragment(
+            This: *mut IDWriteFontFileStream,
+            fragmentStart: *mut *const c_void,
+            fileOffset: UINT64,
+            fragmentSize: UINT64,
+            fragmentContext: *mut *mut c_void,
+        ) -> HRESULT {
+            let this = FileFontFileStream::from_interface(This);
+            *fragmentStart = ptr::null();
+            *fragmentContext = ptr::null_mut();
+            match fileOffset.checked_add(fragmentSize) {
+                Some(end) if end <= this.file_size => {}
+                _ => return E_FAIL,
+            }
+
+            // The fragment has to stay valid until ReleaseFileFragment, so it
+            // owns its own buffer, which is handed back to us as the context.
+            let mut fragment = vec![0u8; fragmentSize as usize].into_boxed_slice();
+            let mut read = 0;
+            while read < fragment.len() {
+                match this
+                    .file
+                    .seek_read(&mut fragment[read..], fileOffset + read as u64)
+                {
+                    Ok(0) | Err(_) => return E_FAIL,
+                    Ok(n) => read += n,
+                }
+            }
+            *fragmentStart = fragment.as_ptr() as *const c_void;
+            *fragmentContext = Box::into_raw(Box::new(fragment)) as *mut c_void;
+            S_OK
+        }
+        ReadFileFragment
+    },
+    ReleaseFileFragment: {
+        unsafe extern "system" fn ReleaseFileFragment(
+            _This: *mut IDWriteFontFileStream,
+            fragmentContext: *mut c_void,
+        ) {
+            if !fragmentContext.is_null() {
+                drop(Box::from_raw(fragmentContext as *mut Box<[u8]>));
+            }
+        }
+        ReleaseFileFragment
+    },
+    GetFileSize: {
+        unsafe extern "system" fn GetFileSize(
+            This: *mut IDWriteFontFileStream,
+            fileSize: *mut UINT64,
+        ) -> HRESULT {
+            let this = FileFontFileStream::from_interface(This);
+            *fileSize = this.file_size;
+            S_OK
+        }
+        GetFileSize
+    },
+    GetLastWriteTime: {
+        unsafe extern "system" fn GetLastWriteTime(
+            This: *mut IDWriteFontFileStream,
+            lastWriteTime: *mut UINT64,
+        ) -> HRESULT {
+            let this = FileFontFileStream::from_interface(This);
+            *lastWriteTime = this.last_write_time;
+            S_OK
+        }
+        GetLastWriteTime
+    },
+};
+
+impl FileFontFileStream {
+    pub fn new(key: usize, file: File) -> io::Result<FileFontFileStream> {
+        let metadata = file.metadata()?;
+        Ok(FileFontFileStream {
+            refcount: AtomicUsize::new(1),
+            key,
+            file,
+            file_size: metadata.len(),
+            // Already a FILETIME, which is what DirectWrite expects.
+            last_write_time: metadata.last_write_time(),
+        })
+    }
+}
+
+impl Drop for FileFontFileStream {
+    fn drop(&mut self) {
+        DataFontHelper::unregister_font_data(self.key);
+    }
+}
+
+impl Com<IDWriteFontFileStream> for FileFontFileStream {
+    type Vtbl = IDWriteFontFileStreamVtbl;
+    fn vtbl() -> &'static IDWriteFontFileStreamVtbl {
+        FileFontFileStreamVtbl
+    }
+}
+
+impl Com<IUnknown> for FileFontFileStream {
+    type Vtbl = IUnknownVtbl;
+    fn vtbl() -> &'static IUnknownVtbl {
+        &FileFontFileStreamVtbl.parent
+    }
+}
+
 struct FontFileStreamPtr(*mut IDWriteFontFileStream);
 
 unsafe impl Send for FontFileStreamPtr {}
 
-static mut FONT_FILE_KEY: atomic::AtomicUsize = AtomicUsize::new(0);
+static FONT_FILE_KEY: atomic::AtomicUsize = AtomicUsize::new(0);
 
 #[derive(Clone)]
 struct FontFileLoaderWrapper(ComPtr<IDWriteFontFileLoader>);
@@ -176,6 +300,28 @@ struct FontFileLoaderWrapper(ComPtr<IDWriteFontFileLoader>);
 unsafe impl Send for FontFileLoaderWrapper {}
 unsafe impl Sync for FontFileLoaderWrapper {}
 
+// The "owner object" handed to the in-memory loader.  DirectWrite holds a
+// reference to it for as long as it needs the data, and the data is freed
+// when the last reference goes away.
+struct FontDataOwner {
+    refcount: atomic::AtomicUsize,
+    _data: Arc<dyn AsRef<[u8]> + Sync + Send>,
+}
+
+const FontDataOwnerVtbl: &'static IUnknownVtbl = &implement_iunknown!(IUnknown, FontDataOwner);
+
+impl Com<IUnknown> for FontDataOwner {
+    type Vtbl = IUnknownVtbl;
+    fn vtbl() -> &'static IUnknownVtbl {
+        FontDataOwnerVtbl
+    }
+}
+
+struct InMemoryFontFileLoaderWrapper(ComPtr<IDWriteInMemoryFontFileLoader>);
+
+unsafe impl Send for InMemoryFontFileLoaderWrapper {}
+unsafe impl Sync for InMemoryFontFileLoaderWrapper {}
+
 lazy_static! {
     static ref FONT_FILE_STREAM_MAP: Mutex<HashMap<usize, FontFileStreamPtr>> =
         { Mutex::new(HashMap::new()) };
@@ -188,13 +334,45 @@ lazy_static! {
             Mutex::new(FontFileLoaderWrapper(ffl))
         }
     };
+    // Only available on Windows 10 Creators Update and later.
+    static ref IN_MEMORY_FONT_FILE_LOADER: Option<InMemoryFontFileLoaderWrapper> = {
+        unsafe {
+            let factory = ComPtr::from_raw(DWriteFactory());
+            let factory5: Option<ComPtr<IDWriteFactory5>> = factory.cast().ok();
+            mem::forget(factory);
+            match factory5 {
+                None => None,
+                Some(factory5) => {
+                    let mut loader: *mut IDWriteInMemoryFontFileLoader = ptr::null_mut();
+                    let hr = factory5.CreateInMemoryFontFileLoader(&mut loader);
+                    if hr != S_OK {
+                        return None;
+                    }
+                    let loader = ComPtr::from_raw(loader);
+                    let hr = factory5.RegisterFontFileLoader(loader.as_raw() as *mut _);
+                    assert!(hr == S_OK);
+                    Some(InMemoryFontFileLoaderWrapper(loader))
+                }
+            }
+        }
+    };
 }
 
 pub struct DataFontHelper;
 
 impl DataFontHelper {
+    // Register our loader with a factory other than the shared one, which
+    // gets it registered on first use.
+    pub fn register_loader(factory: *mut IDWriteFactory) -> HRESULT {
+        unsafe {
+            let loader = FONT_FILE_LOADER.lock().unwrap();
+            (*factory).RegisterFontFileLoader(loader.0.as_raw())
+        }
+    }
+
     pub fn register_font_data(
-        font_data: Arc<Vec<u8>>,
+        font_data: Arc<dyn AsRef<[u8]> + Sync + Send>,
+        factory: *mut IDWriteFactory,
     ) -> (
         ComPtr<IDWriteFontFile>,
         ComPtr<IDWriteFontFileStream>,
@@ -205,26 +383,90 @@ impl DataFontHelper {
             let font_file_stream_native = FontFileStream::new(key, font_data);
             let font_file_stream: ComPtr<IDWriteFontFileStream> =
                 ComPtr::from_raw(font_file_stream_native.into_interface());
+            let font_file = DataFontHelper::register_stream(&font_file_stream, key, factory);
 
-            {
-                let mut map = FONT_FILE_STREAM_MAP.lock().unwrap();
-                map.insert(key, FontFileStreamPtr(font_file_stream.as_raw()));
-            }
+            (font_file, font_file_stream, key)
+        }
+    }
+
+    pub fn register_font_file(
+        file: File,
+        factory: *mut IDWriteFactory,
+    ) -> io::Result<(
+        ComPtr<IDWriteFontFile>,
+        ComPtr<IDWriteFontFileStream>,
+        usize,
+    )> {
+        unsafe {
+            let key = FONT_FILE_KEY.fetch_add(1, atomic::Ordering::Relaxed);
+            let font_file_stream_native = FileFontFileStream::new(key, file)?;
+            let font_file_stream: ComPtr<IDWriteFontFileStream> =
+                ComPtr::from_raw(font_file_stream_native.into_interface());
+            let font_file = DataFontHelper::register_stream(&font_file_stream, key, factory);
+
+            Ok((font_file, font_file_stream, key))
+        }
+    }
+
+    unsafe fn register_stream(
+        font_file_stream: &ComPtr<IDWriteFontFileStream>,
+        key: usize,
+        factory: *mut IDWriteFactory,
+    ) -> ComPtr<IDWriteFontFile> {
+        {
+            let mut map = FONT_FILE_STREAM_MAP.lock().unwrap();
+            map.insert(key, FontFileStreamPtr(font_file_stream.as_raw()));
+        }
+
+        let mut font_file: *mut IDWriteFontFile = ptr::null_mut();
+        {
+            let loader = FONT_FILE_LOADER.lock().unwrap();
+            let hr = (*factory).CreateCustomFontFileReference(
+                mem::transmute(&key),
+                mem::size_of::<usize>() as UINT32,
+                loader.0.as_raw(),
+                &mut font_file,
+            );
+            assert!(hr == S_OK);
+        }
+        ComPtr::from_raw(font_file)
+    }
+
+    // Create a font file through the system in-memory loader, which reads
+    // straight out of `font_data` instead of going through our own stream.
+    // Returns `None` if that loader isn't available.
+    pub fn create_in_memory_font_file(
+        font_data: Arc<dyn AsRef<[u8]> + Sync + Send>,
+    ) -> Option<Result<ComPtr<IDWriteFontFile>, HRESULT>> {
+        let loader = match *IN_MEMORY_FONT_FILE_LOADER {
+            Some(ref loader) => loader,
+            None => return None,
+        };
+        unsafe {
+            let (data_ptr, data_len) = {
+                let bytes = (*font_data).as_ref();
+                (bytes.as_ptr(), bytes.len())
+            };
+            let owner = ComPtr::<IUnknown>::from_raw(
+                FontDataOwner {
+                    refcount: AtomicUsize::new(1),
+                    _data: font_data,
+                }
+                .into_interface(),
+            );
 
             let mut font_file: *mut IDWriteFontFile = ptr::null_mut();
-            {
-                let loader = FONT_FILE_LOADER.lock().unwrap();
-                let hr = (*DWriteFactory()).CreateCustomFontFileReference(
-                    mem::transmute(&key),
-                    mem::size_of::<usize>() as UINT32,
-                    loader.0.as_raw(),
-                    &mut font_file,
-                );
-                assert!(hr == S_OK);
+            let hr = loader.0.CreateInMemoryFontFileReference(
+                DWriteFactory(),
+                data_ptr as *const c_void,
+                data_len as UINT32,
+                owner.as_raw(),
+                &mut font_file,
+            );
+            if hr != S_OK {
+                return Some(Err(hr));
             }
-            let font_file = ComPtr::from_raw(font_file);
-
-            (font_file, font_file_stream, key)
+            Some(Ok(ComPtr::from_raw(font_file)))
         }
     }
 
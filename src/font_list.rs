@@ -0,0 +1,117 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::UnsafeCell;
+use std::iter::FusedIterator;
+use std::ptr;
+use winapi::um::dwrite::{IDWriteFont, IDWriteFontCollection, IDWriteFontList};
+use wio::com::ComPtr;
+
+use super::{Font, FontCollection};
+use crate::error::Error;
+
+/// An ordered list of fonts, such as the result of
+/// `FontFamily::matching_fonts`.
+pub struct FontList {
+    native: UnsafeCell<ComPtr<IDWriteFontList>>,
+}
+
+impl FontList {
+    pub fn take(native: ComPtr<IDWriteFontList>) -> FontList {
+        FontList {
+            native: UnsafeCell::new(native),
+        }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut IDWriteFontList {
+        (*self.native.get()).as_raw()
+    }
+
+    pub fn len(&self) -> usize {
+        unsafe { (*self.native.get()).GetFontCount() as usize }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Result<Font, Error> {
+        unsafe { get_font(&*self.native.get(), index as u32) }
+    }
+
+    /// Returns the collection that the fonts in this list belong to.
+    pub fn get_font_collection(&self) -> Result<FontCollection, Error> {
+        unsafe {
+            let mut collection: *mut IDWriteFontCollection = ptr::null_mut();
+            let hr = (*self.native.get()).GetFontCollection(&mut collection);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(FontCollection::take(ComPtr::from_raw(collection)))
+        }
+    }
+
+    /// Returns an iterator over the fonts in this list, yielding an error for
+    /// any font DirectWrite fails to return.
+    ///
+    /// The iterator holds its own reference to the list, so it stays valid if
+    /// the `FontList` is dropped.
+    pub fn iter(&self) -> FontListIterator {
+        unsafe {
+            FontListIterator {
+                list: (*self.native.get()).clone(),
+                curr: 0,
+                count: (*self.native.get()).GetFontCount(),
+            }
+        }
+    }
+}
+
+unsafe fn get_font(list: &ComPtr<IDWriteFontList>, index: u32) -> Result<Font, Error> {
+    let mut font: *mut IDWriteFont = ptr::null_mut();
+    let hr = list.GetFont(index, &mut font);
+    if hr != 0 {
+        return Err(hr.into());
+    }
+    Ok(Font::take(ComPtr::from_raw(font)))
+}
+
+pub struct FontListIterator {
+    list: ComPtr<IDWriteFontList>,
+    curr: u32,
+    count: u32,
+}
+
+impl Iterator for FontListIterator {
+    type Item = Result<Font, Error>;
+    fn next(&mut self) -> Option<Result<Font, Error>> {
+        if self.curr == self.count {
+            return None;
+        }
+
+        let font = unsafe { get_font(&self.list, self.curr) };
+        self.curr += 1;
+        Some(font)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.count - self.curr) as usize;
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for FontListIterator {
+    fn next_back(&mut self) -> Option<Result<Font, Error>> {
+        if self.curr == self.count {
+            return None;
+        }
+
+        self.count -= 1;
+        Some(unsafe { get_font(&self.list, self.count) })
+    }
+}
+
+impl ExactSizeIterator for FontListIterator {}
+
+impl FusedIterator for FontListIterator {}
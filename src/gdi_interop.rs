@@ -3,24 +3,42 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use std::cell::UnsafeCell;
+use std::mem;
 use std::ptr;
+use winapi::shared::minwindef::{BOOL, FALSE};
+use winapi::shared::windef::HDC;
 use winapi::um::dwrite::IDWriteBitmapRenderTarget;
 use winapi::um::dwrite::IDWriteGdiInterop;
+use winapi::um::dwrite::{IDWriteFont, IDWriteFontFace};
+use winapi::um::wingdi::LOGFONTW;
 use wio::com::ComPtr;
 
-use super::{BitmapRenderTarget, DWriteFactory};
+use super::{BitmapRenderTarget, Font, FontFace};
+use crate::error::Error;
+use crate::factory::{factory_or_shared, Factory};
 
 pub struct GdiInterop {
     native: UnsafeCell<ComPtr<IDWriteGdiInterop>>,
 }
 
 impl GdiInterop {
+    #[deprecated(note = "use `try_create`, which returns an error instead of panicking")]
     pub fn create() -> GdiInterop {
+        GdiInterop::try_create().expect("error creating GDI interop")
+    }
+
+    pub fn try_create() -> Result<GdiInterop, Error> {
+        GdiInterop::try_create_with_factory(None)
+    }
+
+    pub fn try_create_with_factory(factory: Option<&Factory>) -> Result<GdiInterop, Error> {
         unsafe {
             let mut native: *mut IDWriteGdiInterop = ptr::null_mut();
-            let hr = (*DWriteFactory()).GetGdiInterop(&mut native);
-            assert!(hr == 0);
-            GdiInterop::take(ComPtr::from_raw(native))
+            let hr = (*factory_or_shared(factory)).GetGdiInterop(&mut native);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(GdiInterop::take(ComPtr::from_raw(native)))
         }
     }
 
@@ -30,17 +48,83 @@ impl GdiInterop {
         }
     }
 
-    pub fn create_bitmap_render_target(&self, width: u32, height: u32) -> BitmapRenderTarget {
+    /// Creates a bitmap render target compatible with `reference_dc`, or with
+    /// the screen if `None`.
+    pub fn create_bitmap_render_target(
+        &self,
+        reference_dc: Option<HDC>,
+        width: u32,
+        height: u32,
+    ) -> Result<BitmapRenderTarget, Error> {
         unsafe {
             let mut native: *mut IDWriteBitmapRenderTarget = ptr::null_mut();
             let hr = (*self.native.get()).CreateBitmapRenderTarget(
-                ptr::null_mut(),
+                reference_dc.unwrap_or(ptr::null_mut()),
                 width,
                 height,
                 &mut native,
             );
-            assert!(hr == 0);
-            BitmapRenderTarget::take(ComPtr::from_raw(native))
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(BitmapRenderTarget::take(ComPtr::from_raw(native)))
+        }
+    }
+
+    /// Creates a font face matching the font currently selected into `hdc`.
+    ///
+    /// # Safety
+    ///
+    /// `hdc` must be a valid device context.
+    pub unsafe fn font_face_from_hdc(&self, hdc: HDC) -> Result<FontFace, Error> {
+        let mut native: *mut IDWriteFontFace = ptr::null_mut();
+        let hr = (*self.native.get()).CreateFontFaceFromHdc(hdc, &mut native);
+        if hr != 0 {
+            return Err(hr.into());
+        }
+        Ok(FontFace::take(ComPtr::from_raw(native)))
+    }
+
+    /// Converts `font` to a GDI `LOGFONTW`. The returned flag is true if the
+    /// font is part of the system font collection.
+    pub fn logfont_from_font(&self, font: &Font) -> Result<(LOGFONTW, bool), Error> {
+        unsafe {
+            let mut logfont: LOGFONTW = mem::zeroed();
+            let mut is_system_font: BOOL = FALSE;
+            let hr = (*self.native.get()).ConvertFontToLOGFONT(
+                font.as_ptr(),
+                &mut logfont,
+                &mut is_system_font,
+            );
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok((logfont, is_system_font != FALSE))
+        }
+    }
+
+    pub fn logfont_from_font_face(&self, font_face: &FontFace) -> Result<LOGFONTW, Error> {
+        unsafe {
+            let mut logfont: LOGFONTW = mem::zeroed();
+            let hr =
+                (*self.native.get()).ConvertFontFaceToLOGFONT(font_face.as_ptr(), &mut logfont);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(logfont)
+        }
+    }
+
+    /// Finds the system font matching `logfont`. Returns `Error::NoFont` if
+    /// no installed font has the requested face name.
+    pub fn font_from_logfont(&self, logfont: &LOGFONTW) -> Result<Font, Error> {
+        unsafe {
+            let mut native: *mut IDWriteFont = ptr::null_mut();
+            let hr = (*self.native.get()).CreateFontFromLOGFONT(logfont, &mut native);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(Font::take(ComPtr::from_raw(native)))
         }
     }
 }
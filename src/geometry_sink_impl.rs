@@ -13,7 +13,7 @@ use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
 use winapi::um::winnt::HRESULT;
 
 use crate::com_helpers::Com;
-use crate::outline_builder::OutlineBuilder;
+use crate::outline_builder::{OutlineError, TryOutlineBuilder};
 
 static GEOMETRY_SINK_VTBL: ID2D1SimplifiedGeometrySinkVtbl = ID2D1SimplifiedGeometrySinkVtbl {
     parent: implement_iunknown!(static ID2D1SimplifiedGeometrySink, GeometrySinkImpl),
@@ -26,14 +26,22 @@ static GEOMETRY_SINK_VTBL: ID2D1SimplifiedGeometrySinkVtbl = ID2D1SimplifiedGeom
     SetSegmentFlags: GeometrySinkImpl_SetSegmentFlags,
 };
 
+// Only lives for the duration of a `GetGlyphRunOutline` call, see
+// `FontFace::try_get_glyph_run_outline`.
 #[repr(C)]
-pub struct GeometrySinkImpl {
+pub struct GeometrySinkImpl<'a> {
     // NB: This must be the first field.
     _refcount: AtomicUsize,
-    outline_builder: Box<dyn OutlineBuilder>,
+    outline_builder: &'a mut dyn TryOutlineBuilder,
+    // The end of the last segment, where the next Bézier starts.
+    current: D2D1_POINT_2F,
+    // The first error the builder returned. DirectWrite can't be told to
+    // stop, so once this is set the remaining callbacks are ignored, and
+    // `try_get_glyph_run_outline` returns the error afterwards.
+    error: Option<OutlineError>,
 }
 
-impl Com<ID2D1SimplifiedGeometrySink> for GeometrySinkImpl {
+impl Com<ID2D1SimplifiedGeometrySink> for GeometrySinkImpl<'_> {
     type Vtbl = ID2D1SimplifiedGeometrySinkVtbl;
     #[inline]
     fn vtbl() -> &'static ID2D1SimplifiedGeometrySinkVtbl {
@@ -41,7 +49,7 @@ impl Com<ID2D1SimplifiedGeometrySink> for GeometrySinkImpl {
     }
 }
 
-impl Com<IUnknown> for GeometrySinkImpl {
+impl Com<IUnknown> for GeometrySinkImpl<'_> {
     type Vtbl = IUnknownVtbl;
     #[inline]
     fn vtbl() -> &'static IUnknownVtbl {
@@ -49,11 +57,26 @@ impl Com<IUnknown> for GeometrySinkImpl {
     }
 }
 
-impl GeometrySinkImpl {
-    pub fn new(outline_builder: Box<dyn OutlineBuilder>) -> GeometrySinkImpl {
+impl<'a> GeometrySinkImpl<'a> {
+    pub fn new(outline_builder: &'a mut dyn TryOutlineBuilder) -> GeometrySinkImpl<'a> {
         GeometrySinkImpl {
             _refcount: AtomicUsize::new(1),
             outline_builder,
+            current: D2D1_POINT_2F { x: 0.0, y: 0.0 },
+            error: None,
+        }
+    }
+
+    pub fn take_error(&mut self) -> Option<OutlineError> {
+        self.error.take()
+    }
+
+    fn call<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut dyn TryOutlineBuilder) -> Result<(), OutlineError>,
+    {
+        if self.error.is_none() {
+            self.error = f(&mut *self.outline_builder).err();
         }
     }
 }
@@ -64,9 +87,8 @@ unsafe extern "system" fn GeometrySinkImpl_BeginFigure(
     _: D2D1_FIGURE_BEGIN,
 ) {
     let this = GeometrySinkImpl::from_interface(this);
-    (*this)
-        .outline_builder
-        .move_to(start_point.x, start_point.y)
+    this.current = start_point;
+    this.call(|builder| builder.try_move_to(start_point.x, start_point.y))
 }
 
 unsafe extern "system" fn GeometrySinkImpl_EndFigure(
@@ -75,7 +97,7 @@ unsafe extern "system" fn GeometrySinkImpl_EndFigure(
 ) {
     let this = GeometrySinkImpl::from_interface(this);
     if figure_end == D2D1_FIGURE_END_CLOSED {
-        (*this).outline_builder.close()
+        this.call(|builder| builder.try_close())
     }
 }
 
@@ -87,7 +109,8 @@ unsafe extern "system" fn GeometrySinkImpl_AddLines(
     let this = GeometrySinkImpl::from_interface(this);
     let points = slice::from_raw_parts(points, points_count as usize);
     for point in points {
-        (*this).outline_builder.line_to(point.x, point.y)
+        this.current = *point;
+        this.call(|builder| builder.try_line_to(point.x, point.y))
     }
 }
 
@@ -99,15 +122,55 @@ unsafe extern "system" fn GeometrySinkImpl_AddBeziers(
     let this = GeometrySinkImpl::from_interface(this);
     let beziers = slice::from_raw_parts(beziers, beziers_count as usize);
     for bezier in beziers {
-        (*this).outline_builder.curve_to(
-            bezier.point1.x,
-            bezier.point1.y,
-            bezier.point2.x,
-            bezier.point2.y,
-            bezier.point3.x,
-            bezier.point3.y,
-        )
+        let start = mem::replace(&mut this.current, bezier.point3);
+        let quadratic = if this.outline_builder.supports_quads() {
+            quadratic_control_point(start, bezier)
+        } else {
+            None
+        };
+        match quadratic {
+            Some(cp) => this.call(|builder| {
+                builder.try_quad_to(
+                    start.x,
+                    start.y,
+                    cp.x,
+                    cp.y,
+                    bezier.point3.x,
+                    bezier.point3.y,
+                )
+            }),
+            None => this.call(|builder| {
+                builder.try_curve_to(
+                    bezier.point1.x,
+                    bezier.point1.y,
+                    bezier.point2.x,
+                    bezier.point2.y,
+                    bezier.point3.x,
+                    bezier.point3.y,
+                )
+            }),
+        }
+    }
+}
+
+// DirectWrite raises quadratic curves to cubic ones, putting the cubic's
+// control points two thirds of the way from each end to the quadratic's. If
+// both control points lead back to the same point, that's the quadratic's.
+fn quadratic_control_point(
+    start: D2D1_POINT_2F,
+    bezier: &D2D1_BEZIER_SEGMENT,
+) -> Option<D2D1_POINT_2F> {
+    const EPSILON: f32 = 1e-3;
+    let (p1, p2, end) = (bezier.point1, bezier.point2, bezier.point3);
+    let from_start = ((3.0 * p1.x - start.x) / 2.0, (3.0 * p1.y - start.y) / 2.0);
+    let from_end = ((3.0 * p2.x - end.x) / 2.0, (3.0 * p2.y - end.y) / 2.0);
+    if (from_start.0 - from_end.0).abs() > EPSILON || (from_start.1 - from_end.1).abs() > EPSILON {
+        return None;
     }
+    Some(D2D1_POINT_2F {
+        x: (from_start.0 + from_end.0) / 2.0,
+        y: (from_start.1 + from_end.1) / 2.0,
+    })
 }
 
 unsafe extern "system" fn GeometrySinkImpl_Close(_: *mut ID2D1SimplifiedGeometrySink) -> HRESULT {
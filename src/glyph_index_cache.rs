@@ -0,0 +1,108 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::collections::HashMap;
+use winapi::um::dwrite::DWRITE_FONT_SIMULATIONS;
+
+use crate::font_face::FontFace;
+use crate::font_file::FontFileLoaderId;
+
+/// Memoizes code point to glyph index lookups, per font face.
+///
+/// Faces are told apart by what they are a face of: their files, their index
+/// in those files and their simulations. Different face objects for the same
+/// font therefore share entries. The cache keeps every face object it has
+/// seen alive, and it never evicts anything, so it grows with the number of
+/// distinct faces and characters looked up. Call `clear` or `remove_face` to
+/// bound it.
+#[derive(Default)]
+pub struct GlyphIndexCache {
+    // The identity of each face object seen so far, by address, so that it's
+    // only worked out once per object. Holding a reference keeps the address
+    // from being reused by another face while it's a key in the map.
+    face_keys: HashMap<usize, (FontFace, FaceKey)>,
+    glyphs: HashMap<FaceKey, HashMap<char, u16>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct FaceKey {
+    // The loader and reference key of each file.
+    files: Vec<(FontFileLoaderId, Vec<u8>)>,
+    index: u32,
+    simulations: DWRITE_FONT_SIMULATIONS,
+}
+
+impl FaceKey {
+    fn new(face: &FontFace) -> FaceKey {
+        FaceKey {
+            files: face
+                .get_files()
+                .iter()
+                .map(|file| (file.loader_identity(), file.reference_key()))
+                .collect(),
+            index: face.get_index(),
+            simulations: face.get_simulations(),
+        }
+    }
+}
+
+impl GlyphIndexCache {
+    pub fn new() -> GlyphIndexCache {
+        GlyphIndexCache::default()
+    }
+
+    /// Returns the glyph index of `c` in `face`, asking DirectWrite only the
+    /// first time a character is looked up for that face. As with
+    /// `FontFace::get_glyph_indices`, 0 means the face doesn't cover `c`.
+    pub fn get_glyph(&mut self, face: &FontFace, c: char) -> u16 {
+        let (_, key) = self
+            .face_keys
+            .entry(face_address(face))
+            .or_insert_with(|| (face.clone(), FaceKey::new(face)));
+        if let Some(glyphs) = self.glyphs.get_mut(key) {
+            return *glyphs
+                .entry(c)
+                .or_insert_with(|| face.get_glyph_indices(&[c as u32])[0]);
+        }
+        let glyph = face.get_glyph_indices(&[c as u32])[0];
+        let mut glyphs = HashMap::new();
+        glyphs.insert(c, glyph);
+        self.glyphs.insert(key.clone(), glyphs);
+        glyph
+    }
+
+    /// Returns the glyph indices for all characters of `text` in `face`.
+    pub fn get_glyphs(&mut self, face: &FontFace, text: &str) -> Vec<u16> {
+        text.chars().map(|c| self.get_glyph(face, c)).collect()
+    }
+
+    /// Returns the total number of cached characters, over all faces.
+    pub fn len(&self) -> usize {
+        self.glyphs.values().map(|glyphs| glyphs.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops the entries for `face`, and the cache's references to every face
+    /// object for the same font.
+    pub fn remove_face(&mut self, face: &FontFace) {
+        let key = match self.face_keys.get(&face_address(face)) {
+            Some((_, key)) => key.clone(),
+            None => FaceKey::new(face),
+        };
+        self.face_keys.retain(|_, (_, other_key)| *other_key != key);
+        self.glyphs.remove(&key);
+    }
+
+    pub fn clear(&mut self) {
+        self.face_keys.clear();
+        self.glyphs.clear();
+    }
+}
+
+fn face_address(face: &FontFace) -> usize {
+    unsafe { face.as_ptr() as usize }
+}
@@ -6,21 +6,92 @@ use std::cell::UnsafeCell;
 use std::mem;
 use std::ptr;
 use winapi::shared::windef::RECT;
+use winapi::shared::winerror::{ERROR_INSUFFICIENT_BUFFER, E_NOINTERFACE, HRESULT_FROM_WIN32};
 use winapi::um::dcommon::DWRITE_MEASURING_MODE;
-use winapi::um::dwrite::DWRITE_TEXTURE_CLEARTYPE_3x1;
 use winapi::um::dwrite::IDWriteGlyphRunAnalysis;
-use winapi::um::dwrite::{DWRITE_TEXTURE_ALIASED_1x1, DWRITE_GLYPH_RUN, DWRITE_TEXTURE_TYPE};
+use winapi::um::dwrite::{DWRITE_TEXTURE_ALIASED_1x1, DWRITE_TEXTURE_CLEARTYPE_3x1};
+use winapi::um::dwrite::{DWRITE_GLYPH_RUN, DWRITE_TEXTURE_TYPE};
 use winapi::um::dwrite::{DWRITE_MATRIX, DWRITE_RENDERING_MODE};
-use winapi::um::winnt::HRESULT;
+use winapi::um::dwrite_1::DWRITE_TEXT_ANTIALIAS_MODE;
+use winapi::um::dwrite_1::{
+    DWRITE_TEXT_ANTIALIAS_MODE_CLEARTYPE, DWRITE_TEXT_ANTIALIAS_MODE_GRAYSCALE,
+};
+use winapi::um::dwrite_2::{IDWriteFactory2, DWRITE_GRID_FIT_MODE, DWRITE_GRID_FIT_MODE_DEFAULT};
+use winapi::um::dwrite_2::{DWRITE_GRID_FIT_MODE_DISABLED, DWRITE_GRID_FIT_MODE_ENABLED};
 use wio::com::ComPtr;
 
-use super::DWriteFactory;
+use super::{FontFace, GlyphOffset, GlyphRun, RenderingParams};
+use crate::error::Error;
+use crate::factory::{factory_or_shared, Factory};
+
+// mirrors DWRITE_TEXTURE_TYPE
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureType {
+    /// One byte of coverage per pixel.
+    Aliased1x1 = DWRITE_TEXTURE_ALIASED_1x1,
+    /// Three bytes of coverage per pixel, one for each of R, G and B.
+    ClearType3x1 = DWRITE_TEXTURE_CLEARTYPE_3x1,
+}
+
+impl TextureType {
+    /// The number of bytes each pixel takes up in the alpha texture.
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            TextureType::Aliased1x1 => 1,
+            TextureType::ClearType3x1 => 3,
+        }
+    }
+}
+
+// mirrors DWRITE_GRID_FIT_MODE
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GridFitMode {
+    /// Let DirectWrite decide based on the rendering mode and font.
+    Default = DWRITE_GRID_FIT_MODE_DEFAULT,
+    Disabled = DWRITE_GRID_FIT_MODE_DISABLED,
+    Enabled = DWRITE_GRID_FIT_MODE_ENABLED,
+}
+
+impl GridFitMode {
+    pub(crate) fn from_raw(mode: DWRITE_GRID_FIT_MODE) -> GridFitMode {
+        match mode {
+            DWRITE_GRID_FIT_MODE_DISABLED => GridFitMode::Disabled,
+            DWRITE_GRID_FIT_MODE_ENABLED => GridFitMode::Enabled,
+            _ => GridFitMode::Default,
+        }
+    }
+}
+
+// mirrors DWRITE_TEXT_ANTIALIAS_MODE
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextAntialiasMode {
+    ClearType = DWRITE_TEXT_ANTIALIAS_MODE_CLEARTYPE,
+    Grayscale = DWRITE_TEXT_ANTIALIAS_MODE_GRAYSCALE,
+}
+
+impl TextAntialiasMode {
+    pub(crate) fn from_raw(mode: DWRITE_TEXT_ANTIALIAS_MODE) -> TextAntialiasMode {
+        match mode {
+            DWRITE_TEXT_ANTIALIAS_MODE_GRAYSCALE => TextAntialiasMode::Grayscale,
+            _ => TextAntialiasMode::ClearType,
+        }
+    }
+}
 
 pub struct GlyphRunAnalysis {
     native: UnsafeCell<ComPtr<IDWriteGlyphRunAnalysis>>,
 }
 
 impl GlyphRunAnalysis {
+    /// Analyzes a glyph run for rasterization.
+    ///
+    /// `transform`, if given, maps DIPs to the output coordinate space, and
+    /// `get_alpha_texture_bounds`/`create_alpha_texture` work in that
+    /// transformed space. A transform that DirectWrite can't use (such as a
+    /// non-invertible one) is reported as an error.
     pub fn create(
         glyph_run: &DWRITE_GLYPH_RUN,
         pixels_per_dip: f32,
@@ -29,10 +100,32 @@ impl GlyphRunAnalysis {
         measuring_mode: DWRITE_MEASURING_MODE,
         baseline_x: f32,
         baseline_y: f32,
-    ) -> Result<GlyphRunAnalysis, HRESULT> {
+    ) -> Result<GlyphRunAnalysis, Error> {
+        GlyphRunAnalysis::create_with_factory(
+            glyph_run,
+            pixels_per_dip,
+            transform,
+            rendering_mode,
+            measuring_mode,
+            baseline_x,
+            baseline_y,
+            None,
+        )
+    }
+
+    pub fn create_with_factory(
+        glyph_run: &DWRITE_GLYPH_RUN,
+        pixels_per_dip: f32,
+        transform: Option<DWRITE_MATRIX>,
+        rendering_mode: DWRITE_RENDERING_MODE,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        baseline_x: f32,
+        baseline_y: f32,
+        factory: Option<&Factory>,
+    ) -> Result<GlyphRunAnalysis, Error> {
         unsafe {
             let mut native: *mut IDWriteGlyphRunAnalysis = ptr::null_mut();
-            let hr = (*DWriteFactory()).CreateGlyphRunAnalysis(
+            let hr = (*factory_or_shared(factory)).CreateGlyphRunAnalysis(
                 glyph_run as *const DWRITE_GLYPH_RUN,
                 pixels_per_dip,
                 transform
@@ -46,61 +139,282 @@ impl GlyphRunAnalysis {
                 &mut native,
             );
             if hr != 0 {
-                Err(hr)
+                Err(hr.into())
+            } else {
+                Ok(GlyphRunAnalysis::take(ComPtr::from_raw(native)))
+            }
+        }
+    }
+
+    /// Like `create`, but takes an owned `GlyphRun`.
+    pub fn create_from_run(
+        glyph_run: &GlyphRun,
+        pixels_per_dip: f32,
+        transform: Option<DWRITE_MATRIX>,
+        rendering_mode: DWRITE_RENDERING_MODE,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        baseline_x: f32,
+        baseline_y: f32,
+    ) -> Result<GlyphRunAnalysis, Error> {
+        GlyphRunAnalysis::create(
+            &glyph_run.as_raw(),
+            pixels_per_dip,
+            transform,
+            rendering_mode,
+            measuring_mode,
+            baseline_x,
+            baseline_y,
+        )
+    }
+
+    /// Like `create`, but builds the glyph run from its parts, at one pixel
+    /// per DIP and with no transform.
+    ///
+    /// `advances` and `offsets` may be empty to use the font's advances and
+    /// no offsets; otherwise they must have one entry per glyph, or this fails
+    /// with `Error::InvalidArg`.
+    pub fn from_glyphs(
+        font_face: &FontFace,
+        em_size: f32,
+        glyphs: &[u16],
+        advances: &[f32],
+        offsets: &[GlyphOffset],
+        is_sideways: bool,
+        bidi_level: u32,
+        rendering_mode: DWRITE_RENDERING_MODE,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        baseline_x: f32,
+        baseline_y: f32,
+    ) -> Result<GlyphRunAnalysis, Error> {
+        let glyph_run = GlyphRun::from_parts(
+            font_face,
+            em_size,
+            glyphs.to_vec(),
+            advances.to_vec(),
+            offsets.to_vec(),
+        )?
+        .sideways(is_sideways)
+        .bidi_level(bidi_level);
+        GlyphRunAnalysis::create_from_run(
+            &glyph_run,
+            1.0,
+            None,
+            rendering_mode,
+            measuring_mode,
+            baseline_x,
+            baseline_y,
+        )
+    }
+
+    /// Like `create`, but also controls grid fitting and the antialiasing
+    /// mode, as Direct2D does. There is no `pixels_per_dip` argument; scale
+    /// `transform` instead.
+    ///
+    /// Fails with `E_NOINTERFACE` if `IDWriteFactory2` isn't available.
+    pub fn create2(
+        glyph_run: &DWRITE_GLYPH_RUN,
+        transform: Option<&DWRITE_MATRIX>,
+        rendering_mode: DWRITE_RENDERING_MODE,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        grid_fit_mode: GridFitMode,
+        antialias_mode: TextAntialiasMode,
+        baseline_x: f32,
+        baseline_y: f32,
+    ) -> Result<GlyphRunAnalysis, Error> {
+        GlyphRunAnalysis::create2_with_factory(
+            glyph_run,
+            transform,
+            rendering_mode,
+            measuring_mode,
+            grid_fit_mode,
+            antialias_mode,
+            baseline_x,
+            baseline_y,
+            None,
+        )
+    }
+
+    pub fn create2_with_factory(
+        glyph_run: &DWRITE_GLYPH_RUN,
+        transform: Option<&DWRITE_MATRIX>,
+        rendering_mode: DWRITE_RENDERING_MODE,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        grid_fit_mode: GridFitMode,
+        antialias_mode: TextAntialiasMode,
+        baseline_x: f32,
+        baseline_y: f32,
+        factory: Option<&Factory>,
+    ) -> Result<GlyphRunAnalysis, Error> {
+        unsafe {
+            let factory = ComPtr::from_raw(factory_or_shared(factory));
+            let factory2: Option<ComPtr<IDWriteFactory2>> = factory.cast().ok();
+            mem::forget(factory);
+            let factory2 = factory2.ok_or(E_NOINTERFACE)?;
+
+            let mut native: *mut IDWriteGlyphRunAnalysis = ptr::null_mut();
+            let hr = factory2.CreateGlyphRunAnalysis(
+                glyph_run as *const DWRITE_GLYPH_RUN,
+                transform.map_or(ptr::null(), |x| x as *const _),
+                rendering_mode,
+                measuring_mode,
+                grid_fit_mode as DWRITE_GRID_FIT_MODE,
+                antialias_mode as DWRITE_TEXT_ANTIALIAS_MODE,
+                baseline_x,
+                baseline_y,
+                &mut native,
+            );
+            if hr != 0 {
+                Err(hr.into())
             } else {
                 Ok(GlyphRunAnalysis::take(ComPtr::from_raw(native)))
             }
         }
     }
 
+    /// Like `create2`, but takes an owned `GlyphRun`.
+    pub fn create2_from_run(
+        glyph_run: &GlyphRun,
+        transform: Option<&DWRITE_MATRIX>,
+        rendering_mode: DWRITE_RENDERING_MODE,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        grid_fit_mode: GridFitMode,
+        antialias_mode: TextAntialiasMode,
+        baseline_x: f32,
+        baseline_y: f32,
+    ) -> Result<GlyphRunAnalysis, Error> {
+        GlyphRunAnalysis::create2(
+            &glyph_run.as_raw(),
+            transform,
+            rendering_mode,
+            measuring_mode,
+            grid_fit_mode,
+            antialias_mode,
+            baseline_x,
+            baseline_y,
+        )
+    }
+
     pub fn take(native: ComPtr<IDWriteGlyphRunAnalysis>) -> GlyphRunAnalysis {
         GlyphRunAnalysis {
             native: UnsafeCell::new(native),
         }
     }
 
-    pub fn get_alpha_texture_bounds(
-        &self,
-        texture_type: DWRITE_TEXTURE_TYPE,
-    ) -> Result<RECT, HRESULT> {
+    /// Returns the bounds of the alpha texture of the given type. The
+    /// bounds are empty if the run has no ink for that texture type, either
+    /// because its glyphs are blank or because the rendering mode doesn't
+    /// produce that type.
+    pub fn get_alpha_texture_bounds(&self, texture_type: TextureType) -> Result<RECT, Error> {
         unsafe {
             let mut rect: RECT = mem::zeroed();
             rect.left = 1234;
             rect.top = 1234;
-            let hr = (*self.native.get()).GetAlphaTextureBounds(texture_type, &mut rect);
+            let hr = (*self.native.get())
+                .GetAlphaTextureBounds(texture_type as DWRITE_TEXTURE_TYPE, &mut rect);
             if hr != 0 {
-                Err(hr)
+                Err(hr.into())
             } else {
                 Ok(rect)
             }
         }
     }
 
+    /// Returns the bounds of the pixels the glyph run covers, without
+    /// rasterizing it.
+    ///
+    /// DirectWrite only reports bounds for the texture type that matches the
+    /// rendering mode the analysis was created with, so this tries the
+    /// aliased texture first and falls back to the ClearType one.
+    pub fn ink_bounds(&self) -> Result<RECT, Error> {
+        let rect = self.get_alpha_texture_bounds(TextureType::Aliased1x1)?;
+        if rect.left < rect.right && rect.top < rect.bottom {
+            return Ok(rect);
+        }
+        self.get_alpha_texture_bounds(TextureType::ClearType3x1)
+    }
+
+    /// Returns the `(gamma, enhanced_contrast, cleartype_level)` to use when
+    /// blending the alpha texture with the given rendering parameters.
+    ///
+    /// These are meant for a gamma-correct blend of the texture into a
+    /// premultiplied surface: apply the gamma and contrast adjustment to the
+    /// coverage values, and use the ClearType level to interpolate between
+    /// per-channel and grayscale coverage.
+    pub fn get_alpha_blend_params(
+        &self,
+        rendering_params: &RenderingParams,
+    ) -> Result<(f32, f32, f32), Error> {
+        unsafe {
+            let mut gamma = 0.0;
+            let mut enhanced_contrast = 0.0;
+            let mut cleartype_level = 0.0;
+            let hr = (*self.native.get()).GetAlphaBlendParams(
+                rendering_params.as_ptr(),
+                &mut gamma,
+                &mut enhanced_contrast,
+                &mut cleartype_level,
+            );
+            if hr != 0 {
+                Err(hr.into())
+            } else {
+                Ok((gamma, enhanced_contrast, cleartype_level))
+            }
+        }
+    }
+
+    /// Returns the number of bytes `create_alpha_texture_into` needs for a
+    /// texture of the given type covering `rect`.
+    pub fn required_len(rect: RECT, texture_type: TextureType) -> usize {
+        if rect.left >= rect.right || rect.top >= rect.bottom {
+            return 0;
+        }
+        let rect_pixels = (rect.right - rect.left) as usize * (rect.bottom - rect.top) as usize;
+        rect_pixels * texture_type.bytes_per_pixel()
+    }
+
+    /// Rasterizes the part of the run inside `rect` into a texture of the
+    /// given type, with `texture_type.bytes_per_pixel()` bytes per pixel in
+    /// row-major order. An empty `rect` gives an empty texture.
     pub fn create_alpha_texture(
         &self,
-        texture_type: DWRITE_TEXTURE_TYPE,
+        texture_type: TextureType,
         rect: RECT,
-    ) -> Result<Vec<u8>, HRESULT> {
+    ) -> Result<Vec<u8>, Error> {
+        let mut out_bytes = vec![0; GlyphRunAnalysis::required_len(rect, texture_type)];
+        self.create_alpha_texture_into(texture_type, rect, &mut out_bytes)?;
+        Ok(out_bytes)
+    }
+
+    /// Like `create_alpha_texture`, but writes the texture into the start of
+    /// `out` instead of allocating, which lets glyph caches rasterize
+    /// straight into their atlas storage.
+    ///
+    /// Fails with `HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER)` if `out` is
+    /// shorter than `required_len(rect, texture_type)`.
+    pub fn create_alpha_texture_into(
+        &self,
+        texture_type: TextureType,
+        rect: RECT,
+        out: &mut [u8],
+    ) -> Result<(), Error> {
+        let len = GlyphRunAnalysis::required_len(rect, texture_type);
+        if len == 0 {
+            return Ok(());
+        }
+        if out.len() < len {
+            return Err(HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER).into());
+        }
         unsafe {
-            let rect_pixels = (rect.right - rect.left) * (rect.bottom - rect.top);
-            let rect_bytes = rect_pixels
-                * match texture_type {
-                    DWRITE_TEXTURE_ALIASED_1x1 => 1,
-                    DWRITE_TEXTURE_CLEARTYPE_3x1 => 3,
-                    _ => panic!("bad texture type specified"),
-                };
-
-            let mut out_bytes: Vec<u8> = vec![0; rect_bytes as usize];
             let hr = (*self.native.get()).CreateAlphaTexture(
-                texture_type,
+                texture_type as DWRITE_TEXTURE_TYPE,
                 &rect,
-                out_bytes.as_mut_ptr(),
-                out_bytes.len() as u32,
+                out.as_mut_ptr(),
+                len as u32,
             );
             if hr != 0 {
-                Err(hr)
+                Err(hr.into())
             } else {
-                Ok(out_bytes)
+                Ok(())
             }
         }
     }
@@ -0,0 +1,342 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// A small gzip (RFC 1952) and DEFLATE (RFC 1951) decoder, for the compressed
+// SVG documents fonts may store; see `FontFace::get_svg_glyph`. Malformed
+// input gives `None` rather than a panic.
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const GZIP_DEFLATE: u8 = 8;
+
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+const MAX_BITS: usize = 15;
+
+// The largest document `gunzip` will produce. SVG glyph documents are
+// typically a few kilobytes; this stops a small table from claiming gigabytes.
+const MAX_OUTPUT: usize = 16 << 20;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// The order code length code lengths are stored in, in dynamic blocks.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+pub(crate) fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+}
+
+/// Decompresses a gzip member, checking its CRC and length. The output is
+/// capped at the length the trailer claims, which may be at most `MAX_OUTPUT`.
+pub(crate) fn gunzip(data: &[u8]) -> Option<Vec<u8>> {
+    if !is_gzip(data) || *data.get(2)? != GZIP_DEFLATE || data.len() < 18 {
+        return None;
+    }
+    let size = &data[data.len() - 4..];
+    let size = u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize;
+    if size > MAX_OUTPUT {
+        return None;
+    }
+    let flags = *data.get(3)?;
+    // Skip the magic, method, flags, mtime, extra flags and OS.
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        let len = u16::from_le_bytes([*data.get(pos)?, *data.get(pos + 1)?]);
+        pos += 2 + len as usize;
+    }
+    if flags & FNAME != 0 {
+        pos += data.get(pos..)?.iter().position(|&b| b == 0)? + 1;
+    }
+    if flags & FCOMMENT != 0 {
+        pos += data.get(pos..)?.iter().position(|&b| b == 0)? + 1;
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+
+    let mut inflater = Inflater::new(data.get(pos..)?, size);
+    inflater.inflate()?;
+    let trailer = data.get(pos + inflater.pos..)?;
+    if trailer.len() != 8 {
+        return None;
+    }
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let out = inflater.out;
+    if crc != crc32(&out) || size != out.len() {
+        return None;
+    }
+    Some(out)
+}
+
+/// Decompresses a raw DEFLATE stream, failing if it would produce more than
+/// `limit` bytes.
+#[cfg(test)]
+pub(crate) fn inflate(data: &[u8], limit: usize) -> Option<Vec<u8>> {
+    let mut inflater = Inflater::new(data, limit);
+    inflater.inflate()?;
+    Some(inflater.out)
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// A canonical Huffman code, stored as the number of codes of each length and
+// the symbols ordered by code.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    // Builds the code for the given code lengths, one per symbol (0 for
+    // unused symbols). Fails if the lengths describe more codes than fit.
+    fn new(lengths: &[u8]) -> Option<Huffman> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return None;
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Some(Huffman { counts, symbols })
+    }
+}
+
+struct Inflater<'a> {
+    data: &'a [u8],
+    // The next byte of `data` to read.
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+    out: Vec<u8>,
+    // The most bytes `out` may hold.
+    limit: usize,
+}
+
+impl<'a> Inflater<'a> {
+    fn new(data: &'a [u8], limit: usize) -> Inflater<'a> {
+        Inflater {
+            data,
+            pos: 0,
+            bit_buf: 0,
+            bit_count: 0,
+            out: vec![],
+            limit,
+        }
+    }
+
+    // Checks there's room in the output for `len` more bytes.
+    fn reserve(&mut self, len: usize) -> Option<()> {
+        if len > self.limit - self.out.len() {
+            return None;
+        }
+        Some(())
+    }
+
+    fn bits(&mut self, count: u32) -> Option<u32> {
+        while self.bit_count < count {
+            let byte = *self.data.get(self.pos)?;
+            self.pos += 1;
+            self.bit_buf |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buf & ((1 << count) - 1);
+        self.bit_buf >>= count;
+        self.bit_count -= count;
+        Some(value)
+    }
+
+    fn inflate(&mut self) -> Option<()> {
+        loop {
+            let last = self.bits(1)? == 1;
+            match self.bits(2)? {
+                0 => self.stored()?,
+                1 => {
+                    let (lengths, distances) = fixed_codes();
+                    self.codes(&lengths, &distances)?
+                }
+                2 => {
+                    let (lengths, distances) = self.dynamic_codes()?;
+                    self.codes(&lengths, &distances)?
+                }
+                _ => return None,
+            }
+            if last {
+                // Any bits left in the buffer are padding up to the trailer.
+                return Some(());
+            }
+        }
+    }
+
+    fn stored(&mut self) -> Option<()> {
+        // Stored blocks start at a byte boundary.
+        self.bit_buf = 0;
+        self.bit_count = 0;
+        let header = self.data.get(self.pos..self.pos + 4)?;
+        let len = u16::from_le_bytes([header[0], header[1]]);
+        let nlen = u16::from_le_bytes([header[2], header[3]]);
+        if len != !nlen {
+            return None;
+        }
+        self.pos += 4;
+        self.reserve(len as usize)?;
+        let block = self.data.get(self.pos..self.pos + len as usize)?;
+        self.out.extend_from_slice(block);
+        self.pos += len as usize;
+        Some(())
+    }
+
+    fn dynamic_codes(&mut self) -> Option<(Huffman, Huffman)> {
+        let length_count = self.bits(5)? as usize + 257;
+        let dist_count = self.bits(5)? as usize + 1;
+        let code_length_count = self.bits(4)? as usize + 4;
+        if length_count > 286 || dist_count > 30 {
+            return None;
+        }
+
+        let mut code_lengths = [0u8; 19];
+        for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_lengths[index] = self.bits(3)? as u8;
+        }
+        let code_length_code = Huffman::new(&code_lengths)?;
+
+        let mut lengths = vec![0u8; length_count + dist_count];
+        let mut index = 0;
+        while index < lengths.len() {
+            let symbol = self.decode(&code_length_code)?;
+            let (len, repeat) = match symbol {
+                0..=15 => (symbol as u8, 1),
+                16 => (*lengths.get(index.checked_sub(1)?)?, 3 + self.bits(2)?),
+                17 => (0, 3 + self.bits(3)?),
+                18 => (0, 11 + self.bits(7)?),
+                _ => return None,
+            };
+            let end = index + repeat as usize;
+            lengths
+                .get_mut(index..end)?
+                .iter_mut()
+                .for_each(|l| *l = len);
+            index = end;
+        }
+        // Without an end-of-block code the block can't end.
+        if lengths[256] == 0 {
+            return None;
+        }
+
+        let length_code = Huffman::new(&lengths[..length_count])?;
+        let dist_code = Huffman::new(&lengths[length_count..])?;
+        Some((length_code, dist_code))
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> Option<u16> {
+        // Codes are stored most significant bit first, and the codes of each
+        // length follow on from the shorter ones.
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for &count in &huffman.counts[1..] {
+            code |= self.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return huffman
+                    .symbols
+                    .get((index + code - first) as usize)
+                    .copied();
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        None
+    }
+
+    fn codes(&mut self, lengths: &Huffman, distances: &Huffman) -> Option<()> {
+        loop {
+            let symbol = self.decode(lengths)? as usize;
+            if symbol < 256 {
+                self.reserve(1)?;
+                self.out.push(symbol as u8);
+                continue;
+            }
+            if symbol == 256 {
+                return Some(());
+            }
+
+            let symbol = symbol - 257;
+            let len = *LENGTH_BASE.get(symbol)? as usize
+                + self.bits(*LENGTH_EXTRA.get(symbol)? as u32)? as usize;
+            let symbol = self.decode(distances)? as usize;
+            let dist = *DIST_BASE.get(symbol)? as usize
+                + self.bits(*DIST_EXTRA.get(symbol)? as u32)? as usize;
+            if dist > self.out.len() {
+                return None;
+            }
+            self.reserve(len)?;
+            // The copy may overlap the bytes it produces, so go byte by byte.
+            let start = self.out.len() - dist;
+            for i in 0..len {
+                let byte = self.out[start + i];
+                self.out.push(byte);
+            }
+        }
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].iter_mut().for_each(|l| *l = 8);
+    lengths[144..256].iter_mut().for_each(|l| *l = 9);
+    lengths[256..280].iter_mut().for_each(|l| *l = 7);
+    lengths[280..].iter_mut().for_each(|l| *l = 8);
+    let distances = [5u8; 30];
+    (
+        Huffman::new(&lengths).unwrap(),
+        Huffman::new(&distances).unwrap(),
+    )
+}
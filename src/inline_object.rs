@@ -0,0 +1,174 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::UnsafeCell;
+use std::mem;
+use std::ptr;
+use winapi::shared::minwindef::{FALSE, TRUE};
+use winapi::shared::winerror::S_OK;
+use winapi::um::dwrite::DWRITE_OVERHANG_METRICS;
+use winapi::um::dwrite::{IDWriteInlineObject, IDWriteTextRenderer};
+use winapi::um::dwrite::{DWRITE_BREAK_CONDITION, DWRITE_INLINE_OBJECT_METRICS};
+use winapi::um::dwrite::{
+    DWRITE_BREAK_CONDITION_CAN_BREAK, DWRITE_BREAK_CONDITION_MAY_NOT_BREAK,
+    DWRITE_BREAK_CONDITION_MUST_BREAK, DWRITE_BREAK_CONDITION_NEUTRAL,
+};
+use wio::com::ComPtr;
+
+use super::{OverhangMetrics, TextRendererMethods};
+use crate::com_helpers::Com;
+use crate::error::Error;
+use crate::inline_object_impl::{InlineObjectImpl, InlineObjectMethods};
+use crate::text_renderer_impl::TextRendererImpl;
+
+// mirrors DWRITE_BREAK_CONDITION
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BreakCondition {
+    Neutral = DWRITE_BREAK_CONDITION_NEUTRAL,
+    CanBreak = DWRITE_BREAK_CONDITION_CAN_BREAK,
+    MayNotBreak = DWRITE_BREAK_CONDITION_MAY_NOT_BREAK,
+    MustBreak = DWRITE_BREAK_CONDITION_MUST_BREAK,
+}
+
+impl BreakCondition {
+    fn from_raw(condition: DWRITE_BREAK_CONDITION) -> BreakCondition {
+        match condition {
+            DWRITE_BREAK_CONDITION_CAN_BREAK => BreakCondition::CanBreak,
+            DWRITE_BREAK_CONDITION_MAY_NOT_BREAK => BreakCondition::MayNotBreak,
+            DWRITE_BREAK_CONDITION_MUST_BREAK => BreakCondition::MustBreak,
+            _ => BreakCondition::Neutral,
+        }
+    }
+}
+
+/// The size of an inline object. `baseline` is the distance from the top of
+/// the object to the baseline of the line it sits on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InlineObjectMetrics {
+    pub width: f32,
+    pub height: f32,
+    pub baseline: f32,
+    pub supports_sideways: bool,
+}
+
+impl<'a> From<&'a DWRITE_INLINE_OBJECT_METRICS> for InlineObjectMetrics {
+    fn from(metrics: &'a DWRITE_INLINE_OBJECT_METRICS) -> InlineObjectMetrics {
+        InlineObjectMetrics {
+            width: metrics.width,
+            height: metrics.height,
+            baseline: metrics.baseline,
+            supports_sideways: metrics.supportsSideways != FALSE,
+        }
+    }
+}
+
+/// An object placed inline with text, such as the trimming sign created by
+/// `TextFormat::create_ellipsis_trimming_sign`, or a custom object created
+/// with `InlineObject::new`.
+pub struct InlineObject {
+    native: UnsafeCell<ComPtr<IDWriteInlineObject>>,
+}
+
+impl InlineObject {
+    /// Wraps a Rust implementation of an inline object, to be placed in a
+    /// layout with `TextLayout::set_inline_object`.
+    ///
+    /// The object can only be drawn through `TextLayout::draw`, by a renderer
+    /// whose `draw_inline_object` calls `InlineObject::draw`.
+    pub fn new(inner: Box<dyn InlineObjectMethods>) -> InlineObject {
+        unsafe {
+            let native = InlineObjectImpl::new(inner).into_interface();
+            InlineObject::take(ComPtr::from_raw(native))
+        }
+    }
+
+    pub fn take(native: ComPtr<IDWriteInlineObject>) -> InlineObject {
+        InlineObject {
+            native: UnsafeCell::new(native),
+        }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut IDWriteInlineObject {
+        (*self.native.get()).as_raw()
+    }
+
+    pub fn metrics(&self) -> Result<InlineObjectMetrics, Error> {
+        unsafe {
+            let mut metrics: DWRITE_INLINE_OBJECT_METRICS = mem::zeroed();
+            let hr = (*self.native.get()).GetMetrics(&mut metrics);
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(InlineObjectMetrics::from(&metrics))
+        }
+    }
+
+    pub fn overhang_metrics(&self) -> Result<OverhangMetrics, Error> {
+        unsafe {
+            let mut metrics: DWRITE_OVERHANG_METRICS = mem::zeroed();
+            let hr = (*self.native.get()).GetOverhangMetrics(&mut metrics);
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(OverhangMetrics::from(&metrics))
+        }
+    }
+
+    /// Returns whether lines can be broken before and after the object.
+    pub fn break_conditions(&self) -> Result<(BreakCondition, BreakCondition), Error> {
+        unsafe {
+            let mut before: DWRITE_BREAK_CONDITION = 0;
+            let mut after: DWRITE_BREAK_CONDITION = 0;
+            let hr = (*self.native.get()).GetBreakConditions(&mut before, &mut after);
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok((
+                BreakCondition::from_raw(before),
+                BreakCondition::from_raw(after),
+            ))
+        }
+    }
+
+    /// Draws the object with its top left corner at `(x, y)` through
+    /// `renderer`. This is what a `TextRendererMethods::draw_inline_object`
+    /// implementation calls to have the object draw itself.
+    pub fn draw(
+        &self,
+        renderer: &mut dyn TextRendererMethods,
+        x: f32,
+        y: f32,
+        is_sideways: bool,
+        is_right_to_left: bool,
+    ) -> Result<(), Error> {
+        unsafe {
+            let renderer = TextRendererImpl::new(renderer).into_interface();
+            let hr = (*self.native.get()).Draw(
+                ptr::null_mut(),
+                renderer,
+                x,
+                y,
+                if is_sideways { TRUE } else { FALSE },
+                if is_right_to_left { TRUE } else { FALSE },
+                ptr::null_mut(),
+            );
+            <TextRendererImpl as Com<IDWriteTextRenderer>>::destroy(renderer);
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(())
+        }
+    }
+}
+
+impl Clone for InlineObject {
+    fn clone(&self) -> InlineObject {
+        unsafe {
+            InlineObject {
+                native: UnsafeCell::new((*self.native.get()).clone()),
+            }
+        }
+    }
+}
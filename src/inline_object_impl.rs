@@ -0,0 +1,166 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A custom implementation of the "inline object" interface, so that client
+//! code can embed non-text content in a `TextLayout`.
+
+#![allow(non_snake_case)]
+
+use std::sync::atomic::{self, AtomicUsize};
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::REFIID;
+use winapi::shared::minwindef::{BOOL, FALSE, FLOAT, ULONG};
+use winapi::shared::winerror::{E_NOTIMPL, S_OK};
+use winapi::um::dwrite::DWRITE_OVERHANG_METRICS;
+use winapi::um::dwrite::{IDWriteInlineObject, IDWriteInlineObjectVtbl, IDWriteTextRenderer};
+use winapi::um::dwrite::{DWRITE_BREAK_CONDITION, DWRITE_INLINE_OBJECT_METRICS};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::um::winnt::HRESULT;
+
+use super::{BreakCondition, InlineObjectMetrics, OverhangMetrics, TextRendererMethods};
+use crate::com_helpers::Com;
+use crate::error::Error;
+use crate::text_renderer_impl::renderer_methods;
+
+/// The Rust side of a custom inline object, see `InlineObject::new`.
+pub trait InlineObjectMethods {
+    /// The size of the object, and where its baseline is.
+    fn metrics(&self) -> InlineObjectMetrics;
+
+    /// How far the object's ink extends past the box given by `metrics`.
+    fn overhang_metrics(&self) -> OverhangMetrics {
+        OverhangMetrics {
+            left: 0.0,
+            top: 0.0,
+            right: 0.0,
+            bottom: 0.0,
+        }
+    }
+
+    /// Whether lines can be broken before and after the object.
+    fn break_conditions(&self) -> (BreakCondition, BreakCondition) {
+        (BreakCondition::Neutral, BreakCondition::Neutral)
+    }
+
+    /// Draws the object with its top left corner at `(x, y)`, using the
+    /// renderer the layout is being drawn with.
+    fn draw(
+        &mut self,
+        renderer: &mut dyn TextRendererMethods,
+        x: f32,
+        y: f32,
+        is_sideways: bool,
+        is_right_to_left: bool,
+    ) -> Result<(), Error>;
+}
+
+static INLINE_OBJECT_VTBL: IDWriteInlineObjectVtbl = IDWriteInlineObjectVtbl {
+    parent: implement_iunknown!(IDWriteInlineObject, InlineObjectImpl),
+    Draw: InlineObjectImpl_Draw,
+    GetMetrics: InlineObjectImpl_GetMetrics,
+    GetOverhangMetrics: InlineObjectImpl_GetOverhangMetrics,
+    GetBreakConditions: InlineObjectImpl_GetBreakConditions,
+};
+
+pub struct InlineObjectImpl {
+    refcount: AtomicUsize,
+    inner: Box<dyn InlineObjectMethods>,
+}
+
+impl Com<IDWriteInlineObject> for InlineObjectImpl {
+    type Vtbl = IDWriteInlineObjectVtbl;
+    #[inline]
+    fn vtbl() -> &'static IDWriteInlineObjectVtbl {
+        &INLINE_OBJECT_VTBL
+    }
+}
+
+impl Com<IUnknown> for InlineObjectImpl {
+    type Vtbl = IUnknownVtbl;
+    #[inline]
+    fn vtbl() -> &'static IUnknownVtbl {
+        &INLINE_OBJECT_VTBL.parent
+    }
+}
+
+impl InlineObjectImpl {
+    pub fn new(inner: Box<dyn InlineObjectMethods>) -> InlineObjectImpl {
+        InlineObjectImpl {
+            refcount: AtomicUsize::new(1),
+            inner,
+        }
+    }
+}
+
+unsafe extern "system" fn InlineObjectImpl_Draw(
+    this: *mut IDWriteInlineObject,
+    _: *mut c_void,
+    renderer: *mut IDWriteTextRenderer,
+    origin_x: FLOAT,
+    origin_y: FLOAT,
+    is_sideways: BOOL,
+    is_right_to_left: BOOL,
+    _: *mut IUnknown,
+) -> HRESULT {
+    let this = InlineObjectImpl::from_interface(this);
+    // We can only hand over renderers implemented in Rust; anything else
+    // would need its own `TextRendererMethods` wrapper.
+    let renderer = match renderer_methods(renderer) {
+        Some(renderer) => renderer,
+        None => return E_NOTIMPL,
+    };
+    let result = this.inner.draw(
+        renderer,
+        origin_x,
+        origin_y,
+        is_sideways != FALSE,
+        is_right_to_left != FALSE,
+    );
+    match result {
+        Ok(()) => S_OK,
+        Err(error) => error.hresult(),
+    }
+}
+
+unsafe extern "system" fn InlineObjectImpl_GetMetrics(
+    this: *mut IDWriteInlineObject,
+    metrics: *mut DWRITE_INLINE_OBJECT_METRICS,
+) -> HRESULT {
+    let this = InlineObjectImpl::from_interface(this);
+    let result = this.inner.metrics();
+    *metrics = DWRITE_INLINE_OBJECT_METRICS {
+        width: result.width,
+        height: result.height,
+        baseline: result.baseline,
+        supportsSideways: result.supports_sideways as BOOL,
+    };
+    S_OK
+}
+
+unsafe extern "system" fn InlineObjectImpl_GetOverhangMetrics(
+    this: *mut IDWriteInlineObject,
+    overhangs: *mut DWRITE_OVERHANG_METRICS,
+) -> HRESULT {
+    let this = InlineObjectImpl::from_interface(this);
+    let result = this.inner.overhang_metrics();
+    *overhangs = DWRITE_OVERHANG_METRICS {
+        left: result.left,
+        top: result.top,
+        right: result.right,
+        bottom: result.bottom,
+    };
+    S_OK
+}
+
+unsafe extern "system" fn InlineObjectImpl_GetBreakConditions(
+    this: *mut IDWriteInlineObject,
+    before: *mut DWRITE_BREAK_CONDITION,
+    after: *mut DWRITE_BREAK_CONDITION,
+) -> HRESULT {
+    let this = InlineObjectImpl::from_interface(this);
+    let (result_before, result_after) = this.inner.break_conditions();
+    *before = result_before as DWRITE_BREAK_CONDITION;
+    *after = result_after as DWRITE_BREAK_CONDITION;
+    S_OK
+}
@@ -19,20 +19,22 @@ include!("types.rs");
 
 use std::ffi::CString;
 use std::ptr;
-use winapi::Interface;
 use winapi::shared::guiddef::REFIID;
-use winapi::shared::winerror::S_OK;
-use winapi::um::dwrite::DWRITE_FACTORY_TYPE;
-use winapi::um::dwrite::DWRITE_FACTORY_TYPE_SHARED;
+use winapi::shared::winerror::{HRESULT_FROM_WIN32, S_OK};
 use winapi::um::dwrite::IDWriteFactory;
 use winapi::um::dwrite::IDWriteRenderingParams;
+use winapi::um::dwrite::DWRITE_FACTORY_TYPE;
+use winapi::um::dwrite::DWRITE_FACTORY_TYPE_SHARED;
+use winapi::um::errhandlingapi::GetLastError;
 use winapi::um::unknwnbase::IUnknown;
 use winapi::um::winnt::LPCSTR;
+use winapi::Interface;
 
+pub use winapi::um::wingdi::LOGFONTW;
 pub use winapi::um::winnt::HRESULT;
 
 mod helpers;
-use helpers::ToWide;
+pub use helpers::ToWide;
 use std::os::raw::c_void;
 
 #[cfg(test)]
@@ -41,7 +43,22 @@ mod test;
 // We still use the DWrite structs for things like metrics; re-export them
 // here
 pub use winapi::shared::windef::RECT;
+// The errors DirectWrite reports, for matching the `HRESULT`s returned by
+// fallible methods (see `Error::hresult`).
+pub use winapi::shared::winerror::{
+    DWRITE_E_ALREADYREGISTERED, DWRITE_E_CACHEFORMAT, DWRITE_E_CACHEVERSION, DWRITE_E_FILEACCESS,
+    DWRITE_E_FILEFORMAT, DWRITE_E_FILENOTFOUND, DWRITE_E_FLOWDIRECTIONCONFLICTS,
+    DWRITE_E_FONTCOLLECTIONOBSOLETE, DWRITE_E_NOCOLOR, DWRITE_E_NOFONT,
+    DWRITE_E_TEXTRENDERERINCOMPATIBLE, DWRITE_E_UNEXPECTED, DWRITE_E_UNSUPPORTEDOPERATION,
+};
 pub use winapi::um::dcommon::DWRITE_MEASURING_MODE;
+pub use winapi::um::dcommon::{
+    DWRITE_GLYPH_IMAGE_FORMATS, DWRITE_GLYPH_IMAGE_FORMATS_CFF, DWRITE_GLYPH_IMAGE_FORMATS_COLR,
+    DWRITE_GLYPH_IMAGE_FORMATS_JPEG, DWRITE_GLYPH_IMAGE_FORMATS_NONE,
+    DWRITE_GLYPH_IMAGE_FORMATS_PNG, DWRITE_GLYPH_IMAGE_FORMATS_PREMULTIPLIED_B8G8R8A8,
+    DWRITE_GLYPH_IMAGE_FORMATS_SVG, DWRITE_GLYPH_IMAGE_FORMATS_TIFF,
+    DWRITE_GLYPH_IMAGE_FORMATS_TRUETYPE,
+};
 pub use winapi::um::dcommon::{
     DWRITE_MEASURING_MODE_GDI_CLASSIC, DWRITE_MEASURING_MODE_GDI_NATURAL,
     DWRITE_MEASURING_MODE_NATURAL,
@@ -50,12 +67,14 @@ pub use winapi::um::dwrite::DWRITE_FONT_METRICS as FontMetrics0;
 pub use winapi::um::dwrite::DWRITE_FONT_SIMULATIONS;
 pub use winapi::um::dwrite::DWRITE_GLYPH_OFFSET as GlyphOffset;
 pub use winapi::um::dwrite::DWRITE_RENDERING_MODE;
+pub use winapi::um::dwrite::DWRITE_SHAPING_GLYPH_PROPERTIES;
+pub use winapi::um::dwrite::DWRITE_SHAPING_TEXT_PROPERTIES;
 pub use winapi::um::dwrite::DWRITE_TEXTURE_TYPE;
 pub use winapi::um::dwrite::{DWRITE_TEXTURE_ALIASED_1x1, DWRITE_TEXTURE_CLEARTYPE_3x1};
 pub use winapi::um::dwrite::{
     DWRITE_FONT_SIMULATIONS_BOLD, DWRITE_FONT_SIMULATIONS_NONE, DWRITE_FONT_SIMULATIONS_OBLIQUE,
 };
-pub use winapi::um::dwrite::{DWRITE_GLYPH_RUN, DWRITE_MATRIX};
+pub use winapi::um::dwrite::{DWRITE_GLYPH_RUN, DWRITE_MATRIX, DWRITE_SCRIPT_ANALYSIS};
 pub use winapi::um::dwrite::{
     DWRITE_RENDERING_MODE_ALIASED, DWRITE_RENDERING_MODE_CLEARTYPE_GDI_CLASSIC,
     DWRITE_RENDERING_MODE_CLEARTYPE_GDI_NATURAL, DWRITE_RENDERING_MODE_CLEARTYPE_NATURAL,
@@ -64,43 +83,93 @@ pub use winapi::um::dwrite::{
     DWRITE_RENDERING_MODE_NATURAL, DWRITE_RENDERING_MODE_NATURAL_SYMMETRIC,
     DWRITE_RENDERING_MODE_OUTLINE,
 };
-pub use winapi::um::dwrite_1::DWRITE_FONT_METRICS1 as FontMetrics1;
+pub use winapi::um::dwrite_1::DWRITE_CARET_METRICS as CaretMetrics;
+pub use winapi::um::dwrite_1::DWRITE_JUSTIFICATION_OPPORTUNITY as JustificationOpportunity;
+pub use winapi::um::dwrite_2::DWRITE_COLOR_F;
 pub use winapi::um::dwrite_3::DWRITE_FONT_AXIS_VALUE;
+pub use winapi::um::dwrite_3::{
+    DWRITE_RENDERING_MODE1, DWRITE_RENDERING_MODE1_ALIASED, DWRITE_RENDERING_MODE1_DEFAULT,
+    DWRITE_RENDERING_MODE1_GDI_CLASSIC, DWRITE_RENDERING_MODE1_GDI_NATURAL,
+    DWRITE_RENDERING_MODE1_NATURAL, DWRITE_RENDERING_MODE1_NATURAL_SYMMETRIC,
+    DWRITE_RENDERING_MODE1_NATURAL_SYMMETRIC_DOWNSAMPLED, DWRITE_RENDERING_MODE1_OUTLINE,
+};
 use winapi::um::libloaderapi::{GetProcAddress, LoadLibraryW};
 
 #[macro_use]
 mod com_helpers;
 
 mod bitmap_render_target;
-pub use bitmap_render_target::BitmapRenderTarget;
+pub use bitmap_render_target::{BitmapRenderTarget, PixelView, PixelViewMut};
+mod cluster_map;
+pub use cluster_map::ClusterMap;
+mod color_glyph_run;
+pub use color_glyph_run::ColorGlyphRun;
+mod error;
+pub use error::{Error, DWRITE_E_REMOTEFONT};
+mod factory;
+pub use factory::{capabilities, max_interface_level, Capabilities, Factory};
 mod font;
-pub use font::{Font, FontMetrics, InformationalStringId};
+pub use font::{Font, FontMetrics, FontMetrics1, InformationalStringId};
 mod font_collection;
-pub use font_collection::FontCollection;
+pub use font_collection::{
+    DescriptorError, FamilyIter, FontCollection, FontCollectionFamilyIterator, SystemFontWatcher,
+};
 mod font_face;
-pub use font_face::{FontFace, FontFaceType};
+pub use font_face::{FontFace, FontFaceType, ScaledFontMetrics, VerticalGlyphMetrics};
 mod font_fallback;
 pub use font_fallback::{FallbackResult, FontFallback};
 mod font_family;
-pub use font_family::FontFamily;
+pub use font_family::{FontFamily, FontFamilyFontIterator, FontIter};
 mod font_file;
-pub use font_file::FontFile;
+pub use font_file::{FontFile, FontFileAnalysis, FontFileLoaderId, FontFileType};
+mod font_list;
+pub use font_list::{FontList, FontListIterator};
 mod gdi_interop;
 pub use gdi_interop::GdiInterop;
+mod glyph_index_cache;
+pub use glyph_index_cache::GlyphIndexCache;
+mod inline_object;
+pub use inline_object::{BreakCondition, InlineObject, InlineObjectMetrics};
+mod localized_strings;
+pub use localized_strings::{LocalizedStrings, LocalizedStringsIter};
 mod outline_builder;
-pub use outline_builder::OutlineBuilder;
+pub use outline_builder::{
+    OutlineBuilder, OutlineError, PathCollector, PathCommand, TryOutlineBuilder,
+};
+mod panose;
+pub use panose::Panose;
 mod rendering_params;
-pub use rendering_params::RenderingParams;
+pub use rendering_params::{PixelGeometry, RenderingParams};
+mod text_analyzer;
+pub use text_analyzer::{Baseline, FeatureRange, FontFeature, ShapedGlyphs, TextAnalyzer};
 mod text_analysis_source;
 pub use text_analysis_source::TextAnalysisSource;
+mod text_format;
+pub use text_format::{
+    FlowDirection, LineSpacing, LineSpacingMethod, ParagraphAlignment, TextAlignment, TextFormat,
+    Trimming, TrimmingGranularity, WordWrapping,
+};
+mod text_layout;
+pub use text_layout::{
+    ClusterMetrics, HitTestMetrics, HitTestResult, LineMetrics, OverhangMetrics, TextLayout,
+    TextMetrics,
+};
+mod typography;
+pub use typography::Typography;
 mod glyph_run_analysis;
-pub use glyph_run_analysis::GlyphRunAnalysis;
+pub use glyph_run_analysis::{GlyphRunAnalysis, GridFitMode, TextAntialiasMode, TextureType};
 
 // This is an internal implementation of FontFileLoader, for our utility
 // functions.  We don't wrap the DWriteFontFileLoader interface and
 // related things.
 mod font_file_loader_impl;
 
+// Interfaces from newer SDK headers that winapi doesn't declare.
+mod dwrite_ext;
+
+// Decompresses gzip'd SVG glyph documents for `FontFace::get_svg_glyph`.
+mod inflate;
+
 // This is an implementation of `FontCollectionLoader` for client code.
 mod font_collection_impl;
 pub use font_collection_impl::CustomFontCollectionLoaderImpl;
@@ -108,40 +177,26 @@ pub use font_collection_impl::CustomFontCollectionLoaderImpl;
 // This is an implementation of `TextAnalysisSource` for client code.
 mod text_analysis_source_impl;
 pub use text_analysis_source_impl::{
-    CustomTextAnalysisSourceImpl, NumberSubstitution, TextAnalysisSourceMethods,
+    CustomTextAnalysisSourceImpl, NumberSubstitution, SimpleTextAnalysisSource,
+    TextAnalysisSourceMethods,
 };
 
 // This is an internal implementation of `GeometrySink` so that we can
 // expose `IDWriteGeometrySink` in an idiomatic way.
 mod geometry_sink_impl;
 
+// This is an implementation of `IDWriteTextRenderer` so that
+// `TextLayout::draw` can call back into client code.
+mod text_renderer_impl;
+pub use text_renderer_impl::{Decoration, GlyphRunView, TextRendererMethods};
+
+// This is an implementation of `IDWriteInlineObject` for client code.
+mod inline_object_impl;
+pub use inline_object_impl::InlineObjectMethods;
+
 lazy_static! {
-    static ref DWRITE_FACTORY_RAW_PTR: usize = {
-        unsafe {
-            type DWriteCreateFactoryType =
-                extern "system" fn(DWRITE_FACTORY_TYPE, REFIID, *mut *mut IUnknown) -> HRESULT;
-
-            let dwrite_dll = LoadLibraryW("dwrite.dll".to_wide_null().as_ptr());
-            assert!(!dwrite_dll.is_null());
-            let create_factory_name = CString::new("DWriteCreateFactory").unwrap();
-            let dwrite_create_factory_ptr =
-                GetProcAddress(dwrite_dll, create_factory_name.as_ptr() as LPCSTR);
-            assert!(!dwrite_create_factory_ptr.is_null());
-
-            let dwrite_create_factory = mem::transmute::<*const c_void, DWriteCreateFactoryType>(
-                dwrite_create_factory_ptr as *const _,
-            );
-
-            let mut factory: *mut IDWriteFactory = ptr::null_mut();
-            let hr = dwrite_create_factory(
-                DWRITE_FACTORY_TYPE_SHARED,
-                &IDWriteFactory::uuidof(),
-                &mut factory as *mut *mut IDWriteFactory as *mut *mut IUnknown,
-            );
-            assert!(hr == S_OK);
-            factory as usize
-        }
-    };
+    static ref DWRITE_FACTORY_RAW_PTR: usize =
+        unsafe { create_dwrite_factory(DWRITE_FACTORY_TYPE_SHARED).unwrap() as usize };
     static ref DEFAULT_DWRITE_RENDERING_PARAMS_RAW_PTR: usize = {
         unsafe {
             let mut default_rendering_params: *mut IDWriteRenderingParams = ptr::null_mut();
@@ -152,6 +207,42 @@ lazy_static! {
     };
 } // end lazy static
 
+// Load dwrite.dll and create a new factory of the given type.  The shared
+// factory above goes through here once; isolated factories (see `Factory`)
+// call it for every instance.
+unsafe fn create_dwrite_factory(
+    factory_type: DWRITE_FACTORY_TYPE,
+) -> Result<*mut IDWriteFactory, HRESULT> {
+    type DWriteCreateFactoryType =
+        extern "system" fn(DWRITE_FACTORY_TYPE, REFIID, *mut *mut IUnknown) -> HRESULT;
+
+    let dwrite_dll = LoadLibraryW("dwrite.dll".to_wide_null().as_ptr());
+    if dwrite_dll.is_null() {
+        return Err(HRESULT_FROM_WIN32(GetLastError()));
+    }
+    let create_factory_name = CString::new("DWriteCreateFactory").unwrap();
+    let dwrite_create_factory_ptr =
+        GetProcAddress(dwrite_dll, create_factory_name.as_ptr() as LPCSTR);
+    if dwrite_create_factory_ptr.is_null() {
+        return Err(HRESULT_FROM_WIN32(GetLastError()));
+    }
+
+    let dwrite_create_factory = mem::transmute::<*const c_void, DWriteCreateFactoryType>(
+        dwrite_create_factory_ptr as *const _,
+    );
+
+    let mut factory: *mut IDWriteFactory = ptr::null_mut();
+    let hr = dwrite_create_factory(
+        factory_type,
+        &IDWriteFactory::uuidof(),
+        &mut factory as *mut *mut IDWriteFactory as *mut *mut IUnknown,
+    );
+    if hr != S_OK {
+        return Err(hr);
+    }
+    Ok(factory)
+}
+
 // FIXME vlad would be nice to return, say, FactoryPtr<IDWriteFactory>
 // that has a DerefMut impl, so that we can write
 // DWriteFactory().SomeOperation() as opposed to
@@ -0,0 +1,178 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::UnsafeCell;
+use winapi::ctypes::wchar_t;
+use winapi::shared::minwindef::{BOOL, FALSE};
+use winapi::shared::winerror::S_OK;
+use winapi::um::dwrite::IDWriteLocalizedStrings;
+use wio::com::ComPtr;
+
+use crate::error::Error;
+use crate::helpers::{ToWide, EN_US_LOCALE, SYSTEM_LOCALE};
+
+/// A set of strings, such as family names, indexed by locale name.
+pub struct LocalizedStrings {
+    native: UnsafeCell<ComPtr<IDWriteLocalizedStrings>>,
+}
+
+impl LocalizedStrings {
+    pub fn take(native: ComPtr<IDWriteLocalizedStrings>) -> LocalizedStrings {
+        LocalizedStrings {
+            native: UnsafeCell::new(native),
+        }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut IDWriteLocalizedStrings {
+        (*self.native.get()).as_raw()
+    }
+
+    pub fn len(&self) -> usize {
+        unsafe { (*self.native.get()).GetCount() as usize }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the locale name (e.g. `en-us`) of the string at `index`.
+    pub fn locale(&self, index: usize) -> Result<String, Error> {
+        unsafe {
+            let native = &*self.native.get();
+            let mut length: u32 = 0;
+            let hr = native.GetLocaleNameLength(index as u32, &mut length);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+
+            let mut name: Vec<wchar_t> = vec![0; length as usize + 1];
+            let hr = native.GetLocaleName(index as u32, name.as_mut_ptr(), length + 1);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            name.truncate(length as usize);
+            Ok(String::from_utf16_lossy(&name))
+        }
+    }
+
+    /// Returns the string at `index`.
+    pub fn string(&self, index: usize) -> Result<String, Error> {
+        unsafe {
+            let native = &*self.native.get();
+            let mut length: u32 = 0;
+            let hr = native.GetStringLength(index as u32, &mut length);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+
+            let mut string: Vec<wchar_t> = vec![0; length as usize + 1];
+            let hr = native.GetString(index as u32, string.as_mut_ptr(), length + 1);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            string.truncate(length as usize);
+            Ok(String::from_utf16_lossy(&string))
+        }
+    }
+
+    /// Returns the index of the string for `locale`, if there is one. Locale
+    /// names are compared case-insensitively.
+    pub fn find_locale(&self, locale: &str) -> Option<usize> {
+        self.find_locale_wide(&locale.to_wide_null())
+    }
+
+    fn find_locale_wide(&self, locale: &[wchar_t]) -> Option<usize> {
+        unsafe {
+            let mut index: u32 = 0;
+            let mut exists: BOOL = FALSE;
+            let hr = (*self.native.get()).FindLocaleName(locale.as_ptr(), &mut index, &mut exists);
+            if hr != S_OK || exists == FALSE {
+                return None;
+            }
+            Some(index as usize)
+        }
+    }
+
+    /// Returns the string for `locale`, falling back to the user's default
+    /// locale, then to `en-us`, then to the first string. Returns `None` if
+    /// there are no strings at all, or the string can't be read.
+    pub fn string_for_locale(&self, locale: &str) -> Option<String> {
+        let index = self.find_locale(locale).or_else(|| self.default_index())?;
+        self.string(index).ok()
+    }
+
+    /// Returns the string for `locale`, falling back to `en-us`, then to the
+    /// first string. Unlike `string_for_locale`, this doesn't consider the
+    /// user's locale, so the result is the same on every system. Returns
+    /// `None` if there are no strings at all, or the string can't be read.
+    pub fn string_for_locale_or_english(&self, locale: &str) -> Option<String> {
+        let index = self
+            .find_locale(locale)
+            .or_else(|| self.find_locale_wide(&EN_US_LOCALE))
+            .or(if self.is_empty() { None } else { Some(0) })?;
+        self.string(index).ok()
+    }
+
+    /// Returns the string for the user's default locale, falling back to
+    /// `en-us` and then to the first string. Returns `None` if there are no
+    /// strings at all, or the string can't be read.
+    pub fn default_string(&self) -> Option<String> {
+        let index = self.default_index()?;
+        self.string(index).ok()
+    }
+
+    fn default_index(&self) -> Option<usize> {
+        self.find_locale_wide(&SYSTEM_LOCALE)
+            .or_else(|| self.find_locale_wide(&EN_US_LOCALE))
+            .or(if self.is_empty() { None } else { Some(0) })
+    }
+
+    /// Returns an iterator over `(locale, string)` pairs, yielding an error
+    /// for any pair that can't be read.
+    pub fn iter(&self) -> LocalizedStringsIter<'_> {
+        LocalizedStringsIter {
+            strings: self,
+            curr: 0,
+            count: self.len(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a LocalizedStrings {
+    type Item = Result<(String, String), Error>;
+    type IntoIter = LocalizedStringsIter<'a>;
+    fn into_iter(self) -> LocalizedStringsIter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterates over the `(locale, string)` pairs of a `LocalizedStrings`.
+pub struct LocalizedStringsIter<'a> {
+    strings: &'a LocalizedStrings,
+    curr: usize,
+    count: usize,
+}
+
+impl<'a> Iterator for LocalizedStringsIter<'a> {
+    type Item = Result<(String, String), Error>;
+    fn next(&mut self) -> Option<Result<(String, String), Error>> {
+        if self.curr == self.count {
+            return None;
+        }
+
+        let item = self
+            .strings
+            .locale(self.curr)
+            .and_then(|locale| Ok((locale, self.strings.string(self.curr)?)));
+        self.curr += 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.count - self.curr;
+        (len, Some(len))
+    }
+}
+
+impl<'a> ExactSizeIterator for LocalizedStringsIter<'a> {}
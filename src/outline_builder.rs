@@ -1,6 +1,418 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+use winapi::um::winnt::HRESULT;
+
 pub trait OutlineBuilder {
     fn move_to(&mut self, x: f32, y: f32);
     fn line_to(&mut self, x: f32, y: f32);
+    /// DirectWrite reports curves as cubic Béziers; if `supports_quads`
+    /// returns true, this is called instead of `curve_to` for the ones that
+    /// are really quadratic, as in TrueType outlines. `(x0, y0)` is the
+    /// current point. By default the curve is raised back to a cubic one and
+    /// passed to `curve_to`.
+    fn quad_to(&mut self, x0: f32, y0: f32, cpx: f32, cpy: f32, x: f32, y: f32) {
+        let [cp0x, cp0y, cp1x, cp1y] = cubic_control_points(x0, y0, cpx, cpy, x, y);
+        self.curve_to(cp0x, cp0y, cp1x, cp1y, x, y)
+    }
     fn curve_to(&mut self, cp0x: f32, cp0y: f32, cp1x: f32, cp1y: f32, x: f32, y: f32);
     fn close(&mut self);
+
+    /// Whether to call `quad_to` for quadratic curves. By default every curve
+    /// goes to `curve_to`.
+    fn supports_quads(&self) -> bool {
+        false
+    }
+}
+
+impl<T: OutlineBuilder + ?Sized> OutlineBuilder for Box<T> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        (**self).move_to(x, y)
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        (**self).line_to(x, y)
+    }
+
+    fn quad_to(&mut self, x0: f32, y0: f32, cpx: f32, cpy: f32, x: f32, y: f32) {
+        (**self).quad_to(x0, y0, cpx, cpy, x, y)
+    }
+
+    fn curve_to(&mut self, cp0x: f32, cp0y: f32, cp1x: f32, cp1y: f32, x: f32, y: f32) {
+        (**self).curve_to(cp0x, cp0y, cp1x, cp1y, x, y)
+    }
+
+    fn close(&mut self) {
+        (**self).close()
+    }
+
+    fn supports_quads(&self) -> bool {
+        (**self).supports_quads()
+    }
+}
+
+/// Like `OutlineBuilder`, but any callback can fail, which stops outline
+/// extraction and makes `FontFace::try_get_glyph_run_outline` return the
+/// error. Every `OutlineBuilder` is a `TryOutlineBuilder` that never fails.
+pub trait TryOutlineBuilder {
+    fn try_move_to(&mut self, x: f32, y: f32) -> Result<(), OutlineError>;
+    fn try_line_to(&mut self, x: f32, y: f32) -> Result<(), OutlineError>;
+    /// See `OutlineBuilder::quad_to`.
+    fn try_quad_to(
+        &mut self,
+        x0: f32,
+        y0: f32,
+        cpx: f32,
+        cpy: f32,
+        x: f32,
+        y: f32,
+    ) -> Result<(), OutlineError> {
+        let [cp0x, cp0y, cp1x, cp1y] = cubic_control_points(x0, y0, cpx, cpy, x, y);
+        self.try_curve_to(cp0x, cp0y, cp1x, cp1y, x, y)
+    }
+    fn try_curve_to(
+        &mut self,
+        cp0x: f32,
+        cp0y: f32,
+        cp1x: f32,
+        cp1y: f32,
+        x: f32,
+        y: f32,
+    ) -> Result<(), OutlineError>;
+    fn try_close(&mut self) -> Result<(), OutlineError>;
+
+    /// See `OutlineBuilder::supports_quads`.
+    fn supports_quads(&self) -> bool {
+        false
+    }
+}
+
+impl<T: OutlineBuilder + ?Sized> TryOutlineBuilder for T {
+    fn try_move_to(&mut self, x: f32, y: f32) -> Result<(), OutlineError> {
+        self.move_to(x, y);
+        Ok(())
+    }
+
+    fn try_line_to(&mut self, x: f32, y: f32) -> Result<(), OutlineError> {
+        self.line_to(x, y);
+        Ok(())
+    }
+
+    fn try_quad_to(
+        &mut self,
+        x0: f32,
+        y0: f32,
+        cpx: f32,
+        cpy: f32,
+        x: f32,
+        y: f32,
+    ) -> Result<(), OutlineError> {
+        self.quad_to(x0, y0, cpx, cpy, x, y);
+        Ok(())
+    }
+
+    fn try_curve_to(
+        &mut self,
+        cp0x: f32,
+        cp0y: f32,
+        cp1x: f32,
+        cp1y: f32,
+        x: f32,
+        y: f32,
+    ) -> Result<(), OutlineError> {
+        self.curve_to(cp0x, cp0y, cp1x, cp1y, x, y);
+        Ok(())
+    }
+
+    fn try_close(&mut self) -> Result<(), OutlineError> {
+        self.close();
+        Ok(())
+    }
+
+    fn supports_quads(&self) -> bool {
+        OutlineBuilder::supports_quads(self)
+    }
+}
+
+// The control points of the cubic curve equal to the quadratic from `(x0, y0)`
+// through `(cpx, cpy)` to `(x, y)`: two thirds of the way from each end to the
+// quadratic's control point.
+fn cubic_control_points(x0: f32, y0: f32, cpx: f32, cpy: f32, x: f32, y: f32) -> [f32; 4] {
+    [
+        x0 + 2.0 / 3.0 * (cpx - x0),
+        y0 + 2.0 / 3.0 * (cpy - y0),
+        x + 2.0 / 3.0 * (cpx - x),
+        y + 2.0 / 3.0 * (cpy - y),
+    ]
+}
+
+/// Why `FontFace::try_get_glyph_run_outline` failed.
+#[derive(Debug)]
+pub enum OutlineError {
+    /// The builder gave up, e.g. because the path got too complex.
+    Builder(Box<dyn Error + Send + Sync>),
+    /// A DirectWrite call failed, or the arguments were invalid.
+    Com(HRESULT),
+}
+
+impl fmt::Display for OutlineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OutlineError::Builder(ref error) => write!(f, "outline builder failed: {}", error),
+            OutlineError::Com(hr) => write!(f, "DirectWrite call failed: {:#010x}", hr),
+        }
+    }
+}
+
+impl Error for OutlineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            OutlineError::Builder(ref error) => Some(&**error),
+            OutlineError::Com(_) => None,
+        }
+    }
+}
+
+/// A single step of a glyph outline, as returned by `FontFace::get_glyph_outline`.
+///
+/// Coordinates are in DIPs, with the y axis pointing down.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathCommand {
+    MoveTo {
+        x: f32,
+        y: f32,
+    },
+    LineTo {
+        x: f32,
+        y: f32,
+    },
+    /// DirectWrite reports curves as cubic Béziers, so outlines only contain
+    /// this variant when collected with `PathCollector::quadratic`.
+    QuadTo {
+        cpx: f32,
+        cpy: f32,
+        x: f32,
+        y: f32,
+    },
+    CubicTo {
+        cp0x: f32,
+        cp0y: f32,
+        cp1x: f32,
+        cp1y: f32,
+        x: f32,
+        y: f32,
+    },
+    Close,
+}
+
+/// An `OutlineBuilder` that records the outline as a list of `PathCommand`s.
+///
+/// `get_glyph_run_outline` takes ownership of its builder, so pass it a clone
+/// and read the commands back through the original:
+///
+/// ```no_run
+/// use dwrote::{FontCollection, FontStretch, FontStyle, FontWeight, PathCollector};
+///
+/// let family = FontCollection::system().get_font_family_by_name("Arial").unwrap();
+/// let font =
+///     family.get_first_matching_font(FontWeight::Regular, FontStretch::Normal, FontStyle::Normal);
+/// let face = font.try_create_font_face().unwrap();
+/// let glyphs = face.get_glyph_indices(&['O' as u32]);
+///
+/// let collector = PathCollector::quadratic(0.1);
+/// face.get_glyph_run_outline(16.0, &glyphs, None, None, false, false, Box::new(collector.clone()))
+///     .unwrap();
+/// let commands = collector.commands();
+/// ```
+#[derive(Clone, Default)]
+pub struct PathCollector {
+    commands: Rc<RefCell<Vec<PathCommand>>>,
+    quadratic_tolerance: Option<f32>,
+    current: (f32, f32),
+}
+
+impl PathCollector {
+    pub fn new() -> PathCollector {
+        PathCollector::default()
+    }
+
+    /// Creates a collector that approximates each cubic curve with
+    /// quadratic ones, straying at most `tolerance` DIPs from the original.
+    pub fn quadratic(tolerance: f32) -> PathCollector {
+        assert!(tolerance > 0.0);
+        PathCollector {
+            quadratic_tolerance: Some(tolerance),
+            ..PathCollector::default()
+        }
+    }
+
+    /// Returns the commands recorded so far.
+    pub fn commands(&self) -> Vec<PathCommand> {
+        self.commands.borrow().clone()
+    }
+
+    /// Returns the commands recorded so far, leaving the collector empty.
+    pub fn take_commands(&self) -> Vec<PathCommand> {
+        self.commands.take()
+    }
+
+    fn push(&mut self, command: PathCommand) {
+        self.commands.borrow_mut().push(command)
+    }
+
+    // Splits the cubic in half until a single quadratic is close enough to
+    // each piece. The error of the approximation shrinks eightfold with each
+    // split, so this terminates quickly.
+    fn push_quadratics(&mut self, p: [(f32, f32); 4], tolerance: f32, depth: u32) {
+        let [p0, p1, p2, p3] = p;
+        let dx = p3.0 - 3.0 * p2.0 + 3.0 * p1.0 - p0.0;
+        let dy = p3.1 - 3.0 * p2.1 + 3.0 * p1.1 - p0.1;
+        let error = (dx * dx + dy * dy).sqrt() * 3f32.sqrt() / 36.0;
+        if error <= tolerance || depth == 16 {
+            self.push(PathCommand::QuadTo {
+                cpx: (3.0 * (p1.0 + p2.0) - p0.0 - p3.0) / 4.0,
+                cpy: (3.0 * (p1.1 + p2.1) - p0.1 - p3.1) / 4.0,
+                x: p3.0,
+                y: p3.1,
+            });
+            return;
+        }
+
+        let mid = |a: (f32, f32), b: (f32, f32)| ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+        let p01 = mid(p0, p1);
+        let p12 = mid(p1, p2);
+        let p23 = mid(p2, p3);
+        let p012 = mid(p01, p12);
+        let p123 = mid(p12, p23);
+        let p0123 = mid(p012, p123);
+        self.push_quadratics([p0, p01, p012, p0123], tolerance, depth + 1);
+        self.push_quadratics([p0123, p123, p23, p3], tolerance, depth + 1);
+    }
+}
+
+impl OutlineBuilder for PathCollector {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.current = (x, y);
+        self.push(PathCommand::MoveTo { x, y })
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.current = (x, y);
+        self.push(PathCommand::LineTo { x, y })
+    }
+
+    fn quad_to(&mut self, x0: f32, y0: f32, cpx: f32, cpy: f32, x: f32, y: f32) {
+        if self.quadratic_tolerance.is_some() {
+            self.current = (x, y);
+            self.push(PathCommand::QuadTo { cpx, cpy, x, y });
+            return;
+        }
+        let [cp0x, cp0y, cp1x, cp1y] = cubic_control_points(x0, y0, cpx, cpy, x, y);
+        self.curve_to(cp0x, cp0y, cp1x, cp1y, x, y)
+    }
+
+    fn curve_to(&mut self, cp0x: f32, cp0y: f32, cp1x: f32, cp1y: f32, x: f32, y: f32) {
+        let start = self.current;
+        self.current = (x, y);
+        match self.quadratic_tolerance {
+            Some(tolerance) => {
+                let points = [start, (cp0x, cp0y), (cp1x, cp1y), (x, y)];
+                self.push_quadratics(points, tolerance, 0)
+            }
+            None => self.push(PathCommand::CubicTo {
+                cp0x,
+                cp0y,
+                cp1x,
+                cp1y,
+                x,
+                y,
+            }),
+        }
+    }
+
+    fn close(&mut self) {
+        self.push(PathCommand::Close)
+    }
+
+    fn supports_quads(&self) -> bool {
+        self.quadratic_tolerance.is_some()
+    }
+}
+
+/// Flattens `commands` into polygons, one per subpath, approximating curves
+/// with line segments that stray at most `tolerance` from them. A contour
+/// doesn't repeat its first point at the end.
+pub(crate) fn flatten(commands: &[PathCommand], tolerance: f32) -> Vec<Vec<(f32, f32)>> {
+    assert!(tolerance > 0.0);
+    let mut contours = vec![];
+    let mut contour: Vec<(f32, f32)> = vec![];
+    let mut finish = |contour: &mut Vec<(f32, f32)>| {
+        if contour.len() > 1 && contour.first() == contour.last() {
+            contour.pop();
+        }
+        if contour.len() > 1 {
+            contours.push(mem::take(contour));
+        } else {
+            contour.clear();
+        }
+    };
+    for command in commands {
+        let current = contour.last().cloned().unwrap_or((0.0, 0.0));
+        match *command {
+            PathCommand::MoveTo { x, y } => {
+                finish(&mut contour);
+                contour.push((x, y));
+            }
+            PathCommand::LineTo { x, y } => contour.push((x, y)),
+            PathCommand::QuadTo { cpx, cpy, x, y } => {
+                let cp0 = (
+                    current.0 + 2.0 / 3.0 * (cpx - current.0),
+                    current.1 + 2.0 / 3.0 * (cpy - current.1),
+                );
+                let cp1 = (x + 2.0 / 3.0 * (cpx - x), y + 2.0 / 3.0 * (cpy - y));
+                flatten_cubic(&mut contour, [current, cp0, cp1, (x, y)], tolerance, 0)
+            }
+            PathCommand::CubicTo {
+                cp0x,
+                cp0y,
+                cp1x,
+                cp1y,
+                x,
+                y,
+            } => {
+                let points = [current, (cp0x, cp0y), (cp1x, cp1y), (x, y)];
+                flatten_cubic(&mut contour, points, tolerance, 0)
+            }
+            PathCommand::Close => finish(&mut contour),
+        }
+    }
+    finish(&mut contour);
+    contours
+}
+
+// Splits the cubic in half until each piece is within `tolerance` of the
+// line between its end points, then emits those end points.
+fn flatten_cubic(out: &mut Vec<(f32, f32)>, p: [(f32, f32); 4], tolerance: f32, depth: u32) {
+    let [p0, p1, p2, p3] = p;
+    let ux = 3.0 * p1.0 - 2.0 * p0.0 - p3.0;
+    let uy = 3.0 * p1.1 - 2.0 * p0.1 - p3.1;
+    let vx = 3.0 * p2.0 - p0.0 - 2.0 * p3.0;
+    let vy = 3.0 * p2.1 - p0.1 - 2.0 * p3.1;
+    let flatness = (ux * ux).max(vx * vx) + (uy * uy).max(vy * vy);
+    if flatness <= 16.0 * tolerance * tolerance || depth == 16 {
+        out.push(p3);
+        return;
+    }
+
+    let mid = |a: (f32, f32), b: (f32, f32)| ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+    let p01 = mid(p0, p1);
+    let p12 = mid(p1, p2);
+    let p23 = mid(p2, p3);
+    let p012 = mid(p01, p12);
+    let p123 = mid(p12, p23);
+    let p0123 = mid(p012, p123);
+    flatten_cubic(out, [p0, p01, p012, p0123], tolerance, depth + 1);
+    flatten_cubic(out, [p0123, p123, p23, p3], tolerance, depth + 1);
 }
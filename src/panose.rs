@@ -0,0 +1,168 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::fmt;
+use winapi::um::dwrite_1::DWRITE_PANOSE;
+
+const FAMILY_KIND_NAMES: [&str; 6] = [
+    "Any",
+    "NoFit",
+    "TextDisplay",
+    "Script",
+    "Decorative",
+    "Symbol",
+];
+
+const SERIF_STYLE_NAMES: [&str; 17] = [
+    "Any",
+    "NoFit",
+    "Cove",
+    "ObtuseCove",
+    "SquareCove",
+    "ObtuseSquareCove",
+    "Square",
+    "Thin",
+    "Oval",
+    "Exaggerated",
+    "Triangle",
+    "NormalSans",
+    "ObtuseSans",
+    "PerpendicularSans",
+    "Flared",
+    "Rounded",
+    "Script",
+];
+
+const WEIGHT_NAMES: [&str; 12] = [
+    "Any",
+    "NoFit",
+    "VeryLight",
+    "Light",
+    "Thin",
+    "Book",
+    "Medium",
+    "Demi",
+    "Bold",
+    "Heavy",
+    "Black",
+    "ExtraBlack",
+];
+
+const PROPORTION_NAMES: [&str; 10] = [
+    "Any",
+    "NoFit",
+    "OldStyle",
+    "Modern",
+    "EvenWidth",
+    "Expanded",
+    "Condensed",
+    "VeryExpanded",
+    "VeryCondensed",
+    "Monospaced",
+];
+
+/// The PANOSE classification of a font, see `Font::panose`.
+///
+/// The meaning of every byte after the first depends on the family kind. The
+/// named accessors use the layout of the Latin text family kind, which is what
+/// almost all fonts use.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Panose {
+    pub values: [u8; 10],
+}
+
+impl Panose {
+    pub const FAMILY_KIND_TEXT_DISPLAY: u8 = 2;
+    pub const PROPORTION_MONOSPACED: u8 = 9;
+
+    pub(crate) fn from_native(panose: &DWRITE_PANOSE) -> Panose {
+        Panose {
+            values: unsafe { *panose.values() },
+        }
+    }
+
+    pub fn family_kind(&self) -> u8 {
+        self.values[0]
+    }
+
+    pub fn serif_style(&self) -> u8 {
+        self.values[1]
+    }
+
+    pub fn weight(&self) -> u8 {
+        self.values[2]
+    }
+
+    pub fn proportion(&self) -> u8 {
+        self.values[3]
+    }
+
+    pub fn contrast(&self) -> u8 {
+        self.values[4]
+    }
+
+    pub fn stroke_variation(&self) -> u8 {
+        self.values[5]
+    }
+
+    pub fn arm_style(&self) -> u8 {
+        self.values[6]
+    }
+
+    pub fn letterform(&self) -> u8 {
+        self.values[7]
+    }
+
+    pub fn midline(&self) -> u8 {
+        self.values[8]
+    }
+
+    pub fn x_height(&self) -> u8 {
+        self.values[9]
+    }
+
+    /// Returns whether the classification says this is a monospaced text
+    /// font.
+    pub fn is_monospaced_by_panose(&self) -> bool {
+        self.family_kind() == Panose::FAMILY_KIND_TEXT_DISPLAY
+            && self.proportion() == Panose::PROPORTION_MONOSPACED
+    }
+}
+
+struct Class(&'static [&'static str], u8);
+
+impl fmt::Debug for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.get(self.1 as usize) {
+            Some(name) => f.write_str(name),
+            None => write!(f, "{}", self.1),
+        }
+    }
+}
+
+impl fmt::Debug for Panose {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = f.debug_struct("Panose");
+        s.field(
+            "family_kind",
+            &Class(&FAMILY_KIND_NAMES, self.family_kind()),
+        );
+        if self.family_kind() != Panose::FAMILY_KIND_TEXT_DISPLAY {
+            return s.field("values", &&self.values[1..]).finish();
+        }
+        s.field(
+            "serif_style",
+            &Class(&SERIF_STYLE_NAMES, self.serif_style()),
+        )
+        .field("weight", &Class(&WEIGHT_NAMES, self.weight()))
+        .field("proportion", &Class(&PROPORTION_NAMES, self.proportion()))
+        .field("contrast", &self.contrast())
+        .field("stroke_variation", &self.stroke_variation())
+        .field("arm_style", &self.arm_style())
+        .field("letterform", &self.letterform())
+        .field("midline", &self.midline())
+        .field("x_height", &self.x_height())
+        .finish()
+    }
+}
@@ -3,23 +3,271 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use std::cell::UnsafeCell;
+use std::mem;
 use std::ptr;
-use winapi::um::dwrite::IDWriteRenderingParams;
+use winapi::shared::windef::HMONITOR;
+use winapi::shared::winerror::E_NOINTERFACE;
+use winapi::um::dwrite::{IDWriteRenderingParams, DWRITE_RENDERING_MODE};
+use winapi::um::dwrite::{DWRITE_PIXEL_GEOMETRY, DWRITE_PIXEL_GEOMETRY_BGR};
+use winapi::um::dwrite::{DWRITE_PIXEL_GEOMETRY_FLAT, DWRITE_PIXEL_GEOMETRY_RGB};
+use winapi::um::dwrite_1::IDWriteRenderingParams1;
+use winapi::um::dwrite_2::{IDWriteFactory2, IDWriteRenderingParams2, DWRITE_GRID_FIT_MODE};
+use winapi::um::dwrite_3::{IDWriteFactory3, IDWriteRenderingParams3, DWRITE_RENDERING_MODE1};
 use wio::com::ComPtr;
 
-use super::DWriteFactory;
+use super::GridFitMode;
+use crate::error::Error;
+use crate::factory::{factory_or_shared, Factory};
+
+// mirrors DWRITE_PIXEL_GEOMETRY
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelGeometry {
+    /// Not a subpixel display, or one whose layout is unknown.
+    Flat = DWRITE_PIXEL_GEOMETRY_FLAT,
+    Rgb = DWRITE_PIXEL_GEOMETRY_RGB,
+    Bgr = DWRITE_PIXEL_GEOMETRY_BGR,
+}
+
+impl PixelGeometry {
+    fn from_raw(geometry: DWRITE_PIXEL_GEOMETRY) -> PixelGeometry {
+        match geometry {
+            DWRITE_PIXEL_GEOMETRY_RGB => PixelGeometry::Rgb,
+            DWRITE_PIXEL_GEOMETRY_BGR => PixelGeometry::Bgr,
+            _ => PixelGeometry::Flat,
+        }
+    }
+}
 
 pub struct RenderingParams {
     native: UnsafeCell<ComPtr<IDWriteRenderingParams>>,
 }
 
 impl RenderingParams {
+    #[deprecated(
+        note = "use `try_create_for_primary_monitor`, which returns an error instead of panicking"
+    )]
     pub fn create_for_primary_monitor() -> RenderingParams {
+        RenderingParams::try_create_for_primary_monitor().expect("error creating rendering params")
+    }
+
+    /// Creates rendering parameters with the current system settings for the
+    /// primary monitor.
+    pub fn try_create_for_primary_monitor() -> Result<RenderingParams, Error> {
+        RenderingParams::try_create_for_primary_monitor_with_factory(None)
+    }
+
+    pub fn try_create_for_primary_monitor_with_factory(
+        factory: Option<&Factory>,
+    ) -> Result<RenderingParams, Error> {
         unsafe {
             let mut native: *mut IDWriteRenderingParams = ptr::null_mut();
-            let hr = (*DWriteFactory()).CreateRenderingParams(&mut native);
-            assert!(hr == 0);
-            RenderingParams::take(ComPtr::from_raw(native))
+            let hr = (*factory_or_shared(factory)).CreateRenderingParams(&mut native);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(RenderingParams::take(ComPtr::from_raw(native)))
+        }
+    }
+
+    /// Creates rendering parameters with the current system settings for the
+    /// given monitor, including its subpixel layout, which can differ
+    /// between monitors (for example when one of them is rotated).
+    ///
+    /// # Safety
+    ///
+    /// `monitor` must be a valid monitor handle.
+    pub unsafe fn create_for_monitor(monitor: HMONITOR) -> Result<RenderingParams, Error> {
+        RenderingParams::create_for_monitor_with_factory(monitor, None)
+    }
+
+    /// # Safety
+    ///
+    /// `monitor` must be a valid monitor handle.
+    pub unsafe fn create_for_monitor_with_factory(
+        monitor: HMONITOR,
+        factory: Option<&Factory>,
+    ) -> Result<RenderingParams, Error> {
+        let mut native: *mut IDWriteRenderingParams = ptr::null_mut();
+        let hr = (*factory_or_shared(factory)).CreateMonitorRenderingParams(monitor, &mut native);
+        if hr != 0 {
+            return Err(hr.into());
+        }
+        Ok(RenderingParams::take(ComPtr::from_raw(native)))
+    }
+
+    /// Creates rendering parameters with explicit values, for example a
+    /// ClearType level of 0 to render grayscale text.
+    ///
+    /// `gamma` must be positive, `enhanced_contrast` non-negative and
+    /// `cleartype_level` between 0 and 1; otherwise this fails with
+    /// `Error::InvalidArg`.
+    pub fn create_custom(
+        gamma: f32,
+        enhanced_contrast: f32,
+        cleartype_level: f32,
+        pixel_geometry: PixelGeometry,
+        rendering_mode: DWRITE_RENDERING_MODE,
+    ) -> Result<RenderingParams, Error> {
+        RenderingParams::create_custom_with_factory(
+            gamma,
+            enhanced_contrast,
+            cleartype_level,
+            pixel_geometry,
+            rendering_mode,
+            None,
+        )
+    }
+
+    pub fn create_custom_with_factory(
+        gamma: f32,
+        enhanced_contrast: f32,
+        cleartype_level: f32,
+        pixel_geometry: PixelGeometry,
+        rendering_mode: DWRITE_RENDERING_MODE,
+        factory: Option<&Factory>,
+    ) -> Result<RenderingParams, Error> {
+        check_custom_values(gamma, enhanced_contrast, cleartype_level)?;
+        unsafe {
+            let mut native: *mut IDWriteRenderingParams = ptr::null_mut();
+            let hr = (*factory_or_shared(factory)).CreateCustomRenderingParams(
+                gamma,
+                enhanced_contrast,
+                cleartype_level,
+                pixel_geometry as DWRITE_PIXEL_GEOMETRY,
+                rendering_mode,
+                &mut native,
+            );
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(RenderingParams::take(ComPtr::from_raw(native)))
+        }
+    }
+
+    /// Like `create_custom`, but also sets the enhanced contrast for
+    /// grayscale antialiasing and the grid-fit mode.
+    ///
+    /// Fails with `E_NOINTERFACE` if `IDWriteFactory2` isn't available.
+    pub fn create_custom2(
+        gamma: f32,
+        enhanced_contrast: f32,
+        grayscale_enhanced_contrast: f32,
+        cleartype_level: f32,
+        pixel_geometry: PixelGeometry,
+        rendering_mode: DWRITE_RENDERING_MODE,
+        grid_fit_mode: GridFitMode,
+    ) -> Result<RenderingParams, Error> {
+        RenderingParams::create_custom2_with_factory(
+            gamma,
+            enhanced_contrast,
+            grayscale_enhanced_contrast,
+            cleartype_level,
+            pixel_geometry,
+            rendering_mode,
+            grid_fit_mode,
+            None,
+        )
+    }
+
+    pub fn create_custom2_with_factory(
+        gamma: f32,
+        enhanced_contrast: f32,
+        grayscale_enhanced_contrast: f32,
+        cleartype_level: f32,
+        pixel_geometry: PixelGeometry,
+        rendering_mode: DWRITE_RENDERING_MODE,
+        grid_fit_mode: GridFitMode,
+        factory: Option<&Factory>,
+    ) -> Result<RenderingParams, Error> {
+        check_custom_values(gamma, enhanced_contrast, cleartype_level)?;
+        check_custom_values(gamma, grayscale_enhanced_contrast, cleartype_level)?;
+        unsafe {
+            let factory = ComPtr::from_raw(factory_or_shared(factory));
+            let factory2: Option<ComPtr<IDWriteFactory2>> = factory.cast().ok();
+            mem::forget(factory);
+            let factory2 = factory2.ok_or(E_NOINTERFACE)?;
+
+            let mut native: *mut IDWriteRenderingParams2 = ptr::null_mut();
+            let hr = factory2.CreateCustomRenderingParams(
+                gamma,
+                enhanced_contrast,
+                grayscale_enhanced_contrast,
+                cleartype_level,
+                pixel_geometry as DWRITE_PIXEL_GEOMETRY,
+                rendering_mode,
+                grid_fit_mode as DWRITE_GRID_FIT_MODE,
+                &mut native,
+            );
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(RenderingParams::take(ComPtr::from_raw(
+                native as *mut IDWriteRenderingParams,
+            )))
+        }
+    }
+
+    /// Like `create_custom2`, but takes one of the newer rendering modes,
+    /// such as `DWRITE_RENDERING_MODE1_NATURAL_SYMMETRIC_DOWNSAMPLED`.
+    ///
+    /// Fails with `E_NOINTERFACE` if `IDWriteFactory3` isn't available.
+    pub fn create_custom3(
+        gamma: f32,
+        enhanced_contrast: f32,
+        grayscale_enhanced_contrast: f32,
+        cleartype_level: f32,
+        pixel_geometry: PixelGeometry,
+        rendering_mode: DWRITE_RENDERING_MODE1,
+        grid_fit_mode: GridFitMode,
+    ) -> Result<RenderingParams, Error> {
+        RenderingParams::create_custom3_with_factory(
+            gamma,
+            enhanced_contrast,
+            grayscale_enhanced_contrast,
+            cleartype_level,
+            pixel_geometry,
+            rendering_mode,
+            grid_fit_mode,
+            None,
+        )
+    }
+
+    pub fn create_custom3_with_factory(
+        gamma: f32,
+        enhanced_contrast: f32,
+        grayscale_enhanced_contrast: f32,
+        cleartype_level: f32,
+        pixel_geometry: PixelGeometry,
+        rendering_mode: DWRITE_RENDERING_MODE1,
+        grid_fit_mode: GridFitMode,
+        factory: Option<&Factory>,
+    ) -> Result<RenderingParams, Error> {
+        check_custom_values(gamma, enhanced_contrast, cleartype_level)?;
+        check_custom_values(gamma, grayscale_enhanced_contrast, cleartype_level)?;
+        unsafe {
+            let factory = ComPtr::from_raw(factory_or_shared(factory));
+            let factory3: Option<ComPtr<IDWriteFactory3>> = factory.cast().ok();
+            mem::forget(factory);
+            let factory3 = factory3.ok_or(E_NOINTERFACE)?;
+
+            let mut native: *mut IDWriteRenderingParams3 = ptr::null_mut();
+            let hr = factory3.CreateCustomRenderingParams(
+                gamma,
+                enhanced_contrast,
+                grayscale_enhanced_contrast,
+                cleartype_level,
+                pixel_geometry as DWRITE_PIXEL_GEOMETRY,
+                rendering_mode,
+                grid_fit_mode as DWRITE_GRID_FIT_MODE,
+                &mut native,
+            );
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(RenderingParams::take(ComPtr::from_raw(
+                native as *mut IDWriteRenderingParams,
+            )))
         }
     }
 
@@ -32,4 +280,62 @@ impl RenderingParams {
     pub unsafe fn as_ptr(&self) -> *mut IDWriteRenderingParams {
         (*self.native.get()).as_raw()
     }
+
+    pub fn gamma(&self) -> f32 {
+        unsafe { (*self.native.get()).GetGamma() }
+    }
+
+    pub fn enhanced_contrast(&self) -> f32 {
+        unsafe { (*self.native.get()).GetEnhancedContrast() }
+    }
+
+    pub fn cleartype_level(&self) -> f32 {
+        unsafe { (*self.native.get()).GetClearTypeLevel() }
+    }
+
+    pub fn pixel_geometry(&self) -> PixelGeometry {
+        unsafe { PixelGeometry::from_raw((*self.native.get()).GetPixelGeometry()) }
+    }
+
+    pub fn rendering_mode(&self) -> DWRITE_RENDERING_MODE {
+        unsafe { (*self.native.get()).GetRenderingMode() }
+    }
+
+    /// Returns `None` if `IDWriteRenderingParams1` isn't available.
+    pub fn grayscale_enhanced_contrast(&self) -> Option<f32> {
+        unsafe {
+            let params1: ComPtr<IDWriteRenderingParams1> = (*self.native.get()).cast().ok()?;
+            Some(params1.GetGrayscaleEnhancedContrast())
+        }
+    }
+
+    /// Returns `None` if `IDWriteRenderingParams2` isn't available.
+    pub fn grid_fit_mode(&self) -> Option<GridFitMode> {
+        unsafe {
+            let params2: ComPtr<IDWriteRenderingParams2> = (*self.native.get()).cast().ok()?;
+            Some(GridFitMode::from_raw(params2.GetGridFitMode()))
+        }
+    }
+
+    /// Returns `None` if `IDWriteRenderingParams3` isn't available.
+    pub fn rendering_mode1(&self) -> Option<DWRITE_RENDERING_MODE1> {
+        unsafe {
+            let params3: ComPtr<IDWriteRenderingParams3> = (*self.native.get()).cast().ok()?;
+            Some(params3.GetRenderingMode1())
+        }
+    }
+}
+
+// DirectWrite accepts some invalid values (such as a NaN gamma) without
+// complaint, so the constructors check them up front.
+fn check_custom_values(
+    gamma: f32,
+    enhanced_contrast: f32,
+    cleartype_level: f32,
+) -> Result<(), Error> {
+    if gamma > 0.0 && enhanced_contrast >= 0.0 && (0.0..=1.0).contains(&cleartype_level) {
+        Ok(())
+    } else {
+        Err(Error::InvalidArg)
+    }
 }
@@ -5,6 +5,18 @@
 use super::*;
 use std::sync::Arc;
 
+// Regular Arial from the system collection, the font most tests run against.
+fn arial_font() -> Font {
+    FontCollection::system()
+        .get_font_family_by_name("Arial")
+        .unwrap()
+        .get_first_matching_font(FontWeight::Regular, FontStretch::Normal, FontStyle::Normal)
+}
+
+fn arial_face() -> FontFace {
+    arial_font().try_create_font_face().unwrap()
+}
+
 #[test]
 fn test_system_family_iter() {
     let system_fc = FontCollection::system();
@@ -36,6 +48,15 @@ fn test_descriptor_round_trip() {
 }
 
 #[test]
+fn test_contains_family() {
+    let system_fc = FontCollection::system();
+    assert!(system_fc.contains_family("Arial"));
+    assert!(system_fc.contains_family("arial"));
+    assert!(!system_fc.contains_family("Definitely Not An Installed Font"));
+}
+
+#[test]
+#[allow(deprecated)]
 fn test_get_font_file_bytes() {
     let system_fc = FontCollection::system();
 
@@ -75,6 +96,7 @@ fn test_font_file_is_monospace() {
 }
 
 #[test]
+#[allow(deprecated)]
 fn test_create_font_file_from_bytes() {
     let system_fc = FontCollection::system();
 
@@ -99,6 +121,7 @@ fn test_create_font_file_from_bytes() {
 }
 
 #[test]
+#[allow(deprecated)]
 fn test_glyph_image() {
     let system_fc = FontCollection::system();
     let arial_family = system_fc.get_font_family_by_name("Arial").unwrap();
@@ -144,7 +167,9 @@ fn test_glyph_image() {
     );
 
     let gdi_interop = GdiInterop::create();
-    let rt = gdi_interop.create_bitmap_render_target(width_u, height_u);
+    let rt = gdi_interop
+        .create_bitmap_render_target(None, width_u, height_u)
+        .unwrap();
     let rp = RenderingParams::create_for_primary_monitor();
     rt.set_pixels_per_dip(device_pixel_ratio);
     rt.draw_glyph_run(
@@ -161,7 +186,3234 @@ fn test_glyph_image() {
         }],
         &rp,
         &(255.0f32, 255.0f32, 255.0f32),
-    );
+    )
+    .unwrap();
     let bytes = rt.get_opaque_values_as_mask();
     println!("bytes length: {}", bytes.len());
 }
+
+#[test]
+fn test_try_constructors() {
+    let face = arial_face();
+    assert!(!face.get_files().is_empty());
+
+    let factory = Factory::isolated().unwrap();
+    let gdi_interop = GdiInterop::try_create_with_factory(Some(&factory)).unwrap();
+    assert!(gdi_interop
+        .create_bitmap_render_target(None, 16, 16)
+        .is_ok());
+    let params =
+        RenderingParams::try_create_for_primary_monitor_with_factory(Some(&factory)).unwrap();
+    assert!(params.gamma() > 0.0);
+    assert!(NumberSubstitution::try_new_with_factory(
+        winapi::um::dwrite::DWRITE_NUMBER_SUBSTITUTION_METHOD_NONE,
+        "en-us",
+        true,
+        Some(&factory),
+    )
+    .is_ok());
+}
+
+#[test]
+fn test_design_units_per_em() {
+    let face = arial_face();
+
+    let design_units_per_em = match face.metrics() {
+        FontMetrics::Metrics0(ref metrics) => metrics.designUnitsPerEm,
+        FontMetrics::Metrics1(ref metrics) => metrics.designUnitsPerEm,
+    };
+    assert_eq!(face.design_units_per_em(), design_units_per_em);
+    assert_eq!(face.design_units_per_em(), 2048);
+}
+
+#[test]
+fn test_isolated_factory() {
+    let factory = Factory::isolated().unwrap();
+    let system_fc = FontCollection::get_system_with_factory(false, Some(&factory));
+    let arial_family = system_fc.get_font_family_by_name("Arial").unwrap();
+    let arial_font = arial_family.get_first_matching_font(
+        FontWeight::Regular,
+        FontStretch::Normal,
+        FontStyle::Normal,
+    );
+    let arial_file = &arial_font.try_create_font_face().unwrap().get_files()[0];
+    let bytes = Arc::new(arial_file.get_font_file_bytes());
+    let path = arial_file.get_font_file_path().unwrap();
+
+    let files = vec![
+        FontFile::new_from_data_with_factory(bytes.clone(), Some(&factory)).unwrap(),
+        FontFile::new_from_data_in_memory_with_factory(bytes, Some(&factory)).unwrap(),
+        FontFile::new_from_file_mapped_with_factory(&path, Some(&factory)).unwrap(),
+    ];
+    for file in files {
+        let face = file
+            .create_face_with_factory(0, DWRITE_FONT_SIMULATIONS_NONE, Some(&factory))
+            .unwrap();
+        assert!(face.get_glyph_count() > 0);
+    }
+}
+
+#[test]
+fn test_create_font_file_in_memory() {
+    let bytes = arial_face().get_files()[0].get_font_file_bytes();
+
+    let file = FontFile::new_from_data_in_memory(Arc::new(bytes)).unwrap();
+    let face = file.create_face(0, DWRITE_FONT_SIMULATIONS_NONE).unwrap();
+    assert!(face.get_glyph_count() > 0);
+
+    let garbage: Arc<Vec<u8>> = Arc::new(b"definitely not a font".to_vec());
+    assert!(FontFile::new_from_data_in_memory(garbage).is_err());
+}
+
+#[test]
+fn test_create_font_face_with_type() {
+    let arial_face = arial_face();
+    let bytes = Arc::new(arial_face.get_files()[0].get_font_file_bytes());
+
+    let face = FontFile::new_from_buffer_with_type(
+        bytes.clone(),
+        FontFileType::TrueType,
+        FontFaceType::TrueType,
+        0,
+        DWRITE_FONT_SIMULATIONS_BOLD,
+    )
+    .unwrap();
+    assert_eq!(face.get_type(), FontFaceType::TrueType);
+    assert_eq!(face.get_glyph_count(), arial_face.get_glyph_count());
+    assert_eq!(face.simulations(), FontSimulations::Bold);
+
+    let create = |file_type, face_type, index| {
+        FontFile::new_from_buffer_with_type(
+            bytes.clone(),
+            file_type,
+            face_type,
+            index,
+            DWRITE_FONT_SIMULATIONS_NONE,
+        )
+        .err()
+    };
+    assert_eq!(
+        create(FontFileType::Cff, FontFaceType::Cff, 0),
+        Some(Error::FileFormat)
+    );
+    assert_eq!(
+        create(FontFileType::TrueType, FontFaceType::TrueTypeCollection, 0),
+        Some(Error::FileFormat)
+    );
+    assert_eq!(
+        create(FontFileType::TrueType, FontFaceType::TrueType, 1),
+        Some(Error::Other(winapi::shared::winerror::E_BOUNDS))
+    );
+}
+
+#[test]
+fn test_font_face_metrics1() {
+    let face = arial_face();
+
+    let metrics1 = face.metrics1();
+    assert!(!metrics1.synthesized);
+    let metrics = metrics1.metrics;
+    assert!(metrics.underlineThickness > 0);
+    assert!(metrics.strikethroughThickness > 0);
+    assert!(metrics.glyphBoxRight > metrics.glyphBoxLeft);
+
+    // Upright font, so the caret is vertical.
+    let caret = face.caret_metrics().unwrap();
+    assert_eq!(caret.slopeRun, 0);
+}
+
+#[test]
+fn test_create_font_file_mapped() {
+    let face = arial_face();
+    let path = face.get_files()[0].get_font_file_path().unwrap();
+
+    let file = FontFile::new_from_file_mapped(&path).unwrap();
+    let mapped_face = file.create_face(0, DWRITE_FONT_SIMULATIONS_NONE).unwrap();
+    assert_eq!(mapped_face.get_glyph_count(), face.get_glyph_count());
+    assert_eq!(
+        file.get_font_file_bytes(),
+        face.get_files()[0].get_font_file_bytes()
+    );
+
+    let missing = std::path::Path::new("C:\\no\\such\\font.ttf");
+    assert!(FontFile::new_from_file_mapped(missing).is_err());
+}
+
+#[test]
+fn test_font_face_classification() {
+    let system_fc = FontCollection::system();
+    let face_for = |family_name: &str| {
+        system_fc
+            .get_font_family_by_name(family_name)
+            .unwrap()
+            .get_first_matching_font(FontWeight::Regular, FontStretch::Normal, FontStyle::Normal)
+            .try_create_font_face()
+            .unwrap()
+    };
+
+    let arial = face_for("Arial");
+    assert!(!arial.is_monospaced_font());
+    assert!(!arial.is_symbol_font());
+    assert!(!arial.is_color_font());
+
+    assert!(face_for("Courier New").is_monospaced_font());
+    assert!(face_for("Symbol").is_symbol_font());
+    assert!(face_for("Segoe UI Emoji").is_color_font());
+}
+
+#[test]
+fn test_font_file_analyze() {
+    let files = arial_face().get_files();
+    let analysis = files[0].analyze().unwrap();
+    assert!(analysis.is_supported);
+    assert_eq!(analysis.file_type, FontFileType::TrueType);
+    assert_eq!(analysis.face_type, FontFaceType::TrueType);
+    assert_eq!(analysis.number_of_faces, 1);
+
+    let ttc = FontFile::new_from_path("C:\\Windows\\Fonts\\cambria.ttc").unwrap();
+    let analysis = ttc.analyze().unwrap();
+    assert!(analysis.is_supported);
+    assert_eq!(analysis.file_type, FontFileType::OpenTypeCollection);
+    assert_eq!(analysis.face_type, FontFaceType::TrueTypeCollection);
+    assert!(analysis.number_of_faces > 1);
+
+    // A text file with a font extension is rejected up front.
+    let path = std::env::temp_dir().join("dwrote-not-a-font.ttf");
+    std::fs::write(&path, "this is not a font").unwrap();
+    assert!(FontFile::new_from_path(&path).is_none());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_create_face_from_collection_file() {
+    let ttc = FontFile::new_from_path("C:\\Windows\\Fonts\\cambria.ttc").unwrap();
+    let face0 = ttc.create_face(0, DWRITE_FONT_SIMULATIONS_NONE).unwrap();
+    let face1 = ttc.create_face(1, DWRITE_FONT_SIMULATIONS_NONE).unwrap();
+    assert_eq!(face1.get_index(), 1);
+
+    let system_fc = FontCollection::system();
+    let family0 = system_fc.get_font_from_face(&face0).unwrap().family_name();
+    let family1 = system_fc.get_font_from_face(&face1).unwrap().family_name();
+    assert_ne!(family0, family1);
+
+    let face_count = ttc.analyze().unwrap().number_of_faces;
+    assert_eq!(
+        ttc.create_face(face_count, DWRITE_FONT_SIMULATIONS_NONE)
+            .err(),
+        Some(Error::Other(winapi::shared::winerror::E_BOUNDS))
+    );
+}
+
+#[test]
+fn test_should_use_outline() {
+    let face = arial_face();
+    let params = RenderingParams::try_create_for_primary_monitor().unwrap();
+    assert!(!face.should_use_outline(12.0, 1.0, &params));
+    assert!(face.should_use_outline(1000.0, 1.0, &params));
+}
+
+#[test]
+fn test_font_file_reference_key() {
+    let file = &arial_face().get_files()[0];
+    assert_eq!(file.loader_identity(), FontFileLoaderId::Local);
+    assert!(!file.reference_key().is_empty());
+
+    let data_file = FontFile::new_from_data(Arc::new(file.get_font_file_bytes())).unwrap();
+    match data_file.loader_identity() {
+        FontFileLoaderId::Custom(_) => {}
+        FontFileLoaderId::Local => panic!("data font reported the local loader"),
+    }
+}
+
+#[test]
+fn test_get_glyph_outline() {
+    let face = arial_face();
+    let glyph = face.get_glyph_indices(&['O' as u32])[0];
+
+    let outline = face.get_glyph_outline(glyph, 16.0).unwrap();
+    match outline.first() {
+        Some(PathCommand::MoveTo { .. }) => {}
+        other => panic!("expected outline to start with MoveTo, got {:?}", other),
+    }
+    assert!(outline
+        .iter()
+        .any(|c| matches!(c, PathCommand::CubicTo { .. })));
+    assert_eq!(outline.last(), Some(&PathCommand::Close));
+}
+
+#[test]
+fn test_path_collector_contours() {
+    // Signed area of each contour, treating control points as polygon
+    // vertices, which is plenty to tell the winding direction apart.
+    fn contour_areas(commands: &[PathCommand]) -> Vec<f32> {
+        let mut areas = vec![];
+        let mut points: Vec<(f32, f32)> = vec![];
+        for command in commands {
+            match *command {
+                PathCommand::MoveTo { x, y } => points = vec![(x, y)],
+                PathCommand::LineTo { x, y } => points.push((x, y)),
+                PathCommand::QuadTo { cpx, cpy, x, y } => points.extend(&[(cpx, cpy), (x, y)]),
+                PathCommand::CubicTo {
+                    cp0x,
+                    cp0y,
+                    cp1x,
+                    cp1y,
+                    x,
+                    y,
+                } => points.extend(&[(cp0x, cp0y), (cp1x, cp1y), (x, y)]),
+                PathCommand::Close => {
+                    let n = points.len();
+                    let area: f32 = (0..n)
+                        .map(|i| {
+                            let (a, b) = (points[i], points[(i + 1) % n]);
+                            a.0 * b.1 - b.0 * a.1
+                        })
+                        .sum();
+                    areas.push(area / 2.0);
+                }
+            }
+        }
+        areas
+    }
+
+    let face = arial_face();
+    let glyphs = face.get_glyph_indices(&['O' as u32]);
+
+    let cubic = PathCollector::new();
+    face.get_glyph_run_outline(
+        64.0,
+        &glyphs,
+        None,
+        None,
+        false,
+        false,
+        Box::new(cubic.clone()),
+    )
+    .unwrap();
+    let cubic = cubic.commands();
+    let quadratic = PathCollector::quadratic(0.05);
+    face.get_glyph_run_outline(
+        64.0,
+        &glyphs,
+        None,
+        None,
+        false,
+        false,
+        Box::new(quadratic.clone()),
+    )
+    .unwrap();
+    let quadratic = quadratic.commands();
+
+    assert!(!quadratic
+        .iter()
+        .any(|c| matches!(c, PathCommand::CubicTo { .. })));
+    assert!(quadratic.len() >= cubic.len());
+    for commands in &[cubic, quadratic] {
+        let moves = commands
+            .iter()
+            .filter(|c| matches!(c, PathCommand::MoveTo { .. }))
+            .count();
+        assert_eq!(moves, 2);
+        let areas = contour_areas(commands);
+        assert_eq!(areas.len(), 2);
+        assert!(areas[0] * areas[1] < 0.0);
+    }
+}
+
+#[test]
+fn test_glyph_outline_flattened() {
+    let face = arial_face();
+    let glyph = face.get_glyph_indices(&['O' as u32])[0];
+
+    let coarse = face.get_glyph_outline_flattened(glyph, 64.0, 1.0).unwrap();
+    let fine = face.get_glyph_outline_flattened(glyph, 64.0, 0.01).unwrap();
+    assert_eq!(coarse.len(), 2);
+    assert_eq!(fine.len(), 2);
+    for (coarse, fine) in coarse.iter().zip(&fine) {
+        assert!(coarse.len() >= 4);
+        assert!(fine.len() > coarse.len());
+        assert_ne!(fine.first(), fine.last());
+    }
+    // Everything lies in the glyph box, above the baseline.
+    assert!(fine
+        .iter()
+        .flatten()
+        .all(|&(x, y)| (0.0..=64.0).contains(&x) && (-64.0..=1.0).contains(&y)));
+}
+
+#[test]
+fn test_glyph_run_outline_positions() {
+    let face = arial_face();
+    let glyphs = face.get_glyph_indices(&['l' as u32, 'l' as u32]);
+    let move_tos = |commands: Vec<PathCommand>| -> Vec<(f32, f32)> {
+        commands
+            .into_iter()
+            .filter_map(|c| match c {
+                PathCommand::MoveTo { x, y } => Some((x, y)),
+                _ => None,
+            })
+            .collect()
+    };
+
+    let collector = PathCollector::new();
+    face.get_glyph_run_outline(
+        32.0,
+        &glyphs,
+        Some(&[50.0, 0.0]),
+        None,
+        false,
+        false,
+        Box::new(collector.clone()),
+    )
+    .unwrap();
+    // 'l' is a single contour, so there's one MoveTo per glyph.
+    let starts = move_tos(collector.take_commands());
+    assert_eq!(starts.len(), 2);
+    assert!((starts[1].0 - starts[0].0 - 50.0).abs() < 0.01);
+    assert_eq!(starts[1].1, starts[0].1);
+
+    let offsets = [
+        GlyphOffset {
+            advanceOffset: 0.0,
+            ascenderOffset: 0.0,
+        },
+        GlyphOffset {
+            advanceOffset: 0.0,
+            ascenderOffset: 10.0,
+        },
+    ];
+    face.get_glyph_run_outline(
+        32.0,
+        &glyphs,
+        Some(&[50.0, 0.0]),
+        Some(&offsets),
+        false,
+        false,
+        Box::new(collector.clone()),
+    )
+    .unwrap();
+    let raised = move_tos(collector.take_commands());
+    assert!((raised[1].1 - (starts[1].1 - 10.0)).abs() < 0.01);
+
+    // Mismatched lengths are caught before calling into DirectWrite.
+    assert_eq!(
+        face.get_glyph_run_outline(
+            32.0,
+            &glyphs,
+            Some(&[50.0]),
+            None,
+            false,
+            false,
+            Box::new(collector.clone()),
+        ),
+        Err(Error::InvalidArg)
+    );
+    assert!(collector.commands().is_empty());
+}
+
+#[test]
+fn test_glyph_run_outline_builder_error() {
+    // Gives up as soon as a second contour starts.
+    struct OneContour {
+        contours: usize,
+        commands: usize,
+    }
+
+    impl TryOutlineBuilder for OneContour {
+        fn try_move_to(&mut self, _: f32, _: f32) -> Result<(), OutlineError> {
+            if self.contours == 1 {
+                return Err(OutlineError::Builder("too many contours".into()));
+            }
+            self.commands += 1;
+            Ok(())
+        }
+
+        fn try_line_to(&mut self, _: f32, _: f32) -> Result<(), OutlineError> {
+            self.commands += 1;
+            Ok(())
+        }
+
+        fn try_curve_to(
+            &mut self,
+            _: f32,
+            _: f32,
+            _: f32,
+            _: f32,
+            _: f32,
+            _: f32,
+        ) -> Result<(), OutlineError> {
+            self.commands += 1;
+            Ok(())
+        }
+
+        fn try_close(&mut self) -> Result<(), OutlineError> {
+            self.contours += 1;
+            self.commands += 1;
+            Ok(())
+        }
+    }
+
+    let face = arial_face();
+    let glyphs = face.get_glyph_indices(&['O' as u32]);
+
+    let mut builder = OneContour {
+        contours: 0,
+        commands: 0,
+    };
+    match face.try_get_glyph_run_outline(32.0, &glyphs, None, None, false, false, &mut builder) {
+        Err(OutlineError::Builder(error)) => assert_eq!(error.to_string(), "too many contours"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    // Nothing reached the builder after the error.
+    let commands = builder.commands;
+    assert_eq!(builder.contours, 1);
+    let full = face.get_glyph_outline(glyphs[0], 32.0).unwrap();
+    assert!(commands < full.len());
+
+    // Infallible builders work too.
+    let mut collector = PathCollector::new();
+    face.try_get_glyph_run_outline(32.0, &glyphs, None, None, false, false, &mut collector)
+        .unwrap();
+    assert_eq!(collector.commands(), full);
+}
+
+#[test]
+fn test_extract_outline() {
+    #[derive(Default)]
+    struct Counts {
+        moves: usize,
+        lines: usize,
+        quads: usize,
+        curves: usize,
+        closes: usize,
+    }
+
+    impl OutlineBuilder for Counts {
+        fn move_to(&mut self, _: f32, _: f32) {
+            self.moves += 1;
+        }
+
+        fn line_to(&mut self, _: f32, _: f32) {
+            self.lines += 1;
+        }
+
+        fn quad_to(&mut self, _: f32, _: f32, _: f32, _: f32, _: f32, _: f32) {
+            self.quads += 1;
+        }
+
+        fn curve_to(&mut self, _: f32, _: f32, _: f32, _: f32, _: f32, _: f32) {
+            self.curves += 1;
+        }
+
+        fn close(&mut self) {
+            self.closes += 1;
+        }
+
+        fn supports_quads(&self) -> bool {
+            true
+        }
+    }
+
+    let face = arial_face();
+    let glyph = face.get_glyph_indices(&['O' as u32])[0];
+
+    let mut counts = Counts::default();
+    face.extract_outline(glyph, 32.0, &mut counts).unwrap();
+    assert_eq!(counts.moves, 2);
+    assert_eq!(counts.closes, 2);
+    // Arial is a TrueType font, so its curves come through as quadratics.
+    assert!(counts.quads > 0);
+    let full = face.get_glyph_outline(glyph, 32.0).unwrap();
+    assert_eq!(
+        counts.moves + counts.lines + counts.quads + counts.curves + counts.closes,
+        full.len()
+    );
+
+    let mut collector = PathCollector::quadratic(0.05);
+    face.extract_outline(glyph, 32.0, &mut collector).unwrap();
+    assert!(collector
+        .commands()
+        .iter()
+        .all(|c| !matches!(c, PathCommand::CubicTo { .. })));
+
+    // Builders that don't opt into quads get every curve as a cubic.
+    let mut collector = PathCollector::new();
+    face.extract_outline(glyph, 32.0, &mut collector).unwrap();
+    assert_eq!(collector.commands(), full);
+
+    // Opting into quads without implementing `quad_to` raises them back to
+    // cubics.
+    struct NoQuadTo(PathCollector);
+
+    impl OutlineBuilder for NoQuadTo {
+        fn move_to(&mut self, x: f32, y: f32) {
+            self.0.move_to(x, y)
+        }
+
+        fn line_to(&mut self, x: f32, y: f32) {
+            self.0.line_to(x, y)
+        }
+
+        fn curve_to(&mut self, cp0x: f32, cp0y: f32, cp1x: f32, cp1y: f32, x: f32, y: f32) {
+            self.0.curve_to(cp0x, cp0y, cp1x, cp1y, x, y)
+        }
+
+        fn close(&mut self) {
+            self.0.close()
+        }
+
+        fn supports_quads(&self) -> bool {
+            true
+        }
+    }
+
+    let mut builder = NoQuadTo(PathCollector::new());
+    face.extract_outline(glyph, 32.0, &mut builder).unwrap();
+    let commands = builder.0.commands();
+    assert_eq!(commands.len(), full.len());
+    for (command, expected) in commands.iter().zip(&full) {
+        match (command, expected) {
+            (
+                &PathCommand::CubicTo {
+                    cp0x,
+                    cp0y,
+                    cp1x,
+                    cp1y,
+                    ..
+                },
+                &PathCommand::CubicTo {
+                    cp0x: x0,
+                    cp0y: y0,
+                    cp1x: x1,
+                    cp1y: y1,
+                    ..
+                },
+            ) => {
+                assert!((cp0x - x0).abs() < 0.01 && (cp0y - y0).abs() < 0.01);
+                assert!((cp1x - x1).abs() < 0.01 && (cp1y - y1).abs() < 0.01);
+            }
+            _ => assert_eq!(command, expected),
+        }
+    }
+}
+
+#[test]
+fn test_font_face_files_and_simulations() {
+    let face = arial_face();
+    let files = face.files().unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(face.get_index(), 0);
+    assert_eq!(face.simulations(), FontSimulations::None);
+
+    let bold = face.create_font_face_with_simulations(DWRITE_FONT_SIMULATIONS_BOLD);
+    assert_eq!(bold.simulations(), FontSimulations::Bold);
+    assert_eq!(
+        bold.files().unwrap()[0].reference_key(),
+        files[0].reference_key()
+    );
+}
+
+#[test]
+fn test_get_glyph_outline_sideways() {
+    let face = arial_face();
+    let glyph = face.get_glyph_indices(&['L' as u32])[0];
+
+    let upright = face.get_glyph_outline(glyph, 16.0).unwrap();
+    let sideways = face
+        .get_glyph_outline_with_orientation(glyph, 16.0, true, false)
+        .unwrap();
+    assert_eq!(upright.len(), sideways.len());
+    assert_ne!(upright, sideways);
+}
+
+#[test]
+fn test_get_glyph_indices_into() {
+    let face = arial_face();
+
+    let mut glyphs = vec![1234; 10];
+    face.get_glyph_indices_into(&['a' as u32, 'b' as u32], &mut glyphs);
+    assert_eq!(glyphs, face.get_glyph_indices(&['a' as u32, 'b' as u32]));
+
+    // Arial has no glyph for a private use code point.
+    face.get_glyph_indices_into(&[0xE000], &mut glyphs);
+    assert_eq!(glyphs, [0]);
+}
+
+#[test]
+fn test_family_and_font_iterators() {
+    let system_fc = FontCollection::system();
+    let count = system_fc.get_font_family_count() as usize;
+    let mut families = system_fc.families();
+    assert_eq!(families.len(), count);
+    let last = families.next_back().unwrap().unwrap();
+    assert_eq!(
+        last.name(),
+        system_fc.get_font_family(count as u32 - 1).name()
+    );
+    assert_eq!(families.len(), count - 1);
+    assert!(families.all(|family| family.is_ok()));
+
+    // The skipping adaptor can't know its exact length up front.
+    let families = system_fc.families_iter();
+    assert_eq!(families.size_hint(), (0, Some(count)));
+    assert_eq!(families.count(), count);
+
+    let arial_family = system_fc.get_font_family_by_name("Arial").unwrap();
+    let fonts = arial_family.fonts();
+    drop(system_fc);
+    assert_eq!(fonts.len(), arial_family.get_font_count() as usize);
+    let fonts: Vec<Font> = fonts.rev().map(Result::unwrap).collect();
+    assert!(fonts.iter().all(|f| f.family_name() == "Arial"));
+    assert_eq!(arial_family.fonts_iter().count(), fonts.len());
+}
+
+#[test]
+fn test_family_names_for_locale() {
+    let system_fc = FontCollection::system();
+    let arial_family = system_fc.get_font_family_by_name("Arial").unwrap();
+
+    let names = arial_family.family_names();
+    assert!(!names.is_empty());
+    for i in 0..names.len() {
+        assert!(!names.locale(i).unwrap().is_empty());
+        let family = system_fc
+            .get_font_family_by_name(&names.string(i).unwrap())
+            .unwrap();
+        assert_eq!(family.name(), arial_family.name());
+    }
+
+    assert_eq!(arial_family.name_for_locale("en-US").unwrap(), "Arial");
+    assert_eq!(
+        arial_family.name_for_locale("xx-bogus"),
+        Some(arial_family.name())
+    );
+    assert_eq!(arial_family.english_name(), "Arial");
+    let pairs: Vec<(String, String)> = names.iter().collect::<Result<_, _>>().unwrap();
+    assert!(pairs
+        .iter()
+        .any(|(locale, name)| locale.eq_ignore_ascii_case("en-us") && name == "Arial"));
+    assert_eq!(arial_family.family_name_pairs(), Ok(pairs));
+
+    // Whatever the user's locale, an unknown locale falls back to English.
+    assert_eq!(
+        arial_family.name_for_locale_or_english("xx-bogus").unwrap(),
+        "Arial"
+    );
+    assert_eq!(
+        arial_family.name_for_locale_or_english("en-US").unwrap(),
+        "Arial"
+    );
+}
+
+struct FixedLocaleSource(&'static str);
+
+impl TextAnalysisSourceMethods for FixedLocaleSource {
+    fn get_locale_name(&self, _text_position: u32) -> (std::borrow::Cow<'_, str>, u32) {
+        (self.0.into(), u32::MAX)
+    }
+
+    fn get_paragraph_reading_direction(&self) -> winapi::um::dwrite::DWRITE_READING_DIRECTION {
+        winapi::um::dwrite::DWRITE_READING_DIRECTION_LEFT_TO_RIGHT
+    }
+}
+
+#[test]
+fn test_font_fallback_map_characters() {
+    let fallback = FontFallback::get_system_fallback().unwrap();
+    let system_fc = FontCollection::system();
+    let text: Vec<u16> = "日本語abc".encode_utf16().collect();
+    let source = TextAnalysisSource::from_text(
+        Box::new(FixedLocaleSource("ja-jp")),
+        std::borrow::Cow::Borrowed(&text),
+    );
+
+    let result = fallback.map_characters(
+        &source,
+        0,
+        text.len() as u32,
+        &system_fc,
+        Some("Arial"),
+        FontWeight::Regular,
+        FontStyle::Normal,
+        FontStretch::Normal,
+    );
+    // Arial can't render the kanji, so only those get mapped to another font.
+    assert_eq!(result.mapped_length, 3);
+    let font = result.mapped_font.unwrap();
+    assert_ne!(font.family_name(), "Arial");
+    let face = font.try_create_font_face().unwrap();
+    assert_ne!(face.get_glyph_indices(&['日' as u32]), [0]);
+    assert!(result.scale > 0.0);
+}
+
+#[test]
+fn test_localized_strings() {
+    let system_fc = FontCollection::system();
+    let arial_family = system_fc.get_font_family_by_name("Arial").unwrap();
+    let arial_font = arial_family.get_first_matching_font(
+        FontWeight::Bold,
+        FontStretch::Normal,
+        FontStyle::Normal,
+    );
+
+    let face_names = arial_font.face_names();
+    let pairs: Vec<(String, String)> = face_names.iter().collect::<Result<_, _>>().unwrap();
+    assert_eq!(pairs.len(), face_names.len());
+    let en_us = face_names.find_locale("EN-us").unwrap();
+    assert!(pairs[en_us].0.eq_ignore_ascii_case("en-us"));
+    assert_eq!(pairs[en_us].1, "Bold");
+    assert_eq!(face_names.find_locale("xx-bogus"), None);
+    assert_eq!(arial_font.face_name_for_locale("en-US").unwrap(), "Bold");
+    assert_eq!(
+        arial_font.face_name_for_locale("xx-bogus"),
+        Some(arial_font.face_name())
+    );
+
+    let full_names = arial_font
+        .informational_strings(InformationalStringId::FullName)
+        .unwrap()
+        .unwrap();
+    assert!((&full_names)
+        .into_iter()
+        .any(|pair| pair.unwrap().1 == "Arial Bold"));
+}
+
+#[test]
+fn test_font_face_get_simulations() {
+    let face = arial_face();
+    assert_eq!(face.get_simulations(), DWRITE_FONT_SIMULATIONS_NONE);
+
+    let simulated = face.create_font_face_with_simulations(
+        DWRITE_FONT_SIMULATIONS_BOLD | DWRITE_FONT_SIMULATIONS_OBLIQUE,
+    );
+    assert_eq!(
+        simulated.get_simulations(),
+        DWRITE_FONT_SIMULATIONS_BOLD | DWRITE_FONT_SIMULATIONS_OBLIQUE
+    );
+    assert_eq!(simulated.simulations(), FontSimulations::BoldOblique);
+
+    let oblique = simulated.create_font_face_with_simulations(DWRITE_FONT_SIMULATIONS_OBLIQUE);
+    assert_eq!(oblique.get_simulations(), DWRITE_FONT_SIMULATIONS_OBLIQUE);
+}
+
+#[test]
+fn test_font_postscript_name() {
+    let system_fc = FontCollection::system();
+    let arial_family = system_fc.get_font_family_by_name("Arial").unwrap();
+    let arial_bold = arial_family.get_first_matching_font(
+        FontWeight::Bold,
+        FontStretch::Normal,
+        FontStyle::Normal,
+    );
+    assert_eq!(arial_bold.postscript_name().unwrap(), "Arial-BoldMT");
+    assert!(arial_bold
+        .informational_string(InformationalStringId::CopyrightNotice)
+        .is_some());
+}
+
+#[test]
+fn test_font_panose() {
+    let system_fc = FontCollection::system();
+    let get_font = |name| {
+        system_fc
+            .get_font_family_by_name(name)
+            .unwrap()
+            .get_first_matching_font(FontWeight::Regular, FontStretch::Normal, FontStyle::Normal)
+    };
+
+    let courier = get_font("Courier New").panose().unwrap();
+    assert_eq!(courier.family_kind(), Panose::FAMILY_KIND_TEXT_DISPLAY);
+    assert!(courier.is_monospaced_by_panose());
+
+    let arial = get_font("Arial").panose().unwrap();
+    assert!(!arial.is_monospaced_by_panose());
+    let debug = format!("{:?}", arial);
+    assert!(debug.contains("TextDisplay"), "{}", debug);
+    assert!(debug.contains("NormalSans"), "{}", debug);
+}
+
+#[test]
+fn test_font_has_character() {
+    let system_fc = FontCollection::system();
+    let get_font = |name| {
+        system_fc
+            .get_font_family_by_name(name)
+            .unwrap()
+            .get_first_matching_font(FontWeight::Regular, FontStretch::Normal, FontStyle::Normal)
+    };
+
+    let arial = get_font("Arial");
+    assert_eq!(arial.has_character('A'), Ok(true));
+    assert_eq!(arial.has_character('\u{1F600}'), Ok(false));
+    assert_eq!(arial.first_missing_character("abc"), None);
+    assert_eq!(
+        arial.first_missing_character("é\u{1F600}x"),
+        Some((2, '\u{1F600}'))
+    );
+
+    let emoji = get_font("Segoe UI Emoji");
+    assert_eq!(emoji.has_character('\u{1F600}'), Ok(true));
+}
+
+#[test]
+fn test_font_face_files_outlive_face() {
+    let ttc = FontFile::new_from_path("C:\\Windows\\Fonts\\cambria.ttc").unwrap();
+    let face0 = ttc.create_face(0, DWRITE_FONT_SIMULATIONS_NONE).unwrap();
+    let face1 = ttc.create_face(1, DWRITE_FONT_SIMULATIONS_NONE).unwrap();
+    drop(ttc);
+
+    let files0 = face0.files().unwrap();
+    let files1 = face1.files().unwrap();
+    drop(face0);
+    drop(face1);
+    assert_eq!(files0.len(), 1);
+    assert_eq!(files1.len(), 1);
+    assert_eq!(files0[0].reference_key(), files1[0].reference_key());
+    assert_eq!(files0[0].analyze().unwrap().number_of_faces, 2);
+}
+
+#[test]
+fn test_font_metrics1() {
+    let arial_font = arial_font();
+
+    let metrics1 = arial_font.metrics1();
+    assert!(!metrics1.synthesized);
+    assert!(metrics1.metrics.superscriptSizeY > 0);
+    let metrics = arial_font.metrics();
+    assert!(metrics.is_metrics1());
+    assert_eq!(
+        metrics.metrics1().metrics.subscriptSizeY,
+        metrics1.metrics.subscriptSizeY
+    );
+
+    let synthesized = FontMetrics::Metrics0(arial_font.metrics().metrics0()).metrics1();
+    assert!(synthesized.synthesized);
+    assert_eq!(synthesized.metrics.ascent, metrics1.metrics.ascent);
+    assert_eq!(synthesized.metrics.superscriptSizeY, 0);
+    assert_eq!(synthesized.metrics.hasTypographicMetrics, 0);
+}
+
+#[test]
+fn test_factory_gdi_interop() {
+    let gdi_interop = Factory::shared().gdi_interop().unwrap();
+    for size in 1..4 {
+        let rt = gdi_interop
+            .create_bitmap_render_target(None, size * 16, size * 16)
+            .unwrap();
+        assert!(!rt.get_memory_dc().is_null());
+    }
+}
+
+#[test]
+fn test_bitmap_render_target_with_reference_dc() {
+    let gdi_interop = GdiInterop::try_create().unwrap();
+    let screen_rt = gdi_interop
+        .create_bitmap_render_target(None, 16, 16)
+        .unwrap();
+    let rt = gdi_interop
+        .create_bitmap_render_target(Some(screen_rt.get_memory_dc()), 32, 24)
+        .unwrap();
+    assert!(!rt.get_memory_dc().is_null());
+    assert_eq!(rt.size(), Ok((32, 24)));
+    assert_eq!(rt.pixels_per_dip(), screen_rt.pixels_per_dip());
+}
+
+#[test]
+fn test_gdi_interop_logfont_round_trip() {
+    let arial_font = arial_font();
+
+    let gdi_interop = GdiInterop::try_create().unwrap();
+    let (logfont, is_system_font) = gdi_interop.logfont_from_font(&arial_font).unwrap();
+    assert!(is_system_font);
+    let font = gdi_interop.font_from_logfont(&logfont).unwrap();
+    assert_eq!(font.family_name(), "Arial");
+
+    let face_logfont = gdi_interop
+        .logfont_from_font_face(&arial_font.try_create_font_face().unwrap())
+        .unwrap();
+    assert_eq!(&face_logfont.lfFaceName[..], &logfont.lfFaceName[..]);
+
+    let mut missing = logfont;
+    missing.lfFaceName = [0; 32];
+    for (dst, src) in missing
+        .lfFaceName
+        .iter_mut()
+        .zip("NoSuchFont123".encode_utf16())
+    {
+        *dst = src;
+    }
+    assert_eq!(
+        gdi_interop.font_from_logfont(&missing).err(),
+        Some(Error::NoFont)
+    );
+}
+
+#[test]
+fn test_gdi_interop_font_face_from_hdc() {
+    use winapi::um::wingdi::{CreateFontIndirectW, DeleteObject, SelectObject};
+
+    let arial_font = arial_font();
+
+    let gdi_interop = GdiInterop::try_create().unwrap();
+    let (logfont, _) = gdi_interop.logfont_from_font(&arial_font).unwrap();
+    let rt = gdi_interop
+        .create_bitmap_render_target(None, 16, 16)
+        .unwrap();
+    let hdc = rt.get_memory_dc();
+    unsafe {
+        let hfont = CreateFontIndirectW(&logfont);
+        assert!(!hfont.is_null());
+        let old = SelectObject(hdc, hfont as _);
+        let face = gdi_interop.font_face_from_hdc(hdc).unwrap();
+        SelectObject(hdc, old);
+        DeleteObject(hfont as _);
+        assert_eq!(
+            face.get_glyph_count(),
+            arial_font.try_create_font_face().unwrap().get_glyph_count()
+        );
+    }
+}
+
+#[test]
+fn test_family_matching_fonts() {
+    let system_fc = FontCollection::system();
+    let arial_family = system_fc.get_font_family_by_name("Arial").unwrap();
+
+    let fonts = arial_family
+        .matching_fonts(
+            FontWeight::Unknown(450),
+            FontStretch::Normal,
+            FontStyle::Normal,
+        )
+        .unwrap();
+    assert!(!fonts.is_empty());
+    assert_eq!(fonts.get(0).unwrap().weight(), FontWeight::Regular);
+    assert_eq!(fonts.iter().len(), fonts.len());
+    assert!(fonts
+        .iter()
+        .any(|font| font.unwrap().weight() == FontWeight::Bold));
+    assert_eq!(
+        fonts.get_font_collection().unwrap().get_font_family_count(),
+        system_fc.get_font_family_count()
+    );
+    assert!(fonts.get(fonts.len()).is_err());
+
+    let fonts = arial_family
+        .matching_fonts(
+            FontWeight::Unknown(650),
+            FontStretch::Normal,
+            FontStyle::Normal,
+        )
+        .unwrap();
+    assert_eq!(fonts.get(0).unwrap().weight(), FontWeight::Bold);
+}
+
+#[test]
+fn test_font_face_identity() {
+    let system_fc = FontCollection::system();
+    let arial_family = system_fc.get_font_family_by_name("Arial").unwrap();
+    let regular = arial_family.get_first_matching_font(
+        FontWeight::Regular,
+        FontStretch::Normal,
+        FontStyle::Normal,
+    );
+    let bold = arial_family.get_first_matching_font(
+        FontWeight::Bold,
+        FontStretch::Normal,
+        FontStyle::Normal,
+    );
+
+    let cached = regular.try_create_font_face_cached().unwrap();
+    assert_eq!(unsafe { cached.as_ptr() }, unsafe {
+        regular.try_create_font_face_cached().unwrap().as_ptr()
+    });
+
+    let face = regular.try_create_font_face().unwrap();
+    assert!(face.ptr_eq(&regular.try_create_font_face().unwrap()));
+    assert!(face.ptr_eq(&cached));
+    assert!(!face.ptr_eq(&bold.try_create_font_face().unwrap()));
+    assert!(!face.ptr_eq(&face.create_font_face_with_simulations(DWRITE_FONT_SIMULATIONS_BOLD)));
+}
+
+#[test]
+fn test_wrappers_are_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Font>();
+    assert_send_sync::<FontFace>();
+    assert_send_sync::<FontCollection>();
+    assert_send_sync::<FontFamily>();
+
+    let font = Arc::new(arial_font());
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let font = font.clone();
+            std::thread::spawn(move || {
+                font.try_create_font_face_cached()
+                    .unwrap()
+                    .get_glyph_count()
+            })
+        })
+        .collect();
+    let counts: Vec<u16> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    assert!(counts.iter().all(|&count| count == counts[0] && count > 0));
+}
+
+#[test]
+fn test_glyph_run_ink_bounds() {
+    let face = arial_face();
+    let glyphs = face.get_glyph_indices(&['A' as u32, 'g' as u32]);
+    let advances = [20.0f32, 20.0];
+    let offsets = [GlyphOffset {
+        advanceOffset: 0.,
+        ascenderOffset: 0.,
+    }; 2];
+    let glyph_run = DWRITE_GLYPH_RUN {
+        fontFace: unsafe { face.as_ptr() },
+        fontEmSize: 32.0,
+        glyphCount: glyphs.len() as u32,
+        glyphIndices: glyphs.as_ptr(),
+        glyphAdvances: advances.as_ptr(),
+        glyphOffsets: offsets.as_ptr(),
+        isSideways: 0,
+        bidiLevel: 0,
+    };
+
+    for &rendering_mode in &[DWRITE_RENDERING_MODE_ALIASED, DWRITE_RENDERING_MODE_NATURAL] {
+        let analysis = GlyphRunAnalysis::create(
+            &glyph_run,
+            1.0,
+            None,
+            rendering_mode,
+            DWRITE_MEASURING_MODE_NATURAL,
+            0.0,
+            0.0,
+        )
+        .unwrap();
+        let bounds = analysis.ink_bounds().unwrap();
+        let (gamma, _, cleartype_level) = analysis
+            .get_alpha_blend_params(&RenderingParams::try_create_for_primary_monitor().unwrap())
+            .unwrap();
+        assert!(gamma > 0.0);
+        assert!((0.0..=1.0).contains(&cleartype_level));
+        // The 'A' sits on the baseline and the 'g' descends below it.
+        assert!(bounds.left < bounds.right);
+        assert!(bounds.top < 0 && bounds.bottom > 0);
+        assert!(bounds.right <= 40 + 1);
+    }
+}
+
+#[test]
+fn test_resolve_descriptor() {
+    let system_fc = FontCollection::system();
+    let mut desc = FontDescriptor {
+        family_name: "Arial".to_owned(),
+        weight: FontWeight::Bold,
+        stretch: FontStretch::Normal,
+        style: FontStyle::Normal,
+    };
+    let font = system_fc.resolve_descriptor(&desc).unwrap();
+    assert_eq!(font.to_descriptor(), desc);
+
+    desc.weight = FontWeight::Thin;
+    match system_fc.resolve_descriptor(&desc) {
+        Err(DescriptorError::NoMatchingStyle { nearest }) => {
+            assert_eq!(nearest.family_name(), "Arial");
+            assert_eq!(nearest.weight(), FontWeight::Regular);
+        }
+        other => panic!("unexpected result: {:?}", other.map(|f| f.to_descriptor())),
+    }
+    assert!(system_fc.get_font_from_descriptor(&desc).is_none());
+    let nearest = system_fc.resolve(&desc).unwrap();
+    assert_eq!(nearest.family_name(), "Arial");
+    assert_eq!(nearest.weight(), FontWeight::Regular);
+
+    desc.family_name = "No Such Family".to_owned();
+    match system_fc.resolve_descriptor(&desc) {
+        Err(DescriptorError::FamilyNotFound) => {}
+        other => panic!("unexpected result: {:?}", other.map(|f| f.to_descriptor())),
+    }
+    assert!(system_fc.resolve(&desc).is_none());
+    assert_eq!(
+        system_fc.resolve_descriptor(&desc).err().unwrap().hresult(),
+        None
+    );
+    assert_eq!(
+        DescriptorError::Com(DWRITE_E_NOFONT).hresult(),
+        Some(DWRITE_E_NOFONT)
+    );
+}
+
+#[test]
+fn test_system_font_watcher() {
+    let mut watcher = SystemFontWatcher::new();
+    let count = watcher.collection().get_font_family_count();
+    // Nothing gets installed while the test runs.
+    assert!(!watcher.check_now());
+    assert_eq!(watcher.collection().get_font_family_count(), count);
+
+    let refreshed = FontCollection::refresh();
+    assert_eq!(refreshed.get_font_family_count(), count);
+    assert!(refreshed.contains_family("Arial"));
+}
+
+#[test]
+fn test_eudc_collection() {
+    // Most systems have no EUDC fonts, but the collection itself exists.
+    let eudc = FontCollection::eudc().unwrap();
+    if eudc.get_font_family_count() == 0 {
+        assert!(FontCollection::map_pua_character('\u{E000}').is_none());
+    }
+
+    let system_fc = FontCollection::system();
+    let font = system_fc.find_font_for_character('A').unwrap();
+    assert_eq!(font.has_character('A'), Ok(true));
+}
+
+#[test]
+fn test_get_svg_glyph() {
+    let face = arial_face();
+    let glyph = face.get_glyph_indices(&['A' as u32])[0];
+    // Arial has no SVG table.
+    assert_eq!(face.get_svg_glyph(glyph, 32), None);
+}
+
+const SVG_DOCUMENT: &[u8] =
+    b"<svg xmlns=\"http://www.w3.org/2000/svg\"><path d=\"M0 0H500V-700Z\"/></svg>";
+
+// `SVG_DOCUMENT`, gzip-compressed.
+const GZIPPED_SVG_DOCUMENT: &[u8] = &[
+    0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xb3, 0x29, 0x2e, 0x4b, 0x57, 0xa8,
+    0xc8, 0xcd, 0xc9, 0x2b, 0xb6, 0x55, 0xca, 0x28, 0x29, 0x29, 0xb0, 0xd2, 0xd7, 0x2f, 0x2f, 0x2f,
+    0xd7, 0x2b, 0x37, 0xd6, 0xcb, 0x2f, 0x4a, 0xd7, 0x37, 0x32, 0x30, 0x30, 0xd0, 0x07, 0xaa, 0x50,
+    0xb2, 0xb3, 0x29, 0x48, 0x2c, 0xc9, 0x50, 0x48, 0xb1, 0x55, 0xf2, 0x35, 0x50, 0x30, 0xf0, 0x30,
+    0x35, 0x30, 0x08, 0xd3, 0x35, 0x37, 0x30, 0x88, 0x52, 0xd2, 0xb7, 0xb3, 0x01, 0x29, 0xb0, 0x03,
+    0x00, 0x28, 0xeb, 0x43, 0x47, 0x48, 0x00, 0x00, 0x00,
+];
+
+// Returns a copy of the single-font file `font` with an `SVG ` table that maps
+// glyphs `0..=last_glyph` to `document`.
+fn add_svg_table(font: &[u8], last_glyph: u16, document: &[u8]) -> Vec<u8> {
+    let be32 = |at: usize| u32::from_be_bytes([font[at], font[at + 1], font[at + 2], font[at + 3]]);
+
+    let mut svg = vec![];
+    svg.extend_from_slice(&0u16.to_be_bytes()); // version
+    svg.extend_from_slice(&10u32.to_be_bytes()); // offset of the document list
+    svg.extend_from_slice(&0u32.to_be_bytes()); // reserved
+    svg.extend_from_slice(&1u16.to_be_bytes()); // number of documents
+    svg.extend_from_slice(&0u16.to_be_bytes());
+    svg.extend_from_slice(&last_glyph.to_be_bytes());
+    svg.extend_from_slice(&14u32.to_be_bytes()); // from the document list
+    svg.extend_from_slice(&(document.len() as u32).to_be_bytes());
+    svg.extend_from_slice(document);
+
+    // (tag, checksum, data) for each table, sorted by tag as the table
+    // directory must be.
+    let table_count = u16::from_be_bytes([font[4], font[5]]) as usize;
+    let mut tables: Vec<(&[u8], u32, &[u8])> = (0..table_count)
+        .map(|i| {
+            let record = 12 + 16 * i;
+            let offset = be32(record + 8) as usize;
+            let len = be32(record + 12) as usize;
+            (
+                &font[record..record + 4],
+                be32(record + 4),
+                &font[offset..offset + len],
+            )
+        })
+        .collect();
+    tables.push((b"SVG ", 0, &svg));
+    tables.sort_by_key(|&(tag, _, _)| tag);
+
+    let table_count = tables.len() as u16;
+    let entry_selector = 15 - table_count.leading_zeros() as u16;
+    let search_range = 16u16 << entry_selector;
+    let mut out = font[..4].to_vec();
+    out.extend_from_slice(&table_count.to_be_bytes());
+    out.extend_from_slice(&search_range.to_be_bytes());
+    out.extend_from_slice(&entry_selector.to_be_bytes());
+    out.extend_from_slice(&(table_count * 16 - search_range).to_be_bytes());
+
+    let data_start = out.len() + 16 * tables.len();
+    let mut data = vec![];
+    for (tag, checksum, table) in tables {
+        out.extend_from_slice(tag);
+        out.extend_from_slice(&checksum.to_be_bytes());
+        out.extend_from_slice(&((data_start + data.len()) as u32).to_be_bytes());
+        out.extend_from_slice(&(table.len() as u32).to_be_bytes());
+        data.extend_from_slice(table);
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+    }
+    out.extend_from_slice(&data);
+    out
+}
+
+#[test]
+fn test_get_svg_glyph_compressed() {
+    let arial_face = arial_face();
+    let bytes = arial_face.get_files()[0].get_font_file_bytes();
+    let last_glyph = arial_face.get_glyph_count() - 1;
+
+    for document in [SVG_DOCUMENT, GZIPPED_SVG_DOCUMENT].iter() {
+        let bytes = add_svg_table(&bytes, last_glyph, document);
+        let file = FontFile::new_from_data_in_memory(Arc::new(bytes)).unwrap();
+        let face = file.create_face(0, DWRITE_FONT_SIMULATIONS_NONE).unwrap();
+        let glyph = face.get_glyph_indices(&['A' as u32])[0];
+        // Either way, the document comes back uncompressed.
+        assert_eq!(face.get_svg_glyph(glyph, 32).as_deref(), Some(SVG_DOCUMENT));
+    }
+}
+
+#[test]
+fn test_gunzip() {
+    assert_eq!(
+        inflate::gunzip(GZIPPED_SVG_DOCUMENT).as_deref(),
+        Some(SVG_DOCUMENT)
+    );
+
+    // A corrupted CRC or a truncated stream is rejected.
+    let mut corrupt = GZIPPED_SVG_DOCUMENT.to_vec();
+    let crc = corrupt.len() - 8;
+    corrupt[crc] ^= 1;
+    assert_eq!(inflate::gunzip(&corrupt), None);
+    let truncated = &GZIPPED_SVG_DOCUMENT[..GZIPPED_SVG_DOCUMENT.len() - 4];
+    assert_eq!(inflate::gunzip(truncated), None);
+    assert_eq!(inflate::gunzip(SVG_DOCUMENT), None);
+}
+
+#[test]
+fn test_gunzip_output_limit() {
+    // A trailer claiming a shorter document stops inflation early, and one
+    // claiming a huge document is rejected before inflating anything.
+    let len = GZIPPED_SVG_DOCUMENT.len();
+    let mut short = GZIPPED_SVG_DOCUMENT.to_vec();
+    short[len - 4..].copy_from_slice(&(SVG_DOCUMENT.len() as u32 - 1).to_le_bytes());
+    assert_eq!(inflate::gunzip(&short), None);
+    let mut huge = GZIPPED_SVG_DOCUMENT.to_vec();
+    huge[len - 4..].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(inflate::gunzip(&huge), None);
+}
+
+#[test]
+fn test_inflate_blocks() {
+    // A stored block holding "hello".
+    let stored = [0x01, 0x05, 0x00, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o'];
+    assert_eq!(inflate::inflate(&stored, 5).as_deref(), Some(&b"hello"[..]));
+    assert_eq!(inflate::inflate(&stored, 4), None);
+
+    // A fixed Huffman block with back references.
+    let fixed = [
+        0x4b, 0x4c, 0x4a, 0x4e, 0x84, 0x21, 0x85, 0x8c, 0xd4, 0x9c, 0x9c, 0x7c, 0x08, 0x09, 0x00,
+    ];
+    let text = &b"abcabcabcabc hello hello"[..];
+    assert_eq!(inflate::inflate(&fixed, 100).as_deref(), Some(text));
+    assert_eq!(inflate::inflate(&fixed, text.len() - 1), None);
+
+    // A dynamic Huffman block.
+    let dynamic = [
+        0x0d, 0xca, 0x31, 0x11, 0x00, 0x30, 0x0c, 0xc3, 0x40, 0x2a, 0x82, 0x10, 0xa7, 0x6e, 0xae,
+        0xe1, 0x4f, 0xac, 0x9e, 0x34, 0xe8, 0x0b, 0x61, 0x16, 0x0d, 0x7d, 0x39, 0x83, 0x97, 0x31,
+        0x4f, 0xa8, 0x0a, 0x75, 0x6a, 0xe7, 0x46, 0x6c, 0x48, 0x4c, 0xdf, 0xf4, 0x2d, 0xa7, 0x1d,
+        0xaf, 0x0f,
+    ];
+    let text = (0..20)
+        .map(|i: u32| (i * i).to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    assert_eq!(
+        inflate::inflate(&dynamic, 1000).as_deref(),
+        Some(text.as_bytes())
+    );
+}
+
+#[test]
+fn test_inflate_malformed() {
+    // A stored block whose NLEN isn't the complement of LEN.
+    let bad_nlen = [0x01, 0x05, 0x00, 0xfa, 0xfe, b'h', b'e', b'l', b'l', b'o'];
+    assert_eq!(inflate::inflate(&bad_nlen, 100), None);
+
+    // A fixed block starting with a back reference, before any output.
+    let distance_too_far = [0x03, 0x02, 0x00];
+    assert_eq!(inflate::inflate(&distance_too_far, 100), None);
+
+    // A dynamic block giving all 19 code length codes a length of 1.
+    let oversubscribed = [0x05, 0xe0, 0x93, 0x24, 0x49, 0x92, 0x24, 0x49, 0x92, 0x00];
+    assert_eq!(inflate::inflate(&oversubscribed, 100), None);
+
+    // The reserved block type.
+    assert_eq!(inflate::inflate(&[0x07], 100), None);
+}
+
+#[test]
+fn test_glyph_run_analysis_transform() {
+    let face = arial_face();
+    let glyphs = face.get_glyph_indices(&['L' as u32]);
+    let advances = [0.0f32];
+    let offsets = [GlyphOffset {
+        advanceOffset: 0.,
+        ascenderOffset: 0.,
+    }];
+    let glyph_run = DWRITE_GLYPH_RUN {
+        fontFace: unsafe { face.as_ptr() },
+        fontEmSize: 48.0,
+        glyphCount: 1,
+        glyphIndices: glyphs.as_ptr(),
+        glyphAdvances: advances.as_ptr(),
+        glyphOffsets: offsets.as_ptr(),
+        isSideways: 0,
+        bidiLevel: 0,
+    };
+    let rasterize = |transform| {
+        let analysis = GlyphRunAnalysis::create(
+            &glyph_run,
+            1.0,
+            transform,
+            DWRITE_RENDERING_MODE_ALIASED,
+            DWRITE_MEASURING_MODE_NATURAL,
+            0.0,
+            0.0,
+        )?;
+        let bounds = analysis.get_alpha_texture_bounds(TextureType::Aliased1x1)?;
+        let texture = analysis.create_alpha_texture(TextureType::Aliased1x1, bounds)?;
+        let coverage: u64 = texture.iter().map(|&b| b as u64).sum();
+        Ok::<_, Error>((bounds, coverage))
+    };
+
+    let (upright, upright_coverage) = rasterize(None).unwrap();
+    let rotated_transform = DWRITE_MATRIX {
+        m11: 0.0,
+        m12: 1.0,
+        m21: -1.0,
+        m22: 0.0,
+        dx: 0.0,
+        dy: 0.0,
+    };
+    let (rotated, rotated_coverage) = rasterize(Some(rotated_transform)).unwrap();
+    // Rotating by 90° swaps the extents; edge pixels may round differently.
+    assert!(((upright.right - upright.left) - (rotated.bottom - rotated.top)).abs() <= 1);
+    assert!(((upright.bottom - upright.top) - (rotated.right - rotated.left)).abs() <= 1);
+    let difference = (upright_coverage as i64 - rotated_coverage as i64).abs();
+    assert!(difference * 50 <= upright_coverage as i64);
+
+    let degenerate = DWRITE_MATRIX {
+        m11: 0.0,
+        m12: 0.0,
+        m21: 0.0,
+        m22: 0.0,
+        dx: 0.0,
+        dy: 0.0,
+    };
+    assert!(rasterize(Some(degenerate)).is_err());
+}
+
+#[test]
+fn test_glyph_image_formats() {
+    let system_fc = FontCollection::system();
+    let get_face = |name| {
+        system_fc
+            .get_font_family_by_name(name)
+            .unwrap()
+            .get_first_matching_font(FontWeight::Regular, FontStretch::Normal, FontStyle::Normal)
+            .try_create_font_face()
+            .unwrap()
+    };
+
+    let arial = get_face("Arial");
+    let formats = arial.get_glyph_image_formats().unwrap();
+    assert_ne!(formats & DWRITE_GLYPH_IMAGE_FORMATS_TRUETYPE, 0);
+    assert_eq!(formats & DWRITE_GLYPH_IMAGE_FORMATS_COLR, 0);
+
+    let emoji = get_face("Segoe UI Emoji");
+    assert_ne!(
+        emoji.get_glyph_image_formats().unwrap() & DWRITE_GLYPH_IMAGE_FORMATS_COLR,
+        0
+    );
+    let smiley = emoji.get_glyph_indices(&[0x1F600])[0];
+    let glyph_formats = emoji
+        .get_glyph_image_formats_for_glyph(smiley, 1, 1000)
+        .unwrap();
+    assert_ne!(glyph_formats & DWRITE_GLYPH_IMAGE_FORMATS_COLR, 0);
+}
+
+#[test]
+fn test_text_analyzer_baseline() {
+    let face = arial_face();
+    let analyzer = TextAnalyzer::create().unwrap();
+    let script = DWRITE_SCRIPT_ANALYSIS {
+        script: 0,
+        shapes: 0,
+    };
+
+    let (roman, _) = analyzer
+        .get_baseline(&face, Baseline::Roman, false, true, script, "en-us")
+        .unwrap();
+    let (ideographic, _) = analyzer
+        .get_baseline(
+            &face,
+            Baseline::IdeographicBottom,
+            false,
+            true,
+            script,
+            "ja-jp",
+        )
+        .unwrap();
+    assert_eq!(roman, 0);
+    assert_ne!(roman, ideographic);
+}
+
+#[test]
+fn test_glyph_run_analysis_grid_fit() {
+    let face = arial_face();
+    let text: Vec<u32> = "Hamburgefonts".chars().map(|c| c as u32).collect();
+    let glyphs = face.get_glyph_indices(&text);
+    let advances = vec![5.5f32; glyphs.len()];
+    let offsets = vec![
+        GlyphOffset {
+            advanceOffset: 0.,
+            ascenderOffset: 0.,
+        };
+        glyphs.len()
+    ];
+    let glyph_run = DWRITE_GLYPH_RUN {
+        fontFace: unsafe { face.as_ptr() },
+        fontEmSize: 9.0,
+        glyphCount: glyphs.len() as u32,
+        glyphIndices: glyphs.as_ptr(),
+        glyphAdvances: advances.as_ptr(),
+        glyphOffsets: offsets.as_ptr(),
+        isSideways: 0,
+        bidiLevel: 0,
+    };
+    let rasterize = |grid_fit_mode| {
+        let analysis = GlyphRunAnalysis::create2(
+            &glyph_run,
+            None,
+            DWRITE_RENDERING_MODE_ALIASED,
+            DWRITE_MEASURING_MODE_NATURAL,
+            grid_fit_mode,
+            TextAntialiasMode::Grayscale,
+            0.0,
+            0.0,
+        )
+        .unwrap();
+        let bounds = analysis
+            .get_alpha_texture_bounds(TextureType::Aliased1x1)
+            .unwrap();
+        let texture = analysis
+            .create_alpha_texture(TextureType::Aliased1x1, bounds)
+            .unwrap();
+        (
+            bounds.left,
+            bounds.top,
+            bounds.right,
+            bounds.bottom,
+            texture,
+        )
+    };
+
+    assert_ne!(
+        rasterize(GridFitMode::Enabled),
+        rasterize(GridFitMode::Disabled)
+    );
+}
+
+#[test]
+fn test_alpha_blend_params_match_rendering_params() {
+    let face = arial_face();
+    let glyphs = face.get_glyph_indices(&['a' as u32]);
+    let advances = [0.0f32];
+    let offsets = [GlyphOffset {
+        advanceOffset: 0.,
+        ascenderOffset: 0.,
+    }];
+    let glyph_run = DWRITE_GLYPH_RUN {
+        fontFace: unsafe { face.as_ptr() },
+        fontEmSize: 16.0,
+        glyphCount: 1,
+        glyphIndices: glyphs.as_ptr(),
+        glyphAdvances: advances.as_ptr(),
+        glyphOffsets: offsets.as_ptr(),
+        isSideways: 0,
+        bidiLevel: 0,
+    };
+    let analysis = GlyphRunAnalysis::create(
+        &glyph_run,
+        1.0,
+        None,
+        DWRITE_RENDERING_MODE_NATURAL,
+        DWRITE_MEASURING_MODE_NATURAL,
+        0.0,
+        0.0,
+    )
+    .unwrap();
+
+    let params = RenderingParams::try_create_for_primary_monitor().unwrap();
+    let (gamma, enhanced_contrast, cleartype_level) =
+        analysis.get_alpha_blend_params(&params).unwrap();
+    assert!((gamma - params.gamma()).abs() < 0.01);
+    assert!(enhanced_contrast >= 0.0);
+    assert!((cleartype_level - params.cleartype_level()).abs() < 0.01);
+}
+
+#[test]
+fn test_text_analyzer_justification() {
+    let face = arial_face();
+    let analyzer = TextAnalyzer::create().unwrap();
+    let script = DWRITE_SCRIPT_ANALYSIS {
+        script: 0,
+        shapes: 0,
+    };
+
+    let text: Vec<u16> = "a b".encode_utf16().collect();
+    let cluster_map = [0u16, 1, 2];
+    let mut glyph_props = [DWRITE_SHAPING_GLYPH_PROPERTIES { bit_fields: 0 }; 3];
+    for props in glyph_props.iter_mut() {
+        props.set_isClusterStart(1);
+    }
+    let opportunities = analyzer
+        .get_justification_opportunities(&face, 16.0, script, &text, &cluster_map, &glyph_props)
+        .unwrap();
+    assert_eq!(opportunities.len(), 3);
+
+    let advances = [10.0f32, 5.0, 10.0];
+    let offsets = [GlyphOffset {
+        advanceOffset: 0.,
+        ascenderOffset: 0.,
+    }; 3];
+    let mut justified_advances = vec![];
+    let mut justified_offsets = vec![];
+    analyzer
+        .justify_glyph_advances(
+            35.0,
+            &opportunities,
+            &advances,
+            &offsets,
+            &mut justified_advances,
+            &mut justified_offsets,
+        )
+        .unwrap();
+    assert_eq!(justified_advances.len(), 3);
+    assert_eq!(justified_offsets.len(), 3);
+    let width: f32 = justified_advances.iter().sum();
+    assert!(justified_advances[1] > advances[1]);
+    assert!(width <= 35.0 + 0.01);
+}
+
+#[test]
+fn test_alpha_texture_types() {
+    let face = arial_face();
+    let analyze = |ch: char| {
+        let glyphs = face.get_glyph_indices(&[ch as u32]);
+        let advances = [0.0f32];
+        let offsets = [GlyphOffset {
+            advanceOffset: 0.,
+            ascenderOffset: 0.,
+        }];
+        let glyph_run = DWRITE_GLYPH_RUN {
+            fontFace: unsafe { face.as_ptr() },
+            fontEmSize: 24.0,
+            glyphCount: 1,
+            glyphIndices: glyphs.as_ptr(),
+            glyphAdvances: advances.as_ptr(),
+            glyphOffsets: offsets.as_ptr(),
+            isSideways: 0,
+            bidiLevel: 0,
+        };
+        GlyphRunAnalysis::create(
+            &glyph_run,
+            1.0,
+            None,
+            DWRITE_RENDERING_MODE_NATURAL,
+            DWRITE_MEASURING_MODE_NATURAL,
+            0.0,
+            0.0,
+        )
+        .unwrap()
+    };
+
+    // A ClearType rendering mode only produces the 3x1 texture.
+    let analysis = analyze('g');
+    let bounds = analysis
+        .get_alpha_texture_bounds(TextureType::ClearType3x1)
+        .unwrap();
+    let pixels = ((bounds.right - bounds.left) * (bounds.bottom - bounds.top)) as usize;
+    assert!(pixels > 0);
+    let texture = analysis
+        .create_alpha_texture(TextureType::ClearType3x1, bounds)
+        .unwrap();
+    assert_eq!(texture.len(), pixels * 3);
+
+    let bounds = analysis
+        .get_alpha_texture_bounds(TextureType::Aliased1x1)
+        .unwrap();
+    assert!(bounds.left >= bounds.right || bounds.top >= bounds.bottom);
+    let texture = analysis
+        .create_alpha_texture(TextureType::Aliased1x1, bounds)
+        .unwrap();
+    assert!(texture.is_empty());
+
+    // Blank glyphs have no ink for either texture type.
+    let analysis = analyze(' ');
+    for &texture_type in &[TextureType::Aliased1x1, TextureType::ClearType3x1] {
+        let bounds = analysis.get_alpha_texture_bounds(texture_type).unwrap();
+        let texture = analysis.create_alpha_texture(texture_type, bounds).unwrap();
+        assert!(texture.is_empty());
+    }
+}
+
+#[test]
+fn test_cluster_map() {
+    // "ffi" as a single ligature glyph, followed by "e" and "x".
+    let ligature = ClusterMap::new(vec![0, 0, 0, 1, 2], 3);
+    assert_eq!(ligature.glyph_range_for_char(0), 0..1);
+    assert_eq!(ligature.glyph_range_for_char(2), 0..1);
+    assert_eq!(ligature.glyph_range_for_char(4), 2..3);
+    assert_eq!(ligature.char_index_for_glyph(0), 0);
+    assert_eq!(ligature.char_index_for_glyph(1), 3);
+
+    // A character that decomposes into three glyphs, between two others.
+    let decomposed = ClusterMap::new(vec![0, 1, 4], 5);
+    assert_eq!(decomposed.glyph_range_for_char(1), 1..4);
+    assert_eq!(decomposed.glyph_range_for_char(2), 4..5);
+    assert_eq!(decomposed.char_index_for_glyph(2), 1);
+    assert_eq!(decomposed.char_index_for_glyph(3), 1);
+    assert_eq!(decomposed.char_index_for_glyph(4), 2);
+}
+
+#[test]
+fn test_text_analyzer_typographic_features() {
+    let system_fc = FontCollection::system();
+    let face = system_fc
+        .get_font_family_by_name("Calibri")
+        .unwrap()
+        .get_first_matching_font(FontWeight::Regular, FontStretch::Normal, FontStyle::Normal)
+        .try_create_font_face()
+        .unwrap();
+    let analyzer = TextAnalyzer::create().unwrap();
+    let script = DWRITE_SCRIPT_ANALYSIS {
+        script: 0,
+        shapes: 0,
+    };
+
+    // A small initial buffer still returns every feature.
+    let features = analyzer
+        .get_typographic_features(&face, script, "en-us", 1)
+        .unwrap();
+    assert!(features.len() > 1);
+    let liga = u32::from_le_bytes(*b"liga");
+    assert!(features.contains(&liga));
+
+    let glyphs = face.get_glyph_indices(&['f' as u32, ' ' as u32]);
+    let applies = analyzer
+        .check_typographic_feature(&face, script, "en-us", liga, &glyphs)
+        .unwrap();
+    assert_eq!(applies, vec![true, false]);
+}
+
+#[test]
+fn test_glyph_run_builder() {
+    let face = arial_face();
+    let glyphs = face.get_glyph_indices(&['H' as u32, 'i' as u32]);
+    let run = GlyphRun::new(&face, 32.0)
+        .with_glyphs(&glyphs)
+        .with_advances(&[20.0, 10.0]);
+    let raw = run.as_raw();
+    assert_eq!(raw.glyphCount, 2);
+    assert!(raw.glyphOffsets.is_null());
+
+    let analysis = GlyphRunAnalysis::create_from_run(
+        &run,
+        1.0,
+        None,
+        DWRITE_RENDERING_MODE_ALIASED,
+        DWRITE_MEASURING_MODE_NATURAL,
+        0.0,
+        0.0,
+    )
+    .unwrap();
+    let bounds = analysis.ink_bounds().unwrap();
+    assert!(bounds.right > bounds.left && bounds.bottom > bounds.top);
+}
+
+#[test]
+#[should_panic]
+fn test_glyph_run_builder_length_mismatch() {
+    let face = arial_face();
+    let run = GlyphRun::new(&face, 32.0)
+        .with_glyphs(&[1, 2, 3])
+        .with_advances(&[10.0]);
+    run.as_raw();
+}
+
+#[test]
+fn test_text_analyzer_get_glyphs_with_features() {
+    let system_fc = FontCollection::system();
+    let face = system_fc
+        .get_font_family_by_name("Calibri")
+        .unwrap()
+        .get_first_matching_font(FontWeight::Regular, FontStretch::Normal, FontStyle::Normal)
+        .try_create_font_face()
+        .unwrap();
+    let analyzer = TextAnalyzer::create().unwrap();
+    let script = DWRITE_SCRIPT_ANALYSIS {
+        script: 0,
+        shapes: 0,
+    };
+    let text: Vec<u16> = "abc".encode_utf16().collect();
+
+    let plain = analyzer
+        .get_glyphs(&text, &face, false, false, script, "en-us", &[])
+        .unwrap();
+    assert_eq!(plain.cluster_map, vec![0, 1, 2]);
+    assert_eq!(plain.glyph_indices.len(), 3);
+
+    let small_caps = [FeatureRange {
+        features: vec![FontFeature {
+            tag: u32::from_le_bytes(*b"smcp"),
+            value: 1,
+        }],
+        length: text.len() as u32,
+    }];
+    let shaped = analyzer
+        .get_glyphs(&text, &face, false, false, script, "en-us", &small_caps)
+        .unwrap();
+    assert_eq!(shaped.glyph_indices.len(), 3);
+    assert_ne!(shaped.glyph_indices, plain.glyph_indices);
+}
+
+#[test]
+fn test_alpha_texture_into_buffer() {
+    let face = arial_face();
+    let glyphs = face.get_glyph_indices(&['W' as u32]);
+    let run = GlyphRun::new(&face, 24.0).with_glyphs(&glyphs);
+    let analysis = GlyphRunAnalysis::create_from_run(
+        &run,
+        1.0,
+        None,
+        DWRITE_RENDERING_MODE_ALIASED,
+        DWRITE_MEASURING_MODE_NATURAL,
+        0.0,
+        0.0,
+    )
+    .unwrap();
+    let bounds = analysis
+        .get_alpha_texture_bounds(TextureType::Aliased1x1)
+        .unwrap();
+    let len = GlyphRunAnalysis::required_len(bounds, TextureType::Aliased1x1);
+    assert_eq!(
+        GlyphRunAnalysis::required_len(bounds, TextureType::ClearType3x1),
+        len * 3
+    );
+
+    let texture = analysis
+        .create_alpha_texture(TextureType::Aliased1x1, bounds)
+        .unwrap();
+    let mut atlas = vec![0u8; len + 16];
+    analysis
+        .create_alpha_texture_into(TextureType::Aliased1x1, bounds, &mut atlas)
+        .unwrap();
+    assert_eq!(&atlas[..len], &texture[..]);
+    assert!(atlas[len..].iter().all(|&b| b == 0));
+
+    assert!(analysis
+        .create_alpha_texture_into(TextureType::Aliased1x1, bounds, &mut atlas[..len - 1])
+        .is_err());
+}
+
+#[test]
+fn test_design_glyph_advances() {
+    let face = arial_face();
+    let glyphs = face.get_glyph_indices(&['i' as u32, 'W' as u32]);
+    let advances = face.get_design_glyph_advances(&glyphs, false).unwrap();
+    let metrics = face.get_design_glyph_metrics(&glyphs, false);
+    assert_eq!(advances.len(), 2);
+    assert_eq!(advances[0], metrics[0].advanceWidth as i32);
+    assert_eq!(advances[1], metrics[1].advanceWidth as i32);
+    assert!(advances[0] < advances[1]);
+
+    let vertical = face.get_design_glyph_advances(&glyphs, true).unwrap();
+    assert_eq!(vertical[0], metrics[0].advanceHeight as i32);
+}
+
+#[test]
+fn test_recommended_rendering_mode() {
+    let system_fc = FontCollection::system();
+    let face = system_fc
+        .get_font_family_by_name("Segoe UI")
+        .unwrap()
+        .get_first_matching_font(FontWeight::Regular, FontStretch::Normal, FontStyle::Normal)
+        .try_create_font_face()
+        .unwrap();
+    let params = RenderingParams::try_create_for_primary_monitor().unwrap();
+
+    let small = face.recommended_rendering_mode(7.0, 1.0, DWRITE_MEASURING_MODE_NATURAL, &params);
+    let large = face.recommended_rendering_mode(20.0, 1.0, DWRITE_MEASURING_MODE_NATURAL, &params);
+    assert_ne!(small, large);
+
+    if let Some((mode, _grid_fit)) =
+        face.recommended_rendering_mode3(20.0, 1.0, DWRITE_MEASURING_MODE_NATURAL, &params)
+    {
+        assert_ne!(mode, DWRITE_RENDERING_MODE1_DEFAULT);
+    }
+}
+
+#[test]
+fn test_draw_glyph_run_dirty_rect() {
+    let face = arial_face();
+    let gdi_interop = GdiInterop::try_create().unwrap();
+    let rt = gdi_interop
+        .create_bitmap_render_target(None, 64, 32)
+        .unwrap();
+    let rp = RenderingParams::try_create_for_primary_monitor().unwrap();
+    let white = (1.0f32, 1.0f32, 1.0f32);
+
+    let glyphs = face.get_glyph_indices(&['H' as u32, 'i' as u32]);
+    let run = GlyphRun::new(&face, 16.0).with_glyphs(&glyphs);
+    let dirty = rt
+        .draw_run(
+            4.5,
+            20.25,
+            DWRITE_MEASURING_MODE_GDI_CLASSIC,
+            &run,
+            &rp,
+            &white,
+        )
+        .unwrap()
+        .unwrap();
+    assert!(dirty.left < dirty.right && dirty.top < dirty.bottom);
+    assert!(dirty.left >= 0 && dirty.top >= 0);
+    assert!(dirty.right <= 64 && dirty.bottom <= 32);
+
+    let empty = GlyphRun::new(&face, 16.0);
+    assert!(rt
+        .draw_run(
+            4.5,
+            20.25,
+            DWRITE_MEASURING_MODE_NATURAL,
+            &empty,
+            &rp,
+            &white
+        )
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_gdi_compatible_glyph_advances() {
+    let face = arial_face();
+    let glyphs = face.get_glyph_indices(&['i' as u32, 'm' as u32, 'W' as u32]);
+    let em_size = 11.0;
+    let units_per_pixel = face.design_units_per_em() as f32 / em_size;
+
+    let advances = face
+        .get_gdi_compatible_glyph_advances(em_size, 1.0, None, false, false, &glyphs)
+        .unwrap();
+    assert_eq!(advances.len(), 3);
+    // GDI classic advances are hinted to whole pixels.
+    for &advance in &advances {
+        let pixels = advance as f32 / units_per_pixel;
+        assert!((pixels - pixels.round()).abs() < 0.01);
+    }
+
+    let design = face.get_design_glyph_advances(&glyphs, false).unwrap();
+    for (gdi, design) in advances.iter().zip(design.iter()) {
+        assert!(((gdi - design) as f32 / units_per_pixel).abs() <= 1.0);
+    }
+}
+
+#[test]
+fn test_bitmap_render_target_resize() {
+    let face = arial_face();
+    let gdi_interop = GdiInterop::try_create().unwrap();
+    let rt = gdi_interop
+        .create_bitmap_render_target(None, 16, 16)
+        .unwrap();
+    let memory_dc = rt.get_memory_dc();
+    assert_eq!(rt.size(), Ok((16, 16)));
+
+    rt.set_pixels_per_dip(2.0);
+    assert_eq!(rt.pixels_per_dip(), 2.0);
+
+    rt.resize(128, 48).unwrap();
+    assert_eq!(rt.size(), Ok((128, 48)));
+    assert_eq!(rt.get_memory_dc(), memory_dc);
+
+    let rp = RenderingParams::try_create_for_primary_monitor().unwrap();
+    let glyphs = face.get_glyph_indices(&['W' as u32]);
+    let run = GlyphRun::new(&face, 16.0).with_glyphs(&glyphs);
+    let dirty = rt
+        .draw_run(
+            40.0,
+            20.0,
+            DWRITE_MEASURING_MODE_NATURAL,
+            &run,
+            &rp,
+            &(1.0, 1.0, 1.0),
+        )
+        .unwrap()
+        .unwrap();
+    // At 2 pixels per dip, the glyph lands beyond the original 16x16 bitmap.
+    assert!(dirty.right > 16);
+    assert_eq!(rt.get_opaque_values_as_mask().len(), 128 * 48 * 4);
+}
+
+#[test]
+fn test_glyph_has_color_layers() {
+    let system_fc = FontCollection::system();
+    let get_face = |name| {
+        system_fc
+            .get_font_family_by_name(name)
+            .unwrap()
+            .get_first_matching_font(FontWeight::Regular, FontStretch::Normal, FontStyle::Normal)
+            .try_create_font_face()
+            .unwrap()
+    };
+    let emoji = get_face("Segoe UI Emoji");
+    let smiley = emoji.get_glyph_indices(&[0x1F600])[0];
+    assert!(emoji.glyph_has_color_layers(smiley));
+    assert!(!emoji.glyph_has_color_layers(0));
+
+    let arial = get_face("Arial");
+    let a = arial.get_glyph_indices(&['A' as u32])[0];
+    assert!(!arial.glyph_has_color_layers(a));
+}
+
+#[test]
+fn test_bitmap_render_target_pixels() {
+    let face = arial_face();
+    let gdi_interop = GdiInterop::try_create().unwrap();
+    let mut rt = gdi_interop
+        .create_bitmap_render_target(None, 40, 30)
+        .unwrap();
+
+    {
+        let mut pixels = rt.pixels_mut();
+        assert_eq!((pixels.width(), pixels.height()), (40, 30));
+        assert!(pixels.stride() >= 40 * 4);
+        pixels.fill((0, 0, 0));
+    }
+
+    let rp = RenderingParams::try_create_for_primary_monitor().unwrap();
+    let glyphs = face.get_glyph_indices(&['T' as u32]);
+    let run = GlyphRun::new(&face, 24.0).with_glyphs(&glyphs);
+    rt.draw_run(
+        8.0,
+        24.0,
+        DWRITE_MEASURING_MODE_NATURAL,
+        &run,
+        &rp,
+        &(1.0, 1.0, 1.0),
+    )
+    .unwrap();
+
+    // The crossbar of the T is near the top, and the bottom row is blank.
+    let pixels = rt.pixels();
+    let inked = |row: usize| pixels.row(row).iter().any(|&b| b != 0);
+    assert!((0..12).any(inked));
+    assert!(!inked(29));
+
+    let mask = rt.get_opaque_values_as_mask();
+    assert_eq!(mask.len(), 40 * 30 * 4);
+}
+
+#[test]
+fn test_color_palettes() {
+    let system_fc = FontCollection::system();
+    let get_face = |name| {
+        system_fc
+            .get_font_family_by_name(name)
+            .unwrap()
+            .get_first_matching_font(FontWeight::Regular, FontStretch::Normal, FontStyle::Normal)
+            .try_create_font_face()
+            .unwrap()
+    };
+    let emoji = get_face("Segoe UI Emoji");
+    let palette_count = emoji.get_color_palette_count();
+    let entry_count = emoji.get_palette_entry_count();
+    assert!(palette_count >= 1);
+    assert!(entry_count > 0);
+
+    let entries = emoji.get_palette_entries(0, 0, entry_count).unwrap();
+    assert_eq!(entries.len(), entry_count as usize);
+    assert!(entries.iter().all(|c| c.a >= 0.0 && c.a <= 1.0));
+    assert_eq!(
+        emoji.get_palette_entries(0, entry_count, 0).unwrap().len(),
+        0
+    );
+
+    assert!(match emoji.get_palette_entries(palette_count, 0, 1) {
+        Err(error) => error == Error::InvalidArg,
+        Ok(_) => false,
+    });
+    assert!(emoji.get_palette_entries(0, entry_count, 1).is_err());
+    assert!(emoji.get_palette_entries(0, 1, u32::MAX).is_err());
+
+    assert_eq!(get_face("Arial").get_color_palette_count(), 0);
+}
+
+#[test]
+fn test_bitmap_render_target_antialias_mode() {
+    let face = arial_face();
+    let gdi_interop = GdiInterop::try_create().unwrap();
+    let mut rt = gdi_interop
+        .create_bitmap_render_target(None, 48, 32)
+        .unwrap();
+    let rp = RenderingParams::try_create_for_primary_monitor().unwrap();
+    let glyphs = face.get_glyph_indices(&['W' as u32]);
+    let run = GlyphRun::new(&face, 20.0).with_glyphs(&glyphs);
+
+    let render = |rt: &mut BitmapRenderTarget| {
+        rt.pixels_mut().fill((0, 0, 0));
+        rt.draw_run(
+            4.0,
+            24.0,
+            DWRITE_MEASURING_MODE_NATURAL,
+            &run,
+            &rp,
+            &(1.0, 1.0, 1.0),
+        )
+        .unwrap();
+        rt.get_opaque_values_as_mask()
+    };
+
+    rt.set_text_antialias_mode(TextAntialiasMode::ClearType)
+        .unwrap();
+    let cleartype = render(&mut rt);
+    rt.set_text_antialias_mode(TextAntialiasMode::Grayscale)
+        .unwrap();
+    assert_eq!(
+        rt.get_text_antialias_mode(),
+        Some(TextAntialiasMode::Grayscale)
+    );
+    let grayscale = render(&mut rt);
+    // The mode sticks for later draws.
+    let grayscale_again = render(&mut rt);
+
+    assert_ne!(cleartype, grayscale);
+    assert_eq!(grayscale, grayscale_again);
+}
+
+#[test]
+fn test_translate_color_glyph_run() {
+    let system_fc = FontCollection::system();
+    let get_face = |name| {
+        system_fc
+            .get_font_family_by_name(name)
+            .unwrap()
+            .get_first_matching_font(FontWeight::Regular, FontStretch::Normal, FontStyle::Normal)
+            .try_create_font_face()
+            .unwrap()
+    };
+    let emoji = get_face("Segoe UI Emoji");
+    let smiley = emoji.get_glyph_indices(&[0x1F600]);
+    let run = GlyphRun::new(&emoji, 32.0).with_glyphs(&smiley);
+
+    let layers = ColorGlyphRun::translate(0.0, 32.0, &run, DWRITE_MEASURING_MODE_NATURAL, None)
+        .unwrap()
+        .unwrap();
+    assert!(layers.len() > 1);
+    assert!(layers
+        .iter()
+        .all(|layer| layer.glyph_run.glyphs().len() == 1));
+    assert!(layers.iter().any(|layer| layer.run_color.is_some()));
+
+    let last_palette = emoji.get_color_palette_count() - 1;
+    let themed = ColorGlyphRun::translate_with_palette(
+        0.0,
+        32.0,
+        &run,
+        DWRITE_MEASURING_MODE_NATURAL,
+        None,
+        last_palette,
+        None,
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(themed.len(), layers.len());
+
+    let arial = get_face("Arial");
+    let glyphs = arial.get_glyph_indices(&['A' as u32]);
+    let plain = GlyphRun::new(&arial, 32.0).with_glyphs(&glyphs);
+    assert!(
+        ColorGlyphRun::translate(0.0, 32.0, &plain, DWRITE_MEASURING_MODE_NATURAL, None)
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn test_vertical_glyph_metrics() {
+    let face = arial_face();
+    let glyphs = face.get_glyph_indices(&['A' as u32, 'g' as u32]);
+    let metrics = face.get_vertical_glyph_metrics(&glyphs);
+    let design = face.get_design_glyph_metrics(&glyphs, true);
+    assert_eq!(metrics.len(), 2);
+    assert_eq!(metrics[0].advance_height, design[0].advanceHeight);
+    assert_eq!(metrics[0].vertical_origin_y, design[0].verticalOriginY);
+
+    // 'A' sits on the baseline and 'g' descends below it; both fit inside
+    // their advance boxes.
+    let (a, g) = (metrics[0], metrics[1]);
+    assert!(a.black_box_top() > 0);
+    assert!(a.black_box_top() - a.black_box_height() == 0);
+    assert!(g.black_box_top() - g.black_box_height() < 0);
+    assert!(a.top_side_bearing >= 0 && a.bottom_side_bearing >= 0);
+}
+
+#[test]
+fn test_bitmap_render_target_transform() {
+    let face = arial_face();
+    let gdi_interop = GdiInterop::try_create().unwrap();
+    let rt = gdi_interop
+        .create_bitmap_render_target(None, 128, 128)
+        .unwrap();
+    let rp = RenderingParams::try_create_for_primary_monitor().unwrap();
+    let glyphs = face.get_glyph_indices(&['H' as u32]);
+    let run = GlyphRun::new(&face, 16.0).with_glyphs(&glyphs);
+    let draw = || {
+        rt.draw_run(
+            8.0,
+            24.0,
+            DWRITE_MEASURING_MODE_NATURAL,
+            &run,
+            &rp,
+            &(1.0, 1.0, 1.0),
+        )
+        .unwrap()
+        .unwrap()
+    };
+
+    assert!(rt.current_transform().unwrap().is_none());
+    let plain = draw();
+
+    let scale = DWRITE_MATRIX {
+        m11: 2.0,
+        m12: 0.0,
+        m21: 0.0,
+        m22: 2.0,
+        dx: 0.0,
+        dy: 0.0,
+    };
+    rt.set_current_transform(Some(&scale)).unwrap();
+    assert_eq!(rt.current_transform().unwrap().unwrap().m11, 2.0);
+    let scaled = draw();
+
+    let (plain_w, plain_h) = (plain.right - plain.left, plain.bottom - plain.top);
+    let (scaled_w, scaled_h) = (scaled.right - scaled.left, scaled.bottom - scaled.top);
+    assert!((scaled_w - 2 * plain_w).abs() <= 3);
+    assert!((scaled_h - 2 * plain_h).abs() <= 3);
+
+    rt.set_current_transform(None).unwrap();
+    assert!(rt.current_transform().unwrap().is_none());
+}
+
+#[test]
+fn test_glyph_run_analysis_from_glyphs() {
+    let face = arial_face();
+    let glyphs = face.get_glyph_indices(&['O' as u32, 'K' as u32]);
+    let analyze = |advances: &[f32]| {
+        GlyphRunAnalysis::from_glyphs(
+            &face,
+            20.0,
+            &glyphs,
+            advances,
+            &[],
+            false,
+            0,
+            DWRITE_RENDERING_MODE_ALIASED,
+            DWRITE_MEASURING_MODE_NATURAL,
+            0.0,
+            0.0,
+        )
+        .unwrap()
+        .ink_bounds()
+        .unwrap()
+    };
+
+    let natural = analyze(&[]);
+    let spread = analyze(&[40.0, 0.0]);
+    assert!(natural.right > natural.left);
+    assert!(spread.right - spread.left > natural.right - natural.left);
+
+    let mismatched = GlyphRunAnalysis::from_glyphs(
+        &face,
+        20.0,
+        &glyphs,
+        &[40.0],
+        &[],
+        false,
+        0,
+        DWRITE_RENDERING_MODE_ALIASED,
+        DWRITE_MEASURING_MODE_NATURAL,
+        0.0,
+        0.0,
+    );
+    assert_eq!(mismatched.err(), Some(Error::InvalidArg));
+}
+
+#[test]
+fn test_custom_rendering_params() {
+    let params = RenderingParams::create_custom(
+        1.8,
+        0.5,
+        0.0,
+        PixelGeometry::Bgr,
+        DWRITE_RENDERING_MODE_NATURAL_SYMMETRIC,
+    )
+    .unwrap();
+    assert!((params.gamma() - 1.8).abs() < 1e-6);
+    assert!((params.enhanced_contrast() - 0.5).abs() < 1e-6);
+    assert_eq!(params.cleartype_level(), 0.0);
+    assert_eq!(params.pixel_geometry(), PixelGeometry::Bgr);
+    assert_eq!(
+        params.rendering_mode(),
+        DWRITE_RENDERING_MODE_NATURAL_SYMMETRIC
+    );
+
+    let invalid = |gamma, contrast, level| match RenderingParams::create_custom(
+        gamma,
+        contrast,
+        level,
+        PixelGeometry::Flat,
+        DWRITE_RENDERING_MODE_DEFAULT,
+    ) {
+        Err(error) => error == Error::InvalidArg,
+        Ok(_) => false,
+    };
+    assert!(invalid(0.0, 0.5, 0.5));
+    assert!(invalid(-1.0, 0.5, 0.5));
+    assert!(invalid(1.8, -0.5, 0.5));
+    assert!(invalid(1.8, 0.5, 1.5));
+}
+
+#[test]
+fn test_rendering_params_for_monitor() {
+    use winapi::shared::windef::POINT;
+    use winapi::um::winuser::{MonitorFromPoint, MONITOR_DEFAULTTOPRIMARY};
+
+    let monitor = unsafe { MonitorFromPoint(POINT { x: 0, y: 0 }, MONITOR_DEFAULTTOPRIMARY) };
+    assert!(!monitor.is_null());
+    let params = unsafe { RenderingParams::create_for_monitor(monitor).unwrap() };
+    let primary = RenderingParams::try_create_for_primary_monitor().unwrap();
+    assert_eq!(params.pixel_geometry(), primary.pixel_geometry());
+    assert_eq!(params.cleartype_level(), primary.cleartype_level());
+    assert!(params.gamma() > 0.0);
+}
+
+#[test]
+fn test_glyph_run_from_parts() {
+    let face = arial_face();
+    let glyphs = face.get_glyph_indices(&['o' as u32, 'k' as u32]);
+    let offset = GlyphOffset {
+        advanceOffset: 0.,
+        ascenderOffset: 1.,
+    };
+
+    let run =
+        GlyphRun::from_parts(&face, 12.0, glyphs.clone(), vec![7.0, 6.0], vec![offset; 2]).unwrap();
+    assert_eq!(run.len(), 2);
+    assert!(!run.is_empty());
+    assert_eq!(run[1], glyphs[1]);
+    let cached = run.clone();
+    assert_eq!(cached.as_raw().glyphCount, 2);
+    assert_eq!(cached.offsets()[0].ascenderOffset, 1.);
+
+    assert!(GlyphRun::from_parts(&face, 12.0, glyphs.clone(), vec![], vec![]).is_ok());
+    assert!(GlyphRun::from_parts(&face, 12.0, glyphs.clone(), vec![7.0], vec![]).is_err());
+    assert!(GlyphRun::from_parts(&face, 12.0, glyphs, vec![], vec![offset; 3]).is_err());
+}
+
+#[test]
+fn test_text_analysis_source_owns_text() {
+    let fallback = FontFallback::get_system_fallback().unwrap();
+    let system_fc = FontCollection::system();
+    let source = {
+        let text = String::from("日本語abc");
+        let source =
+            TextAnalysisSource::from_owned_text(Box::new(FixedLocaleSource("ja-jp")), &text);
+        drop(text);
+        source
+    };
+
+    // The source still works once the caller's text is gone.
+    let result = fallback.map_characters(
+        &source,
+        0,
+        6,
+        &system_fc,
+        Some("Arial"),
+        FontWeight::Regular,
+        FontStyle::Normal,
+        FontStretch::Normal,
+    );
+    assert_eq!(result.mapped_length, 3);
+    assert!(result.mapped_font.is_some());
+}
+
+#[test]
+fn test_custom_rendering_params3() {
+    let params = RenderingParams::create_custom3(
+        2.2,
+        0.5,
+        1.0,
+        0.0,
+        PixelGeometry::Flat,
+        DWRITE_RENDERING_MODE1_NATURAL_SYMMETRIC_DOWNSAMPLED,
+        GridFitMode::Disabled,
+    )
+    .unwrap();
+    assert_eq!(
+        params.rendering_mode1(),
+        Some(DWRITE_RENDERING_MODE1_NATURAL_SYMMETRIC_DOWNSAMPLED)
+    );
+    assert_eq!(params.grid_fit_mode(), Some(GridFitMode::Disabled));
+    assert!((params.grayscale_enhanced_contrast().unwrap() - 1.0).abs() < 1e-6);
+
+    let params = RenderingParams::create_custom2(
+        2.2,
+        0.5,
+        0.25,
+        1.0,
+        PixelGeometry::Rgb,
+        DWRITE_RENDERING_MODE_NATURAL,
+        GridFitMode::Enabled,
+    )
+    .unwrap();
+    assert_eq!(params.grid_fit_mode(), Some(GridFitMode::Enabled));
+    assert_eq!(params.pixel_geometry(), PixelGeometry::Rgb);
+}
+
+#[test]
+fn test_text_analysis_source_reading_direction() {
+    let source = TextAnalysisSource::from_owned_text(Box::new(FixedLocaleSource("en-us")), "abc");
+    assert_eq!(
+        source.paragraph_reading_direction(),
+        winapi::um::dwrite::DWRITE_READING_DIRECTION_LEFT_TO_RIGHT
+    );
+}
+
+#[test]
+fn test_simple_text_analysis_source() {
+    use winapi::um::dwrite::DWRITE_READING_DIRECTION_RIGHT_TO_LEFT;
+
+    let source = TextAnalysisSource::from_text_with_locale(
+        "日本語abc",
+        "ja-jp",
+        DWRITE_READING_DIRECTION_RIGHT_TO_LEFT,
+    );
+    assert_eq!(
+        source.paragraph_reading_direction(),
+        DWRITE_READING_DIRECTION_RIGHT_TO_LEFT
+    );
+
+    let fallback = FontFallback::get_system_fallback().unwrap();
+    let result = fallback.map_characters(
+        &source,
+        0,
+        6,
+        &FontCollection::system(),
+        Some("Arial"),
+        FontWeight::Regular,
+        FontStyle::Normal,
+        FontStretch::Normal,
+    );
+    assert_eq!(result.mapped_length, 3);
+    assert!(result.mapped_font.is_some());
+}
+
+#[test]
+fn test_text_analysis_source_from_str() {
+    let text = "abc日本😀";
+    let (source, len) = TextAnalysisSource::from_str(Box::new(FixedLocaleSource("ja-jp")), text);
+    assert_eq!(len, 7);
+    assert_eq!(len as usize, text.encode_utf16().count());
+
+    let fallback = FontFallback::get_system_fallback().unwrap();
+    let result = fallback.map_characters(
+        &source,
+        0,
+        len,
+        &FontCollection::system(),
+        Some("Arial"),
+        FontWeight::Regular,
+        FontStyle::Normal,
+        FontStretch::Normal,
+    );
+    assert_eq!(result.mapped_length, 3);
+
+    let number_subst = NumberSubstitution::try_new(
+        winapi::um::dwrite::DWRITE_NUMBER_SUBSTITUTION_METHOD_NONE,
+        "en-us",
+        true,
+    )
+    .unwrap();
+    let (_, len) = TextAnalysisSource::from_str_and_number_subst(
+        Box::new(FixedLocaleSource("en-us")),
+        "123",
+        number_subst,
+    );
+    assert_eq!(len, 3);
+}
+
+#[test]
+fn test_to_wide() {
+    assert_eq!("ab😀".to_wide(), vec![0x61, 0x62, 0xd83d, 0xde00]);
+    assert_eq!("ab".to_wide_null(), vec![0x61, 0x62, 0]);
+    assert_eq!(String::new().to_wide_null(), vec![0]);
+}
+
+#[test]
+fn test_factory_capabilities() {
+    let level = max_interface_level();
+    let factory = Factory::shared();
+    assert_eq!(factory.max_interface_level(), level);
+    assert_eq!(factory.clone().capabilities(), capabilities());
+    assert_eq!(Factory::isolated().unwrap().max_interface_level(), level);
+
+    let caps = capabilities();
+    assert_eq!(caps.has_font_fallback, level >= 2);
+    assert_eq!(
+        caps.has_font_fallback,
+        FontFallback::get_system_fallback().is_some()
+    );
+    assert_eq!(caps, Capabilities::from_interface_level(level));
+    assert!(!Capabilities::from_interface_level(0).has_font_fallback);
+    assert!(Capabilities::from_interface_level(7).has_variable_fonts);
+}
+
+#[test]
+fn test_glyph_index_cache() {
+    let face = arial_face();
+
+    let mut cache = GlyphIndexCache::new();
+    assert!(cache.is_empty());
+    let expected = face.get_glyph_indices(&['A' as u32, 'b' as u32, '日' as u32]);
+    assert_eq!(cache.get_glyphs(&face, "Ab日"), expected);
+    assert_eq!(
+        cache.get_glyphs(&face, "AbAb"),
+        [expected[0], expected[1]].repeat(2)
+    );
+    assert_eq!(cache.get_glyph(&face, '日'), 0);
+    assert_eq!(cache.len(), 3);
+
+    // Another face object for the same font shares the entries, even one
+    // created from a separate file object.
+    let file = &face.get_files()[0];
+    let other_face = file
+        .create_face(face.get_index(), DWRITE_FONT_SIMULATIONS_NONE)
+        .unwrap();
+    assert_eq!(cache.get_glyph(&other_face, 'A'), expected[0]);
+    assert_eq!(cache.len(), 3);
+
+    // The same font with different simulations doesn't.
+    let bold_face = file
+        .create_face(face.get_index(), DWRITE_FONT_SIMULATIONS_BOLD)
+        .unwrap();
+    assert_eq!(cache.get_glyph(&bold_face, 'A'), expected[0]);
+    assert_eq!(cache.len(), 4);
+
+    cache.remove_face(&other_face);
+    assert_eq!(cache.len(), 1);
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_text_format() {
+    use winapi::um::dwrite::DWRITE_READING_DIRECTION_RIGHT_TO_LEFT;
+
+    let format = TextFormat::new(
+        "Arial",
+        None,
+        FontWeight::Bold,
+        FontStyle::Italic,
+        FontStretch::Condensed,
+        13.5,
+        "en-us",
+    )
+    .unwrap();
+    assert_eq!(format.font_family_name().unwrap(), "Arial");
+    assert!(format.font_collection().is_some());
+    assert_eq!(format.font_weight(), FontWeight::Bold);
+    assert_eq!(format.font_style(), FontStyle::Italic);
+    assert_eq!(format.font_stretch(), FontStretch::Condensed);
+    assert_eq!(format.font_size(), 13.5);
+    assert_eq!(format.locale_name().unwrap(), "en-us");
+
+    format.set_text_alignment(TextAlignment::Center).unwrap();
+    assert_eq!(format.text_alignment(), TextAlignment::Center);
+    format
+        .set_paragraph_alignment(ParagraphAlignment::Far)
+        .unwrap();
+    assert_eq!(format.paragraph_alignment(), ParagraphAlignment::Far);
+    format.set_word_wrapping(WordWrapping::NoWrap).unwrap();
+    assert_eq!(format.word_wrapping(), WordWrapping::NoWrap);
+    format
+        .set_reading_direction(DWRITE_READING_DIRECTION_RIGHT_TO_LEFT)
+        .unwrap();
+    assert_eq!(
+        format.reading_direction(),
+        DWRITE_READING_DIRECTION_RIGHT_TO_LEFT
+    );
+    format
+        .set_flow_direction(FlowDirection::BottomToTop)
+        .unwrap();
+    assert_eq!(format.flow_direction(), FlowDirection::BottomToTop);
+    format.set_incremental_tab_stop(48.0).unwrap();
+    assert_eq!(format.incremental_tab_stop(), 48.0);
+    assert!(format.set_incremental_tab_stop(-1.0).is_err());
+
+    let spacing = LineSpacing {
+        method: LineSpacingMethod::Uniform,
+        height: 20.0,
+        baseline: 16.0,
+    };
+    format.set_line_spacing(&spacing).unwrap();
+    assert_eq!(format.line_spacing(), Ok(spacing));
+
+    let (trimming, sign) = format.trimming().unwrap();
+    assert_eq!(trimming.granularity, TrimmingGranularity::None);
+    assert!(sign.is_none());
+    let trimming = Trimming {
+        granularity: TrimmingGranularity::Character,
+        delimiter: Some('\\'),
+        delimiter_count: 1,
+    };
+    let ellipsis = format.create_ellipsis_trimming_sign().unwrap();
+    format.set_trimming(&trimming, Some(&ellipsis)).unwrap();
+    let (round_tripped, sign) = format.trimming().unwrap();
+    assert_eq!(round_tripped, trimming);
+    assert_eq!(
+        sign.map(|sign| unsafe { sign.as_ptr() }),
+        Some(unsafe { ellipsis.as_ptr() })
+    );
+
+    assert!(TextFormat::new(
+        "Arial",
+        None,
+        FontWeight::Regular,
+        FontStyle::Normal,
+        FontStretch::Normal,
+        -1.0,
+        "en-us",
+    )
+    .is_err());
+}
+
+#[test]
+fn test_text_layout_metrics() {
+    let format = TextFormat::new(
+        "Arial",
+        None,
+        FontWeight::Regular,
+        FontStyle::Normal,
+        FontStretch::Normal,
+        16.0,
+        "en-us",
+    )
+    .unwrap();
+    let text = "The quick brown fox jumps over the lazy dog";
+
+    let wide = TextLayout::new(text, &format, 10000.0, 1000.0).unwrap();
+    let wide_metrics = wide.metrics().unwrap();
+    assert_eq!(wide_metrics.line_count, 1);
+    assert_eq!(wide_metrics.layout_width, 10000.0);
+
+    let layout = TextLayout::new(text, &format, 80.0, 1000.0).unwrap();
+    let metrics = layout.metrics().unwrap();
+    assert!(metrics.line_count > 1);
+    assert!(metrics.height > wide_metrics.height);
+
+    let lines = layout.line_metrics().unwrap();
+    assert_eq!(lines.len() as u32, metrics.line_count);
+    let total: u32 = lines.iter().map(|line| line.length).sum();
+    assert_eq!(total as usize, text.len());
+    assert!(lines
+        .iter()
+        .all(|line| line.height > 0.0 && !line.is_trimmed));
+
+    let clusters = layout.cluster_metrics().unwrap();
+    assert_eq!(clusters.len(), text.len());
+    assert!(clusters[3].is_whitespace && clusters[3].can_wrap_line_after);
+    assert!(!clusters[0].is_right_to_left);
+
+    let min_width = layout.determine_min_width().unwrap();
+    assert!(min_width > 0.0 && min_width < wide_metrics.width);
+    assert!(layout.overhang_metrics().is_ok());
+    assert_eq!(layout.text_format().font_size(), 16.0);
+}
+
+#[test]
+fn test_font_property_names() {
+    for v in (100..=950).step_by(50).chain(vec![1, 999]) {
+        let weight = FontWeight::from_u32(v);
+        assert_eq!(weight.to_string().parse::<FontWeight>(), Ok(weight));
+    }
+    assert_eq!(FontWeight::SemiBold.to_string(), "semi-bold");
+    assert_eq!(FontWeight::Unknown(450).to_string(), "450");
+    assert_eq!("Bold".parse::<FontWeight>(), Ok(FontWeight::Bold));
+    assert_eq!("regular".parse::<FontWeight>(), Ok(FontWeight::Regular));
+    assert_eq!("600".parse::<FontWeight>(), Ok(FontWeight::SemiBold));
+    assert!("1000".parse::<FontWeight>().is_err());
+    assert!("heavy".parse::<FontWeight>().is_err());
+
+    for v in 0..=9 {
+        let stretch = FontStretch::from_u32(v);
+        assert_eq!(stretch.to_string().parse::<FontStretch>(), Ok(stretch));
+    }
+    assert_eq!(FontStretch::Condensed.to_string(), "condensed");
+    assert!("squished".parse::<FontStretch>().is_err());
+
+    for style in &[FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic] {
+        assert_eq!(style.to_string().parse::<FontStyle>(), Ok(*style));
+    }
+    assert_eq!(FontStyle::Italic.to_string(), "italic");
+    assert_eq!("ITALIC".parse::<FontStyle>(), Ok(FontStyle::Italic));
+    assert!("slanted".parse::<FontStyle>().is_err());
+}
+
+#[test]
+fn test_scaled_font_metrics() {
+    let face = arial_face();
+    let metrics = face.metrics().metrics0();
+
+    let scaled = face.scaled_metrics(32.0);
+    let scale = 32.0 / metrics.designUnitsPerEm as f32;
+    assert_eq!(scaled.em_size, 32.0);
+    assert_eq!(scaled.ascent, metrics.ascent as f32 * scale);
+    assert_eq!(scaled.descent, metrics.descent as f32 * scale);
+    assert_eq!(scaled.x_height, metrics.xHeight as f32 * scale);
+    assert!(scaled.cap_height > scaled.x_height);
+    assert!(scaled.underline_position < 0.0);
+    assert!(scaled.underline_thickness > 0.0);
+    assert_eq!(
+        scaled.line_height(),
+        scaled.ascent + scaled.descent + scaled.line_gap
+    );
+    assert_eq!(face.scaled_metrics(64.0).ascent, scaled.ascent * 2.0);
+}
+
+#[test]
+fn test_text_layout_hit_testing() {
+    let format = TextFormat::new(
+        "Arial",
+        None,
+        FontWeight::Regular,
+        FontStyle::Normal,
+        FontStretch::Normal,
+        16.0,
+        "en-us",
+    )
+    .unwrap();
+    let text = "hello שלום world";
+    let layout = TextLayout::new(text, &format, 1000.0, 100.0).unwrap();
+
+    let (x, y, metrics) = layout.hit_test_text_position(1, false).unwrap();
+    assert_eq!(metrics.text_position, 1);
+    assert_eq!(x, metrics.left);
+    assert!(x > 0.0 && y == 0.0);
+
+    let hit = layout
+        .hit_test_point(x + metrics.width / 4.0, metrics.height / 2.0)
+        .unwrap();
+    assert!(hit.is_inside && !hit.is_trailing);
+    assert_eq!(hit.metrics.text_position, 1);
+
+    let hit = layout.hit_test_point(5000.0, 0.0).unwrap();
+    assert!(!hit.is_inside);
+
+    // "o שלום w" crosses two direction changes.
+    let rects = layout.hit_test_text_range(4..13, (10.0, 20.0)).unwrap();
+    assert!(rects.len() >= 2);
+    assert!(rects.iter().any(|rect| rect.bidi_level % 2 == 1));
+    assert!(rects
+        .iter()
+        .all(|rect| rect.top >= 20.0 && rect.width > 0.0));
+    let total: u32 = rects.iter().map(|rect| rect.length).sum();
+    assert_eq!(total, 9);
+
+    assert_eq!(
+        layout.hit_test_text_range(0..5, (0.0, 0.0)).unwrap().len(),
+        1
+    );
+}
+
+#[test]
+fn test_text_layout_range_formatting() {
+    let format = TextFormat::new(
+        "Arial",
+        None,
+        FontWeight::Regular,
+        FontStyle::Normal,
+        FontStretch::Normal,
+        16.0,
+        "en-us",
+    )
+    .unwrap();
+    let layout = TextLayout::new("plain bold italic", &format, 1000.0, 100.0).unwrap();
+
+    layout.set_font_weight(6..10, FontWeight::Bold).unwrap();
+    assert_eq!(layout.font_weight(7).unwrap(), (FontWeight::Bold, 6..10));
+    assert_eq!(layout.font_weight(0).unwrap(), (FontWeight::Regular, 0..6));
+
+    layout.set_font_style(11..17, FontStyle::Italic).unwrap();
+    assert_eq!(layout.font_style(11).unwrap(), (FontStyle::Italic, 11..17));
+    layout
+        .set_font_stretch(0..5, FontStretch::Condensed)
+        .unwrap();
+    assert_eq!(
+        layout.font_stretch(2).unwrap(),
+        (FontStretch::Condensed, 0..5)
+    );
+    layout.set_font_size(0..5, 24.0).unwrap();
+    assert_eq!(layout.font_size(0).unwrap(), (24.0, 0..5));
+    assert_eq!(layout.font_size(5).unwrap().0, 16.0);
+
+    layout.set_underline(0..5, true).unwrap();
+    assert_eq!(layout.underline(4).unwrap(), (true, 0..5));
+    assert!(!layout.underline(5).unwrap().0);
+    layout.set_strikethrough(6..10, true).unwrap();
+    assert_eq!(layout.strikethrough(6).unwrap(), (true, 6..10));
+
+    layout
+        .set_font_family_name(11..17, "Times New Roman")
+        .unwrap();
+    assert_eq!(
+        layout.font_family_name(12).unwrap(),
+        ("Times New Roman".to_owned(), 11..17)
+    );
+    assert_eq!(layout.font_family_name(0).unwrap().0, "Arial");
+    layout
+        .set_font_collection(0..17, &FontCollection::system())
+        .unwrap();
+    let (collection, range) = layout.font_collection(3).unwrap();
+    assert!(collection.get_font_family_by_name("Arial").is_some());
+    assert!(range.start == 0 && range.end >= 17);
+    layout.set_locale(6..10, "de-de").unwrap();
+    assert_eq!(layout.locale(6).unwrap(), ("de-de".to_owned(), 6..10));
+
+    assert!(layout.typography(0).unwrap().0.is_none());
+    let typography = Typography::new().unwrap();
+    let smcp = FontFeature {
+        tag: winapi::um::dwrite::DWRITE_FONT_FEATURE_TAG_SMALL_CAPITALS,
+        value: 1,
+    };
+    typography.add_font_feature(smcp).unwrap();
+    layout.set_typography(0..5, &typography).unwrap();
+    let (typography, range) = layout.typography(0).unwrap();
+    assert_eq!(range, 0..5);
+    assert_eq!(typography.unwrap().font_features(), Ok(vec![smcp]));
+
+    // Layout still works with all of this applied.
+    assert_eq!(layout.metrics().unwrap().line_count, 1);
+}
+
+#[test]
+fn test_text_layout_draw() {
+    struct Collector {
+        runs: Vec<(f32, f32, GlyphRun)>,
+        texts: Vec<String>,
+        underlines: Vec<Decoration>,
+    }
+
+    impl TextRendererMethods for Collector {
+        fn draw_glyph_run(
+            &mut self,
+            x: f32,
+            y: f32,
+            _: DWRITE_MEASURING_MODE,
+            run: &GlyphRunView,
+        ) -> Result<(), Error> {
+            assert_eq!(run.glyph_indices.len(), run.glyph_advances.len());
+            assert_eq!(run.cluster_map.len(), run.text.len());
+            assert_eq!(run.locale, "en-us");
+            self.runs.push((x, y, run.to_glyph_run()));
+            self.texts.push(String::from_utf16_lossy(run.text));
+            Ok(())
+        }
+
+        fn draw_underline(&mut self, _: f32, _: f32, underline: &Decoration) -> Result<(), Error> {
+            self.underlines.push(underline.clone());
+            Ok(())
+        }
+    }
+
+    let format = TextFormat::new(
+        "Arial",
+        None,
+        FontWeight::Regular,
+        FontStyle::Normal,
+        FontStretch::Normal,
+        16.0,
+        "en-us",
+    )
+    .unwrap();
+    let layout = TextLayout::new("hello world", &format, 1000.0, 100.0).unwrap();
+    layout.set_underline(0..5, true).unwrap();
+
+    let mut collector = Collector {
+        runs: vec![],
+        texts: vec![],
+        underlines: vec![],
+    };
+    layout.draw(&mut collector, (10.0, 20.0)).unwrap();
+
+    assert!(!collector.runs.is_empty());
+    assert_eq!(collector.texts.concat(), "hello world");
+    let glyph_count: usize = collector.runs.iter().map(|run| run.2.glyphs().len()).sum();
+    assert_eq!(glyph_count, 11);
+    let (x, y, _) = collector.runs[0];
+    assert_eq!(x, 10.0);
+    assert!(y > 20.0);
+    assert_eq!(collector.underlines.len(), 1);
+    assert!(collector.underlines[0].width > 0.0);
+
+    // An error from the renderer stops drawing and is returned.
+    struct Failing;
+    impl TextRendererMethods for Failing {
+        fn draw_glyph_run(
+            &mut self,
+            _: f32,
+            _: f32,
+            _: DWRITE_MEASURING_MODE,
+            _: &GlyphRunView,
+        ) -> Result<(), Error> {
+            Err(Error::Other(winapi::shared::winerror::E_FAIL))
+        }
+    }
+    assert_eq!(
+        layout.draw(&mut Failing, (0.0, 0.0)),
+        Err(Error::Other(winapi::shared::winerror::E_FAIL))
+    );
+}
+
+#[test]
+fn test_text_layout_inline_object() {
+    struct Square;
+
+    impl InlineObjectMethods for Square {
+        fn metrics(&self) -> InlineObjectMetrics {
+            InlineObjectMetrics {
+                width: 20.0,
+                height: 20.0,
+                baseline: 16.0,
+                supports_sideways: false,
+            }
+        }
+
+        fn draw(
+            &mut self,
+            renderer: &mut dyn TextRendererMethods,
+            x: f32,
+            y: f32,
+            _: bool,
+            _: bool,
+        ) -> Result<(), Error> {
+            // Draw the square as a thick "underline" through the renderer.
+            let decoration = Decoration {
+                width: 20.0,
+                thickness: 20.0,
+                offset: 0.0,
+                run_height: 20.0,
+                reading_direction: winapi::um::dwrite::DWRITE_READING_DIRECTION_LEFT_TO_RIGHT,
+                flow_direction: FlowDirection::TopToBottom,
+                locale: String::new(),
+                measuring_mode: DWRITE_MEASURING_MODE_NATURAL,
+            };
+            renderer.draw_underline(x, y, &decoration)
+        }
+    }
+
+    struct Collector {
+        squares: Vec<(f32, f32)>,
+    }
+
+    impl TextRendererMethods for Collector {
+        fn draw_glyph_run(
+            &mut self,
+            _: f32,
+            _: f32,
+            _: DWRITE_MEASURING_MODE,
+            _: &GlyphRunView,
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn draw_underline(&mut self, x: f32, y: f32, _: &Decoration) -> Result<(), Error> {
+            self.squares.push((x, y));
+            Ok(())
+        }
+
+        fn draw_inline_object(
+            &mut self,
+            x: f32,
+            y: f32,
+            object: &InlineObject,
+            is_sideways: bool,
+            is_right_to_left: bool,
+        ) -> Result<(), Error> {
+            object.draw(self, x, y, is_sideways, is_right_to_left)
+        }
+    }
+
+    let format = TextFormat::new(
+        "Arial",
+        None,
+        FontWeight::Regular,
+        FontStyle::Normal,
+        FontStretch::Normal,
+        16.0,
+        "en-us",
+    )
+    .unwrap();
+    let plain = TextLayout::new("a\u{FFFC}b", &format, 1000.0, 100.0).unwrap();
+    let layout = TextLayout::new("a\u{FFFC}b", &format, 1000.0, 100.0).unwrap();
+    assert!(layout.inline_object(1).unwrap().0.is_none());
+
+    let square = InlineObject::new(Box::new(Square));
+    assert_eq!(square.metrics().unwrap().width, 20.0);
+    assert_eq!(
+        square.break_conditions().unwrap(),
+        (BreakCondition::Neutral, BreakCondition::Neutral)
+    );
+    layout.set_inline_object(1..2, &square).unwrap();
+    let (object, range) = layout.inline_object(1).unwrap();
+    assert_eq!(range, 1..2);
+    assert_eq!(unsafe { object.unwrap().as_ptr() }, unsafe {
+        square.as_ptr()
+    });
+    assert!(layout.metrics().unwrap().width > plain.metrics().unwrap().width);
+
+    let mut collector = Collector { squares: vec![] };
+    layout.draw(&mut collector, (0.0, 0.0)).unwrap();
+    assert_eq!(collector.squares.len(), 1);
+    let (x, y) = collector.squares[0];
+    assert!(x > 0.0);
+    assert!((0.0..20.0).contains(&y));
+
+    let ellipsis = format.create_ellipsis_trimming_sign().unwrap();
+    assert!(ellipsis.metrics().unwrap().width > 0.0);
+}
+
+#[test]
+fn test_text_layout_gdi_compatible() {
+    let format = TextFormat::new(
+        "Arial",
+        None,
+        FontWeight::Regular,
+        FontStyle::Normal,
+        FontStretch::Normal,
+        13.0,
+        "en-us",
+    )
+    .unwrap();
+    let text = "The quick brown fox jumps over the lazy dog";
+
+    let ideal = TextLayout::new(text, &format, 10000.0, 1000.0).unwrap();
+    let gdi =
+        TextLayout::new_gdi_compatible(text, &format, 10000.0, 1000.0, 1.0, None, false).unwrap();
+    let ideal_width = ideal.metrics().unwrap().width_including_trailing_whitespace;
+    let gdi_width = gdi.metrics().unwrap().width_including_trailing_whitespace;
+    assert_ne!(ideal_width, gdi_width);
+    // GDI classic advances are whole pixels.
+    assert_eq!(gdi_width.fract(), 0.0);
+
+    // The other layout methods work as usual.
+    gdi.set_font_weight(4..9, FontWeight::Bold).unwrap();
+    assert_eq!(gdi.metrics().unwrap().line_count, 1);
+    let natural =
+        TextLayout::new_gdi_compatible(text, &format, 10000.0, 1000.0, 2.0, None, true).unwrap();
+    assert!(natural.metrics().unwrap().width > 0.0);
+}
+
+#[test]
+fn test_error_hresults() {
+    use winapi::shared::winerror::{E_ACCESSDENIED, E_FAIL, E_INVALIDARG};
+
+    for &hr in &[
+        DWRITE_E_FILEFORMAT,
+        DWRITE_E_NOFONT,
+        DWRITE_E_FILENOTFOUND,
+        DWRITE_E_REMOTEFONT,
+        DWRITE_E_NOCOLOR,
+        E_ACCESSDENIED,
+        E_INVALIDARG,
+        E_FAIL,
+    ] {
+        assert_eq!(Error::from(hr).hresult(), hr);
+    }
+    assert_eq!(Error::from(E_ACCESSDENIED), Error::AccessDenied);
+    assert_eq!(Error::from(E_FAIL), Error::Other(E_FAIL));
+    assert_eq!(HRESULT::from(Error::NoColor), DWRITE_E_NOCOLOR);
+    assert_eq!(
+        Error::InvalidArg.to_string(),
+        "invalid argument (0x80070057)"
+    );
+
+    assert!(arial_font().try_create_font_face().is_ok());
+}
+
+#[test]
+fn test_text_analysis_source_query_interface() {
+    use winapi::shared::guiddef::GUID;
+    use winapi::shared::winerror::E_NOINTERFACE;
+    use winapi::um::dwrite::IDWriteTextAnalysisSource;
+    use winapi::um::dwrite_1::{
+        IDWriteTextAnalysisSource1, DWRITE_VERTICAL_GLYPH_ORIENTATION_STACKED,
+    };
+    use winapi::um::unknwnbase::IUnknown;
+    use winapi::Interface;
+
+    struct StackedSource;
+
+    impl TextAnalysisSourceMethods for StackedSource {
+        fn get_locale_name(&self, _text_position: u32) -> (std::borrow::Cow<'_, str>, u32) {
+            ("ja-jp".into(), u32::MAX)
+        }
+
+        fn get_paragraph_reading_direction(&self) -> winapi::um::dwrite::DWRITE_READING_DIRECTION {
+            winapi::um::dwrite::DWRITE_READING_DIRECTION_TOP_TO_BOTTOM
+        }
+
+        fn supports_vertical_glyph_orientation(&self) -> bool {
+            true
+        }
+
+        fn get_vertical_glyph_orientation(
+            &self,
+            _text_position: u32,
+        ) -> (
+            winapi::um::dwrite_1::DWRITE_VERTICAL_GLYPH_ORIENTATION,
+            u8,
+            u32,
+        ) {
+            (DWRITE_VERTICAL_GLYPH_ORIENTATION_STACKED, 0, 2)
+        }
+    }
+
+    let random = GUID {
+        Data1: 0x5f3a_9c11,
+        Data2: 0x2b7e,
+        Data3: 0x4d60,
+        Data4: [0x91, 0x0c, 0x7a, 0x3e, 0x52, 0xd8, 0x1f, 0x64],
+    };
+    let query = |source: &TextAnalysisSource, iid: &GUID| unsafe {
+        let mut object = std::ptr::null_mut();
+        let hr = (*source.as_ptr()).QueryInterface(iid, &mut object);
+        (hr, object)
+    };
+
+    let plain = TextAnalysisSource::from_owned_text(Box::new(FixedLocaleSource("en-us")), "abc");
+    let native = unsafe { plain.as_ptr() as *mut _ };
+    assert_eq!(query(&plain, &IUnknown::uuidof()), (S_OK, native));
+    assert_eq!(
+        query(&plain, &IDWriteTextAnalysisSource::uuidof()),
+        (S_OK, native)
+    );
+    let not_found = (E_NOINTERFACE, std::ptr::null_mut());
+    assert_eq!(
+        query(&plain, &IDWriteTextAnalysisSource1::uuidof()),
+        not_found
+    );
+    assert_eq!(query(&plain, &random), not_found);
+
+    let stacked = TextAnalysisSource::from_owned_text(Box::new(StackedSource), "縦書き");
+    let native = unsafe { stacked.as_ptr() as *mut _ };
+    assert_eq!(query(&stacked, &IUnknown::uuidof()), (S_OK, native));
+    assert_eq!(
+        query(&stacked, &IDWriteTextAnalysisSource::uuidof()),
+        (S_OK, native)
+    );
+    assert_eq!(
+        query(&stacked, &IDWriteTextAnalysisSource1::uuidof()),
+        (S_OK, native)
+    );
+    assert_eq!(query(&stacked, &random), not_found);
+    assert_eq!(
+        stacked.paragraph_reading_direction(),
+        winapi::um::dwrite::DWRITE_READING_DIRECTION_TOP_TO_BOTTOM
+    );
+
+    unsafe {
+        let source1 = native as *mut IDWriteTextAnalysisSource1;
+        let (mut len, mut orientation, mut bidi_level) = (0, 0, 1);
+        let hr =
+            (*source1).GetVerticalGlyphOrientation(1, &mut len, &mut orientation, &mut bidi_level);
+        assert_eq!(hr, S_OK);
+        assert_eq!(
+            (len, orientation, bidi_level),
+            (2, DWRITE_VERTICAL_GLYPH_ORIENTATION_STACKED, 0)
+        );
+    }
+}
+
+#[test]
+fn test_com_refcount_threads() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    struct Counted(Arc<AtomicUsize>);
+
+    impl InlineObjectMethods for Counted {
+        fn metrics(&self) -> InlineObjectMetrics {
+            InlineObjectMetrics {
+                width: 1.0,
+                height: 1.0,
+                baseline: 1.0,
+                supports_sideways: false,
+            }
+        }
+
+        fn draw(
+            &mut self,
+            _: &mut dyn TextRendererMethods,
+            _: f32,
+            _: f32,
+            _: bool,
+            _: bool,
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    const THREADS: usize = 8;
+    for _ in 0..20 {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let object = InlineObject::new(Box::new(Counted(drops.clone())));
+        // Each thread gets a reference of its own, and releases it last, so
+        // whichever thread finishes last drops the object.
+        let native = unsafe {
+            let native = object.as_ptr();
+            for _ in 0..THREADS {
+                (*native).AddRef();
+            }
+            native as usize
+        };
+        drop(object);
+        assert_eq!(drops.load(Ordering::SeqCst), 0);
+
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                thread::spawn(move || unsafe {
+                    let native = native as *mut winapi::um::dwrite::IDWriteInlineObject;
+                    for _ in 0..1000 {
+                        assert!((*native).AddRef() >= 2);
+                        assert!((*native).Release() >= 1);
+                    }
+                    (*native).Release();
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+    }
+}
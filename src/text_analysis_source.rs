@@ -5,15 +5,14 @@
 use std::borrow::Cow;
 use std::marker::PhantomData;
 use winapi::ctypes::wchar_t;
-use winapi::um::dwrite::IDWriteTextAnalysisSource;
+use winapi::um::dwrite::{IDWriteTextAnalysisSource, DWRITE_READING_DIRECTION};
 use wio::com::ComPtr;
 
 use super::*;
-use crate::com_helpers::Com;
 
 pub struct TextAnalysisSource<'a> {
     native: ComPtr<IDWriteTextAnalysisSource>,
-    phantom: PhantomData<CustomTextAnalysisSourceImpl<'a>>
+    phantom: PhantomData<CustomTextAnalysisSourceImpl<'a>>,
 }
 
 impl<'a> TextAnalysisSource<'a> {
@@ -28,10 +27,13 @@ impl<'a> TextAnalysisSource<'a> {
     ) -> TextAnalysisSource<'a> {
         let native = unsafe {
             ComPtr::from_raw(
-                CustomTextAnalysisSourceImpl::from_text_native(inner, text).into_interface()
+                CustomTextAnalysisSourceImpl::from_text_native(inner, text).into_native(),
             )
         };
-        TextAnalysisSource { native, phantom: PhantomData }
+        TextAnalysisSource {
+            native,
+            phantom: PhantomData,
+        }
     }
 
     /// Create a new custom TextAnalysisSource for the given text and a trait
@@ -51,13 +53,91 @@ impl<'a> TextAnalysisSource<'a> {
                     text,
                     number_subst,
                 )
-                .into_interface()
+                .into_native(),
             )
         };
-        TextAnalysisSource { native, phantom: PhantomData }
+        TextAnalysisSource {
+            native,
+            phantom: PhantomData,
+        }
     }
 
-    pub fn as_ptr(&self) -> *mut IDWriteTextAnalysisSource {
+    /// Create a new custom TextAnalysisSource for a UTF-8 string, which is
+    /// encoded to UTF-16 internally.
+    ///
+    /// Also returns the length of the text in UTF-16 code units, which is what
+    /// positions and lengths reported by DirectWrite refer to.
+    pub fn from_str(
+        inner: Box<dyn TextAnalysisSourceMethods + 'a>,
+        text: &str,
+    ) -> (TextAnalysisSource<'a>, u32) {
+        TextAnalysisSource::from_str_owned(inner, text, None)
+    }
+
+    /// Like `from_str`, but with a `NumberSubstitution` for the entire string.
+    pub fn from_str_and_number_subst(
+        inner: Box<dyn TextAnalysisSourceMethods + 'a>,
+        text: &str,
+        number_subst: NumberSubstitution,
+    ) -> (TextAnalysisSource<'a>, u32) {
+        TextAnalysisSource::from_str_owned(inner, text, Some(number_subst))
+    }
+
+    /// Create a new custom TextAnalysisSource that owns a UTF-16 copy of
+    /// `text`, so it doesn't borrow anything from the caller.
+    pub fn from_owned_text(
+        inner: Box<dyn TextAnalysisSourceMethods + 'static>,
+        text: &str,
+    ) -> TextAnalysisSource<'static> {
+        TextAnalysisSource::from_str_owned(inner, text, None).0
+    }
+
+    fn from_str_owned(
+        inner: Box<dyn TextAnalysisSourceMethods + 'a>,
+        text: &str,
+        number_subst: Option<NumberSubstitution>,
+    ) -> (TextAnalysisSource<'a>, u32) {
+        let text: Vec<wchar_t> = text.encode_utf16().collect();
+        let len = text.len() as u32;
+        let source = match number_subst {
+            Some(number_subst) => TextAnalysisSource::from_text_and_number_subst(
+                inner,
+                Cow::Owned(text),
+                number_subst,
+            ),
+            None => TextAnalysisSource::from_text(inner, Cow::Owned(text)),
+        };
+        (source, len)
+    }
+
+    /// Create a new TextAnalysisSource for `text` using a single locale and
+    /// reading direction for the whole paragraph.
+    pub fn from_text_with_locale(
+        text: &str,
+        locale: &str,
+        reading_direction: DWRITE_READING_DIRECTION,
+    ) -> TextAnalysisSource<'static> {
+        let inner = SimpleTextAnalysisSource {
+            locale: locale.to_owned(),
+            reading_direction,
+        };
+        TextAnalysisSource::from_owned_text(Box::new(inner), text)
+    }
+
+    /// Returns the paragraph reading direction, as reported to DirectWrite by
+    /// the `TextAnalysisSourceMethods` implementation.
+    pub fn paragraph_reading_direction(&self) -> DWRITE_READING_DIRECTION {
+        unsafe { self.native.GetParagraphReadingDirection() }
+    }
+
+    /// Returns the underlying COM object, which refers to the text and to
+    /// `inner` for as long as `'a`.
+    ///
+    /// This is unsafe because nothing ties the pointer's lifetime to `'a`:
+    /// the caller must not use it, or let DirectWrite keep a reference to
+    /// it, once the borrowed text or `inner` may have been dropped. Sources
+    /// made with `from_owned_text` (or an owned `Cow`) have no such limit.
+    pub unsafe fn as_ptr(&self) -> *mut IDWriteTextAnalysisSource {
         self.native.as_raw()
     }
 }
@@ -9,12 +9,11 @@
 
 use std::borrow::Cow;
 use std::ffi::OsStr;
-use std::mem;
 use std::os::windows::ffi::OsStrExt;
 use std::ptr::{self, null};
 use std::sync::atomic::AtomicUsize;
 use winapi::ctypes::wchar_t;
-use winapi::shared::basetsd::UINT32;
+use winapi::shared::basetsd::{UINT32, UINT8};
 use winapi::shared::guiddef::REFIID;
 use winapi::shared::minwindef::{FALSE, TRUE, ULONG};
 use winapi::shared::ntdef::LOCALE_NAME_MAX_LENGTH;
@@ -24,12 +23,16 @@ use winapi::um::dwrite::IDWriteTextAnalysisSource;
 use winapi::um::dwrite::IDWriteTextAnalysisSourceVtbl;
 use winapi::um::dwrite::DWRITE_NUMBER_SUBSTITUTION_METHOD;
 use winapi::um::dwrite::DWRITE_READING_DIRECTION;
+use winapi::um::dwrite_1::DWRITE_VERTICAL_GLYPH_ORIENTATION;
+use winapi::um::dwrite_1::DWRITE_VERTICAL_GLYPH_ORIENTATION_DEFAULT;
+use winapi::um::dwrite_1::{IDWriteTextAnalysisSource1, IDWriteTextAnalysisSource1Vtbl};
 use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
 use winapi::um::winnt::HRESULT;
 use wio::com::ComPtr;
 
-use super::DWriteFactory;
 use crate::com_helpers::Com;
+use crate::error::Error;
+use crate::factory::{factory_or_shared, Factory};
 use crate::helpers::ToWide;
 
 /// The Rust side of a custom text analysis source implementation.
@@ -42,6 +45,44 @@ pub trait TextAnalysisSourceMethods {
 
     /// Get the text direction for the paragraph.
     fn get_paragraph_reading_direction(&self) -> DWRITE_READING_DIRECTION;
+
+    /// Whether this source provides `get_vertical_glyph_orientation`. If so,
+    /// DirectWrite sees it as an `IDWriteTextAnalysisSource1`.
+    fn supports_vertical_glyph_orientation(&self) -> bool {
+        false
+    }
+
+    /// Determine the vertical glyph orientation and bidi level for a range of
+    /// text. Only called if `supports_vertical_glyph_orientation` is true.
+    ///
+    /// Return the orientation, bidi level and length of text (in utf-16 code
+    /// units) for which they're valid.
+    fn get_vertical_glyph_orientation(
+        &self,
+        _text_position: u32,
+    ) -> (DWRITE_VERTICAL_GLYPH_ORIENTATION, u8, u32) {
+        (DWRITE_VERTICAL_GLYPH_ORIENTATION_DEFAULT, 0, u32::MAX)
+    }
+}
+
+/// A `TextAnalysisSourceMethods` implementation for a paragraph with a single
+/// locale and reading direction.
+#[derive(Clone, Debug)]
+pub struct SimpleTextAnalysisSource {
+    pub locale: String,
+    pub reading_direction: DWRITE_READING_DIRECTION,
+}
+
+impl TextAnalysisSourceMethods for SimpleTextAnalysisSource {
+    fn get_locale_name<'a>(&'a self, _text_position: u32) -> (Cow<'a, str>, u32) {
+        // The locale covers the rest of the text; the length is clamped to
+        // the end of the text before it's handed to DirectWrite.
+        (Cow::Borrowed(&self.locale), u32::MAX)
+    }
+
+    fn get_paragraph_reading_direction(&self) -> DWRITE_READING_DIRECTION {
+        self.reading_direction
+    }
 }
 
 #[repr(C)]
@@ -70,6 +111,24 @@ static TEXT_ANALYSIS_SOURCE_VTBL: IDWriteTextAnalysisSourceVtbl = IDWriteTextAna
     GetTextBeforePosition: CustomTextAnalysisSourceImpl_GetTextBeforePosition,
 };
 
+// Used instead of the above for sources that support vertical glyph
+// orientation.
+static TEXT_ANALYSIS_SOURCE1_VTBL: IDWriteTextAnalysisSource1Vtbl =
+    IDWriteTextAnalysisSource1Vtbl {
+        parent: IDWriteTextAnalysisSourceVtbl {
+            parent: implement_iunknown!(
+                static [IDWriteTextAnalysisSource1, IDWriteTextAnalysisSource],
+                CustomTextAnalysisSourceImpl
+            ),
+            GetLocaleName: CustomTextAnalysisSourceImpl_GetLocaleName,
+            GetNumberSubstitution: CustomTextAnalysisSourceImpl_GetNumberSubstitution,
+            GetParagraphReadingDirection: CustomTextAnalysisSourceImpl_GetParagraphReadingDirection,
+            GetTextAtPosition: CustomTextAnalysisSourceImpl_GetTextAtPosition,
+            GetTextBeforePosition: CustomTextAnalysisSourceImpl_GetTextBeforePosition,
+        },
+        GetVerticalGlyphOrientation: CustomTextAnalysisSourceImpl_GetVerticalGlyphOrientation,
+    };
+
 impl<'a> CustomTextAnalysisSourceImpl<'a> {
     /// Create a new custom TextAnalysisSource for the given text and a trait
     /// implementation.
@@ -109,6 +168,19 @@ impl<'a> CustomTextAnalysisSourceImpl<'a> {
             locale_buf: [0u16; LOCALE_NAME_MAX_LENGTH],
         }
     }
+
+    /// Turns this into a COM object, which also answers for
+    /// `IDWriteTextAnalysisSource1` if `inner` supports vertical glyph
+    /// orientation.
+    pub(crate) fn into_native(self) -> *mut IDWriteTextAnalysisSource {
+        if self.inner.supports_vertical_glyph_orientation() {
+            // The `IDWriteTextAnalysisSource1` vtable starts with the
+            // `IDWriteTextAnalysisSource` one.
+            Com::<IDWriteTextAnalysisSource1>::into_interface(self) as *mut _
+        } else {
+            Com::<IDWriteTextAnalysisSource>::into_interface(self)
+        }
+    }
 }
 
 impl Com<IDWriteTextAnalysisSource> for CustomTextAnalysisSourceImpl<'_> {
@@ -119,6 +191,14 @@ impl Com<IDWriteTextAnalysisSource> for CustomTextAnalysisSourceImpl<'_> {
     }
 }
 
+impl Com<IDWriteTextAnalysisSource1> for CustomTextAnalysisSourceImpl<'_> {
+    type Vtbl = IDWriteTextAnalysisSource1Vtbl;
+    #[inline]
+    fn vtbl() -> &'static IDWriteTextAnalysisSource1Vtbl {
+        &TEXT_ANALYSIS_SOURCE1_VTBL
+    }
+}
+
 impl Com<IUnknown> for CustomTextAnalysisSourceImpl<'_> {
     type Vtbl = IUnknownVtbl;
     #[inline]
@@ -137,17 +217,21 @@ unsafe extern "system" fn CustomTextAnalysisSourceImpl_GetLocaleName(
     let (locale, text_len) = this.inner.get_locale_name(text_position);
 
     // Copy the locale data into the buffer
-    for (i, c) in OsStr::new(&*locale).encode_wide().chain(Some(0)).enumerate() {
+    for (i, c) in OsStr::new(&*locale)
+        .encode_wide()
+        .chain(Some(0))
+        .enumerate()
+    {
         // -1 here is deliberate: it ensures that we never write to the last character in
         // this.locale_buf, so that the buffer is always null-terminated.
         if i >= this.locale_buf.len() - 1 {
-            break
+            break;
         }
 
         *this.locale_buf.get_unchecked_mut(i) = c;
     }
 
-    *text_length = text_len;
+    *text_length = text_len.min((this.text.len() as u32).saturating_sub(text_position));
     *locale_name = this.locale_buf.as_ptr();
     S_OK
 }
@@ -169,8 +253,8 @@ unsafe extern "system" fn CustomTextAnalysisSourceImpl_GetNumberSubstitution(
             let com_ptr = &number_subst.native;
             com_ptr.AddRef();
             com_ptr.as_raw()
-        },
-        None => std::ptr::null_mut()
+        }
+        None => std::ptr::null_mut(),
     };
 
     S_OK
@@ -217,24 +301,64 @@ unsafe extern "system" fn CustomTextAnalysisSourceImpl_GetTextBeforePosition(
     S_OK
 }
 
+unsafe extern "system" fn CustomTextAnalysisSourceImpl_GetVerticalGlyphOrientation(
+    this: *mut IDWriteTextAnalysisSource1,
+    text_position: UINT32,
+    text_length: *mut UINT32,
+    glyph_orientation: *mut DWRITE_VERTICAL_GLYPH_ORIENTATION,
+    bidi_level: *mut UINT8,
+) -> HRESULT {
+    let this = CustomTextAnalysisSourceImpl::from_interface(this);
+    if text_position >= (this.text.len() as u32) {
+        return E_INVALIDARG;
+    }
+
+    let (orientation, level, len) = this.inner.get_vertical_glyph_orientation(text_position);
+    *text_length = len.min((this.text.len() as u32) - text_position);
+    *glyph_orientation = orientation;
+    *bidi_level = level;
+    S_OK
+}
+
 impl NumberSubstitution {
+    #[deprecated(note = "use `try_new`, which returns an error instead of panicking")]
     pub fn new(
         subst_method: DWRITE_NUMBER_SUBSTITUTION_METHOD,
         locale: &str,
         ignore_user_overrides: bool,
     ) -> NumberSubstitution {
+        NumberSubstitution::try_new(subst_method, locale, ignore_user_overrides)
+            .expect("error creating number substitution")
+    }
+
+    pub fn try_new(
+        subst_method: DWRITE_NUMBER_SUBSTITUTION_METHOD,
+        locale: &str,
+        ignore_user_overrides: bool,
+    ) -> Result<NumberSubstitution, Error> {
+        NumberSubstitution::try_new_with_factory(subst_method, locale, ignore_user_overrides, None)
+    }
+
+    pub fn try_new_with_factory(
+        subst_method: DWRITE_NUMBER_SUBSTITUTION_METHOD,
+        locale: &str,
+        ignore_user_overrides: bool,
+        factory: Option<&Factory>,
+    ) -> Result<NumberSubstitution, Error> {
         unsafe {
             let mut native: *mut IDWriteNumberSubstitution = ptr::null_mut();
-            let hr = (*DWriteFactory()).CreateNumberSubstitution(
+            let hr = (*factory_or_shared(factory)).CreateNumberSubstitution(
                 subst_method,
                 locale.to_wide_null().as_ptr(),
                 if ignore_user_overrides { TRUE } else { FALSE },
                 &mut native,
             );
-            assert_eq!(hr, 0, "error creating number substitution");
-            NumberSubstitution {
-                native: ComPtr::from_raw(native),
+            if hr != S_OK {
+                return Err(hr.into());
             }
+            Ok(NumberSubstitution {
+                native: ComPtr::from_raw(native),
+            })
         }
     }
 }
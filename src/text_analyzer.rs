@@ -0,0 +1,405 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::UnsafeCell;
+use std::mem;
+use std::ptr;
+use winapi::shared::minwindef::{BOOL, FALSE, TRUE};
+use winapi::shared::winerror::{ERROR_INSUFFICIENT_BUFFER, HRESULT_FROM_WIN32};
+use winapi::shared::winerror::{E_NOINTERFACE, S_OK};
+use winapi::um::dwrite::DWRITE_SHAPING_TEXT_PROPERTIES;
+use winapi::um::dwrite::{IDWriteTextAnalyzer, DWRITE_SCRIPT_ANALYSIS};
+use winapi::um::dwrite::{DWRITE_FONT_FEATURE, DWRITE_TYPOGRAPHIC_FEATURES};
+use winapi::um::dwrite::{DWRITE_GLYPH_OFFSET, DWRITE_SHAPING_GLYPH_PROPERTIES};
+use winapi::um::dwrite_1::IDWriteTextAnalyzer1;
+use winapi::um::dwrite_1::DWRITE_BASELINE_ROMAN;
+use winapi::um::dwrite_1::DWRITE_JUSTIFICATION_OPPORTUNITY;
+use winapi::um::dwrite_1::{DWRITE_BASELINE, DWRITE_BASELINE_CENTRAL, DWRITE_BASELINE_DEFAULT};
+use winapi::um::dwrite_1::{DWRITE_BASELINE_HANGING, DWRITE_BASELINE_IDEOGRAPHIC_BOTTOM};
+use winapi::um::dwrite_1::{DWRITE_BASELINE_IDEOGRAPHIC_TOP, DWRITE_BASELINE_MATH};
+use winapi::um::dwrite_1::{DWRITE_BASELINE_MAXIMUM, DWRITE_BASELINE_MINIMUM};
+use winapi::um::dwrite_2::IDWriteTextAnalyzer2;
+use wio::com::ComPtr;
+
+use super::FontFace;
+use crate::error::Error;
+use crate::factory::{factory_or_shared, Factory};
+use crate::helpers::ToWide;
+
+// mirrors DWRITE_BASELINE
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Baseline {
+    Default = DWRITE_BASELINE_DEFAULT,
+    Roman = DWRITE_BASELINE_ROMAN,
+    Central = DWRITE_BASELINE_CENTRAL,
+    Math = DWRITE_BASELINE_MATH,
+    Hanging = DWRITE_BASELINE_HANGING,
+    IdeographicBottom = DWRITE_BASELINE_IDEOGRAPHIC_BOTTOM,
+    IdeographicTop = DWRITE_BASELINE_IDEOGRAPHIC_TOP,
+    Minimum = DWRITE_BASELINE_MINIMUM,
+    Maximum = DWRITE_BASELINE_MAXIMUM,
+}
+
+/// An OpenType feature to apply during shaping, such as `liga` or `smcp`.
+///
+/// `tag` is a `DWRITE_FONT_FEATURE_TAG`. `value` is usually 1 to enable the
+/// feature or 0 to disable it, but selects the alternate for features like
+/// `salt` or `aalt`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontFeature {
+    pub tag: u32,
+    pub value: u32,
+}
+
+/// A set of features applied to the next `length` UTF-16 code units of the
+/// text being shaped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeatureRange {
+    pub features: Vec<FontFeature>,
+    pub length: u32,
+}
+
+/// The output of `TextAnalyzer::get_glyphs`. `cluster_map` and
+/// `text_props` have one entry per code unit of the text, and
+/// `glyph_indices` and `glyph_props` one per glyph.
+pub struct ShapedGlyphs {
+    pub cluster_map: Vec<u16>,
+    pub text_props: Vec<DWRITE_SHAPING_TEXT_PROPERTIES>,
+    pub glyph_indices: Vec<u16>,
+    pub glyph_props: Vec<DWRITE_SHAPING_GLYPH_PROPERTIES>,
+}
+
+pub struct TextAnalyzer {
+    native: UnsafeCell<ComPtr<IDWriteTextAnalyzer>>,
+}
+
+impl TextAnalyzer {
+    pub fn create() -> Result<TextAnalyzer, Error> {
+        TextAnalyzer::create_with_factory(None)
+    }
+
+    pub fn create_with_factory(factory: Option<&Factory>) -> Result<TextAnalyzer, Error> {
+        unsafe {
+            let mut native: *mut IDWriteTextAnalyzer = ptr::null_mut();
+            let hr = (*factory_or_shared(factory)).CreateTextAnalyzer(&mut native);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(TextAnalyzer::take(ComPtr::from_raw(native)))
+        }
+    }
+
+    pub fn take(native: ComPtr<IDWriteTextAnalyzer>) -> TextAnalyzer {
+        TextAnalyzer {
+            native: UnsafeCell::new(native),
+        }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut IDWriteTextAnalyzer {
+        (*self.native.get()).as_raw()
+    }
+
+    unsafe fn get_analyzer1(&self) -> Result<ComPtr<IDWriteTextAnalyzer1>, Error> {
+        (*self.native.get())
+            .cast()
+            .map_err(|_| Error::from(E_NOINTERFACE))
+    }
+
+    unsafe fn get_analyzer2(&self) -> Result<ComPtr<IDWriteTextAnalyzer2>, Error> {
+        (*self.native.get())
+            .cast()
+            .map_err(|_| Error::from(E_NOINTERFACE))
+    }
+
+    /// Shapes `text` (UTF-16) into glyphs of `face`.
+    ///
+    /// `feature_ranges` applies OpenType features to consecutive ranges of
+    /// the text; their lengths must add up to the length of `text`. With no
+    /// ranges, only the default features for the script are applied.
+    pub fn get_glyphs(
+        &self,
+        text: &[u16],
+        face: &FontFace,
+        is_sideways: bool,
+        is_right_to_left: bool,
+        script_analysis: DWRITE_SCRIPT_ANALYSIS,
+        locale: &str,
+        feature_ranges: &[FeatureRange],
+    ) -> Result<ShapedGlyphs, Error> {
+        debug_assert!(
+            feature_ranges.is_empty()
+                || feature_ranges
+                    .iter()
+                    .map(|r| r.length as usize)
+                    .sum::<usize>()
+                    == text.len()
+        );
+        let mut features: Vec<Vec<DWRITE_FONT_FEATURE>> = feature_ranges
+            .iter()
+            .map(|range| {
+                range
+                    .features
+                    .iter()
+                    .map(|feature| DWRITE_FONT_FEATURE {
+                        nameTag: feature.tag,
+                        parameter: feature.value,
+                    })
+                    .collect()
+            })
+            .collect();
+        let typographic_features: Vec<DWRITE_TYPOGRAPHIC_FEATURES> = features
+            .iter_mut()
+            .map(|features| DWRITE_TYPOGRAPHIC_FEATURES {
+                features: features.as_mut_ptr(),
+                featureCount: features.len() as u32,
+            })
+            .collect();
+        let mut feature_ptrs: Vec<*const DWRITE_TYPOGRAPHIC_FEATURES> = typographic_features
+            .iter()
+            .map(|features| features as *const _)
+            .collect();
+        let range_lengths: Vec<u32> = feature_ranges.iter().map(|range| range.length).collect();
+
+        let locale = locale.to_wide_null();
+        unsafe {
+            let mut cluster_map = vec![0; text.len()];
+            let mut text_props = vec![mem::zeroed(); text.len()];
+            // This is the buffer size DirectWrite's documentation suggests.
+            let mut max_glyph_count = text.len() * 3 / 2 + 16;
+            loop {
+                let mut glyph_indices = vec![0; max_glyph_count];
+                let mut glyph_props = vec![mem::zeroed(); max_glyph_count];
+                let mut actual_glyph_count = 0;
+                let hr = (*self.native.get()).GetGlyphs(
+                    text.as_ptr(),
+                    text.len() as u32,
+                    face.as_ptr(),
+                    if is_sideways { TRUE } else { FALSE },
+                    if is_right_to_left { TRUE } else { FALSE },
+                    &script_analysis,
+                    locale.as_ptr(),
+                    ptr::null_mut(),
+                    if feature_ptrs.is_empty() {
+                        ptr::null_mut()
+                    } else {
+                        feature_ptrs.as_mut_ptr()
+                    },
+                    if range_lengths.is_empty() {
+                        ptr::null()
+                    } else {
+                        range_lengths.as_ptr()
+                    },
+                    range_lengths.len() as u32,
+                    max_glyph_count as u32,
+                    cluster_map.as_mut_ptr(),
+                    text_props.as_mut_ptr(),
+                    glyph_indices.as_mut_ptr(),
+                    glyph_props.as_mut_ptr(),
+                    &mut actual_glyph_count,
+                );
+                if hr == HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER) {
+                    max_glyph_count *= 2;
+                    continue;
+                }
+                if hr != S_OK {
+                    return Err(hr.into());
+                }
+                glyph_indices.truncate(actual_glyph_count as usize);
+                glyph_props.truncate(actual_glyph_count as usize);
+                return Ok(ShapedGlyphs {
+                    cluster_map,
+                    text_props,
+                    glyph_indices,
+                    glyph_props,
+                });
+            }
+        }
+    }
+
+    /// Returns the position of `baseline` for the given face, script and
+    /// locale, in design units relative to the roman baseline (positive
+    /// values are above it), and whether the font actually defines it.
+    ///
+    /// If the font doesn't define the baseline and `is_simulation_allowed`
+    /// is true, DirectWrite derives one from the font's other metrics.
+    pub fn get_baseline(
+        &self,
+        face: &FontFace,
+        baseline: Baseline,
+        is_vertical: bool,
+        is_simulation_allowed: bool,
+        script_analysis: DWRITE_SCRIPT_ANALYSIS,
+        locale: &str,
+    ) -> Result<(i32, bool), Error> {
+        unsafe {
+            let analyzer1 = self.get_analyzer1()?;
+            let mut coordinate = 0;
+            let mut exists: BOOL = FALSE;
+            let hr = analyzer1.GetBaseline(
+                face.as_ptr(),
+                baseline as DWRITE_BASELINE,
+                if is_vertical { TRUE } else { FALSE },
+                if is_simulation_allowed { TRUE } else { FALSE },
+                script_analysis,
+                locale.to_wide_null().as_ptr(),
+                &mut coordinate,
+                &mut exists,
+            );
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok((coordinate, exists == TRUE))
+        }
+    }
+
+    /// Returns one justification opportunity per glyph of a shaped run,
+    /// describing how far each glyph may be expanded or compressed and
+    /// whether the expansion is done with kashida (for Arabic scripts).
+    ///
+    /// `text` is the UTF-16 text of the run, and `cluster_map` and
+    /// `glyph_props` are the outputs of shaping it with `GetGlyphs`; the
+    /// glyph count is taken from `glyph_props`.
+    pub fn get_justification_opportunities(
+        &self,
+        face: &FontFace,
+        em_size: f32,
+        script_analysis: DWRITE_SCRIPT_ANALYSIS,
+        text: &[u16],
+        cluster_map: &[u16],
+        glyph_props: &[DWRITE_SHAPING_GLYPH_PROPERTIES],
+    ) -> Result<Vec<DWRITE_JUSTIFICATION_OPPORTUNITY>, Error> {
+        assert_eq!(text.len(), cluster_map.len());
+        unsafe {
+            let analyzer1 = self.get_analyzer1()?;
+            let mut opportunities = vec![mem::zeroed(); glyph_props.len()];
+            let hr = analyzer1.GetJustificationOpportunities(
+                face.as_ptr(),
+                em_size,
+                script_analysis,
+                text.len() as u32,
+                glyph_props.len() as u32,
+                text.as_ptr(),
+                cluster_map.as_ptr(),
+                glyph_props.as_ptr(),
+                opportunities.as_mut_ptr(),
+            );
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(opportunities)
+        }
+    }
+
+    /// Distributes the difference between the run's natural width and
+    /// `line_width` over its glyphs according to `opportunities`, writing
+    /// the adjusted advances and offsets into `justified_advances` and
+    /// `justified_offsets` (which are resized to the glyph count).
+    ///
+    /// Runs justified with kashida still need `GetJustifiedGlyphs` to
+    /// insert the kashida glyphs themselves.
+    pub fn justify_glyph_advances(
+        &self,
+        line_width: f32,
+        opportunities: &[DWRITE_JUSTIFICATION_OPPORTUNITY],
+        advances: &[f32],
+        offsets: &[DWRITE_GLYPH_OFFSET],
+        justified_advances: &mut Vec<f32>,
+        justified_offsets: &mut Vec<DWRITE_GLYPH_OFFSET>,
+    ) -> Result<(), Error> {
+        let glyph_count = opportunities.len();
+        assert_eq!(advances.len(), glyph_count);
+        assert_eq!(offsets.len(), glyph_count);
+        unsafe {
+            let analyzer1 = self.get_analyzer1()?;
+            justified_advances.clear();
+            justified_advances.resize(glyph_count, 0.0);
+            justified_offsets.clear();
+            justified_offsets.resize(glyph_count, mem::zeroed());
+            let hr = analyzer1.JustifyGlyphAdvances(
+                line_width,
+                glyph_count as u32,
+                opportunities.as_ptr(),
+                advances.as_ptr(),
+                offsets.as_ptr(),
+                justified_advances.as_mut_ptr(),
+                justified_offsets.as_mut_ptr(),
+            );
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(())
+        }
+    }
+
+    /// Returns the OpenType feature tags (as `DWRITE_FONT_FEATURE_TAG`s)
+    /// that the face supports for the given script and locale.
+    ///
+    /// `max_tags` is only the initial buffer size; if the face supports more
+    /// features than that, all of them are still returned.
+    pub fn get_typographic_features(
+        &self,
+        face: &FontFace,
+        script_analysis: DWRITE_SCRIPT_ANALYSIS,
+        locale: &str,
+        max_tags: u32,
+    ) -> Result<Vec<u32>, Error> {
+        unsafe {
+            let analyzer2 = self.get_analyzer2()?;
+            let locale = locale.to_wide_null();
+            let mut tags = vec![0; max_tags as usize];
+            loop {
+                let mut actual_count = 0;
+                let hr = analyzer2.GetTypographicFeatures(
+                    face.as_ptr(),
+                    script_analysis,
+                    locale.as_ptr(),
+                    tags.len() as u32,
+                    &mut actual_count,
+                    tags.as_mut_ptr(),
+                );
+                if hr == HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER)
+                    && actual_count as usize > tags.len()
+                {
+                    tags.resize(actual_count as usize, 0);
+                    continue;
+                }
+                if hr != S_OK {
+                    return Err(hr.into());
+                }
+                tags.truncate(actual_count as usize);
+                return Ok(tags);
+            }
+        }
+    }
+
+    /// Returns, for each of `glyphs`, whether the feature `feature_tag`
+    /// applies to it in the given script and locale.
+    pub fn check_typographic_feature(
+        &self,
+        face: &FontFace,
+        script_analysis: DWRITE_SCRIPT_ANALYSIS,
+        locale: &str,
+        feature_tag: u32,
+        glyphs: &[u16],
+    ) -> Result<Vec<bool>, Error> {
+        unsafe {
+            let analyzer2 = self.get_analyzer2()?;
+            let mut applies = vec![0u8; glyphs.len()];
+            let hr = analyzer2.CheckTypographicFeature(
+                face.as_ptr(),
+                script_analysis,
+                locale.to_wide_null().as_ptr(),
+                feature_tag,
+                glyphs.len() as u32,
+                glyphs.as_ptr(),
+                applies.as_mut_ptr(),
+            );
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(applies.into_iter().map(|applies| applies != 0).collect())
+        }
+    }
+}
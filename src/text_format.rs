@@ -0,0 +1,471 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::UnsafeCell;
+use std::mem;
+use std::ptr;
+use winapi::ctypes::wchar_t;
+use winapi::shared::winerror::S_OK;
+use winapi::um::dwrite::DWRITE_READING_DIRECTION;
+use winapi::um::dwrite::{IDWriteFontCollection, IDWriteInlineObject, IDWriteTextFormat};
+use winapi::um::dwrite::{
+    DWRITE_FLOW_DIRECTION, DWRITE_FLOW_DIRECTION_BOTTOM_TO_TOP,
+    DWRITE_FLOW_DIRECTION_LEFT_TO_RIGHT, DWRITE_FLOW_DIRECTION_RIGHT_TO_LEFT,
+    DWRITE_FLOW_DIRECTION_TOP_TO_BOTTOM,
+};
+use winapi::um::dwrite::{
+    DWRITE_LINE_SPACING_METHOD, DWRITE_LINE_SPACING_METHOD_DEFAULT,
+    DWRITE_LINE_SPACING_METHOD_PROPORTIONAL, DWRITE_LINE_SPACING_METHOD_UNIFORM,
+};
+use winapi::um::dwrite::{
+    DWRITE_PARAGRAPH_ALIGNMENT, DWRITE_PARAGRAPH_ALIGNMENT_CENTER, DWRITE_PARAGRAPH_ALIGNMENT_FAR,
+    DWRITE_PARAGRAPH_ALIGNMENT_NEAR,
+};
+use winapi::um::dwrite::{
+    DWRITE_TEXT_ALIGNMENT, DWRITE_TEXT_ALIGNMENT_CENTER, DWRITE_TEXT_ALIGNMENT_JUSTIFIED,
+    DWRITE_TEXT_ALIGNMENT_LEADING, DWRITE_TEXT_ALIGNMENT_TRAILING,
+};
+use winapi::um::dwrite::{
+    DWRITE_TRIMMING, DWRITE_TRIMMING_GRANULARITY, DWRITE_TRIMMING_GRANULARITY_CHARACTER,
+    DWRITE_TRIMMING_GRANULARITY_NONE, DWRITE_TRIMMING_GRANULARITY_WORD,
+};
+use winapi::um::dwrite::{
+    DWRITE_WORD_WRAPPING, DWRITE_WORD_WRAPPING_CHARACTER, DWRITE_WORD_WRAPPING_EMERGENCY_BREAK,
+    DWRITE_WORD_WRAPPING_NO_WRAP, DWRITE_WORD_WRAPPING_WHOLE_WORD, DWRITE_WORD_WRAPPING_WRAP,
+};
+use winapi::um::winnt::HRESULT;
+use wio::com::ComPtr;
+
+use super::{FontCollection, FontStretch, FontStyle, FontWeight, InlineObject, ToWide};
+use crate::error::Error;
+use crate::factory::{factory_or_shared, Factory};
+
+// mirrors DWRITE_TEXT_ALIGNMENT
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextAlignment {
+    Leading = DWRITE_TEXT_ALIGNMENT_LEADING,
+    Trailing = DWRITE_TEXT_ALIGNMENT_TRAILING,
+    Center = DWRITE_TEXT_ALIGNMENT_CENTER,
+    Justified = DWRITE_TEXT_ALIGNMENT_JUSTIFIED,
+}
+
+impl TextAlignment {
+    fn from_raw(alignment: DWRITE_TEXT_ALIGNMENT) -> TextAlignment {
+        match alignment {
+            DWRITE_TEXT_ALIGNMENT_TRAILING => TextAlignment::Trailing,
+            DWRITE_TEXT_ALIGNMENT_CENTER => TextAlignment::Center,
+            DWRITE_TEXT_ALIGNMENT_JUSTIFIED => TextAlignment::Justified,
+            _ => TextAlignment::Leading,
+        }
+    }
+}
+
+// mirrors DWRITE_PARAGRAPH_ALIGNMENT
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParagraphAlignment {
+    Near = DWRITE_PARAGRAPH_ALIGNMENT_NEAR,
+    Far = DWRITE_PARAGRAPH_ALIGNMENT_FAR,
+    Center = DWRITE_PARAGRAPH_ALIGNMENT_CENTER,
+}
+
+impl ParagraphAlignment {
+    fn from_raw(alignment: DWRITE_PARAGRAPH_ALIGNMENT) -> ParagraphAlignment {
+        match alignment {
+            DWRITE_PARAGRAPH_ALIGNMENT_FAR => ParagraphAlignment::Far,
+            DWRITE_PARAGRAPH_ALIGNMENT_CENTER => ParagraphAlignment::Center,
+            _ => ParagraphAlignment::Near,
+        }
+    }
+}
+
+// mirrors DWRITE_WORD_WRAPPING
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WordWrapping {
+    Wrap = DWRITE_WORD_WRAPPING_WRAP,
+    NoWrap = DWRITE_WORD_WRAPPING_NO_WRAP,
+    /// Requires `IDWriteTextFormat1` (Windows 8.1 and later).
+    EmergencyBreak = DWRITE_WORD_WRAPPING_EMERGENCY_BREAK,
+    /// Requires `IDWriteTextFormat1` (Windows 8.1 and later).
+    WholeWord = DWRITE_WORD_WRAPPING_WHOLE_WORD,
+    /// Requires `IDWriteTextFormat1` (Windows 8.1 and later).
+    Character = DWRITE_WORD_WRAPPING_CHARACTER,
+}
+
+impl WordWrapping {
+    fn from_raw(wrapping: DWRITE_WORD_WRAPPING) -> WordWrapping {
+        match wrapping {
+            DWRITE_WORD_WRAPPING_NO_WRAP => WordWrapping::NoWrap,
+            DWRITE_WORD_WRAPPING_EMERGENCY_BREAK => WordWrapping::EmergencyBreak,
+            DWRITE_WORD_WRAPPING_WHOLE_WORD => WordWrapping::WholeWord,
+            DWRITE_WORD_WRAPPING_CHARACTER => WordWrapping::Character,
+            _ => WordWrapping::Wrap,
+        }
+    }
+}
+
+// mirrors DWRITE_FLOW_DIRECTION
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlowDirection {
+    TopToBottom = DWRITE_FLOW_DIRECTION_TOP_TO_BOTTOM,
+    BottomToTop = DWRITE_FLOW_DIRECTION_BOTTOM_TO_TOP,
+    LeftToRight = DWRITE_FLOW_DIRECTION_LEFT_TO_RIGHT,
+    RightToLeft = DWRITE_FLOW_DIRECTION_RIGHT_TO_LEFT,
+}
+
+impl FlowDirection {
+    pub(crate) fn from_raw(direction: DWRITE_FLOW_DIRECTION) -> FlowDirection {
+        match direction {
+            DWRITE_FLOW_DIRECTION_BOTTOM_TO_TOP => FlowDirection::BottomToTop,
+            DWRITE_FLOW_DIRECTION_LEFT_TO_RIGHT => FlowDirection::LeftToRight,
+            DWRITE_FLOW_DIRECTION_RIGHT_TO_LEFT => FlowDirection::RightToLeft,
+            _ => FlowDirection::TopToBottom,
+        }
+    }
+}
+
+// mirrors DWRITE_TRIMMING_GRANULARITY
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrimmingGranularity {
+    None = DWRITE_TRIMMING_GRANULARITY_NONE,
+    Character = DWRITE_TRIMMING_GRANULARITY_CHARACTER,
+    Word = DWRITE_TRIMMING_GRANULARITY_WORD,
+}
+
+impl TrimmingGranularity {
+    fn from_raw(granularity: DWRITE_TRIMMING_GRANULARITY) -> TrimmingGranularity {
+        match granularity {
+            DWRITE_TRIMMING_GRANULARITY_CHARACTER => TrimmingGranularity::Character,
+            DWRITE_TRIMMING_GRANULARITY_WORD => TrimmingGranularity::Word,
+            _ => TrimmingGranularity::None,
+        }
+    }
+}
+
+/// How text that overflows the layout box is trimmed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Trimming {
+    pub granularity: TrimmingGranularity,
+    /// A character used as a delimiter, e.g. `\\` to keep the end of a path
+    /// visible, or `None`.
+    pub delimiter: Option<char>,
+    /// How many occurrences of `delimiter` to keep, counting from the end.
+    pub delimiter_count: u32,
+}
+
+// mirrors DWRITE_LINE_SPACING_METHOD
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineSpacingMethod {
+    /// Derive line spacing from the content, ignoring `height` and
+    /// `baseline`.
+    Default = DWRITE_LINE_SPACING_METHOD_DEFAULT,
+    Uniform = DWRITE_LINE_SPACING_METHOD_UNIFORM,
+    /// Requires `IDWriteTextFormat2` (Windows 10 and later).
+    Proportional = DWRITE_LINE_SPACING_METHOD_PROPORTIONAL,
+}
+
+impl LineSpacingMethod {
+    fn from_raw(method: DWRITE_LINE_SPACING_METHOD) -> LineSpacingMethod {
+        match method {
+            DWRITE_LINE_SPACING_METHOD_UNIFORM => LineSpacingMethod::Uniform,
+            DWRITE_LINE_SPACING_METHOD_PROPORTIONAL => LineSpacingMethod::Proportional,
+            _ => LineSpacingMethod::Default,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineSpacing {
+    pub method: LineSpacingMethod,
+    /// The distance from one baseline to the next.
+    pub height: f32,
+    /// The distance from the top of a line to its baseline.
+    pub baseline: f32,
+}
+
+/// The paragraph and font properties used to lay out text.
+pub struct TextFormat {
+    native: UnsafeCell<ComPtr<IDWriteTextFormat>>,
+}
+
+impl TextFormat {
+    /// Creates a text format for `family_name` in `collection`, or in the
+    /// system font collection if `None`. `size` is in DIPs.
+    pub fn new(
+        family_name: &str,
+        collection: Option<&FontCollection>,
+        weight: FontWeight,
+        style: FontStyle,
+        stretch: FontStretch,
+        size: f32,
+        locale: &str,
+    ) -> Result<TextFormat, Error> {
+        TextFormat::new_with_factory(
+            family_name,
+            collection,
+            weight,
+            style,
+            stretch,
+            size,
+            locale,
+            None,
+        )
+    }
+
+    pub fn new_with_factory(
+        family_name: &str,
+        collection: Option<&FontCollection>,
+        weight: FontWeight,
+        style: FontStyle,
+        stretch: FontStretch,
+        size: f32,
+        locale: &str,
+        factory: Option<&Factory>,
+    ) -> Result<TextFormat, Error> {
+        unsafe {
+            let collection = match collection {
+                Some(collection) => collection.as_ptr(),
+                None => ptr::null_mut(),
+            };
+            let mut native: *mut IDWriteTextFormat = ptr::null_mut();
+            let hr = (*factory_or_shared(factory)).CreateTextFormat(
+                family_name.to_wide_null().as_ptr(),
+                collection,
+                weight.to_u32(),
+                style.to_u32(),
+                stretch.to_u32(),
+                size,
+                locale.to_wide_null().as_ptr(),
+                &mut native,
+            );
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(TextFormat::take(ComPtr::from_raw(native)))
+        }
+    }
+
+    pub fn take(native: ComPtr<IDWriteTextFormat>) -> TextFormat {
+        TextFormat {
+            native: UnsafeCell::new(native),
+        }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut IDWriteTextFormat {
+        (*self.native.get()).as_raw()
+    }
+
+    pub fn font_family_name(&self) -> Result<String, Error> {
+        unsafe {
+            let native = &*self.native.get();
+            let length = native.GetFontFamilyNameLength();
+            let mut name: Vec<wchar_t> = vec![0; length as usize + 1];
+            let hr = native.GetFontFamilyName(name.as_mut_ptr(), length + 1);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            name.truncate(length as usize);
+            Ok(String::from_utf16_lossy(&name))
+        }
+    }
+
+    /// Returns the collection passed at creation, or the system collection.
+    pub fn font_collection(&self) -> Option<FontCollection> {
+        unsafe {
+            let mut collection: *mut IDWriteFontCollection = ptr::null_mut();
+            let hr = (*self.native.get()).GetFontCollection(&mut collection);
+            if hr != S_OK || collection.is_null() {
+                return None;
+            }
+            Some(FontCollection::take(ComPtr::from_raw(collection)))
+        }
+    }
+
+    pub fn font_weight(&self) -> FontWeight {
+        unsafe { FontWeight::from_u32((*self.native.get()).GetFontWeight()) }
+    }
+
+    pub fn font_style(&self) -> FontStyle {
+        unsafe { FontStyle::from_u32((*self.native.get()).GetFontStyle()) }
+    }
+
+    pub fn font_stretch(&self) -> FontStretch {
+        unsafe { FontStretch::from_u32((*self.native.get()).GetFontStretch()) }
+    }
+
+    pub fn font_size(&self) -> f32 {
+        unsafe { (*self.native.get()).GetFontSize() }
+    }
+
+    pub fn locale_name(&self) -> Result<String, Error> {
+        unsafe {
+            let native = &*self.native.get();
+            let length = native.GetLocaleNameLength();
+            let mut name: Vec<wchar_t> = vec![0; length as usize + 1];
+            let hr = native.GetLocaleName(name.as_mut_ptr(), length + 1);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            name.truncate(length as usize);
+            Ok(String::from_utf16_lossy(&name))
+        }
+    }
+
+    pub fn set_text_alignment(&self, alignment: TextAlignment) -> Result<(), Error> {
+        unsafe { check((*self.native.get()).SetTextAlignment(alignment as u32)) }
+    }
+
+    pub fn text_alignment(&self) -> TextAlignment {
+        unsafe { TextAlignment::from_raw((*self.native.get()).GetTextAlignment()) }
+    }
+
+    pub fn set_paragraph_alignment(&self, alignment: ParagraphAlignment) -> Result<(), Error> {
+        unsafe { check((*self.native.get()).SetParagraphAlignment(alignment as u32)) }
+    }
+
+    pub fn paragraph_alignment(&self) -> ParagraphAlignment {
+        unsafe { ParagraphAlignment::from_raw((*self.native.get()).GetParagraphAlignment()) }
+    }
+
+    /// Fails with `Error::InvalidArg` for the modes that need a newer
+    /// `IDWriteTextFormat` than the system provides.
+    pub fn set_word_wrapping(&self, wrapping: WordWrapping) -> Result<(), Error> {
+        unsafe { check((*self.native.get()).SetWordWrapping(wrapping as u32)) }
+    }
+
+    pub fn word_wrapping(&self) -> WordWrapping {
+        unsafe { WordWrapping::from_raw((*self.native.get()).GetWordWrapping()) }
+    }
+
+    pub fn set_reading_direction(&self, direction: DWRITE_READING_DIRECTION) -> Result<(), Error> {
+        unsafe { check((*self.native.get()).SetReadingDirection(direction)) }
+    }
+
+    pub fn reading_direction(&self) -> DWRITE_READING_DIRECTION {
+        unsafe { (*self.native.get()).GetReadingDirection() }
+    }
+
+    /// The flow direction must be perpendicular to the reading direction,
+    /// otherwise laying out text fails.
+    pub fn set_flow_direction(&self, direction: FlowDirection) -> Result<(), Error> {
+        unsafe { check((*self.native.get()).SetFlowDirection(direction as u32)) }
+    }
+
+    pub fn flow_direction(&self) -> FlowDirection {
+        unsafe { FlowDirection::from_raw((*self.native.get()).GetFlowDirection()) }
+    }
+
+    /// Sets the distance between tab stops, in DIPs.
+    pub fn set_incremental_tab_stop(&self, tab_stop: f32) -> Result<(), Error> {
+        unsafe { check((*self.native.get()).SetIncrementalTabStop(tab_stop)) }
+    }
+
+    pub fn incremental_tab_stop(&self) -> f32 {
+        unsafe { (*self.native.get()).GetIncrementalTabStop() }
+    }
+
+    /// Sets how overflowing text is trimmed, and the sign (usually an
+    /// ellipsis, see `create_ellipsis_trimming_sign`) shown where it is cut.
+    pub fn set_trimming(
+        &self,
+        trimming: &Trimming,
+        sign: Option<&InlineObject>,
+    ) -> Result<(), Error> {
+        unsafe {
+            let options = DWRITE_TRIMMING {
+                granularity: trimming.granularity as u32,
+                delimiter: trimming.delimiter.map_or(0, |c| c as u32),
+                delimiterCount: trimming.delimiter_count,
+            };
+            let sign = match sign {
+                Some(sign) => sign.as_ptr(),
+                None => ptr::null_mut(),
+            };
+            check((*self.native.get()).SetTrimming(&options, sign))
+        }
+    }
+
+    pub fn trimming(&self) -> Result<(Trimming, Option<InlineObject>), Error> {
+        unsafe {
+            let mut options: DWRITE_TRIMMING = mem::zeroed();
+            let mut sign: *mut IDWriteInlineObject = ptr::null_mut();
+            let hr = (*self.native.get()).GetTrimming(&mut options, &mut sign);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            let trimming = Trimming {
+                granularity: TrimmingGranularity::from_raw(options.granularity),
+                delimiter: std::char::from_u32(options.delimiter).filter(|&c| c != '\0'),
+                delimiter_count: options.delimiterCount,
+            };
+            let sign = if sign.is_null() {
+                None
+            } else {
+                Some(InlineObject::take(ComPtr::from_raw(sign)))
+            };
+            Ok((trimming, sign))
+        }
+    }
+
+    /// Creates an ellipsis sign matching this format's font, to be passed to
+    /// `set_trimming`.
+    pub fn create_ellipsis_trimming_sign(&self) -> Result<InlineObject, Error> {
+        self.create_ellipsis_trimming_sign_with_factory(None)
+    }
+
+    pub fn create_ellipsis_trimming_sign_with_factory(
+        &self,
+        factory: Option<&Factory>,
+    ) -> Result<InlineObject, Error> {
+        unsafe {
+            let mut sign: *mut IDWriteInlineObject = ptr::null_mut();
+            let hr =
+                (*factory_or_shared(factory)).CreateEllipsisTrimmingSign(self.as_ptr(), &mut sign);
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(InlineObject::take(ComPtr::from_raw(sign)))
+        }
+    }
+
+    pub fn set_line_spacing(&self, spacing: &LineSpacing) -> Result<(), Error> {
+        unsafe {
+            check((*self.native.get()).SetLineSpacing(
+                spacing.method as u32,
+                spacing.height,
+                spacing.baseline,
+            ))
+        }
+    }
+
+    pub fn line_spacing(&self) -> Result<LineSpacing, Error> {
+        unsafe {
+            let mut method = DWRITE_LINE_SPACING_METHOD_DEFAULT;
+            let mut height = 0.0;
+            let mut baseline = 0.0;
+            let hr = (*self.native.get()).GetLineSpacing(&mut method, &mut height, &mut baseline);
+            if hr != 0 {
+                return Err(hr.into());
+            }
+            Ok(LineSpacing {
+                method: LineSpacingMethod::from_raw(method),
+                height,
+                baseline,
+            })
+        }
+    }
+}
+
+fn check(hr: HRESULT) -> Result<(), Error> {
+    if hr == S_OK {
+        Ok(())
+    } else {
+        Err(hr.into())
+    }
+}
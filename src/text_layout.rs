@@ -0,0 +1,689 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::UnsafeCell;
+use std::mem;
+use std::ops::Range;
+use std::ptr;
+use winapi::ctypes::wchar_t;
+use winapi::shared::minwindef::{BOOL, FALSE, TRUE};
+use winapi::shared::winerror::{ERROR_INSUFFICIENT_BUFFER, HRESULT_FROM_WIN32, S_OK};
+use winapi::um::dwrite::IDWriteTypography;
+use winapi::um::dwrite::{IDWriteFontCollection, IDWriteInlineObject, IDWriteTextRenderer};
+use winapi::um::dwrite::{IDWriteTextFormat, IDWriteTextLayout, DWRITE_TEXT_RANGE};
+use winapi::um::dwrite::{DWRITE_CLUSTER_METRICS, DWRITE_HIT_TEST_METRICS, DWRITE_LINE_METRICS};
+use winapi::um::dwrite::{DWRITE_MATRIX, DWRITE_OVERHANG_METRICS, DWRITE_TEXT_METRICS};
+use winapi::um::winnt::HRESULT;
+use wio::com::ComPtr;
+
+use super::{FontCollection, FontStretch, FontStyle, FontWeight, InlineObject, TextFormat};
+use super::{ToWide, Typography};
+use crate::com_helpers::Com;
+use crate::error::Error;
+use crate::factory::{factory_or_shared, Factory};
+use crate::text_renderer_impl::{TextRendererImpl, TextRendererMethods};
+
+/// The size of laid out text, in DIPs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextMetrics {
+    /// The left edge of the text, relative to the layout box. This is
+    /// nonzero for e.g. centered or right-aligned text.
+    pub left: f32,
+    pub top: f32,
+    /// The width of the widest line, not counting trailing whitespace.
+    pub width: f32,
+    pub width_including_trailing_whitespace: f32,
+    pub height: f32,
+    pub layout_width: f32,
+    pub layout_height: f32,
+    pub max_bidi_reordering_depth: u32,
+    pub line_count: u32,
+}
+
+impl<'a> From<&'a DWRITE_TEXT_METRICS> for TextMetrics {
+    fn from(metrics: &'a DWRITE_TEXT_METRICS) -> TextMetrics {
+        TextMetrics {
+            left: metrics.left,
+            top: metrics.top,
+            width: metrics.width,
+            width_including_trailing_whitespace: metrics.widthIncludingTrailingWhitespace,
+            height: metrics.height,
+            layout_width: metrics.layoutWidth,
+            layout_height: metrics.layoutHeight,
+            max_bidi_reordering_depth: metrics.maxBidiReorderingDepth,
+            line_count: metrics.lineCount,
+        }
+    }
+}
+
+/// One line of laid out text. Lengths are in UTF-16 code units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineMetrics {
+    /// The length of the line, including trailing whitespace and newline.
+    pub length: u32,
+    pub trailing_whitespace_length: u32,
+    pub newline_length: u32,
+    pub height: f32,
+    /// The distance from the top of the line to its baseline.
+    pub baseline: f32,
+    pub is_trimmed: bool,
+}
+
+impl<'a> From<&'a DWRITE_LINE_METRICS> for LineMetrics {
+    fn from(metrics: &'a DWRITE_LINE_METRICS) -> LineMetrics {
+        LineMetrics {
+            length: metrics.length,
+            trailing_whitespace_length: metrics.trailingWhitespaceLength,
+            newline_length: metrics.newlineLength,
+            height: metrics.height,
+            baseline: metrics.baseline,
+            is_trimmed: metrics.isTrimmed != FALSE,
+        }
+    }
+}
+
+/// A cluster (a group of characters shaped together) in laid out text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClusterMetrics {
+    pub width: f32,
+    /// The number of UTF-16 code units in the cluster.
+    pub length: u16,
+    pub can_wrap_line_after: bool,
+    pub is_whitespace: bool,
+    pub is_newline: bool,
+    pub is_soft_hyphen: bool,
+    pub is_right_to_left: bool,
+}
+
+impl<'a> From<&'a DWRITE_CLUSTER_METRICS> for ClusterMetrics {
+    fn from(metrics: &'a DWRITE_CLUSTER_METRICS) -> ClusterMetrics {
+        ClusterMetrics {
+            width: metrics.width,
+            length: metrics.length,
+            can_wrap_line_after: metrics.canWrapLineAfter() != 0,
+            is_whitespace: metrics.isWhitespace() != 0,
+            is_newline: metrics.isNewline() != 0,
+            is_soft_hyphen: metrics.isSoftHyphen() != 0,
+            is_right_to_left: metrics.isRightToLeft() != 0,
+        }
+    }
+}
+
+/// How far the ink of the text extends past each edge of the layout box.
+/// Positive values mean the ink is outside the box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OverhangMetrics {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl<'a> From<&'a DWRITE_OVERHANG_METRICS> for OverhangMetrics {
+    fn from(metrics: &'a DWRITE_OVERHANG_METRICS) -> OverhangMetrics {
+        OverhangMetrics {
+            left: metrics.left,
+            top: metrics.top,
+            right: metrics.right,
+            bottom: metrics.bottom,
+        }
+    }
+}
+
+/// The position and extent of a range of text within a layout.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HitTestMetrics {
+    /// The first UTF-16 code unit of the range.
+    pub text_position: u32,
+    pub length: u32,
+    pub left: f32,
+    pub top: f32,
+    pub width: f32,
+    pub height: f32,
+    /// Odd levels are right-to-left.
+    pub bidi_level: u32,
+    /// Whether the range is text, as opposed to an inline object.
+    pub is_text: bool,
+    pub is_trimmed: bool,
+}
+
+impl<'a> From<&'a DWRITE_HIT_TEST_METRICS> for HitTestMetrics {
+    fn from(metrics: &'a DWRITE_HIT_TEST_METRICS) -> HitTestMetrics {
+        HitTestMetrics {
+            text_position: metrics.textPosition,
+            length: metrics.length,
+            left: metrics.left,
+            top: metrics.top,
+            width: metrics.width,
+            height: metrics.height,
+            bidi_level: metrics.bidiLevel,
+            is_text: metrics.isText != FALSE,
+            is_trimmed: metrics.isTrimmed != FALSE,
+        }
+    }
+}
+
+/// The result of `TextLayout::hit_test_point`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HitTestResult {
+    /// Whether the point is on the trailing side of the hit character, so a
+    /// caret would go after it.
+    pub is_trailing: bool,
+    /// Whether the point is inside the text, rather than closest to it.
+    pub is_inside: bool,
+    pub metrics: HitTestMetrics,
+}
+
+/// A fully analyzed and formatted block of text.
+pub struct TextLayout {
+    native: UnsafeCell<ComPtr<IDWriteTextLayout>>,
+}
+
+impl TextLayout {
+    /// Lays out `text` with `format` in a box of `max_width` by `max_height`
+    /// DIPs.
+    pub fn new(
+        text: &str,
+        format: &TextFormat,
+        max_width: f32,
+        max_height: f32,
+    ) -> Result<TextLayout, Error> {
+        TextLayout::new_with_factory(text, format, max_width, max_height, None)
+    }
+
+    pub fn new_with_factory(
+        text: &str,
+        format: &TextFormat,
+        max_width: f32,
+        max_height: f32,
+        factory: Option<&Factory>,
+    ) -> Result<TextLayout, Error> {
+        unsafe {
+            let text = text.to_wide();
+            let mut native: *mut IDWriteTextLayout = ptr::null_mut();
+            let hr = (*factory_or_shared(factory)).CreateTextLayout(
+                text.as_ptr(),
+                text.len() as u32,
+                format.as_ptr(),
+                max_width,
+                max_height,
+                &mut native,
+            );
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(TextLayout::take(ComPtr::from_raw(native)))
+        }
+    }
+
+    /// Creates a layout whose glyph advances are rounded the way GDI rounds
+    /// them, so that measurements match what a `BitmapRenderTarget` draws.
+    /// `transform` is the transform the text will be drawn with, on top of
+    /// the `pixels_per_dip` scale. With `use_gdi_natural`, advances are laid
+    /// out as for `DWRITE_MEASURING_MODE_GDI_NATURAL`; otherwise as for
+    /// `DWRITE_MEASURING_MODE_GDI_CLASSIC`.
+    pub fn new_gdi_compatible(
+        text: &str,
+        format: &TextFormat,
+        max_width: f32,
+        max_height: f32,
+        pixels_per_dip: f32,
+        transform: Option<&DWRITE_MATRIX>,
+        use_gdi_natural: bool,
+    ) -> Result<TextLayout, Error> {
+        TextLayout::new_gdi_compatible_with_factory(
+            text,
+            format,
+            max_width,
+            max_height,
+            pixels_per_dip,
+            transform,
+            use_gdi_natural,
+            None,
+        )
+    }
+
+    pub fn new_gdi_compatible_with_factory(
+        text: &str,
+        format: &TextFormat,
+        max_width: f32,
+        max_height: f32,
+        pixels_per_dip: f32,
+        transform: Option<&DWRITE_MATRIX>,
+        use_gdi_natural: bool,
+        factory: Option<&Factory>,
+    ) -> Result<TextLayout, Error> {
+        unsafe {
+            let text = text.to_wide();
+            let transform = match transform {
+                Some(transform) => transform as *const _,
+                None => ptr::null(),
+            };
+            let mut native: *mut IDWriteTextLayout = ptr::null_mut();
+            let hr = (*factory_or_shared(factory)).CreateGdiCompatibleTextLayout(
+                text.as_ptr(),
+                text.len() as u32,
+                format.as_ptr(),
+                max_width,
+                max_height,
+                pixels_per_dip,
+                transform,
+                if use_gdi_natural { TRUE } else { FALSE },
+                &mut native,
+            );
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(TextLayout::take(ComPtr::from_raw(native)))
+        }
+    }
+
+    pub fn take(native: ComPtr<IDWriteTextLayout>) -> TextLayout {
+        TextLayout {
+            native: UnsafeCell::new(native),
+        }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut IDWriteTextLayout {
+        (*self.native.get()).as_raw()
+    }
+
+    /// Returns the layout as a `TextFormat`, for reading and changing its
+    /// paragraph properties. Font properties read through it are those at the
+    /// start of the text.
+    pub fn text_format(&self) -> TextFormat {
+        unsafe {
+            let native = (*self.native.get()).clone();
+            TextFormat::take(native.up::<IDWriteTextFormat>())
+        }
+    }
+
+    pub fn set_font_collection(
+        &self,
+        range: Range<u32>,
+        collection: &FontCollection,
+    ) -> Result<(), Error> {
+        unsafe {
+            check((*self.native.get()).SetFontCollection(collection.as_ptr(), text_range(range)))
+        }
+    }
+
+    /// Returns the font collection at `position`, and the range of text that
+    /// uses it.
+    pub fn font_collection(&self, position: u32) -> Result<(FontCollection, Range<u32>), Error> {
+        unsafe {
+            let mut collection: *mut IDWriteFontCollection = ptr::null_mut();
+            let mut range: DWRITE_TEXT_RANGE = mem::zeroed();
+            check((*self.native.get()).GetFontCollection(position, &mut collection, &mut range))?;
+            let collection = FontCollection::take(ComPtr::from_raw(collection));
+            Ok((collection, from_text_range(&range)))
+        }
+    }
+
+    pub fn set_font_family_name(&self, range: Range<u32>, family_name: &str) -> Result<(), Error> {
+        unsafe {
+            let family_name = family_name.to_wide_null();
+            check((*self.native.get()).SetFontFamilyName(family_name.as_ptr(), text_range(range)))
+        }
+    }
+
+    pub fn font_family_name(&self, position: u32) -> Result<(String, Range<u32>), Error> {
+        unsafe {
+            let native = &*self.native.get();
+            let mut length = 0;
+            check(native.GetFontFamilyNameLength(position, &mut length, ptr::null_mut()))?;
+            let mut name: Vec<wchar_t> = vec![0; length as usize + 1];
+            let mut range: DWRITE_TEXT_RANGE = mem::zeroed();
+            check(native.GetFontFamilyName(position, name.as_mut_ptr(), length + 1, &mut range))?;
+            name.truncate(length as usize);
+            Ok((String::from_utf16_lossy(&name), from_text_range(&range)))
+        }
+    }
+
+    pub fn set_font_weight(&self, range: Range<u32>, weight: FontWeight) -> Result<(), Error> {
+        unsafe { check((*self.native.get()).SetFontWeight(weight.to_u32(), text_range(range))) }
+    }
+
+    pub fn font_weight(&self, position: u32) -> Result<(FontWeight, Range<u32>), Error> {
+        unsafe {
+            let mut weight = 0;
+            let mut range: DWRITE_TEXT_RANGE = mem::zeroed();
+            check((*self.native.get()).GetFontWeight(position, &mut weight, &mut range))?;
+            Ok((FontWeight::from_u32(weight), from_text_range(&range)))
+        }
+    }
+
+    pub fn set_font_style(&self, range: Range<u32>, style: FontStyle) -> Result<(), Error> {
+        unsafe { check((*self.native.get()).SetFontStyle(style.to_u32(), text_range(range))) }
+    }
+
+    pub fn font_style(&self, position: u32) -> Result<(FontStyle, Range<u32>), Error> {
+        unsafe {
+            let mut style = 0;
+            let mut range: DWRITE_TEXT_RANGE = mem::zeroed();
+            check((*self.native.get()).GetFontStyle(position, &mut style, &mut range))?;
+            Ok((FontStyle::from_u32(style), from_text_range(&range)))
+        }
+    }
+
+    pub fn set_font_stretch(&self, range: Range<u32>, stretch: FontStretch) -> Result<(), Error> {
+        unsafe { check((*self.native.get()).SetFontStretch(stretch.to_u32(), text_range(range))) }
+    }
+
+    pub fn font_stretch(&self, position: u32) -> Result<(FontStretch, Range<u32>), Error> {
+        unsafe {
+            let mut stretch = 0;
+            let mut range: DWRITE_TEXT_RANGE = mem::zeroed();
+            check((*self.native.get()).GetFontStretch(position, &mut stretch, &mut range))?;
+            Ok((FontStretch::from_u32(stretch), from_text_range(&range)))
+        }
+    }
+
+    /// Sets the font size of `range`, in DIPs.
+    pub fn set_font_size(&self, range: Range<u32>, size: f32) -> Result<(), Error> {
+        unsafe { check((*self.native.get()).SetFontSize(size, text_range(range))) }
+    }
+
+    pub fn font_size(&self, position: u32) -> Result<(f32, Range<u32>), Error> {
+        unsafe {
+            let mut size = 0.0;
+            let mut range: DWRITE_TEXT_RANGE = mem::zeroed();
+            check((*self.native.get()).GetFontSize(position, &mut size, &mut range))?;
+            Ok((size, from_text_range(&range)))
+        }
+    }
+
+    pub fn set_underline(&self, range: Range<u32>, underline: bool) -> Result<(), Error> {
+        unsafe { check((*self.native.get()).SetUnderline(underline as BOOL, text_range(range))) }
+    }
+
+    pub fn underline(&self, position: u32) -> Result<(bool, Range<u32>), Error> {
+        unsafe {
+            let mut underline = FALSE;
+            let mut range: DWRITE_TEXT_RANGE = mem::zeroed();
+            check((*self.native.get()).GetUnderline(position, &mut underline, &mut range))?;
+            Ok((underline != FALSE, from_text_range(&range)))
+        }
+    }
+
+    pub fn set_strikethrough(&self, range: Range<u32>, strikethrough: bool) -> Result<(), Error> {
+        unsafe {
+            check((*self.native.get()).SetStrikethrough(strikethrough as BOOL, text_range(range)))
+        }
+    }
+
+    pub fn strikethrough(&self, position: u32) -> Result<(bool, Range<u32>), Error> {
+        unsafe {
+            let mut strikethrough = FALSE;
+            let mut range: DWRITE_TEXT_RANGE = mem::zeroed();
+            check((*self.native.get()).GetStrikethrough(position, &mut strikethrough, &mut range))?;
+            Ok((strikethrough != FALSE, from_text_range(&range)))
+        }
+    }
+
+    pub fn set_locale(&self, range: Range<u32>, locale: &str) -> Result<(), Error> {
+        unsafe {
+            let locale = locale.to_wide_null();
+            check((*self.native.get()).SetLocaleName(locale.as_ptr(), text_range(range)))
+        }
+    }
+
+    pub fn locale(&self, position: u32) -> Result<(String, Range<u32>), Error> {
+        unsafe {
+            let native = &*self.native.get();
+            let mut length = 0;
+            check(native.GetLocaleNameLength(position, &mut length, ptr::null_mut()))?;
+            let mut name: Vec<wchar_t> = vec![0; length as usize + 1];
+            let mut range: DWRITE_TEXT_RANGE = mem::zeroed();
+            check(native.GetLocaleName(position, name.as_mut_ptr(), length + 1, &mut range))?;
+            name.truncate(length as usize);
+            Ok((String::from_utf16_lossy(&name), from_text_range(&range)))
+        }
+    }
+
+    pub fn set_typography(&self, range: Range<u32>, typography: &Typography) -> Result<(), Error> {
+        unsafe { check((*self.native.get()).SetTypography(typography.as_ptr(), text_range(range))) }
+    }
+
+    /// Returns the typography at `position`, or `None` if none was set.
+    pub fn typography(&self, position: u32) -> Result<(Option<Typography>, Range<u32>), Error> {
+        unsafe {
+            let mut typography: *mut IDWriteTypography = ptr::null_mut();
+            let mut range: DWRITE_TEXT_RANGE = mem::zeroed();
+            check((*self.native.get()).GetTypography(position, &mut typography, &mut range))?;
+            let typography = if typography.is_null() {
+                None
+            } else {
+                Some(Typography::take(ComPtr::from_raw(typography)))
+            };
+            Ok((typography, from_text_range(&range)))
+        }
+    }
+
+    /// Replaces the text in `range` with `object`. The range usually covers
+    /// a single placeholder character, such as U+FFFC.
+    pub fn set_inline_object(&self, range: Range<u32>, object: &InlineObject) -> Result<(), Error> {
+        unsafe { check((*self.native.get()).SetInlineObject(object.as_ptr(), text_range(range))) }
+    }
+
+    /// Returns the inline object at `position`, or `None` if there is none.
+    pub fn inline_object(
+        &self,
+        position: u32,
+    ) -> Result<(Option<InlineObject>, Range<u32>), Error> {
+        unsafe {
+            let mut object: *mut IDWriteInlineObject = ptr::null_mut();
+            let mut range: DWRITE_TEXT_RANGE = mem::zeroed();
+            check((*self.native.get()).GetInlineObject(position, &mut object, &mut range))?;
+            let object = if object.is_null() {
+                None
+            } else {
+                Some(InlineObject::take(ComPtr::from_raw(object)))
+            };
+            Ok((object, from_text_range(&range)))
+        }
+    }
+
+    pub fn metrics(&self) -> Result<TextMetrics, Error> {
+        unsafe {
+            let mut metrics: DWRITE_TEXT_METRICS = mem::zeroed();
+            let hr = (*self.native.get()).GetMetrics(&mut metrics);
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(TextMetrics::from(&metrics))
+        }
+    }
+
+    pub fn line_metrics(&self) -> Result<Vec<LineMetrics>, Error> {
+        unsafe {
+            let native = &*self.native.get();
+            let mut count = 0;
+            let hr = native.GetLineMetrics(ptr::null_mut(), 0, &mut count);
+            if hr != S_OK && hr != HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER) {
+                return Err(hr.into());
+            }
+            let mut metrics: Vec<DWRITE_LINE_METRICS> = vec![mem::zeroed(); count as usize];
+            let hr = native.GetLineMetrics(metrics.as_mut_ptr(), count, &mut count);
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            metrics.truncate(count as usize);
+            Ok(metrics.iter().map(LineMetrics::from).collect())
+        }
+    }
+
+    pub fn cluster_metrics(&self) -> Result<Vec<ClusterMetrics>, Error> {
+        unsafe {
+            let native = &*self.native.get();
+            let mut count = 0;
+            let hr = native.GetClusterMetrics(ptr::null_mut(), 0, &mut count);
+            if hr != S_OK && hr != HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER) {
+                return Err(hr.into());
+            }
+            let mut metrics: Vec<DWRITE_CLUSTER_METRICS> = vec![mem::zeroed(); count as usize];
+            let hr = native.GetClusterMetrics(metrics.as_mut_ptr(), count, &mut count);
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            metrics.truncate(count as usize);
+            Ok(metrics.iter().map(ClusterMetrics::from).collect())
+        }
+    }
+
+    pub fn overhang_metrics(&self) -> Result<OverhangMetrics, Error> {
+        unsafe {
+            let mut metrics: DWRITE_OVERHANG_METRICS = mem::zeroed();
+            let hr = (*self.native.get()).GetOverhangMetrics(&mut metrics);
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(OverhangMetrics::from(&metrics))
+        }
+    }
+
+    /// Returns the smallest width the layout can have without breaking words
+    /// (or other clusters that can't be wrapped).
+    pub fn determine_min_width(&self) -> Result<f32, Error> {
+        unsafe {
+            let mut min_width = 0.0;
+            let hr = (*self.native.get()).DetermineMinWidth(&mut min_width);
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(min_width)
+        }
+    }
+
+    /// Draws the layout with its top left corner at `origin`, by calling
+    /// back into `renderer` for each glyph run, decoration and inline object.
+    pub fn draw(
+        &self,
+        renderer: &mut impl TextRendererMethods,
+        origin: (f32, f32),
+    ) -> Result<(), Error> {
+        unsafe {
+            let renderer = TextRendererImpl::new(renderer).into_interface();
+            let hr = (*self.native.get()).Draw(ptr::null_mut(), renderer, origin.0, origin.1);
+            <TextRendererImpl as Com<IDWriteTextRenderer>>::destroy(renderer);
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(())
+        }
+    }
+
+    /// Finds the character at, or nearest to, the point `(x, y)` relative to
+    /// the layout's origin.
+    pub fn hit_test_point(&self, x: f32, y: f32) -> Result<HitTestResult, Error> {
+        unsafe {
+            let mut is_trailing: BOOL = FALSE;
+            let mut is_inside: BOOL = FALSE;
+            let mut metrics: DWRITE_HIT_TEST_METRICS = mem::zeroed();
+            let hr = (*self.native.get()).HitTestPoint(
+                x,
+                y,
+                &mut is_trailing,
+                &mut is_inside,
+                &mut metrics,
+            );
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(HitTestResult {
+                is_trailing: is_trailing != FALSE,
+                is_inside: is_inside != FALSE,
+                metrics: HitTestMetrics::from(&metrics),
+            })
+        }
+    }
+
+    /// Returns where a caret at `position` goes: on the leading edge of the
+    /// character there, or on its trailing edge if `is_trailing`. Also returns
+    /// the metrics of the character.
+    pub fn hit_test_text_position(
+        &self,
+        position: u32,
+        is_trailing: bool,
+    ) -> Result<(f32, f32, HitTestMetrics), Error> {
+        unsafe {
+            let (mut x, mut y) = (0.0, 0.0);
+            let mut metrics: DWRITE_HIT_TEST_METRICS = mem::zeroed();
+            let hr = (*self.native.get()).HitTestTextPosition(
+                position,
+                is_trailing as BOOL,
+                &mut x,
+                &mut y,
+                &mut metrics,
+            );
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok((x, y, HitTestMetrics::from(&metrics)))
+        }
+    }
+
+    /// Returns the rectangles covering the text in `range`, offset by
+    /// `origin`, e.g. for drawing a selection. A range that spans lines or
+    /// bidi runs is covered by several disjoint rectangles.
+    pub fn hit_test_text_range(
+        &self,
+        range: Range<u32>,
+        origin: (f32, f32),
+    ) -> Result<Vec<HitTestMetrics>, Error> {
+        unsafe {
+            let native = &*self.native.get();
+            let length = range.end.saturating_sub(range.start);
+            let mut count = 0;
+            let hr = native.HitTestTextRange(
+                range.start,
+                length,
+                origin.0,
+                origin.1,
+                ptr::null_mut(),
+                0,
+                &mut count,
+            );
+            if hr != S_OK && hr != HRESULT_FROM_WIN32(ERROR_INSUFFICIENT_BUFFER) {
+                return Err(hr.into());
+            }
+            let mut metrics: Vec<DWRITE_HIT_TEST_METRICS> = vec![mem::zeroed(); count as usize];
+            let hr = native.HitTestTextRange(
+                range.start,
+                length,
+                origin.0,
+                origin.1,
+                metrics.as_mut_ptr(),
+                count,
+                &mut count,
+            );
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            metrics.truncate(count as usize);
+            Ok(metrics.iter().map(HitTestMetrics::from).collect())
+        }
+    }
+}
+
+fn check(hr: HRESULT) -> Result<(), Error> {
+    if hr == S_OK {
+        Ok(())
+    } else {
+        Err(hr.into())
+    }
+}
+
+fn text_range(range: Range<u32>) -> DWRITE_TEXT_RANGE {
+    DWRITE_TEXT_RANGE {
+        startPosition: range.start,
+        length: range.end.saturating_sub(range.start),
+    }
+}
+
+// The range of the last run of a property extends to the largest position
+// DirectWrite can represent, rather than to the end of the text.
+fn from_text_range(range: &DWRITE_TEXT_RANGE) -> Range<u32> {
+    range.startPosition..range.startPosition.saturating_add(range.length)
+}
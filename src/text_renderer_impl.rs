@@ -0,0 +1,382 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A custom implementation of the "text renderer" interface, so that
+//! `TextLayout::draw` can hand the laid out glyph runs and decorations back
+//! to Rust code.
+
+#![allow(non_snake_case)]
+
+use std::ptr;
+use std::slice;
+use std::sync::atomic::AtomicUsize;
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::REFIID;
+use winapi::shared::minwindef::{BOOL, FALSE, FLOAT, ULONG};
+use winapi::shared::winerror::S_OK;
+use winapi::um::dcommon::DWRITE_MEASURING_MODE;
+use winapi::um::dwrite::{IDWriteInlineObject, IDWritePixelSnapping, IDWritePixelSnappingVtbl};
+use winapi::um::dwrite::{IDWriteTextRenderer, IDWriteTextRendererVtbl};
+use winapi::um::dwrite::{DWRITE_GLYPH_RUN, DWRITE_GLYPH_RUN_DESCRIPTION, DWRITE_MATRIX};
+use winapi::um::dwrite::{DWRITE_READING_DIRECTION, DWRITE_STRIKETHROUGH, DWRITE_UNDERLINE};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::um::winnt::{HRESULT, WCHAR};
+use wio::com::ComPtr;
+
+use super::{FlowDirection, FontFace, GlyphOffset, GlyphRun, InlineObject};
+use crate::com_helpers::Com;
+use crate::error::Error;
+
+/// The callbacks through which `TextLayout::draw` reports what to draw.
+///
+/// Only `draw_glyph_run` has to be implemented: decorations and inline
+/// objects are ignored by default, and the defaults for the pixel snapping
+/// queries describe an untransformed surface at 96 DPI. Returning an error
+/// from any of the draw methods stops drawing, and `TextLayout::draw` returns
+/// that error.
+pub trait TextRendererMethods {
+    /// Draws a run of glyphs with its baseline origin at `(x, y)`.
+    fn draw_glyph_run(
+        &mut self,
+        x: f32,
+        y: f32,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        run: &GlyphRunView,
+    ) -> Result<(), Error>;
+
+    fn draw_underline(&mut self, _x: f32, _y: f32, _underline: &Decoration) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn draw_strikethrough(
+        &mut self,
+        _x: f32,
+        _y: f32,
+        _strikethrough: &Decoration,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Draws an inline object, such as a trimming sign, with its top left
+    /// corner at `(x, y)`. Implementations that want objects to draw
+    /// themselves call `object.draw(self, x, y, is_sideways, is_right_to_left)`.
+    fn draw_inline_object(
+        &mut self,
+        _x: f32,
+        _y: f32,
+        _object: &InlineObject,
+        _is_sideways: bool,
+        _is_right_to_left: bool,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn is_pixel_snapping_disabled(&self) -> bool {
+        false
+    }
+
+    /// The transform from DIPs to the surface being drawn on.
+    fn current_transform(&self) -> DWRITE_MATRIX {
+        DWRITE_MATRIX {
+            m11: 1.0,
+            m12: 0.0,
+            m21: 0.0,
+            m22: 1.0,
+            dx: 0.0,
+            dy: 0.0,
+        }
+    }
+
+    fn pixels_per_dip(&self) -> f32 {
+        1.0
+    }
+}
+
+/// A glyph run passed to `TextRendererMethods::draw_glyph_run`, borrowing the
+/// layout's arrays for the duration of the call.
+pub struct GlyphRunView<'a> {
+    /// The face the run resolved to, which can be a fallback font rather
+    /// than the one the text was formatted with.
+    pub font_face: FontFace,
+    pub em_size: f32,
+    pub glyph_indices: &'a [u16],
+    pub glyph_advances: &'a [f32],
+    /// Empty if no glyph in the run has an offset.
+    pub glyph_offsets: &'a [GlyphOffset],
+    pub is_sideways: bool,
+    /// Odd levels are right-to-left.
+    pub bidi_level: u32,
+    pub locale: String,
+    /// The UTF-16 text of the run.
+    pub text: &'a [u16],
+    /// The index of the first glyph of each code unit's cluster, with one
+    /// entry per code unit of `text`.
+    pub cluster_map: &'a [u16],
+    /// The position of `text` in the layout's text.
+    pub text_position: u32,
+}
+
+impl<'a> GlyphRunView<'a> {
+    /// Copies the glyphs, for use after the callback returns.
+    pub fn to_glyph_run(&self) -> GlyphRun {
+        GlyphRun::new(&self.font_face, self.em_size)
+            .with_glyphs(self.glyph_indices)
+            .with_advances(self.glyph_advances)
+            .with_offsets(self.glyph_offsets)
+            .sideways(self.is_sideways)
+            .bidi_level(self.bidi_level)
+    }
+}
+
+/// An underline or strikethrough, relative to the baseline origin passed
+/// along with it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decoration {
+    pub width: f32,
+    pub thickness: f32,
+    /// The offset of the top of the line from the baseline; negative values
+    /// are above it.
+    pub offset: f32,
+    /// The height of the tallest run the underline is drawn under. Always 0
+    /// for strikethroughs.
+    pub run_height: f32,
+    pub reading_direction: DWRITE_READING_DIRECTION,
+    pub flow_direction: FlowDirection,
+    pub locale: String,
+    pub measuring_mode: DWRITE_MEASURING_MODE,
+}
+
+static TEXT_RENDERER_VTBL: IDWriteTextRendererVtbl = IDWriteTextRendererVtbl {
+    parent: IDWritePixelSnappingVtbl {
+        parent: implement_iunknown!(static IDWriteTextRenderer, TextRendererImpl),
+        IsPixelSnappingDisabled: TextRendererImpl_IsPixelSnappingDisabled,
+        GetCurrentTransform: TextRendererImpl_GetCurrentTransform,
+        GetPixelsPerDip: TextRendererImpl_GetPixelsPerDip,
+    },
+    DrawGlyphRun: TextRendererImpl_DrawGlyphRun,
+    DrawUnderline: TextRendererImpl_DrawUnderline,
+    DrawStrikethrough: TextRendererImpl_DrawStrikethrough,
+    DrawInlineObject: TextRendererImpl_DrawInlineObject,
+};
+
+// Only lives for the duration of a `Draw` call, see `TextLayout::draw`.
+#[repr(C)]
+pub struct TextRendererImpl<'a> {
+    // NB: This must be the first field.
+    _refcount: AtomicUsize,
+    inner: &'a mut dyn TextRendererMethods,
+}
+
+impl Com<IDWriteTextRenderer> for TextRendererImpl<'_> {
+    type Vtbl = IDWriteTextRendererVtbl;
+    #[inline]
+    fn vtbl() -> &'static IDWriteTextRendererVtbl {
+        &TEXT_RENDERER_VTBL
+    }
+}
+
+impl Com<IUnknown> for TextRendererImpl<'_> {
+    type Vtbl = IUnknownVtbl;
+    #[inline]
+    fn vtbl() -> &'static IUnknownVtbl {
+        &TEXT_RENDERER_VTBL.parent.parent
+    }
+}
+
+impl<'a> TextRendererImpl<'a> {
+    pub fn new(inner: &'a mut dyn TextRendererMethods) -> TextRendererImpl<'a> {
+        TextRendererImpl {
+            _refcount: AtomicUsize::new(1),
+            inner,
+        }
+    }
+}
+
+/// Returns the Rust renderer behind `renderer`, if it was created by
+/// `TextRendererImpl`.
+pub(crate) unsafe fn renderer_methods<'a>(
+    renderer: *mut IDWriteTextRenderer,
+) -> Option<&'a mut dyn TextRendererMethods> {
+    if renderer.is_null() || !ptr::eq((*renderer).lpVtbl, &TEXT_RENDERER_VTBL) {
+        return None;
+    }
+    Some(&mut *TextRendererImpl::from_interface(renderer).inner)
+}
+
+unsafe extern "system" fn TextRendererImpl_IsPixelSnappingDisabled(
+    this: *mut IDWritePixelSnapping,
+    _: *mut c_void,
+    is_disabled: *mut BOOL,
+) -> HRESULT {
+    let this = TextRendererImpl::from_interface(this as *mut IDWriteTextRenderer);
+    *is_disabled = this.inner.is_pixel_snapping_disabled() as BOOL;
+    S_OK
+}
+
+unsafe extern "system" fn TextRendererImpl_GetCurrentTransform(
+    this: *mut IDWritePixelSnapping,
+    _: *mut c_void,
+    transform: *mut DWRITE_MATRIX,
+) -> HRESULT {
+    let this = TextRendererImpl::from_interface(this as *mut IDWriteTextRenderer);
+    *transform = this.inner.current_transform();
+    S_OK
+}
+
+unsafe extern "system" fn TextRendererImpl_GetPixelsPerDip(
+    this: *mut IDWritePixelSnapping,
+    _: *mut c_void,
+    pixels_per_dip: *mut FLOAT,
+) -> HRESULT {
+    let this = TextRendererImpl::from_interface(this as *mut IDWriteTextRenderer);
+    *pixels_per_dip = this.inner.pixels_per_dip();
+    S_OK
+}
+
+unsafe extern "system" fn TextRendererImpl_DrawGlyphRun(
+    this: *mut IDWriteTextRenderer,
+    _: *mut c_void,
+    baseline_origin_x: FLOAT,
+    baseline_origin_y: FLOAT,
+    measuring_mode: DWRITE_MEASURING_MODE,
+    glyph_run: *const DWRITE_GLYPH_RUN,
+    glyph_run_description: *const DWRITE_GLYPH_RUN_DESCRIPTION,
+    _: *mut IUnknown,
+) -> HRESULT {
+    let this = TextRendererImpl::from_interface(this);
+    let run = &*glyph_run;
+    let count = run.glyphCount as usize;
+    (*run.fontFace).AddRef();
+    let (locale, text, cluster_map, text_position) = match glyph_run_description.as_ref() {
+        Some(desc) => {
+            let text_len = desc.stringLength as usize;
+            (
+                wide_to_string(desc.localeName),
+                slice_or_empty(desc.string, text_len),
+                slice_or_empty(desc.clusterMap, text_len),
+                desc.textPosition,
+            )
+        }
+        None => (String::new(), &[][..], &[][..], 0),
+    };
+    let view = GlyphRunView {
+        font_face: FontFace::take(ComPtr::from_raw(run.fontFace)),
+        em_size: run.fontEmSize,
+        glyph_indices: slice_or_empty(run.glyphIndices, count),
+        glyph_advances: slice_or_empty(run.glyphAdvances, count),
+        glyph_offsets: slice_or_empty(run.glyphOffsets, count),
+        is_sideways: run.isSideways != FALSE,
+        bidi_level: run.bidiLevel,
+        locale,
+        text,
+        cluster_map,
+        text_position,
+    };
+    to_hresult(this.inner.draw_glyph_run(
+        baseline_origin_x,
+        baseline_origin_y,
+        measuring_mode,
+        &view,
+    ))
+}
+
+unsafe extern "system" fn TextRendererImpl_DrawUnderline(
+    this: *mut IDWriteTextRenderer,
+    _: *mut c_void,
+    baseline_origin_x: FLOAT,
+    baseline_origin_y: FLOAT,
+    underline: *const DWRITE_UNDERLINE,
+    _: *mut IUnknown,
+) -> HRESULT {
+    let this = TextRendererImpl::from_interface(this);
+    let underline = &*underline;
+    let decoration = Decoration {
+        width: underline.width,
+        thickness: underline.thickness,
+        offset: underline.offset,
+        run_height: underline.runHeight,
+        reading_direction: underline.readingDirection,
+        flow_direction: FlowDirection::from_raw(underline.flowDirection),
+        locale: wide_to_string(underline.localeName),
+        measuring_mode: underline.measuringMode,
+    };
+    to_hresult(
+        this.inner
+            .draw_underline(baseline_origin_x, baseline_origin_y, &decoration),
+    )
+}
+
+unsafe extern "system" fn TextRendererImpl_DrawStrikethrough(
+    this: *mut IDWriteTextRenderer,
+    _: *mut c_void,
+    baseline_origin_x: FLOAT,
+    baseline_origin_y: FLOAT,
+    strikethrough: *const DWRITE_STRIKETHROUGH,
+    _: *mut IUnknown,
+) -> HRESULT {
+    let this = TextRendererImpl::from_interface(this);
+    let strikethrough = &*strikethrough;
+    let decoration = Decoration {
+        width: strikethrough.width,
+        thickness: strikethrough.thickness,
+        offset: strikethrough.offset,
+        run_height: 0.0,
+        reading_direction: strikethrough.readingDirection,
+        flow_direction: FlowDirection::from_raw(strikethrough.flowDirection),
+        locale: wide_to_string(strikethrough.localeName),
+        measuring_mode: strikethrough.measuringMode,
+    };
+    to_hresult(
+        this.inner
+            .draw_strikethrough(baseline_origin_x, baseline_origin_y, &decoration),
+    )
+}
+
+unsafe extern "system" fn TextRendererImpl_DrawInlineObject(
+    this: *mut IDWriteTextRenderer,
+    _: *mut c_void,
+    origin_x: FLOAT,
+    origin_y: FLOAT,
+    inline_object: *mut IDWriteInlineObject,
+    is_sideways: BOOL,
+    is_right_to_left: BOOL,
+    _: *mut IUnknown,
+) -> HRESULT {
+    let this = TextRendererImpl::from_interface(this);
+    (*inline_object).AddRef();
+    let object = InlineObject::take(ComPtr::from_raw(inline_object));
+    to_hresult(this.inner.draw_inline_object(
+        origin_x,
+        origin_y,
+        &object,
+        is_sideways != FALSE,
+        is_right_to_left != FALSE,
+    ))
+}
+
+fn to_hresult(result: Result<(), Error>) -> HRESULT {
+    match result {
+        Ok(()) => S_OK,
+        Err(error) => error.hresult(),
+    }
+}
+
+unsafe fn slice_or_empty<'a, T>(data: *const T, len: usize) -> &'a [T] {
+    if data.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+unsafe fn wide_to_string(string: *const WCHAR) -> String {
+    if string.is_null() {
+        return String::new();
+    }
+    let mut len = 0;
+    while *string.add(len) != 0 {
+        len += 1;
+    }
+    String::from_utf16_lossy(slice::from_raw_parts(string, len))
+}
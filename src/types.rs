@@ -3,7 +3,9 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 /* this is include!()'d in lib.rs */
+use std::fmt;
 use std::mem;
+use std::str::FromStr;
 use winapi::um::dwrite::{DWRITE_FONT_STYLE, DWRITE_FONT_WEIGHT, DWRITE_FONT_STRETCH};
 
 // mirrors DWRITE_FONT_WEIGHT
@@ -60,6 +62,58 @@ impl FontWeight {
                 _ => FontWeight::Unknown(v)
             }
     }
+
+    // CSS-style names, as used by `Display` and `FromStr`.
+    fn name(&self) -> Option<&'static str> {
+        Some(match self {
+            FontWeight::Thin => "thin",
+            FontWeight::ExtraLight => "extra-light",
+            FontWeight::Light => "light",
+            FontWeight::SemiLight => "semi-light",
+            FontWeight::Regular => "normal",
+            FontWeight::Medium => "medium",
+            FontWeight::SemiBold => "semi-bold",
+            FontWeight::Bold => "bold",
+            FontWeight::ExtraBold => "extra-bold",
+            FontWeight::Black => "black",
+            FontWeight::ExtraBlack => "extra-black",
+            FontWeight::Unknown(_) => return None,
+        })
+    }
+}
+
+/// Displays the CSS-style name of the weight (e.g. `semi-bold`), or its
+/// number for `Unknown` weights.
+impl fmt::Display for FontWeight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "{}", self.to_u32()),
+        }
+    }
+}
+
+/// Parses the names written by `Display`, ignoring ASCII case, as well as
+/// `regular` and numeric weights from 1 to 999.
+impl FromStr for FontWeight {
+    type Err = ParseFontPropertyError;
+
+    fn from_str(s: &str) -> Result<FontWeight, ParseFontPropertyError> {
+        if let Ok(v) = s.parse::<u32>() {
+            if (1..=999).contains(&v) {
+                return Ok(FontWeight::from_u32(v));
+            }
+            return Err(ParseFontPropertyError);
+        }
+        if s.eq_ignore_ascii_case("regular") {
+            return Ok(FontWeight::Regular);
+        }
+        (100..=950)
+            .step_by(50)
+            .map(FontWeight::from_u32)
+            .find(|weight| matches!(weight.name(), Some(name) if name.eq_ignore_ascii_case(s)))
+            .ok_or(ParseFontPropertyError)
+    }
 }
 
 // mirrors DWRITE_FONT_STRETCH
@@ -85,6 +139,40 @@ impl FontStretch {
     }
     pub fn to_u32(&self) -> u32 { unsafe { mem::transmute::<FontStretch, u32>(*self) } }
     pub fn from_u32(v: u32) -> FontStretch { unsafe { mem::transmute::<u32, FontStretch>(v) } }
+
+    // CSS-style names, as used by `Display` and `FromStr`.
+    fn name(&self) -> &'static str {
+        match self {
+            FontStretch::Undefined => "undefined",
+            FontStretch::UltraCondensed => "ultra-condensed",
+            FontStretch::ExtraCondensed => "extra-condensed",
+            FontStretch::Condensed => "condensed",
+            FontStretch::SemiCondensed => "semi-condensed",
+            FontStretch::Normal => "normal",
+            FontStretch::SemiExpanded => "semi-expanded",
+            FontStretch::Expanded => "expanded",
+            FontStretch::ExtraExpanded => "extra-expanded",
+            FontStretch::UltraExpanded => "ultra-expanded",
+        }
+    }
+}
+
+impl fmt::Display for FontStretch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parses the names written by `Display`, ignoring ASCII case.
+impl FromStr for FontStretch {
+    type Err = ParseFontPropertyError;
+
+    fn from_str(s: &str) -> Result<FontStretch, ParseFontPropertyError> {
+        (0..=9)
+            .map(FontStretch::from_u32)
+            .find(|stretch| stretch.name().eq_ignore_ascii_case(s))
+            .ok_or(ParseFontPropertyError)
+    }
 }
 
 // mirrors DWRITE_FONT_STYLE
@@ -103,8 +191,49 @@ impl FontStyle {
     }
     pub fn to_u32(&self) -> u32 { unsafe { mem::transmute::<FontStyle, u32>(*self) } }
     pub fn from_u32(v: u32) -> FontStyle { unsafe { mem::transmute::<u32, FontStyle>(v) } }
+
+    // CSS-style names, as used by `Display` and `FromStr`.
+    fn name(&self) -> &'static str {
+        match self {
+            FontStyle::Normal => "normal",
+            FontStyle::Oblique => "oblique",
+            FontStyle::Italic => "italic",
+        }
+    }
 }
 
+impl fmt::Display for FontStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parses the names written by `Display`, ignoring ASCII case.
+impl FromStr for FontStyle {
+    type Err = ParseFontPropertyError;
+
+    fn from_str(s: &str) -> Result<FontStyle, ParseFontPropertyError> {
+        [FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic]
+            .iter()
+            .cloned()
+            .find(|style| style.name().eq_ignore_ascii_case(s))
+            .ok_or(ParseFontPropertyError)
+    }
+}
+
+/// The error returned when parsing a `FontWeight`, `FontStretch` or
+/// `FontStyle` from an unknown name.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ParseFontPropertyError;
+
+impl fmt::Display for ParseFontPropertyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("unknown font weight, stretch or style")
+    }
+}
+
+impl std::error::Error for ParseFontPropertyError {}
+
 // mirrors DWRITE_FONT_SIMULATIONS
 #[repr(u32)]
 #[derive(PartialEq, Debug, Clone, Copy)]
@@ -124,3 +253,158 @@ pub struct FontDescriptor {
     pub stretch: FontStretch,
     pub style: FontStyle,
 }
+
+/// An owned glyph run, which keeps the glyph, advance and offset arrays that
+/// a `DWRITE_GLYPH_RUN` points into alive.
+///
+/// Advances and offsets are optional; if they are left empty DirectWrite
+/// uses the font's default advances and no offsets. If they are given, they
+/// must have one entry per glyph.
+#[derive(Clone)]
+pub struct GlyphRun {
+    face: FontFace,
+    em_size: f32,
+    glyphs: Vec<u16>,
+    advances: Vec<f32>,
+    offsets: Vec<GlyphOffset>,
+    is_sideways: bool,
+    bidi_level: u32,
+}
+
+impl GlyphRun {
+    pub fn new(face: &FontFace, em_size: f32) -> GlyphRun {
+        GlyphRun {
+            face: face.clone(),
+            em_size,
+            glyphs: Vec::new(),
+            advances: Vec::new(),
+            offsets: Vec::new(),
+            is_sideways: false,
+            bidi_level: 0,
+        }
+    }
+
+    /// Creates a run that takes ownership of its arrays, failing with
+    /// `Error::InvalidArg` if `advances` or `offsets` are neither empty nor the
+    /// same length as `glyphs`.
+    pub fn from_parts(
+        face: &FontFace,
+        em_size: f32,
+        glyphs: Vec<u16>,
+        advances: Vec<f32>,
+        offsets: Vec<GlyphOffset>,
+    ) -> Result<GlyphRun, Error> {
+        let matches = |len: usize| len == 0 || len == glyphs.len();
+        if !matches(advances.len()) || !matches(offsets.len()) {
+            return Err(Error::InvalidArg);
+        }
+        Ok(GlyphRun {
+            face: face.clone(),
+            em_size,
+            glyphs,
+            advances,
+            offsets,
+            is_sideways: false,
+            bidi_level: 0,
+        })
+    }
+
+    pub fn with_glyphs(mut self, glyphs: &[u16]) -> GlyphRun {
+        self.glyphs = glyphs.to_vec();
+        self
+    }
+
+    pub fn with_advances(mut self, advances: &[f32]) -> GlyphRun {
+        self.advances = advances.to_vec();
+        self
+    }
+
+    pub fn with_offsets(mut self, offsets: &[GlyphOffset]) -> GlyphRun {
+        self.offsets = offsets.to_vec();
+        self
+    }
+
+    pub fn sideways(mut self, is_sideways: bool) -> GlyphRun {
+        self.is_sideways = is_sideways;
+        self
+    }
+
+    /// Odd levels are right-to-left.
+    pub fn bidi_level(mut self, bidi_level: u32) -> GlyphRun {
+        self.bidi_level = bidi_level;
+        self
+    }
+
+    pub fn face(&self) -> &FontFace {
+        &self.face
+    }
+
+    pub fn em_size(&self) -> f32 {
+        self.em_size
+    }
+
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.is_empty()
+    }
+
+    pub fn glyphs(&self) -> &[u16] {
+        &self.glyphs
+    }
+
+    pub fn advances(&self) -> &[f32] {
+        &self.advances
+    }
+
+    pub fn offsets(&self) -> &[GlyphOffset] {
+        &self.offsets
+    }
+
+    /// Returns the raw run, pointing into `self`. It must not be used once
+    /// `self` is modified or dropped.
+    ///
+    /// Panics if the advances or offsets don't match the glyph count.
+    pub fn as_raw(&self) -> DWRITE_GLYPH_RUN {
+        assert!(
+            self.advances.is_empty() || self.advances.len() == self.glyphs.len(),
+            "glyph run has {} glyphs but {} advances",
+            self.glyphs.len(),
+            self.advances.len()
+        );
+        assert!(
+            self.offsets.is_empty() || self.offsets.len() == self.glyphs.len(),
+            "glyph run has {} glyphs but {} offsets",
+            self.glyphs.len(),
+            self.offsets.len()
+        );
+        fn ptr_or_null<T>(slice: &[T]) -> *const T {
+            if slice.is_empty() {
+                ptr::null()
+            } else {
+                slice.as_ptr()
+            }
+        }
+        DWRITE_GLYPH_RUN {
+            fontFace: unsafe { self.face.as_ptr() },
+            fontEmSize: self.em_size,
+            glyphCount: self.glyphs.len() as u32,
+            glyphIndices: self.glyphs.as_ptr(),
+            glyphAdvances: ptr_or_null(&self.advances),
+            glyphOffsets: ptr_or_null(&self.offsets),
+            isSideways: self.is_sideways as i32,
+            bidiLevel: self.bidi_level,
+        }
+    }
+}
+
+impl std::ops::Index<usize> for GlyphRun {
+    type Output = u16;
+
+    /// Returns the glyph index at position `index` in the run.
+    fn index(&self, index: usize) -> &u16 {
+        &self.glyphs[index]
+    }
+}
@@ -0,0 +1,88 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::UnsafeCell;
+use std::mem;
+use std::ptr;
+use winapi::shared::winerror::S_OK;
+use winapi::um::dwrite::{IDWriteTypography, DWRITE_FONT_FEATURE};
+use wio::com::ComPtr;
+
+use super::FontFeature;
+use crate::error::Error;
+use crate::factory::{factory_or_shared, Factory};
+
+/// A set of OpenType features, to be applied to a range of a `TextLayout`.
+pub struct Typography {
+    native: UnsafeCell<ComPtr<IDWriteTypography>>,
+}
+
+impl Typography {
+    pub fn new() -> Result<Typography, Error> {
+        Typography::new_with_factory(None)
+    }
+
+    pub fn new_with_factory(factory: Option<&Factory>) -> Result<Typography, Error> {
+        unsafe {
+            let mut native: *mut IDWriteTypography = ptr::null_mut();
+            let hr = (*factory_or_shared(factory)).CreateTypography(&mut native);
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(Typography::take(ComPtr::from_raw(native)))
+        }
+    }
+
+    pub fn take(native: ComPtr<IDWriteTypography>) -> Typography {
+        Typography {
+            native: UnsafeCell::new(native),
+        }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut IDWriteTypography {
+        (*self.native.get()).as_raw()
+    }
+
+    pub fn add_font_feature(&self, feature: FontFeature) -> Result<(), Error> {
+        unsafe {
+            let hr = (*self.native.get()).AddFontFeature(DWRITE_FONT_FEATURE {
+                nameTag: feature.tag,
+                parameter: feature.value,
+            });
+            if hr != S_OK {
+                return Err(hr.into());
+            }
+            Ok(())
+        }
+    }
+
+    pub fn font_features(&self) -> Result<Vec<FontFeature>, Error> {
+        unsafe {
+            let native = &*self.native.get();
+            (0..native.GetFontFeatureCount())
+                .map(|index| {
+                    let mut feature: DWRITE_FONT_FEATURE = mem::zeroed();
+                    let hr = native.GetFontFeature(index, &mut feature);
+                    if hr != 0 {
+                        return Err(hr.into());
+                    }
+                    Ok(FontFeature {
+                        tag: feature.nameTag,
+                        value: feature.parameter,
+                    })
+                })
+                .collect()
+        }
+    }
+}
+
+impl Clone for Typography {
+    fn clone(&self) -> Typography {
+        unsafe {
+            Typography {
+                native: UnsafeCell::new((*self.native.get()).clone()),
+            }
+        }
+    }
+}